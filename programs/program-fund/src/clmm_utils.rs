@@ -0,0 +1,303 @@
+use bytemuck::bytes_of;
+use fixed::types::U64F64;
+
+use solana_program::{
+    account_info::AccountInfo,
+    instruction::{AccountMeta, Instruction},
+    msg,
+    program::invoke_signed,
+    program_error::ProgramError,
+    pubkey::Pubkey,
+};
+use arrayref::array_ref;
+use spl_token::state::Account;
+
+use crate::error::FundError;
+use crate::instruction::Data;
+use crate::processor::parse_token_account;
+
+macro_rules! check {
+    ($cond:expr, $err:expr) => {
+        if !($cond) {
+            return Err(($err).into())
+        }
+    }
+}
+macro_rules! check_eq {
+    ($x:expr, $y:expr) => {
+        if ($x != $y) {
+            return Err(FundError::Default.into())
+        }
+    }
+}
+
+pub mod whirlpool_id {
+    use solana_program::declare_id;
+    declare_id!("whirLbMiicVdio4qvUfM5KAg6Ct8VwpYzGff3uctyCc");
+}
+
+pub mod raydium_clmm_id {
+    use solana_program::declare_id;
+    declare_id!("CAMMCzo5YL8w4VFF8KVHrK22GGUsp5VTaW7grrKgrWqK");
+}
+
+// TokenInfo.token_id / TokenSlot.mux of the concentrated liquidity venues
+pub const WHIRLPOOL_TOKEN_ID: u8 = 3;
+pub const RAYDIUM_CLMM_TOKEN_ID: u8 = 4;
+
+// anchor sighash of "global:swap", same for both programs
+const SWAP_DISCRIMINATOR: [u8; 8] = [248, 198, 158, 145, 225, 117, 135, 200];
+// whirlpool sqrt price bounds, used as "no limit"
+const WHIRLPOOL_MIN_SQRT_PRICE: u128 = 4295048016;
+const WHIRLPOOL_MAX_SQRT_PRICE: u128 = 79226673515401279992447579055;
+
+pub fn is_clmm(token_id: u8) -> bool {
+    token_id == WHIRLPOOL_TOKEN_ID || token_id == RAYDIUM_CLMM_TOKEN_ID
+}
+
+/// Fields shared by Whirlpool and Raydium CLMM pool accounts
+pub struct ClmmPool {
+    pub mint_a: Pubkey,
+    pub mint_b: Pubkey,
+    pub vault_a: Pubkey,
+    pub vault_b: Pubkey,
+    /// Q64.64 sqrt of the price of a in b
+    pub sqrt_price: u128,
+}
+
+pub fn load_clmm_pool(pool_ai: &AccountInfo, token_id: u8) -> Result<ClmmPool, ProgramError> {
+    let data = pool_ai.try_borrow_data()?;
+    let read_pk = |offset: usize| Pubkey::new_from_array(*array_ref![data, offset, 32]);
+    match token_id {
+        WHIRLPOOL_TOKEN_ID => {
+            check_eq!(*pool_ai.owner, whirlpool_id::ID);
+            check!(data.len() >= 245, ProgramError::InvalidAccountData);
+            Ok(ClmmPool {
+                mint_a: read_pk(101),
+                mint_b: read_pk(181),
+                vault_a: read_pk(133),
+                vault_b: read_pk(213),
+                sqrt_price: u128::from_le_bytes(*array_ref![data, 65, 16]),
+            })
+        }
+        RAYDIUM_CLMM_TOKEN_ID => {
+            check_eq!(*pool_ai.owner, raydium_clmm_id::ID);
+            check!(data.len() >= 269, ProgramError::InvalidAccountData);
+            Ok(ClmmPool {
+                mint_a: read_pk(73),
+                mint_b: read_pk(105),
+                vault_a: read_pk(137),
+                vault_b: read_pk(169),
+                sqrt_price: u128::from_le_bytes(*array_ref![data, 253, 16]),
+            })
+        }
+        _ => Err(ProgramError::InvalidArgument)
+    }
+}
+
+impl ClmmPool {
+    /// Price of `coin_mint` in the other pool token, native units like `TokenInfo.pool_price`
+    pub fn get_price(&self, coin_mint: &Pubkey) -> Result<U64F64, ProgramError> {
+        let sqrt_price = U64F64::from_bits(self.sqrt_price);
+        let price = sqrt_price.checked_mul(sqrt_price).ok_or(ProgramError::InvalidAccountData)?;
+        if *coin_mint == self.mint_a {
+            Ok(price)
+        } else if *coin_mint == self.mint_b {
+            U64F64::from_num(1).checked_div(price).ok_or(ProgramError::InvalidAccountData)
+        } else {
+            Err(ProgramError::InvalidAccountData)
+        }
+    }
+    pub fn get_vault(&self, mint: &Pubkey) -> Option<Pubkey> {
+        if *mint == self.mint_a {
+            Some(self.vault_a)
+        } else if *mint == self.mint_b {
+            Some(self.vault_b)
+        } else {
+            None
+        }
+    }
+}
+
+fn swap_data(data: &Data, sqrt_price_limit: u128, flags: &[bool]) -> Vec<u8> {
+    let mut ix_data = SWAP_DISCRIMINATOR.to_vec();
+    ix_data.extend_from_slice(&data.amount_in.to_le_bytes());
+    ix_data.extend_from_slice(&data.min_amount_out.to_le_bytes());
+    ix_data.extend_from_slice(&sqrt_price_limit.to_le_bytes());
+    ix_data.extend(flags.iter().map(|f| *f as u8));
+    ix_data
+}
+
+/// Exact input swap on an Orca Whirlpool, the fund PDA signs as token authority
+pub fn swap_instruction_whirlpool(
+    data: &Data,
+    nonce: u8,
+    accounts: &[AccountInfo]
+) -> Result<(Account, Account), ProgramError> {
+    let accounts = array_ref![accounts, 0, 14];
+    let [
+        _platform_state_ai,
+        fund_account_ai,
+        manager_ai,
+        whirlpool_prog_ai,
+        token_prog_ai,
+        whirlpool_ai,
+        source_token_ai,
+        dest_token_ai,
+        vault_a_ai,
+        vault_b_ai,
+        tick_array_0_ai,
+        tick_array_1_ai,
+        tick_array_2_ai,
+        oracle_ai
+    ] = accounts;
+
+    check_eq!(*whirlpool_prog_ai.key, whirlpool_id::ID);
+    let pool = load_clmm_pool(whirlpool_ai, WHIRLPOOL_TOKEN_ID)?;
+    check_eq!(pool.vault_a, *vault_a_ai.key);
+    check_eq!(pool.vault_b, *vault_b_ai.key);
+    let a_to_b = parse_token_account(source_token_ai)?.mint == pool.mint_a;
+    let (owner_a_ai, owner_b_ai) = if a_to_b { (source_token_ai, dest_token_ai) } else { (dest_token_ai, source_token_ai) };
+    let sqrt_price_limit = if a_to_b { WHIRLPOOL_MIN_SQRT_PRICE } else { WHIRLPOOL_MAX_SQRT_PRICE };
+
+    invoke_signed(
+        &Instruction {
+            program_id: *whirlpool_prog_ai.key,
+            accounts: vec![
+                AccountMeta::new_readonly(*token_prog_ai.key, false),
+                AccountMeta::new_readonly(*fund_account_ai.key, true),
+                AccountMeta::new(*whirlpool_ai.key, false),
+                AccountMeta::new(*owner_a_ai.key, false),
+                AccountMeta::new(*vault_a_ai.key, false),
+                AccountMeta::new(*owner_b_ai.key, false),
+                AccountMeta::new(*vault_b_ai.key, false),
+                AccountMeta::new(*tick_array_0_ai.key, false),
+                AccountMeta::new(*tick_array_1_ai.key, false),
+                AccountMeta::new(*tick_array_2_ai.key, false),
+                AccountMeta::new_readonly(*oracle_ai.key, false),
+            ],
+            // amount is input, a_to_b
+            data: swap_data(data, sqrt_price_limit, &[true, a_to_b]),
+        },
+        &[
+            token_prog_ai.clone(),
+            fund_account_ai.clone(),
+            whirlpool_ai.clone(),
+            owner_a_ai.clone(),
+            vault_a_ai.clone(),
+            owner_b_ai.clone(),
+            vault_b_ai.clone(),
+            tick_array_0_ai.clone(),
+            tick_array_1_ai.clone(),
+            tick_array_2_ai.clone(),
+            oracle_ai.clone(),
+        ],
+        &[&[&*manager_ai.key.as_ref(), bytes_of(&nonce)]]
+    )?;
+    msg!("swap instruction done");
+
+    let source_info = parse_token_account(source_token_ai)?;
+    let dest_info = parse_token_account(dest_token_ai)?;
+    Ok((source_info, dest_info))
+}
+
+/// Exact input swap on a Raydium CLMM pool, the fund PDA signs as payer
+pub fn swap_instruction_raydium_clmm(
+    data: &Data,
+    nonce: u8,
+    accounts: &[AccountInfo]
+) -> Result<(Account, Account), ProgramError> {
+    let accounts = array_ref![accounts, 0, 13];
+    let [
+        _platform_state_ai,
+        fund_account_ai,
+        manager_ai,
+        clmm_prog_ai,
+        token_prog_ai,
+        amm_config_ai,
+        pool_state_ai,
+        source_token_ai,
+        dest_token_ai,
+        input_vault_ai,
+        output_vault_ai,
+        observation_ai,
+        tick_array_ai
+    ] = accounts;
+
+    check_eq!(*clmm_prog_ai.key, raydium_clmm_id::ID);
+    let pool = load_clmm_pool(pool_state_ai, RAYDIUM_CLMM_TOKEN_ID)?;
+    check_eq!(pool.get_vault(&parse_token_account(source_token_ai)?.mint), Some(*input_vault_ai.key));
+    check_eq!(pool.get_vault(&parse_token_account(dest_token_ai)?.mint), Some(*output_vault_ai.key));
+
+    invoke_signed(
+        &Instruction {
+            program_id: *clmm_prog_ai.key,
+            accounts: vec![
+                AccountMeta::new_readonly(*fund_account_ai.key, true),
+                AccountMeta::new_readonly(*amm_config_ai.key, false),
+                AccountMeta::new(*pool_state_ai.key, false),
+                AccountMeta::new(*source_token_ai.key, false),
+                AccountMeta::new(*dest_token_ai.key, false),
+                AccountMeta::new(*input_vault_ai.key, false),
+                AccountMeta::new(*output_vault_ai.key, false),
+                AccountMeta::new(*observation_ai.key, false),
+                AccountMeta::new_readonly(*token_prog_ai.key, false),
+                AccountMeta::new(*tick_array_ai.key, false),
+            ],
+            // 0 sqrt price limit is no limit, amount is base input
+            data: swap_data(data, 0, &[true]),
+        },
+        &[
+            fund_account_ai.clone(),
+            amm_config_ai.clone(),
+            pool_state_ai.clone(),
+            source_token_ai.clone(),
+            dest_token_ai.clone(),
+            input_vault_ai.clone(),
+            output_vault_ai.clone(),
+            observation_ai.clone(),
+            token_prog_ai.clone(),
+            tick_array_ai.clone(),
+        ],
+        &[&[&*manager_ai.key.as_ref(), bytes_of(&nonce)]]
+    )?;
+    msg!("swap instruction done");
+
+    let source_info = parse_token_account(source_token_ai)?;
+    let dest_info = parse_token_account(dest_token_ai)?;
+    Ok((source_info, dest_info))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pool(sqrt_price: u128) -> ClmmPool {
+        ClmmPool {
+            mint_a: Pubkey::new_unique(),
+            mint_b: Pubkey::new_unique(),
+            vault_a: Pubkey::new_unique(),
+            vault_b: Pubkey::new_unique(),
+            sqrt_price,
+        }
+    }
+
+    #[test]
+    fn get_price_of_either_mint() {
+        // sqrt price of 2 in Q64.64
+        let pool = pool(2 << 64);
+        assert_eq!(pool.get_price(&pool.mint_a), Ok(U64F64::from_num(4)));
+        assert_eq!(pool.get_price(&pool.mint_b), Ok(U64F64::from_num(0.25)));
+        assert_eq!(pool.get_price(&Pubkey::new_unique()), Err(ProgramError::InvalidAccountData));
+    }
+
+    #[test]
+    fn get_price_rejects_out_of_range_prices() {
+        // price overflows U64F64
+        let pool_high = pool(1 << 97);
+        assert!(pool_high.get_price(&pool_high.mint_a).is_err());
+        // price rounds to 0, no inverse
+        let pool_low = pool(1);
+        assert!(pool_low.get_price(&pool_low.mint_b).is_err());
+    }
+}
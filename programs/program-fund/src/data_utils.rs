@@ -0,0 +1,42 @@
+use arrayref::array_ref;
+use fixed::types::I80F48;
+
+use solana_program::{
+    program_error::ProgramError,
+    pubkey::Pubkey,
+};
+
+// Little endian readers for accounts of other programs, out of range offsets are InvalidAccountData
+
+pub fn read_pubkey(data: &[u8], offset: usize) -> Result<Pubkey, ProgramError> {
+    let bytes = data.get(offset..offset + 32).ok_or(ProgramError::InvalidAccountData)?;
+    Ok(Pubkey::new(bytes))
+}
+
+pub fn read_u16(data: &[u8], offset: usize) -> Result<u16, ProgramError> {
+    let bytes = data.get(offset..offset + 2).ok_or(ProgramError::InvalidAccountData)?;
+    Ok(u16::from_le_bytes(*array_ref![bytes, 0, 2]))
+}
+
+pub fn read_u32(data: &[u8], offset: usize) -> Result<u32, ProgramError> {
+    let bytes = data.get(offset..offset + 4).ok_or(ProgramError::InvalidAccountData)?;
+    Ok(u32::from_le_bytes(*array_ref![bytes, 0, 4]))
+}
+
+pub fn read_u64(data: &[u8], offset: usize) -> Result<u64, ProgramError> {
+    let bytes = data.get(offset..offset + 8).ok_or(ProgramError::InvalidAccountData)?;
+    Ok(u64::from_le_bytes(*array_ref![bytes, 0, 8]))
+}
+
+pub fn read_i64(data: &[u8], offset: usize) -> Result<i64, ProgramError> {
+    Ok(read_u64(data, offset)? as i64)
+}
+
+pub fn read_u128(data: &[u8], offset: usize) -> Result<u128, ProgramError> {
+    let bytes = data.get(offset..offset + 16).ok_or(ProgramError::InvalidAccountData)?;
+    Ok(u128::from_le_bytes(*array_ref![bytes, 0, 16]))
+}
+
+pub fn read_i80f48(data: &[u8], offset: usize) -> Result<I80F48, ProgramError> {
+    Ok(I80F48::from_bits(read_u128(data, offset)? as i128))
+}
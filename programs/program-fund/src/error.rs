@@ -96,10 +96,34 @@ pub enum FundError {
      FriktionIncorrectULMint,
 
      #[error("Incorrect Friktion Vault")]
-     InvestorIndexError
+     InvestorIndexError,
 
+     #[error("Unsupported account layout version")]
+     UnsupportedAccountVersion,
 
+     #[error("Trade exceeds the fund risk limits")]
+     RiskLimitExceeded,
 
+     #[error("Fund mandate is locked")]
+     MandateLocked,
+
+     #[error("Trade breaches the fund mandate")]
+     MandateViolation,
+
+     #[error("Unknown strategy kind")]
+     InvalidStrategy,
+
+     #[error("Borrowing above the fund's max LTV")]
+     LeverageLimitExceeded,
+
+     #[error("Investor exit has steps left, see QuoteInvestorExit")]
+     ExitStepPending,
+
+     #[error("Price updated in this slot, keeper trades need an earlier update")]
+     PriceUpdatedThisSlot,
+
+     #[error("Reimbursement snapshot not sealed, add the remaining investors first")]
+     ReimbursementSnapshotPending,
 }
 
 impl From<FundError> for ProgramError {
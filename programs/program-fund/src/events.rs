@@ -0,0 +1,75 @@
+use borsh::{BorshDeserialize, BorshSerialize};
+use solana_program::{log::sol_log_data, pubkey::Pubkey};
+
+use crate::state::NUM_TOKENS;
+
+/// Prefix of every event logged by the program, the rest is `borsh(FundEvent)`.
+/// Shows up in the logs as one base64 `Program data:` entry.
+pub const EVENT_DISCRIMINATOR: [u8; 8] = *b"IVNEVT01";
+
+/// Events for indexers. Borsh tags variants by position: append only, never reorder.
+/// U64F64 values are logged as their raw bits.
+#[derive(Clone, Debug, PartialEq, BorshSerialize, BorshDeserialize)]
+pub enum FundEvent {
+    Deposit {
+        fund: Pubkey,
+        investor: Pubkey,
+        investor_state: Pubkey,
+        amount: u64,
+    },
+    /// manager moved router deposits into the fund
+    Transfer {
+        fund: Pubkey,
+        amount: u64,
+        investor_count: u8,
+        performance: u128,
+    },
+    Swap {
+        fund: Pubkey,
+        token_in_slot: u8,
+        token_out_slot: u8,
+        amount_in: u64,
+        amount_out: u64,
+        /// USDC value of the input, 0 if not priced
+        input_value: u128,
+    },
+    FeeClaim {
+        fund: Pubkey,
+        manager_fee: u64,
+        investin_fee: u64,
+    },
+    Withdraw {
+        fund: Pubkey,
+        investor_state: Pubkey,
+        share: u128,
+        token_amounts: [u64; NUM_TOKENS],
+        /// refunded from the router, when the manager had not transferred yet
+        amount_in_router: u64,
+    },
+    FriktionDeposit {
+        fund: Pubkey,
+        volt_vault: Pubkey,
+        amount: u64,
+    },
+    PriceUpdate {
+        token_index: u8,
+        mint: Pubkey,
+        price: u128,
+        timestamp: i64,
+    },
+}
+
+pub fn emit_event(event: &FundEvent) {
+    let mut data = EVENT_DISCRIMINATOR.to_vec();
+    // serializing into a Vec cannot fail
+    event.serialize(&mut data).unwrap();
+    sol_log_data(&[&data]);
+}
+
+/// Decodes one (base64 decoded) `Program data:` entry, None if it is not a fund event
+pub fn decode_event(data: &[u8]) -> Option<FundEvent> {
+    if data.len() < EVENT_DISCRIMINATOR.len() || data[..EVENT_DISCRIMINATOR.len()] != EVENT_DISCRIMINATOR {
+        return None;
+    }
+    FundEvent::try_from_slice(&data[EVENT_DISCRIMINATOR.len()..]).ok()
+}
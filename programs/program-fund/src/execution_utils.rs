@@ -0,0 +1,205 @@
+use std::mem::size_of;
+
+use bytemuck::bytes_of;
+use solana_program::{
+    account_info::{AccountInfo, next_account_info},
+    msg,
+    program::invoke_signed,
+    program_error::ProgramError,
+    program_pack::IsInitialized,
+    pubkey::Pubkey,
+    system_instruction::create_account,
+    sysvar::{Sysvar, clock::Clock, rent::Rent},
+};
+
+use crate::error::FundError;
+use crate::migration::EXECUTION_ORDER_VERSION;
+use crate::state::{NUM_TOKENS, GUARD_SOURCE_EXECUTION_ORDER, ExecutionOrder, FundAccount, PlatformData};
+use crate::jup_utils::{arm_swap_guard, check_guard_pairing, check_price_slots, MAX_SLIPPAGE_BPS};
+
+macro_rules! check {
+    ($cond:expr, $err:expr) => {
+        if !($cond) {
+            return Err(($err).into())
+        }
+    }
+}
+macro_rules! check_eq {
+    ($x:expr, $y:expr) => {
+        if ($x != $y) {
+            return Err(FundError::Default.into())
+        }
+    }
+}
+
+pub const EXECUTION_ORDER_SEED: &[u8] = b"execution_order";
+pub const MAX_ORDER_SLICES: u16 = 1000;
+
+/// Starts a DCA / TWAP order, the account is created on first use and reused after.
+/// The first slice is due right away. Keepers run it, so its slippage bound is required.
+///
+/// Accounts expected
+/// 0. []   Fund State Account
+/// 1. [SIGNER, WRITE] Manager Account
+/// 2. [WRITE]  Execution Order Account, PDA of [fund, "execution_order"]
+/// 3. []   System Program
+pub fn create_execution_order(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    token_in_fund_slot: u8,
+    token_out_fund_slot: u8,
+    total_amount_in: u64,
+    slices: u16,
+    interval: i64,
+    max_slippage_bps: u16,
+) -> Result<(), ProgramError> {
+    let accounts_iter = &mut accounts.iter();
+    let fund_account_ai = next_account_info(accounts_iter)?;
+    let manager_ai = next_account_info(accounts_iter)?;
+    let order_ai = next_account_info(accounts_iter)?;
+    let system_program_ai = next_account_info(accounts_iter)?;
+
+    let fund_data = FundAccount::load_checked(fund_account_ai, program_id)?;
+    check!(fund_data.is_initialized(), FundError::InvalidStateAccount);
+    check!(manager_ai.is_signer, ProgramError::MissingRequiredSignature);
+    check!(fund_data.manager_account == *manager_ai.key, FundError::ManagerMismatch);
+    let (si, di) = (token_in_fund_slot as usize, token_out_fund_slot as usize);
+    check!(si < NUM_TOKENS && di < NUM_TOKENS && si != di, ProgramError::InvalidArgument);
+    check!(fund_data.tokens[si].is_active && fund_data.tokens[di].is_active, ProgramError::InvalidArgument);
+    check!(total_amount_in > 0 && slices > 0 && slices <= MAX_ORDER_SLICES, ProgramError::InvalidArgument);
+    check!(interval >= 0 && max_slippage_bps > 0 && max_slippage_bps <= MAX_SLIPPAGE_BPS, ProgramError::InvalidArgument);
+
+    if order_ai.data_is_empty() {
+        let (pda, nonce) = Pubkey::find_program_address(&[fund_account_ai.key.as_ref(), EXECUTION_ORDER_SEED], program_id);
+        check!(*order_ai.key == pda, FundError::IncorrectPDA);
+        let size = size_of::<ExecutionOrder>();
+        invoke_signed(
+            &create_account(
+                manager_ai.key,
+                order_ai.key,
+                Rent::get()?.minimum_balance(size),
+                size as u64,
+                program_id,
+            ),
+            &[manager_ai.clone(), order_ai.clone(), system_program_ai.clone()],
+            &[&[fund_account_ai.key.as_ref(), EXECUTION_ORDER_SEED, bytes_of(&nonce)]]
+        )?;
+    }
+
+    let mut order = ExecutionOrder::load_mut_checked(order_ai, program_id)?;
+    if order.is_initialized {
+        check_eq!(order.fund, *fund_account_ai.key);
+        check!(!order.is_active, ProgramError::AccountAlreadyInitialized);
+    }
+    order.is_initialized = true;
+    order.version = EXECUTION_ORDER_VERSION;
+    order.is_active = true;
+    order.fund = *fund_account_ai.key;
+    order.token_in_slot = token_in_fund_slot;
+    order.token_out_slot = token_out_fund_slot;
+    order.total_amount_in = total_amount_in;
+    order.amount_in_done = 0;
+    order.slices = slices;
+    order.slices_done = 0;
+    order.interval = interval;
+    order.max_slippage_bps = max_slippage_bps;
+    order.next_slice_at = Clock::get()?.unix_timestamp;
+    Ok(())
+}
+
+/// Accounts expected
+/// 0. []   Fund State Account
+/// 1. [SIGNER] Manager Account
+/// 2. [WRITE]  Execution Order Account
+pub fn cancel_execution_order(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+) -> Result<(), ProgramError> {
+    let accounts_iter = &mut accounts.iter();
+    let fund_account_ai = next_account_info(accounts_iter)?;
+    let manager_ai = next_account_info(accounts_iter)?;
+    let order_ai = next_account_info(accounts_iter)?;
+
+    let fund_data = FundAccount::load_checked(fund_account_ai, program_id)?;
+    check!(manager_ai.is_signer, ProgramError::MissingRequiredSignature);
+    check!(fund_data.manager_account == *manager_ai.key, FundError::ManagerMismatch);
+    let mut order = ExecutionOrder::load_mut_checked(order_ai, program_id)?;
+    check_eq!(order.fund, *fund_account_ai.key);
+    order.is_active = false;
+    Ok(())
+}
+
+/// Permissionless: arms the swap guard for the next due slice.
+/// JupiterSwap and CheckSwapGuard signed by the keeper must follow in the same transaction,
+/// CheckSwapGuard takes the order account last and advances it.
+/// Both token prices must have been updated in an earlier slot.
+///
+/// Accounts expected
+/// 0. []   Instructions Sysvar
+/// 1. []   Platform State Account
+/// 2. [SIGNER] Keeper Account
+/// 3. [WRITE]  Fund State Account
+/// 4. []   Execution Order Account
+pub fn execute_order_slice(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+) -> Result<(), ProgramError> {
+    let accounts_iter = &mut accounts.iter();
+    let sysvar_ix_ai = next_account_info(accounts_iter)?;
+    check_eq!(*sysvar_ix_ai.key, solana_program::sysvar::instructions::id());
+    let platform_ai = next_account_info(accounts_iter)?;
+    let keeper_ai = next_account_info(accounts_iter)?;
+    let fund_pda_ai = next_account_info(accounts_iter)?;
+    let order_ai = next_account_info(accounts_iter)?;
+
+    let platform_data = PlatformData::load_checked(platform_ai, program_id)?;
+    let mut fund_data = FundAccount::load_mut_checked(fund_pda_ai, program_id)?;
+    let order = ExecutionOrder::load_mut_checked(order_ai, program_id)?;
+    check!(keeper_ai.is_signer, ProgramError::MissingRequiredSignature);
+    check_eq!(order.fund, *fund_pda_ai.key);
+    check!(order.is_active, FundError::InvalidInstruction);
+    // 0 would fall back to the looser fund or pair bound
+    check!(order.max_slippage_bps != 0, ProgramError::InvalidArgument);
+
+    let clock = Clock::get()?;
+    let now_ts = clock.unix_timestamp;
+    check!(now_ts >= order.next_slice_at, FundError::InvalidInstruction);
+    // a live guard is never overridden
+    check!(!fund_data.guard.is_active || now_ts - fund_data.guard.triggered_at >= 100, FundError::InvalidInstruction);
+    check_price_slots(&platform_data, &fund_data, &[order.token_in_slot as usize, order.token_out_slot as usize], clock.slot)?;
+
+    let si = order.token_in_slot as usize;
+    let remaining = order.total_amount_in.checked_sub(order.amount_in_done).unwrap();
+    let remaining_slices = order.slices.checked_sub(order.slices_done).unwrap() as u64;
+    let available = fund_data.tokens[si].balance.checked_sub(fund_data.tokens[si].debt).unwrap();
+    let amount_in = (remaining / remaining_slices).min(available);
+    check!(amount_in > 0, ProgramError::InsufficientFunds);
+    msg!("slice {:?}/{:?}, amount_in {:?}", order.slices_done + 1, order.slices, amount_in);
+
+    arm_swap_guard(&platform_data, &mut fund_data, order.token_in_slot, &[], &[order.token_out_slot],
+        amount_in, order.max_slippage_bps, *keeper_ai.key, now_ts)?;
+    fund_data.guard.source = GUARD_SOURCE_EXECUTION_ORDER;
+    // the slice only counts if its swap is checked, CheckSwapGuard books it
+    check_guard_pairing(program_id, sysvar_ix_ai, &fund_data, fund_pda_ai.key, keeper_ai.key)
+}
+
+/// Books a checked slice with the amount the swap actually took, called by CheckSwapGuard
+pub fn book_order_slice(
+    program_id: &Pubkey,
+    fund_key: &Pubkey,
+    order_ai: &AccountInfo,
+    amount_in: u64,
+    now_ts: i64,
+) -> Result<(), ProgramError> {
+    let mut order = ExecutionOrder::load_mut_checked(order_ai, program_id)?;
+    check_eq!(order.fund, *fund_key);
+    check!(order.is_active, FundError::InvalidInstruction);
+    order.slices_done += 1;
+    order.amount_in_done = order.amount_in_done.checked_add(amount_in).unwrap();
+    order.next_slice_at = now_ts.checked_add(order.interval).unwrap();
+    msg!("slice {:?}/{:?} swapped {:?}", order.slices_done, order.slices, amount_in);
+    if order.slices_done == order.slices || order.amount_in_done >= order.total_amount_in {
+        order.is_active = false;
+    }
+    Ok(())
+}
@@ -0,0 +1,210 @@
+use borsh::{BorshDeserialize, BorshSerialize};
+use fixed::types::U64F64;
+use num_enum::TryFromPrimitive;
+
+use solana_program::{
+    account_info::{AccountInfo, next_account_info},
+    msg,
+    program::set_return_data,
+    program_error::ProgramError,
+    pubkey::Pubkey,
+};
+
+use crate::error::FundError;
+use crate::state::{NUM_TOKENS, MAX_FRIKTION_VOLTS, MAX_STRATEGY_POSITIONS, STRATEGY_FRIKTION, FundAccount, InvestorData, PlatformData};
+use crate::jup_utils::get_slot_price;
+use crate::serum_utils::get_serum_pending;
+
+macro_rules! check_eq {
+    ($x:expr, $y:expr) => {
+        if ($x != $y) {
+            return Err(FundError::Default.into())
+        }
+    }
+}
+
+/// Where a withdrawing investor is, kept in `InvestorData.exit_status`.
+///
+/// InvestorWithdrawSettleFunds books the investor's part of every position, each later step
+/// moves one kind of part into the token vaults and is permissionless, so anyone can resume a
+/// stuck exit. Steps are retried until the status reaches `ReadyToWithdraw`.
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq, TryFromPrimitive)]
+pub enum ExitStatus {
+    /// invested, or waiting in the router
+    None = 0,
+    /// MangoInvestorWithdraw
+    MangoPending = 1,
+    /// FriktionInvestorWithdrawUL + FriktionInvestorWithdrawUL2, FriktionInvestorWithdrawFTokens
+    FriktionPending = 2,
+    /// StrategyInvestorExit, unwinds what positions owe all settled investors
+    StrategiesPending = 3,
+    /// SerumOrder cancel and settle, or UpdateSerumValue, moves what open orders owe settled
+    /// investors into the token vaults
+    SerumPending = 5,
+    /// InvestorWithdrawFromFund pays the token debts and closes the investor account
+    ReadyToWithdraw = 4,
+}
+
+/// Next step of the investor's exit, derived from what is still booked on the investor and fund
+pub fn get_exit_status(fund_data: &FundAccount, investor_data: &InvestorData) -> ExitStatus {
+    if !investor_data.has_withdrawn {
+        return ExitStatus::None;
+    }
+    if get_mango_pending(investor_data) {
+        return ExitStatus::MangoPending;
+    }
+    if get_friktion_pending(investor_data).is_some() {
+        return ExitStatus::FriktionPending;
+    }
+    if get_strategy_pending(fund_data).is_some() {
+        return ExitStatus::StrategiesPending;
+    }
+    if get_serum_pending(fund_data).is_some() {
+        return ExitStatus::SerumPending;
+    }
+    ExitStatus::ReadyToWithdraw
+}
+
+/// Stores the status after a step. StrategyInvestorExit runs for the whole fund without investor
+/// accounts, so a stored `StrategiesPending` can be stale, gates re-derive it with `get_exit_status`.
+pub fn refresh_exit_status(fund_data: &FundAccount, investor_data: &mut InvestorData) -> ExitStatus {
+    let status = get_exit_status(fund_data, investor_data);
+    investor_data.exit_status = status as u8;
+    status
+}
+
+fn get_mango_pending(investor_data: &InvestorData) -> bool {
+    !investor_data.withdrawn_from_margin && investor_data.margin_debt != 0
+}
+
+// first volt the investor is still owed underlying or fcTokens on
+fn get_friktion_pending(investor_data: &InvestorData) -> Option<usize> {
+    (0..MAX_FRIKTION_VOLTS).find(|volt| {
+        (!investor_data.has_withdrawn_friktion_ul(*volt) && investor_data.get_friktion_ul_debt(*volt) > 0)
+            || (!investor_data.has_withdrawn_friktion_fc(*volt) && investor_data.get_friktion_fc_debt(*volt) > 0)
+    })
+}
+
+// first non friktion position still holding what settled investors are owed, see strategy_exits_unwound
+fn get_strategy_pending(fund_data: &FundAccount) -> Option<usize> {
+    (0..MAX_STRATEGY_POSITIONS).find(|position| {
+        let info = fund_data.strategy_position(*position);
+        info.is_active && info.kind != STRATEGY_FRIKTION && info.ul_debt > 0
+    })
+}
+
+/// Splits a USDC deficit the investor owes on a position worth less than nothing over their token
+/// debts: USDC first, the rest pro rata over the other tokens at the slot prices.
+/// Never takes more than the debts, what they can't cover stays with the fund and the exit goes on.
+pub fn get_deficit_split(
+    platform_data: &PlatformData,
+    fund_data: &FundAccount,
+    token_debts: &[u64; NUM_TOKENS],
+    deficit: u64,
+    now_ts: i64,
+) -> Result<[u64; NUM_TOKENS], ProgramError> {
+    let mut taken = [0; NUM_TOKENS];
+    taken[0] = deficit.min(token_debts[0]);
+    let rest = deficit - taken[0];
+    if rest == 0 {
+        return Ok(taken);
+    }
+    let mut values = [U64F64::from_num(0); NUM_TOKENS];
+    let mut total = U64F64::from_num(0);
+    for i in 1..NUM_TOKENS {
+        if token_debts[i] == 0 {
+            continue;
+        }
+        values[i] = U64F64::from_num(token_debts[i]).checked_mul(get_slot_price(platform_data, fund_data, i, now_ts)?).unwrap();
+        total = total.checked_add(values[i]).unwrap();
+    }
+    let ratio = if total == U64F64::from_num(0) {
+        U64F64::from_num(0)
+    } else {
+        U64F64::from_num(rest).checked_div(total).unwrap().min(U64F64::from_num(1))
+    };
+    for i in 1..NUM_TOKENS {
+        taken[i] = U64F64::to_num(U64F64::from_num(token_debts[i]).checked_mul(ratio).unwrap());
+    }
+    if ratio == U64F64::from_num(1) || total == U64F64::from_num(0) {
+        msg!("exit deficit {} over the investor's debts, the rest stays with the fund", deficit);
+    }
+    Ok(taken)
+}
+
+/// Books a position's deficit against the investor's token debts, see `get_deficit_split`
+pub fn take_exit_deficit(
+    platform_data: &PlatformData,
+    fund_data: &mut FundAccount,
+    investor_data: &mut InvestorData,
+    deficit: u64,
+    now_ts: i64,
+) -> Result<(), ProgramError> {
+    if deficit == 0 {
+        return Ok(());
+    }
+    let taken = get_deficit_split(platform_data, fund_data, &investor_data.token_debts, deficit, now_ts)?;
+    for i in 0..NUM_TOKENS {
+        investor_data.token_debts[i] -= taken[i];
+        fund_data.tokens[i].debt = fund_data.tokens[i].debt.checked_sub(taken[i]).ok_or(FundError::InvalidStateAccount)?;
+    }
+    Ok(())
+}
+
+#[derive(Clone, Debug, PartialEq, BorshSerialize, BorshDeserialize)]
+pub struct InvestorExitQuote {
+    /// ExitStatus
+    pub status: u8,
+    /// volt of the FriktionPending step, u8::MAX otherwise
+    pub friktion_volt: u8,
+    /// venue account of the StrategiesPending step, default otherwise
+    pub strategy_venue: Pubkey,
+    /// strategy kind for StrategyInvestorExit
+    pub strategy_kind: u8,
+    /// market of the SerumPending step, default otherwise
+    pub serum_market: Pubkey,
+}
+
+/// Simulation only: the investor's exit status and the account the next step needs
+///
+/// Accounts expected
+/// 0. []   Fund State Account
+/// 1. []   Investor State Account
+pub fn quote_investor_exit(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+) -> Result<(), ProgramError> {
+    let accounts_iter = &mut accounts.iter();
+    let fund_account_ai = next_account_info(accounts_iter)?;
+    let investor_state_ai = next_account_info(accounts_iter)?;
+
+    let fund_data = FundAccount::load_checked(fund_account_ai, program_id)?;
+    let investor_data = InvestorData::load_checked(investor_state_ai, program_id)?;
+    check_eq!(investor_data.manager, fund_data.manager_account);
+
+    let status = get_exit_status(&fund_data, &investor_data);
+    let mut quote = InvestorExitQuote {
+        status: status as u8,
+        friktion_volt: u8::MAX,
+        strategy_venue: Pubkey::default(),
+        strategy_kind: 0,
+        serum_market: Pubkey::default(),
+    };
+    match status {
+        ExitStatus::FriktionPending => {
+            quote.friktion_volt = get_friktion_pending(&investor_data).unwrap() as u8;
+        }
+        ExitStatus::StrategiesPending => {
+            let info = fund_data.strategy_position(get_strategy_pending(&fund_data).unwrap());
+            quote.strategy_venue = info.venue;
+            quote.strategy_kind = info.kind;
+        }
+        ExitStatus::SerumPending => {
+            quote.serum_market = fund_data.serum_markets[get_serum_pending(&fund_data).unwrap()].market;
+        }
+        _ => {}
+    }
+    set_return_data(&quote.try_to_vec()?);
+    Ok(())
+}
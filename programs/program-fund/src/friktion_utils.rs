@@ -30,9 +30,11 @@ use mango::matching::{Side, OrderType, Book};
 use volt_abi::*;
 
 use crate::error::FundError;
+use crate::events::{emit_event, FundEvent};
 use crate::instruction::{FundInstruction, Data};
-use crate::state::{FundAccount, InvestorData, PlatformData};
+use crate::state::{MAX_FRIKTION_VOLTS, STRATEGY_FRIKTION, FundAccount, InvestorData, PlatformData};
 use crate::processor::{parse_token_account};
+use crate::exit_utils::refresh_exit_status;
 
 macro_rules! check {
     ($cond:expr, $err:expr) => {
@@ -344,8 +346,11 @@ pub fn friktion_add_to_fund(
     let fund_account_acc = next_account_info(accounts_iter)?;
     let mut fund_data = FundAccount::load_mut_checked(fund_account_acc, program_id)?;
     check!(fund_data.manager_account == *manager_ai.key, FundError::ManagerMismatch);
-    check!(fund_data.friktion_vault.is_active == false, FundError::InvalidStateAccount);
     let volt_vault_ai = next_account_info(accounts_iter)?;
+    check!(fund_data.get_friktion_volt_index(volt_vault_ai.key).is_none(), FundError::InvalidStateAccount);
+    // one volt per underlying, the volt's investor debts land on that token slot
+    check!((0..MAX_FRIKTION_VOLTS).all(|v| !fund_data.strategy_position(v).is_active || fund_data.strategy_position(v).kind != STRATEGY_FRIKTION || fund_data.strategy_position(v).ul_token_slot != ul_token_slot), FundError::InvalidStateAccount);
+    let volt = (0..MAX_FRIKTION_VOLTS).find(|v| !fund_data.strategy_position(*v).is_active).ok_or(FundError::InvalidStateAccount)?;
     let volt_program_ai = next_account_info(accounts_iter)?;
     check!(*volt_program_ai.key == volt_program_id::id(), FundError::IncorrectProgramId);
     // let f_token_vault = next_account_info(accounts_iter)?;
@@ -354,18 +359,20 @@ pub fn friktion_add_to_fund(
     let volt_vault_data :&[u8] = &(volt_vault_ai.data.borrow())[8..];
     let volt_vault_info = volt_abi::VoltVault::try_from_slice(volt_vault_data)?;
     // check!(f_token_data.mint == volt_vault_info.underlying_asset_mint, FundError::InvalidTokenAccount);
-    let token_info = platform_data.token_list[fund_data.tokens[ul_token_slot as usize].index[fund_data.tokens[ul_token_slot as usize].mux as usize] as usize];
+    let token_info = platform_data.token_list[fund_data.tokens[ul_token_slot as usize].get_active_index()];
     check!(token_info.mint == volt_vault_info.underlying_asset_mint, FundError::FriktionIncorrectULMint);
 
     //also verify that it is whitelisted on FUND
-    fund_data.friktion_vault.volt_vault_id = *volt_vault_ai.key;
-    fund_data.friktion_vault.ul_token_slot = ul_token_slot;
-    fund_data.friktion_vault.is_active = true;
-    fund_data.friktion_vault.fc_token_balance = 0;
-    fund_data.friktion_vault.fc_token_debt = 0;
-    fund_data.friktion_vault.ul_token_debt = 0;
-    fund_data.friktion_vault.ul_token_balance = 0;
-    fund_data.friktion_vault.total_value_in_ul = 0;
+    let friktion_vault = fund_data.strategy_position_mut(volt);
+    friktion_vault.venue = *volt_vault_ai.key;
+    friktion_vault.kind = STRATEGY_FRIKTION;
+    friktion_vault.ul_token_slot = ul_token_slot;
+    friktion_vault.is_active = true;
+    friktion_vault.receipt_balance = 0;
+    friktion_vault.receipt_debt = 0;
+    friktion_vault.ul_debt = 0;
+    friktion_vault.ul_balance = 0;
+    friktion_vault.value_in_ul = 0;
 
     Ok(())
 }
@@ -381,17 +388,23 @@ pub fn friktion_remove_from_fund(
     let fund_account_acc = next_account_info(accounts_iter)?;
     let mut fund_data = FundAccount::load_mut_checked(fund_account_acc, program_id)?;
     check!(fund_data.manager_account == *manager_ai.key, FundError::ManagerMismatch);
-    check!(fund_data.friktion_vault.is_active == true, FundError::InvalidStateAccount);
-    check!(Clock::get()?.unix_timestamp - fund_data.friktion_vault.last_updated <= 100, FundError::PriceStaleInAccount);
-    check!(fund_data.friktion_vault.total_value_in_ul == 0 && fund_data.friktion_vault.fc_token_debt == 0 && fund_data.friktion_vault.ul_token_debt == 0, FundError::InvalidStateAccount);
-    fund_data.friktion_vault.volt_vault_id = Pubkey::default();
-    fund_data.friktion_vault.ul_token_slot = 255;
-    fund_data.friktion_vault.is_active = false;
-    fund_data.friktion_vault.fc_token_balance = 0;
-    fund_data.friktion_vault.fc_token_debt = 0;
-    fund_data.friktion_vault.ul_token_debt = 0;
-    fund_data.friktion_vault.ul_token_balance = 0;
-    fund_data.friktion_vault.total_value_in_ul = 0;
+    // volt vault account, first volt if omitted
+    let volt = match accounts_iter.next() {
+        Some(volt_vault_ai) => fund_data.get_friktion_volt_index(volt_vault_ai.key).ok_or(FundError::FriktionIncorrectVault)?,
+        None => 0,
+    };
+    let friktion_vault = fund_data.strategy_position_mut(volt);
+    check!(friktion_vault.is_active == true && friktion_vault.kind == STRATEGY_FRIKTION, FundError::InvalidStateAccount);
+    check!(Clock::get()?.unix_timestamp - friktion_vault.last_updated <= 100, FundError::PriceStaleInAccount);
+    check!(friktion_vault.value_in_ul == 0 && friktion_vault.receipt_debt == 0 && friktion_vault.ul_debt == 0, FundError::InvalidStateAccount);
+    friktion_vault.venue = Pubkey::default();
+    friktion_vault.ul_token_slot = 255;
+    friktion_vault.is_active = false;
+    friktion_vault.receipt_balance = 0;
+    friktion_vault.receipt_debt = 0;
+    friktion_vault.ul_debt = 0;
+    friktion_vault.ul_balance = 0;
+    friktion_vault.value_in_ul = 0;
     Ok(())
 }
 
@@ -437,9 +450,8 @@ pub fn friktion_deposit(
         check!(manager_ai.is_signer, ProgramError::MissingRequiredSignature);
         let mut fund_data = FundAccount::load_mut_checked(fund_account_ai, program_id)?;
         check!(fund_data.manager_account == *manager_ai.key, FundError::ManagerMismatch);
-        check!(fund_data.friktion_vault.is_active == true, FundError::InvalidStateAccount);
         check!(*volt_program_ai.key == volt_program_id::ID, FundError::IncorrectProgramId);
-        check!(*volt_vault_ai.key == fund_data.friktion_vault.volt_vault_id, FundError::FriktionIncorrectVault);
+        let volt = fund_data.get_friktion_volt_index(volt_vault_ai.key).ok_or(FundError::FriktionIncorrectVault)?;
         let token_data = parse_token_account(vault_token_destination_ai)?;
         check!(token_data.owner == *fund_account_ai.key, FundError::InvalidTokenAccount);
         let pda_signer_nonce = fund_data.signer_nonce;
@@ -507,12 +519,17 @@ pub fn friktion_deposit(
         );
 
         fund_data = FundAccount::load_mut_checked(fund_account_ai, program_id)?;
-        let tsi = fund_data.friktion_vault.ul_token_slot as usize;
+        let tsi = fund_data.strategy_position(volt).ul_token_slot as usize;
         let ul_fund_token_data = parse_token_account(underlying_token_source_ai)?;
         fund_data.tokens[tsi].balance = ul_fund_token_data.amount;
         check!(fund_data.tokens[tsi].balance >= fund_data.tokens[tsi].debt, ProgramError::InsufficientFunds);
-        fund_data.friktion_vault.ul_token_balance = deposit_amount;
-        fund_data.friktion_vault.pending_deposit = true;
+        fund_data.strategy_position_mut(volt).ul_balance = deposit_amount;
+        fund_data.strategy_position_mut(volt).pending_deposit = true;
+        emit_event(&FundEvent::FriktionDeposit {
+            fund: *fund_account_ai.key,
+            volt_vault: *volt_vault_ai.key,
+            amount: deposit_amount,
+        });
         Ok(())
 
     }
@@ -555,11 +572,10 @@ let accounts = array_ref![accounts, 0, NUM_FIXED];
     let mut fund_data = FundAccount::load_mut_checked(fund_account_ai, program_id)?;
     check!(fund_data.manager_account == *manager_ai.key, FundError::ManagerMismatch);
     let pda_signer_nonce = fund_data.signer_nonce;
-    check!(fund_data.friktion_vault.is_active == true, FundError::InvalidStateAccount);
     check!(*volt_program_ai.key == volt_program_id::ID, FundError::IncorrectProgramId);
-    check!(*volt_vault_ai.key == fund_data.friktion_vault.volt_vault_id, FundError::FriktionIncorrectVault);
+    let volt = fund_data.get_friktion_volt_index(volt_vault_ai.key).ok_or(FundError::FriktionIncorrectVault)?;
     let fc_token_data = parse_token_account(vault_token_source_ai)?;
-    let withdraw_amount = fc_token_data.amount.checked_sub(fund_data.friktion_vault.fc_token_debt).unwrap();
+    let withdraw_amount = fc_token_data.amount.checked_sub(fund_data.strategy_position(volt).receipt_debt).unwrap();
     drop(fc_token_data);
     drop(fund_data);
     msg!("Trying CPI");
@@ -615,9 +631,9 @@ let accounts = array_ref![accounts, 0, NUM_FIXED];
 
     fund_data = FundAccount::load_mut_checked(fund_account_ai, program_id)?;
     let fc_token_data = parse_token_account(vault_token_source_ai)?;
-    fund_data.friktion_vault.fc_token_balance = fc_token_data.amount;
-    check!(fund_data.friktion_vault.fc_token_balance >= fund_data.friktion_vault.fc_token_debt, ProgramError::InsufficientFunds);
-    fund_data.friktion_vault.pending_withdrawal = true;
+    fund_data.strategy_position_mut(volt).receipt_balance = fc_token_data.amount;
+    check!(fund_data.strategy_position(volt).receipt_balance >= fund_data.strategy_position(volt).receipt_debt, ProgramError::InsufficientFunds);
+    fund_data.strategy_position_mut(volt).pending_withdrawal = true;
     Ok(())
 
 }
@@ -652,9 +668,8 @@ pub fn friktion_cancel_pending_deposit(
         check!(manager_ai.is_signer, ProgramError::MissingRequiredSignature);
         let mut fund_data = FundAccount::load_mut_checked(fund_account_ai, program_id)?;
         check!(fund_data.manager_account == *manager_ai.key, FundError::ManagerMismatch);
-        check!(fund_data.friktion_vault.is_active == true, FundError::InvalidStateAccount);
         check!(*volt_program_ai.key == volt_program_id::ID, FundError::IncorrectProgramId);
-        check!(*volt_vault_ai.key == fund_data.friktion_vault.volt_vault_id, FundError::FriktionIncorrectVault);
+        let volt = fund_data.get_friktion_volt_index(volt_vault_ai.key).ok_or(FundError::FriktionIncorrectVault)?;
         let token_data = parse_token_account(underlying_token_destination_ai)?;
         check!(token_data.owner == *fund_account_ai.key, FundError::InvalidTokenAccount);
         let pda_signer_nonce = fund_data.signer_nonce;
@@ -695,13 +710,13 @@ pub fn friktion_cancel_pending_deposit(
                 &[&[&*manager_ai.key.as_ref(), bytes_of(&pda_signer_nonce)]]
         );
         fund_data = FundAccount::load_mut_checked(fund_account_ai, program_id)?;
-        let tsi = fund_data.friktion_vault.ul_token_slot as usize;
+        let tsi = fund_data.strategy_position(volt).ul_token_slot as usize;
         let ul_fund_token_data = parse_token_account(underlying_token_destination_ai)?;
         fund_data.tokens[tsi].balance = ul_fund_token_data.amount;
         check!(fund_data.tokens[tsi].balance >= fund_data.tokens[tsi].debt, ProgramError::InsufficientFunds);
         check!(fund_data.tokens[tsi].vault == *underlying_token_destination_ai.key, FundError::InvalidTokenAccount);
-        fund_data.friktion_vault.ul_token_balance = 0;
-        fund_data.friktion_vault.pending_deposit = false;
+        fund_data.strategy_position_mut(volt).ul_balance = 0;
+        fund_data.strategy_position_mut(volt).pending_deposit = false;
         Ok(())
 
         }
@@ -735,9 +750,8 @@ pub fn friktion_cancel_pending_withdrawal(
     let mut fund_data = FundAccount::load_mut_checked(fund_account_ai, program_id)?;
     check!(fund_data.manager_account == *manager_ai.key, FundError::ManagerMismatch);
     let pda_signer_nonce = fund_data.signer_nonce;
-    check!(fund_data.friktion_vault.is_active == true, FundError::InvalidStateAccount);
     check!(*volt_program_ai.key == volt_program_id::ID, FundError::IncorrectProgramId);
-    check!(*volt_vault_ai.key == fund_data.friktion_vault.volt_vault_id, FundError::FriktionIncorrectVault);
+    let volt = fund_data.get_friktion_volt_index(volt_vault_ai.key).ok_or(FundError::FriktionIncorrectVault)?;
     drop(fund_data);
     msg!("Trying CPI");
     // authority_check_ai.is_signer = true;
@@ -777,8 +791,8 @@ pub fn friktion_cancel_pending_withdrawal(
 
     fund_data = FundAccount::load_mut_checked(fund_account_ai, program_id)?;
     let fc_token_data = parse_token_account(vault_token_destination_ai)?;
-    fund_data.friktion_vault.fc_token_balance = fc_token_data.amount;
-    fund_data.friktion_vault.pending_withdrawal = false;
+    fund_data.strategy_position_mut(volt).receipt_balance = fc_token_data.amount;
+    fund_data.strategy_position_mut(volt).pending_withdrawal = false;
 
     Ok(())
 
@@ -812,9 +826,8 @@ pub fn friktion_claim_pending_withdrawal(
     let mut fund_data = FundAccount::load_mut_checked(fund_account_ai, program_id)?;
     check!(fund_data.manager_account == *manager_ai.key, FundError::ManagerMismatch);
     let pda_signer_nonce = fund_data.signer_nonce;
-    check!(fund_data.friktion_vault.is_active == true, FundError::InvalidStateAccount);
     check!(*volt_program_ai.key == volt_program_id::ID, FundError::IncorrectProgramId);
-    check!(*volt_vault_ai.key == fund_data.friktion_vault.volt_vault_id, FundError::FriktionIncorrectVault);
+    let volt = fund_data.get_friktion_volt_index(volt_vault_ai.key).ok_or(FundError::FriktionIncorrectVault)?;
     drop(fund_data);
     msg!("Trying CPI");
     // authority_check_ai.is_signer = true;
@@ -851,11 +864,11 @@ pub fn friktion_claim_pending_withdrawal(
             &[&[&*manager_ai.key.as_ref(), bytes_of(&pda_signer_nonce)]]
         );
     fund_data = FundAccount::load_mut_checked(fund_account_ai, program_id)?;
-    let tsi = fund_data.friktion_vault.ul_token_slot as usize;
+    let tsi = fund_data.strategy_position(volt).ul_token_slot as usize;
     let ul_fund_token_data = parse_token_account(underlying_token_destination_ai)?;
     fund_data.tokens[tsi].balance = ul_fund_token_data.amount;
     check!(fund_data.tokens[tsi].vault == *underlying_token_destination_ai.key, FundError::InvalidTokenAccount);
-    fund_data.friktion_vault.pending_withdrawal = false;
+    fund_data.strategy_position_mut(volt).pending_withdrawal = false;
     Ok(())
 
 }
@@ -886,9 +899,8 @@ pub fn friktion_claim_pending_deposit(
         check!(manager_ai.is_signer, ProgramError::MissingRequiredSignature);
         let mut fund_data = FundAccount::load_mut_checked(fund_account_ai, program_id)?;
         check!(fund_data.manager_account == *manager_ai.key, FundError::ManagerMismatch);
-        check!(fund_data.friktion_vault.is_active == true, FundError::InvalidStateAccount);
         check!(*volt_program_ai.key == volt_program_id::ID, FundError::IncorrectProgramId);
-        check!(*volt_vault_ai.key == fund_data.friktion_vault.volt_vault_id, FundError::FriktionIncorrectVault);
+        let volt = fund_data.get_friktion_volt_index(volt_vault_ai.key).ok_or(FundError::FriktionIncorrectVault)?;
         let token_data = parse_token_account(user_vault_tokens_ai)?;
         check!(token_data.owner == *fund_account_ai.key, FundError::InvalidTokenAccount);
 
@@ -929,9 +941,9 @@ pub fn friktion_claim_pending_deposit(
 
         fund_data = FundAccount::load_mut_checked(fund_account_ai, program_id)?;
         let fc_token_data = parse_token_account(user_vault_tokens_ai)?;
-        fund_data.friktion_vault.fc_token_balance = fc_token_data.amount;
-        fund_data.friktion_vault.ul_token_balance = 0;
-        fund_data.friktion_vault.pending_deposit = false;
+        fund_data.strategy_position_mut(volt).receipt_balance = fc_token_data.amount;
+        fund_data.strategy_position_mut(volt).ul_balance = 0;
+        fund_data.strategy_position_mut(volt).pending_deposit = false;
 
 
 
@@ -982,17 +994,16 @@ pub fn friktion_investor_withdraw_ul(
 
         //#Known Pending Deposit debt gets converted to fcTokens on epoch change
 
-        check!(investor_ai.is_signer, ProgramError::MissingRequiredSignature);
+        // permissionless exit step, investor_ai only names the investor
         let mut investor_data = InvestorData::load_mut_checked(investor_state_ai, program_id)?;
         let mut fund_data = FundAccount::load_mut_checked(fund_account_ai, program_id)?;
         let (pda_signer_nonce, manager_account) = (fund_data.signer_nonce, fund_data.manager_account);
         check!(investor_data.owner == *investor_ai.key, ProgramError::MissingRequiredSignature);
         check_eq!(investor_data.manager, fund_data.manager_account);
-        check!(investor_data.has_withdrawn == true && investor_data.withdrawn_ul_from_friktion == false, FundError::InvalidStateAccount);
-        check!(fund_data.friktion_vault.is_active == true, FundError::InvalidStateAccount);
         check!(*volt_program_ai.key == volt_program_id::ID, FundError::IncorrectProgramId);
-        check!(*volt_vault_ai.key == fund_data.friktion_vault.volt_vault_id, FundError::FriktionIncorrectVault);
-        let tsi = fund_data.friktion_vault.ul_token_slot as usize;
+        let volt = fund_data.get_friktion_volt_index(volt_vault_ai.key).ok_or(FundError::FriktionIncorrectVault)?;
+        check!(investor_data.has_withdrawn == true && !investor_data.has_withdrawn_friktion_ul(volt), FundError::InvalidStateAccount);
+        let tsi = fund_data.strategy_position(volt).ul_token_slot as usize;
         check!(fund_data.tokens[tsi].vault == *underlying_token_source_ai.key, FundError::InvalidTokenAccount);
         // let pending_info: &[u8] = &(pending_deposit_info_ai.data.borrow())[8..];
         // let pending_deposit_data = volt_abi::PendingDeposit::try_from_slice(pending_info)?;
@@ -1007,8 +1018,8 @@ pub fn friktion_investor_withdraw_ul(
         // drop(pending_info);
 
         let ix = solana_program::sysvar::instructions::get_instruction_relative(1, sysvar_ix_ai)?;
-        check!(ix.program_id == *program_id && ix.data[0] == 45, FundError::InvalidInstruction);
-        if investor_data.friktion_ul_debt > 0 {
+        check!(ix.program_id == *program_id && FundInstruction::unpack(&ix.data) == Some(FundInstruction::FriktionInvestorWithdrawUL2), FundError::InvalidInstruction);
+        if investor_data.get_friktion_ul_debt(volt) > 0 {
             drop(fund_data);
             invoke_signed(
                 &friktion_cancel_pending_deposit_ins(
@@ -1046,18 +1057,18 @@ pub fn friktion_investor_withdraw_ul(
             let ul_fund_token_data_updated = parse_token_account(underlying_token_source_ai)?;
             let ul_balance_after = ul_fund_token_data_updated.amount;
             let difference = ul_fund_token_data_updated.amount.checked_sub(ul_balance_before).unwrap();
-            if investor_data.friktion_ul_debt > difference {
+            if investor_data.get_friktion_ul_debt(volt) > difference {
                 msg!("Too Late now...");
-                investor_data.friktion_ul_debt = difference;
+                investor_data.set_friktion_ul_debt(volt, difference);
             }
             fund_data = FundAccount::load_mut_checked(fund_account_ai, program_id)?;
-            fund_data.friktion_vault.deposit_amount = difference.checked_sub(investor_data.friktion_ul_debt).unwrap();
+            fund_data.strategy_position_mut(volt).deposit_amount = difference.checked_sub(investor_data.get_friktion_ul_debt(volt)).unwrap();
             // let tsi = fund_data.friktion_vault.ul_token_slot as usize;
             // let ul_fund_token_data = parse_token_account(underlying_token_source_ai)?;
             // fund_data.tokens[tsi].balance = ul_fund_token_data.amount;
             // check!(fund_data.tokens[tsi].balance >= fund_data.tokens[tsi].debt + investor_data.friktion_ul_debt, ProgramError::InsufficientFunds);
             // check!(fund_data.tokens[tsi].vault == *underlying_token_source_ai.key, FundError::InvalidTokenAccount);
-            // fund_data.friktion_vault.ul_token_debt -= investor_data.friktion_ul_debt;
+            // fund_data.friktion_vault.ul_debt -= investor_data.friktion_ul_debt;
             // fund_data.tokens[tsi].debt += investor_data.friktion_ul_debt;
             // investor_data.token_debts[tsi] += investor_data.friktion_ul_debt;
             // investor_data.friktion_ul_debt = 0;
@@ -1128,10 +1139,10 @@ pub fn friktion_investor_withdraw_ul(
             //     );
             // }
             // fund_data = FundAccount::load_mut_checked(fund_account_ai, program_id)?;
-            // // fund_data.friktion_vault.ul_token_balance = deposit_amount;
+            // // fund_data.friktion_vault.ul_balance = deposit_amount;
             // let ul_fund_token_data = parse_token_account(underlying_token_source_ai)?;
             // fund_data.tokens[tsi].balance = ul_fund_token_data.amount;
-            // fund_data.friktion_vault.ul_token_debt = fund_data.friktion_vault.ul_token_debt.checked_sub(investor_data.friktion_ul_debt).unwrap();
+            // fund_data.friktion_vault.ul_debt = fund_data.friktion_vault.ul_debt.checked_sub(investor_data.friktion_ul_debt).unwrap();
             // fund_data.tokens[tsi].debt = fund_data.tokens[tsi].debt.checked_add(investor_data.friktion_ul_debt).unwrap();
             // // check!(fund_data.tokens[tsi].balance >= fund_data.tokens[tsi].debt, ProgramError::InsufficientFunds);
             // investor_data.token_debts[tsi] = investor_data.token_debts[tsi].checked_add(investor_data.friktion_ul_debt).unwrap();
@@ -1185,17 +1196,16 @@ pub fn friktion_investor_withdraw_ul_2(
     
             //#Known Pending Deposit debt gets converted to fcTokens on epoch change
     
-            check!(investor_ai.is_signer, ProgramError::MissingRequiredSignature);
+            // permissionless exit step, investor_ai only names the investor
             let mut investor_data = InvestorData::load_mut_checked(investor_state_ai, program_id)?;
             let mut fund_data = FundAccount::load_mut_checked(fund_account_ai, program_id)?;
             let (pda_signer_nonce, manager_account) = (fund_data.signer_nonce, fund_data.manager_account);
             check!(investor_data.owner == *investor_ai.key, ProgramError::MissingRequiredSignature);
             check_eq!(investor_data.manager, fund_data.manager_account);
-            check!(investor_data.has_withdrawn == true && investor_data.withdrawn_ul_from_friktion == false, FundError::InvalidStateAccount);
-            check!(fund_data.friktion_vault.is_active == true, FundError::InvalidStateAccount);
             check!(*volt_program_ai.key == volt_program_id::ID, FundError::IncorrectProgramId);
-            check!(*volt_vault_ai.key == fund_data.friktion_vault.volt_vault_id, FundError::FriktionIncorrectVault);
-            let tsi = fund_data.friktion_vault.ul_token_slot as usize;
+            let volt = fund_data.get_friktion_volt_index(volt_vault_ai.key).ok_or(FundError::FriktionIncorrectVault)?;
+            check!(investor_data.has_withdrawn == true && !investor_data.has_withdrawn_friktion_ul(volt), FundError::InvalidStateAccount);
+            let tsi = fund_data.strategy_position(volt).ul_token_slot as usize;
             check!(fund_data.tokens[tsi].vault == *underlying_token_source_ai.key, FundError::InvalidTokenAccount);
             // let pending_info: &[u8] = &(pending_deposit_info_ai.data.borrow())[8..];
             // let pending_deposit_data = volt_abi::PendingDeposit::try_from_slice(pending_info)?;
@@ -1209,10 +1219,10 @@ pub fn friktion_investor_withdraw_ul_2(
             // drop(pending_deposit_data);
 
             let ix = solana_program::sysvar::instructions::get_instruction_relative(-1, sysvar_ix_ai)?;
-            check!(ix.program_id == *program_id && ix.data[0] == 43, FundError::InvalidInstruction);
+            check!(ix.program_id == *program_id && FundInstruction::unpack(&ix.data) == Some(FundInstruction::FriktionInvestorWithdrawUL), FundError::InvalidInstruction);
             // drop(pending_info);
     
-            if investor_data.friktion_ul_debt > 0 {
+            if investor_data.get_friktion_ul_debt(volt) > 0 {
                 // drop(fund_data);
                 // invoke_signed(
                 //     &friktion_cancel_pending_deposit_ins(
@@ -1263,7 +1273,7 @@ pub fn friktion_investor_withdraw_ul_2(
                 // // fund_data.tokens[tsi].balance = ul_fund_token_data.amount;
                 // // check!(fund_data.tokens[tsi].balance >= fund_data.tokens[tsi].debt + investor_data.friktion_ul_debt, ProgramError::InsufficientFunds);
                 // // check!(fund_data.tokens[tsi].vault == *underlying_token_source_ai.key, FundError::InvalidTokenAccount);
-                // // fund_data.friktion_vault.ul_token_debt -= investor_data.friktion_ul_debt;
+                // // fund_data.friktion_vault.ul_debt -= investor_data.friktion_ul_debt;
                 // // fund_data.tokens[tsi].debt += investor_data.friktion_ul_debt;
                 // // investor_data.token_debts[tsi] += investor_data.friktion_ul_debt;
                 // // investor_data.friktion_ul_debt = 0;
@@ -1273,7 +1283,7 @@ pub fn friktion_investor_withdraw_ul_2(
                 // authority_check_ai.is_signer = true;
                 // let authority_check_ai_new = AccountInfo::new(authority_check_ai.key, true, authority_check_ai.is_writable, *authority_check_ai.laudachipppa(), *authority_check_ai.data.clone(), authority_check_ai.owner, authority_check_ai.executable, authority_check_ai.rent_epoch);
                 // sol_log_compute_units();
-                let deposit_amount = fund_data.friktion_vault.deposit_amount;
+                let deposit_amount = fund_data.strategy_position(volt).deposit_amount;
                 drop(fund_data);
                 if deposit_amount > 0 {
                     sol_log_compute_units();
@@ -1335,17 +1345,18 @@ pub fn friktion_investor_withdraw_ul_2(
                     );
                 }
                 fund_data = FundAccount::load_mut_checked(fund_account_ai, program_id)?;
-                // fund_data.friktion_vault.ul_token_balance = deposit_amount;
+                // fund_data.friktion_vault.ul_balance = deposit_amount;
                 let ul_fund_token_data = parse_token_account(underlying_token_source_ai)?;
                 fund_data.tokens[tsi].balance = ul_fund_token_data.amount;
-                fund_data.friktion_vault.ul_token_debt = fund_data.friktion_vault.ul_token_debt.checked_sub(investor_data.friktion_ul_debt).unwrap();
-                fund_data.tokens[tsi].debt = fund_data.tokens[tsi].debt.checked_add(investor_data.friktion_ul_debt).unwrap();
+                fund_data.strategy_position_mut(volt).ul_debt = fund_data.strategy_position(volt).ul_debt.checked_sub(investor_data.get_friktion_ul_debt(volt)).unwrap();
+                fund_data.tokens[tsi].debt = fund_data.tokens[tsi].debt.checked_add(investor_data.get_friktion_ul_debt(volt)).unwrap();
                 // check!(fund_data.tokens[tsi].balance >= fund_data.tokens[tsi].debt, ProgramError::InsufficientFunds);
-                investor_data.token_debts[tsi] = investor_data.token_debts[tsi].checked_add(investor_data.friktion_ul_debt).unwrap();
-                investor_data.friktion_ul_debt = 0;
-                fund_data.friktion_vault.deposit_amount = 0;
+                investor_data.token_debts[tsi] = investor_data.token_debts[tsi].checked_add(investor_data.get_friktion_ul_debt(volt)).unwrap();
+                investor_data.set_friktion_ul_debt(volt, 0);
+                fund_data.strategy_position_mut(volt).deposit_amount = 0;
             }
-            investor_data.withdrawn_ul_from_friktion = true;
+            investor_data.set_withdrawn_friktion_ul(volt, true);
+            refresh_exit_status(&fund_data, &mut investor_data);
     
             Ok(())
     
@@ -1388,17 +1399,17 @@ pub fn friktion_investor_withdraw_ftokens(
 
         //#Known Pending Deposit debt gets converted to fcTokens on epoch change
 
-        check!(investor_ai.is_signer, ProgramError::MissingRequiredSignature);
+        // permissionless exit step, investor_ai only names the investor
         let mut investor_data = InvestorData::load_mut_checked(investor_state_ai, program_id)?;
         let mut fund_data = FundAccount::load_mut_checked(fund_account_ai, program_id)?;
         let (manager_account, pda_signer_nonce) = (fund_data.manager_account, fund_data.signer_nonce);
 
         check!(investor_data.owner == *investor_ai.key, ProgramError::MissingRequiredSignature);
         check_eq!(investor_data.manager, fund_data.manager_account);
-        check!(investor_data.has_withdrawn == true && investor_data.withdrawn_ftokens_from_friktion == false, FundError::InvalidStateAccount);
-        check!(fund_data.friktion_vault.is_active == true, FundError::InvalidStateAccount);
         check!(*volt_program_ai.key == volt_program_id::ID, FundError::IncorrectProgramId);
-        check!(*volt_vault_ai.key == fund_data.friktion_vault.volt_vault_id, FundError::FriktionIncorrectVault);
+        let volt = fund_data.get_friktion_volt_index(volt_vault_ai.key).ok_or(FundError::FriktionIncorrectVault)?;
+        check!(investor_data.has_withdrawn == true && !investor_data.has_withdrawn_friktion_fc(volt), FundError::InvalidStateAccount);
+        check!(parse_token_account(investor_f_token_ai)?.owner == investor_data.owner, FundError::InvalidTokenAccount);
         
         //cheks1!!!!
     
@@ -1418,9 +1429,9 @@ pub fn friktion_investor_withdraw_ftokens(
         // } 
         // let withdraw_amount = total_fc_token_balance.checked_sub(investor_data.friktion_fc_debt).unwrap();
         msg!("LFG");
-        if investor_data.friktion_fc_debt > 0 {
+        if investor_data.get_friktion_fc_debt(volt) > 0 {
             let mut withdraw_amount = 0;
-            if fund_data.friktion_vault.pending_withdrawal {
+            if fund_data.strategy_position(volt).pending_withdrawal {
                 drop(fund_data);
                 msg!("CPI::Fkn_CPW");
                 invoke_signed(
@@ -1458,11 +1469,11 @@ pub fn friktion_investor_withdraw_ftokens(
 
                 let fc_token_data = parse_token_account(vault_token_source_ai)?;
                 let fc_token_balance = fc_token_data.amount;
-                if fc_token_balance < investor_data.friktion_fc_debt {
+                if fc_token_balance < investor_data.get_friktion_fc_debt(volt) {
                     msg!("Too Late now...");
-                    investor_data.friktion_fc_debt = fc_token_balance;
+                    investor_data.set_friktion_fc_debt(volt, fc_token_balance);
                 } 
-                withdraw_amount = fc_token_balance.checked_sub(investor_data.friktion_fc_debt).unwrap();
+                withdraw_amount = fc_token_balance.checked_sub(investor_data.get_friktion_fc_debt(volt)).unwrap();
 
                 sol_log_compute_units();
                 // authority_check_ai.is_signer = true;
@@ -1529,7 +1540,7 @@ pub fn friktion_investor_withdraw_ftokens(
                     investor_f_token_ai.key,
                     fund_account_ai.key,
                     &[fund_account_ai.key],
-                    investor_data.friktion_fc_debt
+                    investor_data.get_friktion_fc_debt(volt)
                 ))?,
                 &[
                     vault_token_source_ai.clone(),
@@ -1541,13 +1552,14 @@ pub fn friktion_investor_withdraw_ftokens(
             )?;
 
             fund_data = FundAccount::load_mut_checked(fund_account_ai, program_id)?;
-            fund_data.friktion_vault.fc_token_debt = fund_data.friktion_vault.fc_token_debt.checked_sub(investor_data.friktion_fc_debt).unwrap();
-            investor_data.friktion_fc_debt = 0;
-            fund_data.friktion_vault.fc_token_balance = withdraw_amount;
-            check!(fund_data.friktion_vault.fc_token_balance >= fund_data.friktion_vault.fc_token_debt, ProgramError::InsufficientFunds);
+            fund_data.strategy_position_mut(volt).receipt_debt = fund_data.strategy_position(volt).receipt_debt.checked_sub(investor_data.get_friktion_fc_debt(volt)).unwrap();
+            investor_data.set_friktion_fc_debt(volt, 0);
+            fund_data.strategy_position_mut(volt).receipt_balance = withdraw_amount;
+            check!(fund_data.strategy_position(volt).receipt_balance >= fund_data.strategy_position(volt).receipt_debt, ProgramError::InsufficientFunds);
 
         }
-        investor_data.withdrawn_ftokens_from_friktion = true;
+        investor_data.set_withdrawn_friktion_fc(volt, true);
+        refresh_exit_status(&fund_data, &mut investor_data);
         Ok(())
 
     }
@@ -1564,7 +1576,7 @@ pub fn update_friktion_value(
     let volt_program_id = next_account_info(accounts_iter)?.key;
     check_eq!(*volt_program_id, volt_abi::id());
     let volt_vault_ai = next_account_info(accounts_iter)?;
-    // check!(*volt_vault_ai.key == fund_data.friktion_vault.volt_vault_id, FundError::InvalidStateAccount);
+    let volt = fund_data.get_friktion_volt_index(volt_vault_ai.key).ok_or(FundError::FriktionIncorrectVault)?;
     let volt_vault_info = &(volt_vault_ai.data.borrow())[8..];
     let volt_vault_data = volt_abi::VoltVault::try_from_slice(volt_vault_info)?;
     let current_round = volt_vault_data.round_number;
@@ -1579,9 +1591,9 @@ pub fn update_friktion_value(
         let pending_info: &[u8] = &(pending_deposit_info_ai.data.borrow())[8..];
         let pending_deposit_data :volt_abi::PendingDeposit = volt_abi::PendingDeposit::try_from_slice(pending_info)?;
         check!(pending_deposit_data.round_number == current_round || pending_deposit_data.num_underlying_deposited == 0, FundError::FriktionUnclaimedPendingDeposit);
-        fund_data.friktion_vault.ul_token_balance = pending_deposit_data.num_underlying_deposited;
-        if(fund_data.friktion_vault.ul_token_balance > 0){
-            val = fund_data.friktion_vault.ul_token_balance.checked_sub(fund_data.friktion_vault.ul_token_debt).unwrap();
+        fund_data.strategy_position_mut(volt).ul_balance = pending_deposit_data.num_underlying_deposited;
+        if(fund_data.strategy_position(volt).ul_balance > 0){
+            val = fund_data.strategy_position(volt).ul_balance.checked_sub(fund_data.strategy_position(volt).ul_debt).unwrap();
             msg!("Underlying Deposited: {}, Round_Num {}", pending_deposit_data.num_underlying_deposited, pending_deposit_data.round_number);
         }
     }
@@ -1600,22 +1612,180 @@ pub fn update_friktion_value(
         fc_tokens = fc_tokens.checked_add(pending_withdrawal_data.num_volt_redeemed).unwrap();
         msg!("Volt Tokens Withdrawan: {}, Round_Num {}", pending_withdrawal_data.num_volt_redeemed, pending_withdrawal_data.round_number);
     }
-    fund_data.friktion_vault.fc_token_balance = fc_tokens;
+    fund_data.strategy_position_mut(volt).receipt_balance = fc_tokens;
     let epoch_info_ai = next_account_info(accounts_iter)?;
     let epoch_info = &(epoch_info_ai.data.borrow())[8..];
     let epoch_info_data = volt_abi::FriktionEpochInfo::try_from_slice(epoch_info)?;
-    let fc_tokens_val = epoch_info_data.vault_token_price*((fc_tokens.checked_sub(fund_data.friktion_vault.fc_token_debt).unwrap()) as f64);
+    let fc_tokens_val = epoch_info_data.vault_token_price*((fc_tokens.checked_sub(fund_data.strategy_position(volt).receipt_debt).unwrap()) as f64);
     val = val.checked_add(fc_tokens_val as u64).unwrap();
     msg!("Friktion val in ul: {:?}", val);
-    check!(fund_data.friktion_vault.volt_vault_id == *volt_vault_ai.key, FundError::FriktionIncorrectVault);
-    fund_data.friktion_vault.last_updated = Clock::get()?.unix_timestamp;
-    fund_data.friktion_vault.total_value_in_ul = val;
+    fund_data.strategy_position_mut(volt).last_updated = Clock::get()?.unix_timestamp;
+    fund_data.strategy_position_mut(volt).value_in_ul = val;
 
     Ok(())
 }
 
 
 
+/// Permissionless: claims the fund's pending deposit and withdrawal on a volt once its round
+/// has moved on, then refreshes the position value like UpdateFriktionValue.
+/// Pending positions of the current round are left alone.
+///
+/// Accounts expected
+/// 0. [WRITE]  Fund State Account, authority of the pending positions
+/// 1. []   Volt Program
+/// 2. [WRITE]  Volt Vault
+/// 3. []   Extra Volt Data
+/// 4. []   Vault Authority
+/// 5. [WRITE]  Vault Mint
+/// 6. [WRITE]  Fund Volt Token Account
+/// 7. [WRITE]  Fund Underlying Token Account
+/// 8. [WRITE]  Pending Deposit Round Info
+/// 9. [WRITE]  Pending Deposit Round Volt Tokens
+/// 10. [WRITE] Pending Deposit Info
+/// 11. [WRITE] Pending Withdrawal Round Info
+/// 12. [WRITE] Pending Withdrawal Info
+/// 13. [WRITE] Round Underlying Tokens for Pending Withdrawals
+/// 14. []  Epoch Info
+/// 15. []  System Program
+/// 16. []  Token Program
+pub fn friktion_reconcile(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+) -> Result<(), ProgramError> {
+    const NUM_FIXED:usize = 17;
+    check!(accounts.len() >= NUM_FIXED, ProgramError::NotEnoughAccountKeys);
+    let accounts = array_ref![accounts, 0, NUM_FIXED];
+    let [
+        fund_account_ai,
+        volt_program_ai,
+        volt_vault_ai,
+        extra_volt_data_ai,
+        vault_authority_ai,
+        vault_mint_ai,
+        fc_tokens_ai,
+        ul_tokens_ai,
+        pending_deposit_round_info_ai,
+        pending_deposit_round_volt_tokens_ai,
+        pending_deposit_info_ai,
+        pending_withdrawal_round_info_ai,
+        pending_withdrawal_info_ai,
+        round_underlying_tokens_for_pending_withdrawals_ai,
+        epoch_info_ai,
+        system_program_ai,
+        token_program_ai,
+    ] = accounts;
+
+    check!(*volt_program_ai.key == volt_program_id::ID, FundError::IncorrectProgramId);
+    let fund_data = FundAccount::load_checked(fund_account_ai, program_id)?;
+    let volt = fund_data.get_friktion_volt_index(volt_vault_ai.key).ok_or(FundError::FriktionIncorrectVault)?;
+    let tsi = fund_data.strategy_position(volt).ul_token_slot as usize;
+    check!(fund_data.tokens[tsi].vault == *ul_tokens_ai.key, FundError::InvalidTokenAccount);
+    check!(parse_token_account(fc_tokens_ai)?.owner == *fund_account_ai.key, FundError::InvalidTokenAccount);
+    let manager_account = fund_data.manager_account;
+    let pda_signer_nonce = fund_data.signer_nonce;
+    drop(fund_data);
+
+    let current_round = volt_abi::VoltVault::try_from_slice(&(volt_vault_ai.data.borrow())[8..])?.round_number;
+    let (pending_deposit_pda, _) = Pubkey::find_program_address(&[volt_vault_ai.key.as_ref(), fund_account_ai.key.as_ref(), b"pendingDeposit"], volt_program_ai.key);
+    check!(*pending_deposit_info_ai.key == pending_deposit_pda, FundError::IncorrectPDA);
+    let (pending_withdrawal_pda, _) = Pubkey::find_program_address(&[volt_vault_ai.key.as_ref(), fund_account_ai.key.as_ref(), b"pendingWithdrawal"], volt_program_ai.key);
+    check!(*pending_withdrawal_info_ai.key == pending_withdrawal_pda, FundError::IncorrectPDA);
+
+    let claim_deposit = pending_deposit_info_ai.data_len() > 0 && {
+        let pending = volt_abi::PendingDeposit::try_from_slice(&(pending_deposit_info_ai.data.borrow())[8..])?;
+        pending.round_number < current_round && pending.num_underlying_deposited > 0
+    };
+    if claim_deposit {
+        msg!("claiming pending deposit");
+        invoke_signed(
+            &friktion_claim_pending_deposit_ins(
+                volt_program_ai.key,
+                fund_account_ai.key,
+                volt_vault_ai.key,
+                extra_volt_data_ai.key,
+                vault_authority_ai.key,
+                fc_tokens_ai.key,
+                pending_deposit_round_info_ai.key,
+                pending_deposit_round_volt_tokens_ai.key,
+                pending_deposit_info_ai.key,
+                system_program_ai.key,
+                token_program_ai.key,
+            )?,
+            &[
+                volt_program_ai.clone(),
+                fund_account_ai.clone(),
+                volt_vault_ai.clone(),
+                extra_volt_data_ai.clone(),
+                vault_authority_ai.clone(),
+                fc_tokens_ai.clone(),
+                pending_deposit_round_info_ai.clone(),
+                pending_deposit_round_volt_tokens_ai.clone(),
+                pending_deposit_info_ai.clone(),
+                system_program_ai.clone(),
+                token_program_ai.clone(),
+            ],
+            &[&[manager_account.as_ref(), bytes_of(&pda_signer_nonce)]]
+        )?;
+        let mut fund_data = FundAccount::load_mut_checked(fund_account_ai, program_id)?;
+        fund_data.strategy_position_mut(volt).receipt_balance = parse_token_account(fc_tokens_ai)?.amount;
+        fund_data.strategy_position_mut(volt).ul_balance = 0;
+        fund_data.strategy_position_mut(volt).pending_deposit = false;
+    }
+
+    let claim_withdrawal = pending_withdrawal_info_ai.data_len() > 0 && {
+        let pending = volt_abi::PendingWithdrawal::try_from_slice(&(pending_withdrawal_info_ai.data.borrow())[8..])?;
+        pending.round_number < current_round && pending.num_volt_redeemed > 0
+    };
+    if claim_withdrawal {
+        msg!("claiming pending withdrawal");
+        invoke_signed(
+            &friktion_claim_pending_withdrawal_ins(
+                volt_program_ai.key,
+                fund_account_ai.key,
+                volt_vault_ai.key,
+                extra_volt_data_ai.key,
+                vault_authority_ai.key,
+                vault_mint_ai.key,
+                ul_tokens_ai.key,
+                pending_withdrawal_round_info_ai.key,
+                pending_withdrawal_info_ai.key,
+                round_underlying_tokens_for_pending_withdrawals_ai.key,
+                system_program_ai.key,
+                token_program_ai.key,
+            )?,
+            &[
+                volt_program_ai.clone(),
+                fund_account_ai.clone(),
+                volt_vault_ai.clone(),
+                extra_volt_data_ai.clone(),
+                vault_authority_ai.clone(),
+                vault_mint_ai.clone(),
+                ul_tokens_ai.clone(),
+                pending_withdrawal_round_info_ai.clone(),
+                pending_withdrawal_info_ai.clone(),
+                round_underlying_tokens_for_pending_withdrawals_ai.clone(),
+                system_program_ai.clone(),
+                token_program_ai.clone(),
+            ],
+            &[&[manager_account.as_ref(), bytes_of(&pda_signer_nonce)]]
+        )?;
+        let mut fund_data = FundAccount::load_mut_checked(fund_account_ai, program_id)?;
+        fund_data.tokens[tsi].balance = parse_token_account(ul_tokens_ai)?.amount;
+        fund_data.strategy_position_mut(volt).pending_withdrawal = false;
+    }
+
+    update_friktion_value(program_id, &[
+        fund_account_ai.clone(),
+        volt_program_ai.clone(),
+        volt_vault_ai.clone(),
+        pending_deposit_info_ai.clone(),
+        fc_tokens_ai.clone(),
+        pending_withdrawal_info_ai.clone(),
+        epoch_info_ai.clone(),
+    ])
+}
+
 // All Accounts-->>
 // fund_account_ai,
 // investor_state_ai,
@@ -1,10 +1,18 @@
-use arrayref::{array_ref, array_refs};
-use borsh::BorshSerialize;
-use mango::matching::{Side, OrderType};
-use num_enum::TryFromPrimitive;
+use std::convert::TryInto;
+use borsh::{BorshDeserialize, BorshSerialize};
+use solana_program::pubkey::Pubkey;
 
-#[repr(C)]
-#[derive(Clone)]
+/// First byte of a versioned instruction. Legacy opcodes never reach this value.
+pub const VERSIONED_INSTRUCTION_TAG: u8 = 0xff;
+/// Current version of the Borsh instruction encoding
+pub const INSTRUCTION_VERSION: u8 = 1;
+
+/// Instruction set of the fund program.
+///
+/// Wire format: `[VERSIONED_INSTRUCTION_TAG, INSTRUCTION_VERSION, borsh(FundInstruction)..]`.
+/// Borsh encodes the variant by its position, so new variants must only ever be appended.
+/// Data whose first byte is not the tag is decoded with the legacy numeric opcodes.
+#[derive(Clone, Debug, PartialEq, BorshSerialize, BorshDeserialize)]
 pub enum FundInstruction {
 
     /// Accounts expected
@@ -35,24 +43,20 @@ pub enum FundInstruction {
 
     /// 0. []       Platform State Account
     /// 1. [WRITE]  Fund State Account
-    /// 2. [READ]   Price Account
-    ///             Open orders account
-    ///             mango oracle account
-    /// 3. [READ]   Clock Sysvar Account
-    /// 4. [SIGNER] Manager Wallet Account
-    /// 5. []       Router Base Token Account
-    /// 6. []       Fund Base Token Account
-    /// 7. []       Manager Base Token Account
-    /// 8. []       Investin Base Token Account
-    /// 9. []       PDA of Router
-    /// 10. []       Token Program
-    /// 11..11+MAX_INVESTORS Investor State Accounts for the fund
+    /// 2. [SIGNER] Manager Wallet Account
+    /// 3. []       Router Base Token Account
+    /// 4. []       Fund Base Token Account
+    /// 5. []       Manager Base Token Account
+    /// 6. []       Investin Base Token Account
+    /// 7. []       PDA of Router
+    /// 8. []       Token Program
+    /// 9..9+MAX_INVESTORS Investor State Accounts for the fund
     ManagerTransfer,
     
     /// 0. [WRITE]  Platform State Account
     /// 1. [WRITE]  Fund State Account (derived from FA)
     /// 2. [WRITE]  Investor State Account (derived from IPDA)
-    /// 3. [SIGNER] Investor Wallet Account
+    /// 3. [SIGNER] Investor Wallet Account, signs router refunds only, settled exits are cranked by anyone
     /// 4. []       Router Base Token Account
     /// 5. []       PDA of Manager
     /// 6. []       PDA of Router
@@ -61,21 +65,16 @@ pub enum FundInstruction {
     /// 8+NUM_TOKENS.. 8+2*NUM_TOKENS  Fund Token Accounts
     InvestorWithdrawFromFund,
 
-            // platform_ai,
-            // fund_account_ai, [write]
-            // investor_state_ai, [write]
-            // investor_ai,
-            // mango_account_ai,
-            // mango_group_ai,
-            // mango_cache_ai,
-            // mango_prog_ai,
-            // referrer_mango_account_ai,
-            // default_ai,
-            // ..4 X [perp_market_ai,     // write default_ai if no perp market for i^th index
-            //        bids_ai,            // write default_ai if no perp market for i^th index
-            //        asks_ai,            // write default_ai if no perp market for i^th index
-            //        event_queue_ai,]   //write default_ai if no perp market for i^th index
-            // 
+    /// Books the investor's share of every fund position, paid out by the later withdraw instructions.
+    /// Fails while the strategy positions still owe the previous settled investor, see StrategyInvestorExit
+    ///
+    /// Accounts expected
+    /// 0. []   Platform State Account
+    /// 1. [WRITE]  Fund State Account
+    /// 2. [WRITE]  Investor State Account
+    /// 3. [SIGNER] Investor Wallet Account
+    /// 4. []   Instructions Sysvar, MangoInvestorReduce must follow when the fund has perps
+    /// 5. [WRITE]  NAV History Account, if the fund has one
     InvestorWithdrawSettleFunds,
 
     /// 0. [WRITE] Fund State Account
@@ -85,24 +84,21 @@ pub enum FundInstruction {
     /// 18. [] Source Token Account
     /// 19. [] Destination Token Account
     /// 20. [] PDA of Manager
+    ///
+    /// swap_index: 0 Raydium AMM, 1 Orca, 3 Orca Whirlpool, 4 Raydium CLMM,
+    /// see clmm_utils for the CLMM account lists
     Swap {
         swap_index: u8,
         data: Data
     },
 
-    /// 0. [WRITE] Fund State Account (derived from FA)
-    /// 1. [READ]   Price Account
-    /// 4. [READ]   Mango Group Account (for valuation)
-    /// 5. [READ]   Margin Account (for valuation)
-    ///             Open Orders account
-    ///             Mango oracle account
-    /// 2. [READ]   Clock Sysvar Account
-    /// 3. [SIGNER] Manager Wallet Account
-    /// 4. []       Fund Base Token Account
-    /// 5. []       Manager Base Token Account
-    /// 6. []       Investin Base Token Account
-    /// 7. []       PDA of Manager
-    /// 8. []       Token Program
+    /// 0. []       Platform State Account
+    /// 1. [WRITE]  Fund State Account
+    /// 2. [SIGNER] Manager Wallet Account
+    /// 3. []       Fund Base Token Account
+    /// 4. []       Manager Base Token Account
+    /// 5. []       Investin Base Token Account
+    /// 6. []       Token Program
     ClaimPerformanceFee,
 
     /// 0. [WRITE] Platform State Account
@@ -452,72 +448,563 @@ pub enum FundInstruction {
         withdraw_amount: u64
     },
 
-    JupiterSwap,
+    /// Relayed as-is to Jupiter
+    JupiterSwap {
+        data: Vec<u8>
+    },
+    /// Checks the swap legs since the guard was set, every active vault of the fund is reconciled:
+    /// route vaults against the guard, the others must not have dropped
     CheckSwapGuard,
-    InitOpenOrderAccounts,
+    /// Relayed as-is to Serum (InitOpenOrders / CloseOpenOrders only)
+    InitOpenOrderAccounts {
+        data: Vec<u8>
+    },
     ReadFriktion,
     FriktionCancelPendingDeposit,
     FriktionCancelPendingWithdrawal,
     FriktionClaimPendingDeposit,
     FriktionClaimPendingWithdrawal,
     UpdateFriktionValue,
+    /// Up to MAX_FRIKTION_VOLTS volts, each on its own underlying token slot
     FriktionAddToFund {
         ul_token_slot: u8
     },
+    /// 0. [SIGNER] Manager Account
+    /// 1. [WRITE]  Fund State Account
+    /// 2. []   Volt Vault, the first volt if omitted
     FriktionRemoveFromFund,
     FriktionInvestorWithdrawUL,
     FriktionInvestorWithdrawFTokens,
+    /// Creates the fund's Mango v3 reimbursement account and starts the snapshot of the investors it is paid out to
+    ///
+    /// 0. [WRITE] Fund State Account
+    /// 1. []   Mango Reimbursement Program
+    /// 2. []   Reimbursement Group
+    /// 3. [WRITE] Reimbursement Account of the fund
+    /// 4. [SIGNER, WRITE] Payer
+    /// 5. []   System Program
+    /// 6. []   Rent Sysvar
+    /// 7. [WRITE] Reimbursement Table, PDA of [fund, "reimbursement"]
+    /// 8. []   Platform State Account, the fund is revalued before the snapshot
+    /// 9.. []  Investor State Accounts, a first page of the active investors
+    /// then [WRITE] NAV History Account, if the fund has one
     InitReimbursement,
 
+    /// Claims the reimbursement into the fund USDC vault, credited to the snapshot investors
+    ///
+    /// 0..12 accounts of the Mango reimbursement claim, fund first and rent sysvar last
+    /// 12. [WRITE] Reimbursement Table, omitted for funds initialized before the table, whose
+    ///     reimbursement stays in the fund NAV
     Reimburse{
-        token_index: usize, 
-        index_into_table: usize, 
+        token_index: u64,
+        index_into_table: u64,
+    },
+
+    /// Upgrades accounts to the current layout version, permissionless
+    ///
+    /// 0. [WRITE] Platform State Account
+    /// 1. [WRITE] Fund State Account
+    /// 2.. [WRITE] Investor State Accounts of the fund
+    MigrateFund,
+
+    /// Simulation only: NAV, performance and accrued fee as `FundNavQuote` return data
+    ///
+    /// 0. []   Platform State Account
+    /// 1. []   Fund State Account
+    QuoteFundNav,
+
+    /// Simulation only: investor payout as `InvestorWithdrawalQuote` return data
+    ///
+    /// 0. []   Platform State Account
+    /// 1. []   Fund State Account
+    /// 2. []   Investor State Account
+    QuoteInvestorWithdrawal,
+
+    /// Creates the fund's NAV history ring buffer. Handlers that value the fund
+    /// append to it and take it as their last account from then on.
+    ///
+    /// 0. [WRITE]  Fund State Account
+    /// 1. [SIGNER, WRITE] Manager Account
+    /// 2. [WRITE]  NAV History Account, PDA of [fund, "nav_history"]
+    /// 3. []   System Program
+    InitNavHistory {
+        /// min seconds between entries
+        interval: i64,
+    },
+
+    /// Admin only: swap guard slippage bound of the token's whitelisted pair, against its pc
+    ///
+    /// 0. [WRITE]  Platform Account
+    /// 1. [SIGNER] Investin Admin Account
+    SetTokenSlippage {
+        token_index: u8,
+        /// 0 resets to the default bound
+        max_slippage_bps: u16,
+    },
+
+    /// Fund wide slippage bound, replaces the pair bound within the pair's platform ceiling
+    ///
+    /// 0. [WRITE]  Fund State Account
+    /// 1. [SIGNER] Manager Account
+    SetFundSlippage {
+        /// 0 unsets it
+        max_slippage_bps: u16,
+    },
+
+    /// SetSwapGuard with a per trade slippage bound, the strictest bound applies
+    ///
+    /// Same accounts as SetSwapGuard
+    SetSwapGuardWithSlippage {
+        token_in_fund_slot: u8,
+        token_out_fund_slot: u8,
+        amount_in: u64,
+        max_slippage_bps: u16,
+    },
+
+    /// Guard for a multi-hop (A -> B -> C) or split (A -> B + C) swap over several
+    /// JupiterSwap legs, checked once end to end by CheckSwapGuard
+    ///
+    /// Same accounts as SetSwapGuard.
+    /// CheckSwapGuard then takes the platform account last for splits and for funds with a mandate.
+    SetRouteSwapGuard {
+        token_in_fund_slot: u8,
+        /// intermediate tokens, up to MAX_SWAP_HOPS
+        hop_fund_slots: Vec<u8>,
+        /// one output, or two for a split
+        token_out_fund_slots: Vec<u8>,
+        amount_in: u64,
+        max_slippage_bps: u16,
+    },
+
+    /// Registers a Serum/OpenBook market and the fund's open orders account on it
+    ///
+    /// 0. []   Platform State Account
+    /// 1. [WRITE]  Fund State Account
+    /// 2. [SIGNER] Manager Account
+    /// 3. []   Market
+    /// 4. []   Open Orders Account
+    AddSerumMarket {
+        base_slot: u8,
+        quote_slot: u8,
     },
+
+    /// 0. [WRITE]  Fund State Account
+    /// 1. [SIGNER] Manager Account
+    /// 2. []   Market
+    /// 3. []   Open Orders Account, fully settled
+    RemoveSerumMarket,
+
+    /// Relayed to Serum/OpenBook: NewOrderV3 (manager), CancelOrderV2,
+    /// CancelOrderByClientIdV2 and SettleFunds (manager, or a settled investor
+    /// while the open orders still owe settled investors)
+    ///
+    /// 0. [SIGNER] Manager or Investor Account
+    /// 1. [WRITE]  Fund State Account
+    /// 2. []   Platform State Account
+    /// 3. []   Investor State Account, Fund State Account if the manager signs
+    /// 4. []   Dex Program
+    /// 5.. []  Dex instruction accounts
+    SerumOrder {
+        data: Vec<u8>
+    },
+
+    /// Permissionless refresh of the open orders balances used for valuation, also moves what
+    /// settled investors are owed on the open orders into the token debts once the vaults hold it
+    ///
+    /// 0. [WRITE]  Fund State Account
+    /// 1.. []  Open Orders Accounts of the active markets
+    UpdateSerumValue,
+
+    /// Per trade and rolling 24h turnover limits in bps of the fund value, 0 is unlimited.
+    /// Tighter limits apply at once, looser ones after a timelock.
+    ///
+    /// 0. [WRITE]  Fund State Account
+    /// 1. [SIGNER] Manager Account
+    SetFundRiskLimits {
+        max_trade_bps: u16,
+        max_daily_turnover_bps: u16,
+    },
+
+    /// Initialize with an allocation mandate, enforced after every swap.
+    /// `is_locked` makes the mandate permanent.
+    ///
+    /// Same accounts as Initialize
+    InitializeWithMandate {
+        min_amount: u64,
+        performance_fee_percentage: u64,
+        no_of_tokens: u8,
+        is_private: bool,
+        /// max weight of one non USDC token, bps of the fund value, 0 is unlimited
+        max_token_weight_bps: u16,
+        /// min USDC weight, bps of the fund value
+        min_usdc_buffer_bps: u16,
+        is_locked: bool,
+        /// up to MAX_BANNED_TOKENS
+        banned_mints: Vec<Pubkey>,
+    },
+
+    /// Replaces an unlocked mandate. Stricter mandates apply at once, looser ones after a timelock.
+    ///
+    /// 0. [WRITE]  Fund State Account
+    /// 1. [SIGNER] Manager Account
+    SetFundMandate {
+        max_token_weight_bps: u16,
+        min_usdc_buffer_bps: u16,
+        banned_mints: Vec<Pubkey>,
+    },
+
+    /// Target weight per token slot for the rebalancing crank, all zero turns it off
+    ///
+    /// 0. [WRITE]  Fund State Account
+    /// 1. [SIGNER] Manager Account
+    SetTargetWeights {
+        /// bps of the fund value, NUM_TOKENS of them summing to 10000
+        target_weights_bps: Vec<u16>,
+        tolerance_bps: u16,
+        max_trade_bps: u16,
+    },
+
+    /// Permissionless: guards a keeper swap from an overweight slot into an underweight one,
+    /// followed by JupiterSwap and CheckSwapGuard signed by the keeper in the same transaction.
+    /// Token prices must have been updated in an earlier slot.
+    ///
+    /// 0. []   Instructions Sysvar
+    /// 1. []   Platform State Account
+    /// 2. [SIGNER] Keeper Account
+    /// 3. [WRITE]  Fund State Account
+    RebalanceSwapGuard {
+        token_in_fund_slot: u8,
+        token_out_fund_slot: u8,
+        amount_in: u64,
+    },
+
+    /// Swaps `total_amount_in` in `slices` equal parts, one per `interval` seconds, executed by keepers
+    ///
+    /// 0. []   Fund State Account
+    /// 1. [SIGNER, WRITE] Manager Account
+    /// 2. [WRITE]  Execution Order Account, PDA of [fund, "execution_order"]
+    /// 3. []   System Program
+    CreateExecutionOrder {
+        token_in_fund_slot: u8,
+        token_out_fund_slot: u8,
+        total_amount_in: u64,
+        slices: u16,
+        interval: i64,
+        /// required, 1..=MAX_SLIPPAGE_BPS
+        max_slippage_bps: u16,
+    },
+
+    /// 0. []   Fund State Account
+    /// 1. [SIGNER] Manager Account
+    /// 2. [WRITE]  Execution Order Account
+    CancelExecutionOrder,
+
+    /// Permissionless: guards the next due slice, followed by JupiterSwap and CheckSwapGuard signed by the keeper.
+    /// CheckSwapGuard takes the order account last and books the slice.
+    /// Both token prices must have been updated in an earlier slot.
+    ///
+    /// 0. []   Instructions Sysvar
+    /// 1. []   Platform State Account
+    /// 2. [SIGNER] Keeper Account
+    /// 3. [WRITE]  Fund State Account
+    /// 4. []   Execution Order Account
+    ExecuteOrderSlice,
+
+    /// Stop-loss or take-profit on a token slot, an amount of 0 clears it
+    ///
+    /// 0. [WRITE]  Fund State Account
+    /// 1. [SIGNER] Manager Account
+    SetSpotTrigger {
+        token_fund_slot: u8,
+        is_stop_loss: bool,
+        /// U64F64 bits, USDC price of one native unit
+        trigger_price: u128,
+        amount: u64,
+        token_out_fund_slot: u8,
+    },
+
+    /// Permissionless: guards the swap of a crossed trigger, followed by JupiterSwap and CheckSwapGuard signed by the keeper.
+    /// Both token prices must have been updated in an earlier slot.
+    ///
+    /// 0. []   Instructions Sysvar
+    /// 1. []   Platform State Account
+    /// 2. [SIGNER] Keeper Account
+    /// 3. [WRITE]  Fund State Account
+    ExecuteSpotTrigger {
+        token_fund_slot: u8,
+        is_stop_loss: bool,
+    },
+
+    // Strategy instructions pick the adapter by `kind` (STRATEGY_*) and take the venue's accounts,
+    // for friktion the same as the matching Friktion* instruction
+
+    /// Takes a free strategy position backed by a token slot
+    StrategyAdd {
+        kind: u8,
+        ul_token_slot: u8,
+    },
+
+    /// Frees an empty strategy position
+    StrategyRemove {
+        kind: u8,
+    },
+
+    /// Moves underlying from the fund into the venue
+    StrategyDeposit {
+        kind: u8,
+        amount: u64,
+    },
+
+    /// Redeems receipts back into the underlying
+    StrategyWithdraw {
+        kind: u8,
+        amount: u64,
+    },
+
+    /// Settles a deposit or withdrawal the venue left pending
+    StrategyClaim {
+        kind: u8,
+    },
+
+    /// Refreshes the position value used in the NAV
+    StrategyUpdateValue {
+        kind: u8,
+    },
+
+    /// Pays a settled investor their part of the position, or for venues that owe it in the
+    /// underlying token slot, unwinds what the settled investor is owed into that slot's vault.
+    /// Liquid staking takes the Investor State Account of that investor last and books the venue fees on it
+    StrategyInvestorExit {
+        kind: u8,
+    },
+
+    /// Opens the fund's lending market obligation, or updates its max LTV
+    ///
+    /// 0. [WRITE]  Fund State Account
+    /// 1. [SIGNER] Manager Account
+    /// 2. []   Lending Program
+    /// 3. [WRITE]  Obligation Account
+    /// 4. []   Lending Market
+    /// 5. []   Clock Sysvar
+    /// 6. []   Rent Sysvar
+    /// 7. []   Token Program
+    InitLeverage {
+        max_ltv_bps: u16,
+    },
+
+    /// Moves collateral of a token-lending position into or out of the obligation
+    ///
+    /// 0. [WRITE]  Fund State Account
+    /// 1. [SIGNER] Manager Account
+    /// 2. []   Platform State Account
+    /// 3. [WRITE]  Fund Collateral Associated Token Account
+    /// 4. []   Lending Program
+    /// 5. []   Reserve Account
+    /// 6. [WRITE]  Reserve Collateral Supply
+    /// 7. [WRITE]  Obligation Account
+    /// 8. []   Lending Market
+    /// 9. []   Lending Market Authority
+    /// 10. []  Clock Sysvar
+    /// 11. []  Token Program
+    MoveLeverageCollateral {
+        amount: u64,
+        is_deposit: bool,
+    },
+
+    /// Borrows into a token slot vault, within the max LTV
+    ///
+    /// 0. [WRITE]  Fund State Account
+    /// 1. [SIGNER] Manager Account
+    /// 2. []   Platform State Account
+    /// 3. [WRITE]  Fund Token Vault
+    /// 4. []   Lending Program
+    /// 5. [WRITE]  Borrow Reserve Account
+    /// 6. [WRITE]  Reserve Liquidity Supply
+    /// 7. [WRITE]  Reserve Liquidity Fee Receiver
+    /// 8. [WRITE]  Obligation Account
+    /// 9. []   Lending Market
+    /// 10. []  Lending Market Authority
+    /// 11. []  Clock Sysvar
+    /// 12. []  Token Program
+    LeverageBorrow {
+        amount: u64,
+    },
+
+    /// Repays borrowed liquidity from a token slot vault
+    ///
+    /// 0. [WRITE]  Fund State Account
+    /// 1. [SIGNER] Manager Account
+    /// 2. []   Platform State Account
+    /// 3. [WRITE]  Fund Token Vault
+    /// 4. []   Lending Program
+    /// 5. [WRITE]  Repay Reserve Account
+    /// 6. [WRITE]  Reserve Liquidity Supply
+    /// 7. [WRITE]  Obligation Account
+    /// 8. []   Lending Market
+    /// 9. []   Clock Sysvar
+    /// 10. []  Token Program
+    LeverageRepay {
+        amount: u64,
+    },
+
+    /// Permissionless: repays down to the max LTV when the fund is above it, accounts as LeverageRepay
+    /// with a keeper signing
+    Deleverage {
+        amount: u64,
+    },
+
+    /// Permissionless: refreshes the obligation values used in the NAV
+    ///
+    /// 0. [WRITE]  Fund State Account
+    /// 1. []   Obligation Account
+    UpdateLeverageValue,
+
+    // Mango v4 instructions, see mango_utils for the account lists
+
+    /// Creates the fund's Mango v4 account
+    MangoInit {
+        account_num: u32,
+    },
+
+    /// Enables a perp market, its base priced by a fund token slot
+    MangoAddPerpMarket {
+        price_slot: u8,
+    },
+
+    /// Frees a perp market slot without a position or resting orders
+    MangoRemovePerpMarket {
+        perp_slot: u8,
+    },
+
+    /// Deposits USDC from the fund vault as collateral
+    MangoDepositCollateral {
+        amount: u64,
+    },
+
+    /// Withdraws USDC collateral into the fund vault
+    MangoWithdrawCollateral {
+        amount: u64,
+    },
+
+    /// side: 0 bid, 1 ask; order_type as Mango's PlaceOrderType
+    MangoPlacePerpOrder {
+        perp_slot: u8,
+        side: u8,
+        price_lots: i64,
+        max_base_lots: i64,
+        client_order_id: u64,
+        order_type: u8,
+        reduce_only: bool,
+    },
+
+    /// Cancels a perp order, or all of the market's for client_order_id 0
+    MangoCancelPerpOrders {
+        perp_slot: u8,
+        client_order_id: u64,
+    },
+
+    /// Permissionless: refreshes the mango account equity used in the NAV
+    UpdateMangoValue,
+
+    /// Permissionless: moves a settled investor's mango part into the fund USDC vault
+    MangoInvestorWithdraw,
+
+    /// Follows InvestorWithdrawSettleFunds when the fund has perps: reduces them by the investor's
+    /// share and books the investor's mango part
+    MangoInvestorReduce,
+
+    /// Permissionless: settles perp pnl of the fund's mango account into USDC
+    MangoSettlePerpPnl {
+        perp_slot: u8,
+    },
+
+    /// Pays out an investor's Mango reimbursement, see reimbursement_utils for the accounts
+    ClaimReimbursement,
+
+    /// Permissionless: claims a volt's pending deposit and withdrawal after a round change and
+    /// refreshes its value, see friktion_utils for the accounts
+    FriktionReconcile,
+
+    /// Simulation only: the investor's exit status and next step as `InvestorExitQuote` return data
+    ///
+    /// 0. []   Fund State Account
+    /// 1. []   Investor State Account
+    QuoteInvestorExit,
+
+    /// Permissionless: points a fund token slot at every pool its mint is whitelisted on now,
+    /// for venues listed after the slot was added
+    ///
+    /// 0. []   Platform State Account
+    /// 1. [WRITE]  Fund State Account
+    RefreshTokenSlotIndexes {
+        index: u8 // index of slot
+    },
+
+    /// Admin only: how far fund bounds may loosen the token's pair bound
+    ///
+    /// 0. [WRITE]  Platform Account
+    /// 1. [SIGNER] Investin Admin Account
+    SetTokenFundSlippageCeiling {
+        token_index: u8,
+        /// 0 lets fund bounds only tighten the pair bound
+        max_fund_slippage_bps: u16,
+    },
+
+    /// Permissionless: adds the next page of investors to the reimbursement snapshot, which is
+    /// sealed once every investor active at InitReimbursement is in
+    ///
+    /// 0. [WRITE]  Fund State Account
+    /// 1. [WRITE]  Reimbursement Table, PDA of [fund, "reimbursement"]
+    /// 2.. []  Investor State Accounts not in the table yet
+    AddReimbursementInvestors,
 }
 
 
-#[derive(Clone, BorshSerialize)]
+#[derive(Clone, Debug, PartialEq, BorshSerialize, BorshDeserialize)]
 pub struct Data {
     pub instr: u8,
     pub amount_in: u64,
     pub min_amount_out: u64
 }
 
+
 impl FundInstruction {
+    /// Decodes instruction data, returns None on any malformed input
     pub fn unpack(input: &[u8]) -> Option<Self> {
-        let (&op, data) = array_refs![input, 1; ..;];
-        let op = u8::from_le_bytes(op);
+        let (&op, data) = input.split_first()?;
+        if op == VERSIONED_INSTRUCTION_TAG {
+            let (&version, data) = data.split_first()?;
+            return match version {
+                INSTRUCTION_VERSION => Self::try_from_slice(data).ok(),
+                _ => None
+            };
+        }
+        Self::unpack_legacy(op, data)
+    }
+
+    /// Encodes in the current versioned format
+    pub fn pack(&self) -> Vec<u8> {
+        let mut buf = vec![VERSIONED_INSTRUCTION_TAG, INSTRUCTION_VERSION];
+        // serializing into a Vec cannot fail
+        self.serialize(&mut buf).unwrap();
+        buf
+    }
+
+    /// Numeric opcodes used by clients before the versioned format
+    fn unpack_legacy(op: u8, data: &[u8]) -> Option<Self> {
         Some(match op {
             0 => {
-                let data = array_ref![data, 0, 8 + 8 + 1 + 1];
-                let (
-                    min_amount,
-                    performance_fee_percentage,
-                    no_of_tokens,
-                    is_private
-                ) = array_refs![data, 8, 8, 1, 1];
-                let is_private = match is_private {
-                    [0] => false,
-                    [1] => true,
-                    _ => return None,
-                };
                 FundInstruction::Initialize {
-                    min_amount: u64::from_le_bytes(*min_amount),
-                    performance_fee_percentage: u64::from_le_bytes(*performance_fee_percentage),
-                    no_of_tokens: u8::from_le_bytes(*no_of_tokens),
-                    is_private
+                    min_amount: read_u64(data, 0)?,
+                    performance_fee_percentage: read_u64(data, 8)?,
+                    no_of_tokens: read_u8(data, 16)?,
+                    is_private: read_bool(data, 17)?
                 }
             },
             1 => {
-                let data = array_ref![data, 0, 8 + 1];
-                let (
-                    amount,
-                    index
-                ) = array_refs![data, 8, 1];
-
                 FundInstruction::InvestorDeposit {
-                    amount: u64::from_le_bytes(*amount),
-                    index: u8::from_le_bytes(*index)
+                    amount: read_u64(data, 0)?,
+                    index: read_u8(data, 8)?
                 }
             },
             2 => {
@@ -530,20 +1017,12 @@ impl FundInstruction {
                 FundInstruction::InvestorWithdrawSettleFunds
             }
             5 => {
-                let data = array_ref![data, 0, 1 + 1 + 8 + 8];
-                let (
-                    swap_index,
-                    instruction,
-                    amount_in,
-                    min_amount_out
-                ) = array_refs![data, 1, 1, 8, 8];
-
                 FundInstruction::Swap {
-                    swap_index: u8::from_le_bytes(*swap_index),
+                    swap_index: read_u8(data, 0)?,
                     data: Data {
-                        instr: u8::from_le_bytes(*instruction),
-                        amount_in: u64::from_le_bytes(*amount_in),
-                        min_amount_out: u64::from_le_bytes(*min_amount_out)
+                        instr: read_u8(data, 1)?,
+                        amount_in: read_u64(data, 2)?,
+                        min_amount_out: read_u64(data, 10)?
                     }
                 }
             },
@@ -551,242 +1030,89 @@ impl FundInstruction {
                 FundInstruction::ClaimPerformanceFee
             },
             7 => {
-                let data = array_ref![data, 0, 6 + 8 + 8];
-                let (
-                    intialize_platform,
-                    freeze_platform,
-                    unfreeze_platform,
-                    change_vault,
-                    freeze_fund,
-                    unfreeze_fund,
-                    change_min_amount,
-                    change_perf_fee
-                ) = array_refs![data, 1, 1, 1, 1, 1, 1, 8, 8];
                 FundInstruction::AdminControl {
-                    intialize_platform: u8::from_le_bytes(*intialize_platform),
-                    freeze_platform: u8::from_le_bytes(*freeze_platform),
-                    unfreeze_platform: u8::from_le_bytes(*unfreeze_platform),
-                    change_vault: u8::from_le_bytes(*change_vault),
-                    freeze_fund: u8::from_le_bytes(*freeze_fund),
-                    unfreeze_fund: u8::from_le_bytes(*unfreeze_fund),
-                    change_min_amount: u64::from_le_bytes(*change_min_amount),
-                    change_perf_fee: u64::from_le_bytes(*change_perf_fee)
+                    intialize_platform: read_u8(data, 0)?,
+                    freeze_platform: read_u8(data, 1)?,
+                    unfreeze_platform: read_u8(data, 2)?,
+                    change_vault: read_u8(data, 3)?,
+                    freeze_fund: read_u8(data, 4)?,
+                    unfreeze_fund: read_u8(data, 5)?,
+                    change_min_amount: read_u64(data, 6)?,
+                    change_perf_fee: read_u64(data, 14)?
                 }
             },
-            // 8 => {
-            //     FundInstruction::MangoInitialize
-            // },
-            // 9 => {
-            //     let data = array_ref![data, 0, 1 + 1 + 8];
-
-            //     let (
-            //         token_slot_index,
-            //         mango_token_index,
-            //         quantity
-            //     ) = array_refs![data, 1, 1, 8];
-
-            //     FundInstruction::MangoDeposit{
-            //         token_slot_index: u8::from_le_bytes(*token_slot_index),
-            //         mango_token_index: u8::from_le_bytes(*mango_token_index),
-            //         quantity: u64::from_le_bytes(*quantity)
-            //     }
-            // },
-            // 10 => {
-            //     let data_arr = array_ref![data, 0, 1 + 1 + 8 + 8 + 1];
-            //     let (perp_market_id, side, price, quantity, reduce_only) =
-            //     array_refs![data_arr, 1, 1, 8, 8, 1];
-            //     let reduce_only = match reduce_only {
-            //         [0] => false,
-            //         [1] => true,
-            //         _ => return None,
-            //     };
-            //     FundInstruction::MangoPlacePerpOrder {
-            //         perp_market_id: u8::from_le_bytes(*perp_market_id),
-            //         side: Side::try_from_primitive(side[0]).ok()?,
-            //         price: i64::from_le_bytes(*price),
-            //         quantity: i64::from_le_bytes(*quantity),
-            //         reduce_only
-            //     }
-            // },
-            // 11 => {
-            //     let perp_market_id = array_ref![data, 0, 1];
-            //     FundInstruction::MangoRemovePerpIndex {
-            //         perp_market_id: u8::from_le_bytes(*perp_market_id)
-            //     }
-            // },
-            // 12 => {
-            //     let data_arr = array_ref![data, 0, 17];
-            //     let (
-            //         side,
-            //         price,
-            //         trade_size
-            //     ) = array_refs![data_arr, 1, 8, 8];
-            //     FundInstruction::MangoPlaceSpotOrder {
-            //         side: u8::from_le_bytes(*side),
-            //         price: u64::from_le_bytes(*price),
-            //         trade_size: u64::from_le_bytes(*trade_size),
-            //     }
-            // },
-            // 13 => {
-            //     let data_arr = array_ref![data, 0, 1 + 1 + 8];
-            //     let (token_slot_index, mango_token_index, quantity) = array_refs![data_arr, 1, 1, 8];
-            //     FundInstruction::MangoWithdraw {
-            //         token_slot_index: u8::from_le_bytes(*token_slot_index),
-            //         mango_token_index: u8::from_le_bytes(*mango_token_index),
-            //         quantity: u64::from_le_bytes(*quantity)
-            //     }
-            // },
-            // 14 => {
-            //     FundInstruction::MangoWithdrawInvestor
-            // },
-            // 15 => {
-            //     let price = array_ref![data, 0, 8];
-            //     FundInstruction::MangoWithdrawInvestorPlaceOrder {
-            //         price: u64::from_le_bytes(*price),
-            //     }
-            // },
+            // 8..=15 were Mango v3 instructions
             16 => {
                 FundInstruction::ChangeFundPrivacy
             },
             17 => {
-                let data = array_ref![data, 0, 2];
-                let (
-                    token_id,
-                    pc_index
-                ) = array_refs![data, 1, 1]; 
                 FundInstruction::AddTokenToWhitelist {
-                    token_id: u8::from_le_bytes(*token_id),
-                    pc_index: u8::from_le_bytes(*pc_index)
-                }
-            },
-            18 => {
-                let count = array_ref![data, 0, 1];
-                FundInstruction::UpdateTokenPrices {
-                    count: u8::from_le_bytes(*count)
+                    token_id: read_u8(data, 0)?,
+                    pc_index: read_u8(data, 1)?
                 }
             },
-            // TODO:: remove redunant instruction
-            19 => {
-                let count = array_ref![data, 0, 1];
+            // 19 is a deprecated alias of 18, kept for old clients
+            18 | 19 => {
                 FundInstruction::UpdateTokenPrices {
-                    count: u8::from_le_bytes(*count)
+                    count: read_u8(data, 0)?
                 }
             },
             20 => {
-                let index = array_ref![data, 0, 1];
                 FundInstruction::AddTokenToFund {
-                    index: u8::from_le_bytes(*index)
+                    index: read_u8(data, 0)?
                 }
             },
             21 => {
-                let index = array_ref![data, 0, 1];
-                FundInstruction::RemoveTokenFromFund{
-                    index: u8::from_le_bytes(*index)
+                FundInstruction::RemoveTokenFromFund {
+                    index: read_u8(data, 0)?
                 }
             },
             22 => {
-                let data = array_ref![data, 0, 2];
-                let (
-                    index,
-                    count
-                ) = array_refs![data, 1, 1]; 
-                FundInstruction::FlushDebts{
-                    index: u8::from_le_bytes(*index),
-                    count: u8::from_le_bytes(*count)
+                FundInstruction::FlushDebts {
+                    index: read_u8(data, 0)?,
+                    count: read_u8(data, 1)?
                 }
             }
-
             23 => {
-                FundInstruction::JupiterSwap
+                FundInstruction::JupiterSwap {
+                    data: data.to_vec()
+                }
             }
-
             24 => {
-                let deposit_amount = array_ref![data, 0, 8];
-                FundInstruction::FriktionDeposit{
-                    deposit_amount: u64::from_le_bytes(*deposit_amount)
+                FundInstruction::FriktionDeposit {
+                    deposit_amount: read_u64(data, 0)?
                 }
             }
-
             25 => {
-                let data = array_ref![data, 0, 1 + 1 + 8];
-                let (
-                    token_in_fund_slot,
-                    token_out_fund_slot,
-                    amount_in
-                ) = array_refs![data, 1, 1, 8]; 
-                FundInstruction::SetSwapGuard{
-                    token_in_fund_slot: u8::from_le_bytes(*token_in_fund_slot),
-                    token_out_fund_slot: u8::from_le_bytes(*token_out_fund_slot),
-                    amount_in: u64::from_le_bytes(*amount_in)
+                FundInstruction::SetSwapGuard {
+                    token_in_fund_slot: read_u8(data, 0)?,
+                    token_out_fund_slot: read_u8(data, 1)?,
+                    amount_in: read_u64(data, 2)?
                 }
             }
-
             26 => {
                 FundInstruction::CheckSwapGuard
             }
-
             27 => {
-                FundInstruction::InitOpenOrderAccounts
+                FundInstruction::InitOpenOrderAccounts {
+                    data: data.to_vec()
+                }
             }
-            
-            // 28 => {
-            //     let data_arr = array_ref![data, 0, 1 + 8 + 8 + 8 + 8 + 8 + 1 + 1 + 1 + 1 ];
-            //     let (
-            //         perp_market_id,
-            //         price,
-            //         max_base_quantity,
-            //         max_quote_quantity,
-            //         client_order_id,
-            //         expiry_timestamp,
-            //         side,
-            //         order_type,
-            //         reduce_only,
-            //         limit,
-            //     ) = array_refs![data_arr, 1, 8, 8, 8, 8, 8, 1, 1, 1, 1];
-
-            //     FundInstruction::MangoPlacePerpOrder2 { 
-            //         perp_market_id: u8::from_le_bytes(*perp_market_id),
-            //         price: i64::from_le_bytes(*price),
-            //         max_base_quantity: i64::from_le_bytes(*max_base_quantity),
-            //         max_quote_quantity: i64::from_le_bytes(*max_quote_quantity),
-            //         client_order_id: u64::from_le_bytes(*client_order_id),
-            //         expiry_timestamp: u64::from_le_bytes(*expiry_timestamp),
-            //         side: Side::try_from_primitive(side[0]).ok()?, 
-            //         order_type: OrderType::try_from_primitive(order_type[0]).ok()?, 
-            //         reduce_only: reduce_only[0] != 0,
-            //         limit: u8::from_le_bytes(*limit),
-            //     }     
-            // }
-
-            // 29 => {
-            //     let order_id = array_ref![data, 0, 8];
-            //     FundInstruction::CancelPerpOrder {
-            //         client_order_id: u64::from_le_bytes(*order_id)
-            //     }
-            // }
-            // 30 => {
-            //     FundInstruction::WithdrawProcessLimitOrders
-            // }
-            
+            // 28..=30 were Mango v3 perp limit orders
             33 => {
                 FundInstruction::ReadFriktion
             }
-
             34 => {
-                let deposit_amount = array_ref![data, 0, 8];
-                FundInstruction::FriktionDeposit0{
-                    deposit_amount: u64::from_le_bytes(*deposit_amount)
+                FundInstruction::FriktionDeposit0 {
+                    deposit_amount: read_u64(data, 0)?
                 }
             }
-
             35 => {
                 FundInstruction::FriktionCancelPendingDeposit
-
             }
             36 => {
-                let withdraw_amount = array_ref![data, 0, 8];
-                FundInstruction::FriktionWithdraw{
-                    withdraw_amount: u64::from_le_bytes(*withdraw_amount)
+                FundInstruction::FriktionWithdraw {
+                    withdraw_amount: read_u64(data, 0)?
                 }
             }
             37 => {
@@ -802,9 +1128,8 @@ impl FundInstruction {
                 FundInstruction::UpdateFriktionValue
             }
             41 => {
-                let ul_token_slot = array_ref![data, 0, 1];
                 FundInstruction::FriktionAddToFund {
-                    ul_token_slot: u8::from_le_bytes(*ul_token_slot)
+                    ul_token_slot: read_u8(data, 0)?
                 }
             }
             42 => {
@@ -820,20 +1145,91 @@ impl FundInstruction {
                 FundInstruction::FriktionInvestorWithdrawUL2
             }
             46 => FundInstruction::InitReimbursement,
-            
             47 => {
-                let data = array_ref![data, 0, 8 + 8 ];
-                let (token_index, index_into_table) = array_refs![data, 8, 8];
-
-                FundInstruction::Reimburse { 
-                    token_index: usize::from_le_bytes(*token_index), 
-                    index_into_table: usize::from_le_bytes(*index_into_table), 
+                FundInstruction::Reimburse {
+                    token_index: read_u64(data, 0)?,
+                    index_into_table: read_u64(data, 8)?
                 }
             }
-            
-
-
             _ => { return None; }
         })
     }
-}
\ No newline at end of file
+}
+
+fn read_u8(data: &[u8], offset: usize) -> Option<u8> {
+    data.get(offset).copied()
+}
+
+fn read_u64(data: &[u8], offset: usize) -> Option<u64> {
+    let bytes = data.get(offset..offset.checked_add(8)?)?;
+    Some(u64::from_le_bytes(bytes.try_into().ok()?))
+}
+
+fn read_bool(data: &[u8], offset: usize) -> Option<bool> {
+    match read_u8(data, offset)? {
+        0 => Some(false),
+        1 => Some(true),
+        _ => None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unpack_rejects_truncated_input() {
+        assert_eq!(FundInstruction::unpack(&[]), None);
+        // Initialize needs 18 bytes of arguments
+        assert_eq!(FundInstruction::unpack(&[0, 1, 2, 3]), None);
+        assert_eq!(FundInstruction::unpack(&[VERSIONED_INSTRUCTION_TAG]), None);
+        let packed = FundInstruction::AddTokenToFund { index: 3 }.pack();
+        assert_eq!(FundInstruction::unpack(&packed[..packed.len() - 1]), None);
+    }
+
+    #[test]
+    fn unpack_rejects_unknown_opcodes() {
+        assert_eq!(FundInstruction::unpack(&[8]), None);
+        assert_eq!(FundInstruction::unpack(&[0xfe]), None);
+        assert_eq!(FundInstruction::unpack(&[VERSIONED_INSTRUCTION_TAG, INSTRUCTION_VERSION, 0xff]), None);
+    }
+
+    #[test]
+    fn unpack_legacy_price_update_aliases() {
+        let expected = Some(FundInstruction::UpdateTokenPrices { count: 4 });
+        assert_eq!(FundInstruction::unpack(&[18, 4]), expected);
+        assert_eq!(FundInstruction::unpack(&[19, 4]), expected);
+    }
+
+    #[test]
+    fn unpack_legacy_initialize() {
+        let mut data = vec![0];
+        data.extend_from_slice(&100u64.to_le_bytes());
+        data.extend_from_slice(&20u64.to_le_bytes());
+        data.extend_from_slice(&[3, 1]);
+        assert_eq!(FundInstruction::unpack(&data), Some(FundInstruction::Initialize {
+            min_amount: 100,
+            performance_fee_percentage: 20,
+            no_of_tokens: 3,
+            is_private: true
+        }));
+        // bools are 0 or 1
+        *data.last_mut().unwrap() = 2;
+        assert_eq!(FundInstruction::unpack(&data), None);
+    }
+
+    #[test]
+    fn unpack_versioned_round_trip() {
+        let instruction = FundInstruction::FlushDebts { index: 1, count: 2 };
+        let packed = instruction.pack();
+        assert_eq!(packed[..2], [VERSIONED_INSTRUCTION_TAG, INSTRUCTION_VERSION]);
+        assert_eq!(FundInstruction::unpack(&packed), Some(instruction));
+    }
+
+    #[test]
+    fn unpack_rejects_other_versions() {
+        let mut packed = FundInstruction::ManagerTransfer.pack();
+        packed[1] = INSTRUCTION_VERSION + 1;
+        assert_eq!(FundInstruction::unpack(&packed), None);
+    }
+}
@@ -16,13 +16,25 @@ use solana_program::{
     program::invoke_signed,
     sysvar::{Sysvar, clock::Clock},
 };
-use crate::state::{FundAccount, PlatformData};
+use crate::state::{NUM_TOKENS, MAX_SWAP_HOPS, GUARD_SOURCE_NONE, GUARD_SOURCE_EXECUTION_ORDER, GUARD_SOURCE_STOP_LOSS, GUARD_SOURCE_TAKE_PROFIT, FundAccount, PlatformData, TokenInfo};
 use crate::error::FundError;
+use crate::instruction::FundInstruction;
+use crate::events::{emit_event, FundEvent};
 use crate::processor::{raydium_id, orca_id, parse_token_account};
+use crate::risk_utils::{check_trade_limits, check_mandate};
+use crate::leverage_utils::check_leverage;
+use crate::execution_utils::book_order_slice;
+use crate::trigger_utils::book_trigger_fill;
 // pub use switchboard_aggregator::AggregatorAccountData;
 
 
 
+// 5%, bound used before slippage became configurable
+pub const DEFAULT_SLIPPAGE_BPS: u16 = 500;
+pub const MAX_SLIPPAGE_BPS: u16 = 10_000;
+// instructions searched after a JupiterSwap for its CheckSwapGuard
+pub const MAX_GUARD_LOOKAHEAD: usize = 12;
+
 macro_rules! check {
     ($cond:expr, $err:expr) => {
         if !($cond) {
@@ -64,18 +76,16 @@ pub fn jup_swap(
     let fund_pda_ai = next_account_info(accounts_iter)?;
     let mut fund_data = FundAccount::load_mut_checked(fund_pda_ai, program_id)?;
     check_eq!(fund_data.guard.is_active, true);
-    check!(fund_data.manager_account == *manager_ai.key, FundError::ManagerMismatch);
+    check!(is_guard_authority(&fund_data, manager_ai.key), FundError::ManagerMismatch);
     let pda_signer_nonce = fund_data.signer_nonce;
+    let manager_key = fund_data.manager_account;
     let whitelisted_prog_ai = next_account_info(accounts_iter)?;
     check!(*whitelisted_prog_ai.key == jupiter_pid::ID, FundError::IncorrectProgramId);
-    let (mut check_for_guard, mut index) = (false, 1);
-    while !check_for_guard {
-        let ix = solana_program::sysvar::instructions::get_instruction_relative(index, sysvar_ix_ai)?;
-        check_for_guard = if ix.program_id == *program_id && ix.data[0] == 26 {
-            true
-        } else {
-            index += 1; 
-            false 
+    check_guard_pairing(program_id, sysvar_ix_ai, &fund_data, fund_pda_ai.key, manager_ai.key)?;
+    // the leg may only write to the route vaults, every other account of the fund stays untouched
+    for a in accounts_iter.clone() {
+        if a.is_writable && *a.key != *fund_pda_ai.key && is_fund_account(&fund_data, fund_pda_ai.key, a) {
+            check!(is_route_vault(&fund_data, a.key), FundError::InvalidInstruction);
         }
     }
     
@@ -100,16 +110,101 @@ pub fn jup_swap(
     invoke_signed(
         &relay_instruction,
         accounts.clone(),
-        &[&[&*manager_ai.key.as_ref(), bytes_of(&pda_signer_nonce)]]
+        &[&[manager_key.as_ref(), bytes_of(&pda_signer_nonce)]]
     )?;
 
     fund_data = FundAccount::load_mut_checked(fund_pda_ai, program_id)?;
-    let token_in_fund_slot = fund_data.guard.token_in_slot as usize;
-    let token_out_fund_slot = fund_data.guard.token_out_slot as usize;
+    // legs fired under the current guard
+    fund_data.guard.count = fund_data.guard.count.checked_add(1).unwrap();
 
     Ok(())
 }
 
+/// The manager, or the keeper a rebalancing guard was armed for
+fn is_guard_authority(fund_data: &FundAccount, key: &Pubkey) -> bool {
+    fund_data.manager_account == *key || (fund_data.guard.keeper != Pubkey::default() && fund_data.guard.keeper == *key)
+}
+
+/// Fund slots the armed guard may move: input, outputs and intermediate tokens
+pub fn get_guard_route(fund_data: &FundAccount) -> Vec<usize> {
+    let guard = &fund_data.guard;
+    let mut route = vec![guard.token_in_slot as usize, guard.token_out_slot as usize];
+    if guard.is_split {
+        route.push(guard.split_out_slot as usize);
+    }
+    route.extend(guard.hop_slots[..guard.hop as usize].iter().map(|slot| *slot as usize));
+    route
+}
+
+fn is_route_vault(fund_data: &FundAccount, key: &Pubkey) -> bool {
+    get_guard_route(fund_data).iter().any(|slot| fund_data.tokens[*slot].vault == *key)
+}
+
+// token vaults, or positions the fund keeps outside of them
+fn is_fund_key(fund_data: &FundAccount, key: &Pubkey) -> bool {
+    fund_data.tokens.iter().any(|t| t.is_active && t.vault == *key)
+        || fund_data.serum_markets.iter().any(|m| m.open_orders != Pubkey::default() && m.open_orders == *key)
+        || (fund_data.mango_positions.mango_account != Pubkey::default() && fund_data.mango_positions.mango_account == *key)
+}
+
+// also catches token accounts of the fund that are not a slot vault
+fn is_fund_account(fund_data: &FundAccount, fund_key: &Pubkey, account: &AccountInfo) -> bool {
+    is_fund_key(fund_data, account.key) || (*account.owner == spl_token::ID
+        && parse_token_account(account).map(|token_account| token_account.owner == *fund_key).unwrap_or(false))
+}
+
+/// Walks the rest of the transaction up to the CheckSwapGuard of this fund and manager.
+/// Only further JupiterSwap legs may sit in between, they may write to no fund vault off the route,
+/// and no other program may touch the fund's accounts.
+/// Inner instructions are out of sight here, CheckSwapGuard reconciles every vault for those.
+pub fn check_guard_pairing(
+    program_id: &Pubkey,
+    sysvar_ix_ai: &AccountInfo,
+    fund_data: &FundAccount,
+    fund_key: &Pubkey,
+    manager_key: &Pubkey,
+) -> Result<(), ProgramError> {
+    let is_fund_account = |key: &Pubkey| *key == *fund_key || is_fund_key(fund_data, key);
+    for index in 1..=MAX_GUARD_LOOKAHEAD as i64 {
+        let ix = solana_program::sysvar::instructions::get_instruction_relative(index, sysvar_ix_ai)
+            .map_err(|_| {
+                msg!("no CheckSwapGuard for this fund");
+                FundError::InvalidInstruction
+            })?;
+        let touches_fund = ix.accounts.iter().any(|meta| is_fund_account(&meta.pubkey));
+        if ix.program_id != *program_id {
+            check!(!touches_fund, FundError::InvalidInstruction);
+            continue;
+        }
+        if !touches_fund {
+            continue;
+        }
+        match FundInstruction::unpack(&ix.data) {
+            Some(FundInstruction::CheckSwapGuard) => {
+                // accounts: manager, fund, ...
+                let manager = ix.accounts.get(0).map(|meta| meta.pubkey);
+                let fund = ix.accounts.get(1).map(|meta| meta.pubkey);
+                check!(manager == Some(*manager_key) && fund == Some(*fund_key), FundError::InvalidInstruction);
+                return Ok(())
+            }
+            Some(FundInstruction::JupiterSwap { .. }) => {
+                let off_route = ix.accounts.iter().any(|meta| meta.is_writable && meta.pubkey != *fund_key
+                    && is_fund_key(fund_data, &meta.pubkey) && !is_route_vault(fund_data, &meta.pubkey));
+                if off_route {
+                    msg!("swap leg {} writes to a fund account off the guard route", index);
+                    return Err(FundError::InvalidInstruction.into())
+                }
+            }
+            _ => {
+                msg!("fund instruction {} between swap and CheckSwapGuard", index);
+                return Err(FundError::InvalidInstruction.into())
+            }
+        }
+    }
+    msg!("CheckSwapGuard beyond {} instructions", MAX_GUARD_LOOKAHEAD);
+    Err(FundError::InvalidInstruction.into())
+}
+
 pub fn init_open_order_accs(
     program_id: &Pubkey,
     accounts: &[AccountInfo],
@@ -124,8 +219,8 @@ pub fn init_open_order_accs(
     let pda_signer_nonce = fund_data.signer_nonce;
     let whitelisted_prog_ai = next_account_info(accounts_iter)?;
     check!(*whitelisted_prog_ai.key == serum_dex::ID, FundError::IncorrectProgramId);
-    let selector = array_ref![data, 1, 4];
-    let discrim = u32::from_le_bytes(*selector);
+    let selector = data.get(1..5).ok_or(ProgramError::InvalidInstructionData)?;
+    let discrim = u32::from_le_bytes(selector.try_into().unwrap());
     msg!("Serum Discrim:: {:?}", discrim);
     check!(discrim == 15 || discrim == 14, FundError::InvalidInstruction); //Allow InitOpenOrders and CloseOpenOrders on Serum
     let mut meta_accounts = vec![];
@@ -155,12 +250,81 @@ pub fn init_open_order_accs(
 }
 
 
+/// Bound and fund ceiling of a token's whitelisted pair, the token against its pool pc
+fn get_token_pair_bounds(info: &TokenInfo) -> (u16, u16) {
+    let bps = if info.max_slippage_bps == 0 { DEFAULT_SLIPPAGE_BPS } else { info.max_slippage_bps };
+    (bps, bps.max(info.max_fund_slippage_bps))
+}
+
+/// Bound and fund ceiling of trading `a` for `b`. Tokens priced through different pools cross
+/// both whitelisted pairs, the route then needs the room of the looser one.
+fn get_pair_bounds(platform_data: &PlatformData, a: &TokenInfo, b: &TokenInfo) -> (u16, u16) {
+    if platform_data.token_list[b.pc_index as usize].mint == a.mint {
+        get_token_pair_bounds(b)
+    } else if platform_data.token_list[a.pc_index as usize].mint == b.mint {
+        get_token_pair_bounds(a)
+    } else {
+        let (a_bps, a_ceiling) = get_token_pair_bounds(a);
+        let (b_bps, b_ceiling) = get_token_pair_bounds(b);
+        let bps = a_bps.max(b_bps);
+        (bps, bps.max(a_ceiling.min(b_ceiling)))
+    }
+}
+
+/// Slippage bound of a route, per output leg: the bound of the whitelisted pair, or the fund
+/// bound within the pair's platform ceiling. The trade bound only tightens it. A split gets
+/// the room of its loosest leg, min out is over both outputs.
+pub fn get_slippage_bps(platform_data: &PlatformData, token_in: &TokenInfo, token_outs: &[TokenInfo], fund_bps: u16, trade_bps: u16) -> u16 {
+    token_outs.iter().map(|token_out| {
+        let (pair_bps, ceiling) = get_pair_bounds(platform_data, token_in, token_out);
+        let mut bps = if fund_bps != 0 { fund_bps.min(ceiling) } else { pair_bps };
+        if trade_bps != 0 {
+            bps = bps.min(trade_bps);
+        }
+        bps
+    }).max().unwrap_or(DEFAULT_SLIPPAGE_BPS)
+}
+
+pub fn set_fund_slippage(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    max_slippage_bps: u16
+) -> Result<(), ProgramError> {
+    let accounts_iter = &mut accounts.iter();
+    let fund_pda_ai = next_account_info(accounts_iter)?;
+    let manager_ai = next_account_info(accounts_iter)?;
+
+    let mut fund_data = FundAccount::load_mut_checked(fund_pda_ai, program_id)?;
+    check!(fund_data.manager_account == *manager_ai.key, FundError::ManagerMismatch);
+    check!(manager_ai.is_signer == true, FundError::IncorrectSignature);
+    check!(max_slippage_bps <= MAX_SLIPPAGE_BPS, ProgramError::InvalidArgument);
+    fund_data.max_slippage_bps = max_slippage_bps;
+    Ok(())
+}
+
 pub fn set_swap_guard(
     program_id: &Pubkey,
     accounts: &[AccountInfo],
     token_in_fund_slot: u8,
     token_out_fund_slot: u8,
-    amount_in: u64
+    amount_in: u64,
+    max_slippage_bps: u16
+) -> Result<(), ProgramError> {
+    set_route_swap_guard(program_id, accounts, token_in_fund_slot, &[], &[token_out_fund_slot], amount_in, max_slippage_bps)
+}
+
+/// Guards every Jupiter leg until the next CheckSwapGuard.
+/// `hop_fund_slots` are the intermediate tokens of a multi-hop route, their balances must not drop.
+/// With two `token_out_fund_slots` the swap is a split and min out is a USDC value over both outputs,
+/// otherwise it is an amount of the output token.
+pub fn set_route_swap_guard(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    token_in_fund_slot: u8,
+    hop_fund_slots: &[u8],
+    token_out_fund_slots: &[u8],
+    amount_in: u64,
+    max_slippage_bps: u16
 ) -> Result<(), ProgramError> {
     let accounts_iter = &mut accounts.iter();
     let platform_ai = next_account_info(accounts_iter)?;
@@ -173,66 +337,85 @@ pub fn set_swap_guard(
     check!(fund_data.is_initialized == true, FundError::FundAccountAlreadyInit);
     check!(fund_data.manager_account == *manager_ai.key, FundError::ManagerMismatch);
     check!(manager_ai.is_signer == true, FundError::IncorrectSignature);
-    let source_token_info = platform_data.token_list[fund_data.tokens[token_in_fund_slot as usize].index[fund_data.tokens[token_in_fund_slot as usize].mux as usize] as usize];
-    let dest_token_info = platform_data.token_list[fund_data.tokens[token_out_fund_slot as usize].index[fund_data.tokens[token_out_fund_slot as usize].mux as usize] as usize];
 
     let now_ts = Clock::get()?.unix_timestamp;
-    
-    
-    let mut input_value = U64F64::from_num(amount_in);
-    if token_in_fund_slot != 0 {
-        if now_ts - source_token_info.last_updated > 100 {
-            msg!("price not up-to-date.. aborting");
-            return Err(FundError::PriceStaleInAccount.into())
-        }
-        input_value = input_value.checked_mul(source_token_info.pool_price).unwrap();
-        if source_token_info.pc_index != 0 {
-            let underlying_token_info = platform_data.token_list[source_token_info.pc_index as usize];
-            if now_ts - underlying_token_info.last_updated > 100 {
-               msg!("price not up-to-date.. aborting");
-               return Err(FundError::PriceStaleInAccount.into())
-           }
-            input_value = input_value.checked_mul(underlying_token_info.pool_price).unwrap();
-        }
+    arm_swap_guard(&platform_data, &mut fund_data, token_in_fund_slot, hop_fund_slots, token_out_fund_slots, amount_in, max_slippage_bps, Pubkey::default(), now_ts)
+}
+
+/// Validates the route and sets the guard, `keeper` may then fire and check the swap besides the manager
+pub fn arm_swap_guard(
+    platform_data: &PlatformData,
+    fund_data: &mut FundAccount,
+    token_in_fund_slot: u8,
+    hop_fund_slots: &[u8],
+    token_out_fund_slots: &[u8],
+    amount_in: u64,
+    max_slippage_bps: u16,
+    keeper: Pubkey,
+    now_ts: i64,
+) -> Result<(), ProgramError> {
+    check!(max_slippage_bps <= MAX_SLIPPAGE_BPS, ProgramError::InvalidArgument);
+    check!(hop_fund_slots.len() <= MAX_SWAP_HOPS, ProgramError::InvalidArgument);
+    check!(token_out_fund_slots.len() == 1 || token_out_fund_slots.len() == 2, ProgramError::InvalidArgument);
+
+    // route slots must be distinct active tokens
+    let route: Vec<u8> = [token_in_fund_slot].iter().chain(hop_fund_slots).chain(token_out_fund_slots).copied().collect();
+    for (i, slot) in route.iter().enumerate() {
+        check!((*slot as usize) < NUM_TOKENS && fund_data.tokens[*slot as usize].is_active, ProgramError::InvalidArgument);
+        check!(!route[..i].contains(slot), ProgramError::InvalidArgument);
     }
 
+    let input_value = U64F64::from_num(amount_in).checked_mul(get_slot_price(platform_data, fund_data, token_in_fund_slot as usize, now_ts)?).unwrap();
     fund_data.guard.input_value = input_value;
-
     msg!("input value: {:?}", input_value);
-
-
-    
-    let mut output_price = if token_out_fund_slot != 0 {
-        if now_ts - dest_token_info.last_updated > 100 {
-            msg!("price not up-to-date.. aborting");
-            return Err(FundError::PriceStaleInAccount.into())
-        }
-        dest_token_info.pool_price
+    // counted in the turnover once the swap is checked
+    check_trade_limits(fund_data, input_value, now_ts, false)?;
+
+    let token_outs: Vec<TokenInfo> = token_out_fund_slots.iter()
+        .map(|slot| get_slot_token_info(platform_data, fund_data, *slot as usize))
+        .collect();
+    let token_in = get_slot_token_info(platform_data, fund_data, token_in_fund_slot as usize);
+    let slippage_bps = get_slippage_bps(platform_data, &token_in, &token_outs, fund_data.max_slippage_bps, max_slippage_bps);
+    msg!("slippage bps: {:?}", slippage_bps);
+    let min_out_ratio = U64F64::from_num(MAX_SLIPPAGE_BPS - slippage_bps).checked_div(U64F64::from_num(MAX_SLIPPAGE_BPS)).unwrap();
+
+    let is_split = token_out_fund_slots.len() == 2;
+    fund_data.guard.min_amount_out = if is_split {
+        // USDC value, the outputs are priced again at check time
+        U64F64::to_num(input_value.checked_mul(min_out_ratio).unwrap())
     } else {
-        U64F64!(1)
+        let output_price = get_slot_price(platform_data, fund_data, token_out_fund_slots[0] as usize, now_ts)?;
+        msg!("output price: {:?}", output_price);
+        U64F64::to_num(input_value.checked_div(output_price).unwrap().checked_mul(min_out_ratio).unwrap())
     };
-    
-    if dest_token_info.pc_index != 0 {
-        let underlying_token_info = platform_data.token_list[dest_token_info.pc_index as usize];
-        if now_ts - underlying_token_info.last_updated > 100 {
-           msg!("price not up-to-date.. aborting");
-           return Err(FundError::PriceStaleInAccount.into())
-       }
-        output_price = output_price.checked_mul(underlying_token_info.pool_price).unwrap();
-    }
-    msg!("output price: {:?}", output_price);
-
-    fund_data.guard.min_amount_out = U64F64::to_num(input_value.checked_div(output_price).unwrap().checked_mul(U64F64!(0.95)).unwrap());
     fund_data.guard.amount_in = amount_in;
     fund_data.guard.is_active = true;
     fund_data.guard.triggered_at = now_ts;
     fund_data.guard.token_in_slot = token_in_fund_slot;
-    fund_data.guard.token_out_slot = token_out_fund_slot;
+    fund_data.guard.token_out_slot = token_out_fund_slots[0];
+    fund_data.guard.is_split = is_split;
+    fund_data.guard.split_out_slot = if is_split { token_out_fund_slots[1] } else { u8::MAX };
+    fund_data.guard.hop = hop_fund_slots.len() as u8;
+    fund_data.guard.hop_slots = [u8::MAX; MAX_SWAP_HOPS];
+    fund_data.guard.hop_slots[..hop_fund_slots.len()].copy_from_slice(hop_fund_slots);
+    fund_data.guard.count = 0;
+    fund_data.guard.keeper = keeper;
+    fund_data.guard.source = GUARD_SOURCE_NONE;
+    fund_data.guard.source_slot = u8::MAX;
     msg!("amount_in {:?}, min_aount_out {:?}", fund_data.guard.amount_in, fund_data.guard.min_amount_out);
     Ok(())
 } 
 
-
+/// Accounts expected
+/// 0. [SIGNER] Manager Account, or the guard's keeper
+/// 1. [WRITE]  Fund State Account
+/// 2. []   Input token vault
+/// 3. []   Output token vault
+/// 4. []   Second output token vault, split swaps only
+/// 5.. []  Intermediate token vaults, in guard order
+/// M.. []  Every other active token vault, in slot order
+/// N. []   Platform State Account, split swaps and funds with a mandate
+/// N+1. [WRITE] Execution Order Account, guards armed by ExecuteOrderSlice
 pub fn check_swap_guard(
     program_id: &Pubkey,
     accounts: &[AccountInfo],
@@ -243,11 +426,13 @@ pub fn check_swap_guard(
     check_eq!(manager_ai.is_signer, true);
     let fund_pda_ai = next_account_info(accounts_iter)?;
     let mut fund_data = FundAccount::load_mut_checked(fund_pda_ai, program_id)?;
-    check_eq!(fund_data.manager_account, *manager_ai.key);
+    check!(is_guard_authority(&fund_data, manager_ai.key), FundError::ManagerMismatch);
+    check_eq!(fund_data.guard.is_active, true);
     let si = fund_data.guard.token_in_slot as usize;
     let di = fund_data.guard.token_out_slot as usize;
 
-    check!(Clock::get()?.unix_timestamp - fund_data.guard.triggered_at < 100, FundError::PriceStaleInAccount);
+    let now_ts = Clock::get()?.unix_timestamp;
+    check!(now_ts - fund_data.guard.triggered_at < 100, FundError::PriceStaleInAccount);
 
     let source_token_ai = next_account_info(accounts_iter)?;
     check_eq!(fund_data.tokens[si].vault, *source_token_ai.key);
@@ -260,18 +445,112 @@ pub fn check_swap_guard(
     
     // let prev_amount =  fund_data.tokens[di].balance;
     
-    let swap_amount_in = fund_data.tokens[si].balance - source_amount;
-    let swap_amount_out = dest_amount - fund_data.tokens[di].balance;
+    let swap_amount_in = fund_data.tokens[si].balance.checked_sub(source_amount).ok_or(ProgramError::InsufficientFunds)?;
+    let swap_amount_out = dest_amount.checked_sub(fund_data.tokens[di].balance).ok_or(FundError::MinAmountFailed)?;
 
     msg!("Checking amount_in {:?}, guard_amount_in {:?}", swap_amount_in, fund_data.guard.amount_in);
     check!(fund_data.guard.amount_in >= swap_amount_in, ProgramError::InsufficientFunds); //amountIn check
-    check!(swap_amount_out >= fund_data.guard.min_amount_out, FundError::MinAmountFailed); // minAmountOut guard check
+    // value actually swapped, at the guard's input price
+    let traded_value = fund_data.guard.input_value.checked_mul(U64F64::from_num(swap_amount_in)).unwrap()
+        .checked_div(U64F64::from_num(fund_data.guard.amount_in.max(1))).unwrap();
+    check_trade_limits(&mut fund_data, traded_value, now_ts, true)?;
+    check_leverage(&fund_data, now_ts)?;
+
+    let mut split_out = None;
+    if fund_data.guard.is_split {
+        let di2 = fund_data.guard.split_out_slot as usize;
+        let dest2_token_ai = next_account_info(accounts_iter)?;
+        check_eq!(fund_data.tokens[di2].vault, *dest2_token_ai.key);
+        let dest2_amount = parse_token_account(dest2_token_ai)?.amount;
+        let swap_amount_out2 = dest2_amount.checked_sub(fund_data.tokens[di2].balance).ok_or(FundError::MinAmountFailed)?;
+        split_out = Some((di2, dest2_amount, swap_amount_out2));
+    } else {
+        check!(swap_amount_out >= fund_data.guard.min_amount_out, FundError::MinAmountFailed); // minAmountOut guard check
+    }
+
+    // intermediate tokens pass through, nothing may be left short
+    for h in 0..fund_data.guard.hop as usize {
+        let hi = fund_data.guard.hop_slots[h] as usize;
+        let hop_token_ai = next_account_info(accounts_iter)?;
+        check_eq!(fund_data.tokens[hi].vault, *hop_token_ai.key);
+        let hop_amount = parse_token_account(hop_token_ai)?.amount;
+        msg!("hop {:?} residual {:?}", hi, hop_amount as i128 - fund_data.tokens[hi].balance as i128);
+        check!(hop_amount >= fund_data.tokens[hi].balance, FundError::MinAmountFailed);
+        fund_data.tokens[hi].balance = hop_amount;
+    }
+
+    // inner instructions of a leg are not visible to check_guard_pairing, so every other vault is reconciled
+    let route = get_guard_route(&fund_data);
+    for slot in 0..NUM_TOKENS {
+        if !fund_data.tokens[slot].is_active || route.contains(&slot) {
+            continue;
+        }
+        let vault_ai = next_account_info(accounts_iter)?;
+        check_eq!(fund_data.tokens[slot].vault, *vault_ai.key);
+        check!(parse_token_account(vault_ai)?.amount >= fund_data.tokens[slot].balance, ProgramError::InsufficientFunds);
+    }
+
+    // prices for the split value and the mandate weights
+    let platform_data = if split_out.is_some() || fund_data.mandate.is_set() {
+        let platform_ai = next_account_info(accounts_iter)?;
+        Some(PlatformData::load_checked(platform_ai, program_id)?)
+    } else {
+        None
+    };
+
+    if let Some((di2, dest2_amount, swap_amount_out2)) = split_out {
+        let platform_data = platform_data.as_ref().unwrap();
+        let value_out = U64F64::from_num(swap_amount_out).checked_mul(get_slot_price(platform_data, &fund_data, di, now_ts)?).unwrap()
+            .checked_add(U64F64::from_num(swap_amount_out2).checked_mul(get_slot_price(platform_data, &fund_data, di2, now_ts)?).unwrap()).unwrap();
+        msg!("value out {:?}, min value out {:?}", value_out, fund_data.guard.min_amount_out);
+        check!(value_out >= U64F64::from_num(fund_data.guard.min_amount_out), FundError::MinAmountFailed);
+        fund_data.tokens[di2].balance = dest2_amount;
+        check!(fund_data.tokens[di2].balance >= fund_data.tokens[di2].debt, ProgramError::InsufficientFunds);
+    }
 
     fund_data.tokens[si].balance = source_amount;
     fund_data.tokens[di].balance = dest_amount;
     check!(fund_data.tokens[di].balance >= fund_data.tokens[di].debt, ProgramError::InsufficientFunds);
     check!(fund_data.tokens[si].balance >= fund_data.tokens[si].debt, ProgramError::InsufficientFunds);
-    msg!("Vol: {:?}", fund_data.guard.input_value);
+    if let Some(platform_data) = &platform_data {
+        let out_slots = match split_out {
+            Some((di2, _, _)) => vec![di, di2],
+            None => vec![di],
+        };
+        check_mandate(platform_data, &mut fund_data, si, &out_slots, now_ts)?;
+    }
+    // keeper orders advance by what was actually swapped
+    match fund_data.guard.source {
+        GUARD_SOURCE_EXECUTION_ORDER => {
+            let order_ai = next_account_info(accounts_iter)?;
+            book_order_slice(program_id, fund_pda_ai.key, order_ai, swap_amount_in, now_ts)?;
+        }
+        GUARD_SOURCE_STOP_LOSS | GUARD_SOURCE_TAKE_PROFIT => {
+            let slot = fund_data.guard.source_slot as usize;
+            let is_stop_loss = fund_data.guard.source == GUARD_SOURCE_STOP_LOSS;
+            book_trigger_fill(&mut fund_data, slot, is_stop_loss, swap_amount_in);
+        }
+        _ => {}
+    }
+    emit_event(&FundEvent::Swap {
+        fund: *fund_pda_ai.key,
+        token_in_slot: si as u8,
+        token_out_slot: di as u8,
+        amount_in: swap_amount_in,
+        amount_out: swap_amount_out,
+        input_value: fund_data.guard.input_value.to_bits(),
+    });
+    if let Some((di2, _, swap_amount_out2)) = split_out {
+        // second leg of the split, input already reported above
+        emit_event(&FundEvent::Swap {
+            fund: *fund_pda_ai.key,
+            token_in_slot: si as u8,
+            token_out_slot: di2 as u8,
+            amount_in: 0,
+            amount_out: swap_amount_out2,
+            input_value: 0,
+        });
+    }
     fund_data.guard.is_active = false;
     fund_data.guard.amount_in = 0;
     fund_data.guard.min_amount_out = 0;
@@ -279,5 +558,56 @@ pub fn check_swap_guard(
     fund_data.guard.token_in_slot = u8::MAX;
     fund_data.guard.token_out_slot = u8::MAX;
     fund_data.guard.input_value = U64F64!(0);
+    fund_data.guard.is_split = false;
+    fund_data.guard.split_out_slot = u8::MAX;
+    fund_data.guard.hop = 0;
+    fund_data.guard.hop_slots = [u8::MAX; MAX_SWAP_HOPS];
+    fund_data.guard.count = 0;
+    fund_data.guard.keeper = Pubkey::default();
+    fund_data.guard.source = GUARD_SOURCE_NONE;
+    fund_data.guard.source_slot = u8::MAX;
     Ok(())
-}
\ No newline at end of file
+}
+
+fn get_slot_token_info(platform_data: &PlatformData, fund_data: &FundAccount, slot: usize) -> TokenInfo {
+    platform_data.token_list[fund_data.tokens[slot].get_active_index()]
+}
+
+/// Keeper trades only price off updates from an earlier slot: a pool skewed and read by
+/// UpdateTokenPrices in the keeper's own transaction can set neither the trade nor its min out
+pub fn check_price_slots(platform_data: &PlatformData, fund_data: &FundAccount, slots: &[usize], now_slot: u64) -> Result<(), ProgramError> {
+    for &slot in slots {
+        if slot == 0 || !fund_data.tokens[slot].is_active {
+            continue;
+        }
+        let token_info = get_slot_token_info(platform_data, fund_data, slot);
+        check!(token_info.last_updated_slot != now_slot as u16, FundError::PriceUpdatedThisSlot);
+        if token_info.pc_index != 0 {
+            let underlying_token_info = platform_data.token_list[token_info.pc_index as usize];
+            check!(underlying_token_info.last_updated_slot != now_slot as u16, FundError::PriceUpdatedThisSlot);
+        }
+    }
+    Ok(())
+}
+
+/// USDC price of one native unit of the token in `slot`, errors if a price is stale
+pub fn get_slot_price(platform_data: &PlatformData, fund_data: &FundAccount, slot: usize, now_ts: i64) -> Result<U64F64, ProgramError> {
+    if slot == 0 {
+        return Ok(U64F64!(1))
+    }
+    let token_info = get_slot_token_info(platform_data, fund_data, slot);
+    if now_ts - token_info.last_updated > 100 {
+        msg!("price not up-to-date.. aborting");
+        return Err(FundError::PriceStaleInAccount.into())
+    }
+    let mut price = token_info.pool_price;
+    if token_info.pc_index != 0 {
+        let underlying_token_info = platform_data.token_list[token_info.pc_index as usize];
+        if now_ts - underlying_token_info.last_updated > 100 {
+            msg!("price not up-to-date.. aborting");
+            return Err(FundError::PriceStaleInAccount.into())
+        }
+        price = price.checked_mul(underlying_token_info.pool_price).unwrap();
+    }
+    Ok(price)
+}
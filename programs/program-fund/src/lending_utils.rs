@@ -0,0 +1,322 @@
+use bytemuck::bytes_of;
+use arrayref::array_ref;
+use fixed::types::U64F64;
+
+use solana_program::{
+    account_info::{AccountInfo, next_account_info},
+    instruction::{AccountMeta, Instruction},
+    msg,
+    program::invoke_signed,
+    program_error::ProgramError,
+    pubkey::Pubkey,
+    sysvar::{Sysvar, clock::Clock},
+};
+use spl_associated_token_account::get_associated_token_address;
+
+use crate::error::FundError;
+use crate::data_utils::{read_pubkey, read_u64, read_u128};
+use crate::state::{MAX_STRATEGY_POSITIONS, STRATEGY_TOKEN_LENDING, FundAccount, PlatformData};
+use crate::processor::parse_token_account;
+use crate::strategy_utils::StrategyAdapter;
+
+macro_rules! check {
+    ($cond:expr, $err:expr) => {
+        if !($cond) {
+            return Err(($err).into())
+        }
+    }
+}
+macro_rules! check_eq {
+    ($x:expr, $y:expr) => {
+        if ($x != $y) {
+            return Err(FundError::Default.into())
+        }
+    }
+}
+
+pub mod solend_program_id {
+    use solana_program::declare_id;
+    declare_id!("So1endDq2YkqhipRh3WViPa8hdiSpxWy6z3Z6tMCpAo");
+}
+
+pub mod token_lending_program_id {
+    use solana_program::declare_id;
+    declare_id!("LendZqTs7gn5CTSJU1jWKhKuVpjJGom45nnwPb2AMTi");
+}
+
+pub const LENDING_DEPOSIT_RESERVE_LIQUIDITY: u8 = 4;
+pub const LENDING_REDEEM_RESERVE_COLLATERAL: u8 = 5;
+pub const LENDING_INIT_OBLIGATION: u8 = 6;
+pub const LENDING_DEPOSIT_OBLIGATION_COLLATERAL: u8 = 8;
+pub const LENDING_WITHDRAW_OBLIGATION_COLLATERAL: u8 = 9;
+pub const LENDING_BORROW_OBLIGATION_LIQUIDITY: u8 = 10;
+pub const LENDING_REPAY_OBLIGATION_LIQUIDITY: u8 = 11;
+
+// token-lending Reserve layout, shared by its forks up to the collateral supply.
+// Not Port's: its reserve packs the liquidity oracle as a COption and shifts everything after it
+// last_update (slot, stale) leads reserves and obligations alike
+pub const LAST_UPDATE_SLOT_OFFSET: usize = 1;
+pub const LAST_UPDATE_STALE_OFFSET: usize = 9;
+pub const RESERVE_LIQUIDITY_MINT_OFFSET: usize = 42;
+const RESERVE_AVAILABLE_AMOUNT_OFFSET: usize = 171;
+const RESERVE_BORROWED_WADS_OFFSET: usize = 179;
+const RESERVE_COLLATERAL_MINT_OFFSET: usize = 227;
+const RESERVE_COLLATERAL_SUPPLY_OFFSET: usize = 259;
+pub const WAD: u128 = 1_000_000_000_000_000_000;
+
+pub fn is_lending_program(key: &Pubkey) -> bool {
+    *key == solend_program_id::ID || *key == token_lending_program_id::ID
+}
+
+/// Liquidity per native collateral token, from a reserve refreshed in this slot
+pub fn get_collateral_rate(reserve_ai: &AccountInfo) -> Result<U64F64, ProgramError> {
+    check!(is_lending_program(reserve_ai.owner), FundError::IncorrectProgramId);
+    let data = reserve_ai.try_borrow_data()?;
+    let is_stale = *data.get(LAST_UPDATE_STALE_OFFSET).ok_or(ProgramError::InvalidAccountData)? != 0;
+    check!(!is_stale && read_u64(&data, LAST_UPDATE_SLOT_OFFSET)? == Clock::get()?.slot, FundError::PriceStaleInAccount);
+    let supply = read_u64(&data, RESERVE_COLLATERAL_SUPPLY_OFFSET)?;
+    if supply == 0 {
+        return Ok(U64F64::from_num(1));
+    }
+    let borrowed = read_u128(&data, RESERVE_BORROWED_WADS_OFFSET)?.checked_div(WAD).unwrap();
+    let total_liquidity = (read_u64(&data, RESERVE_AVAILABLE_AMOUNT_OFFSET)? as u128).checked_add(borrowed).unwrap();
+    Ok(U64F64::from_num(total_liquidity).checked_div(U64F64::from_num(supply)).unwrap())
+}
+
+// the fund's ATA for the reserve's collateral, never a token slot vault so it is not valued twice
+pub fn check_collateral_account(fund_data: &FundAccount, fund_key: &Pubkey, reserve_ai: &AccountInfo, collateral_ai: &AccountInfo) -> Result<(), ProgramError> {
+    let collateral_mint = read_pubkey(&reserve_ai.try_borrow_data()?, RESERVE_COLLATERAL_MINT_OFFSET)?;
+    check_eq!(*collateral_ai.key, get_associated_token_address(fund_key, &collateral_mint));
+    check!(fund_data.tokens.iter().all(|t| !t.is_active || t.vault != *collateral_ai.key), FundError::InvalidTokenAccount);
+    Ok(())
+}
+
+pub fn refresh_lending_position(fund_data: &mut FundAccount, position: usize, reserve_ai: &AccountInfo, collateral_ai: &AccountInfo) -> Result<(), ProgramError> {
+    let receipt_balance = parse_token_account(collateral_ai)?.amount;
+    let rate = get_collateral_rate(reserve_ai)?;
+    let info = fund_data.strategy_position_mut(position);
+    info.receipt_balance = receipt_balance;
+    info.value_in_ul = U64F64::to_num(U64F64::from_num(receipt_balance).checked_mul(rate).unwrap());
+    info.last_updated = Clock::get()?.unix_timestamp;
+    Ok(())
+}
+
+/// Supply side of an SPL token-lending reserve (Solend), the token slot's liquidity
+/// against the reserve's collateral tokens in the fund's associated token account.
+///
+/// Collateral is valued at the reserve exchange rate, investors exit in the liquidity token.
+pub struct LendingAdapter;
+
+impl LendingAdapter {
+    /// Deposits `amount` liquidity or redeems `amount` collateral, then refreshes the position.
+    ///
+    /// Accounts expected
+    /// 0. [WRITE]  Fund State Account
+    /// 1. [SIGNER] Manager Account, any signer to unwind investor exits
+    /// 2. [WRITE]  Fund Token Vault of the liquidity slot
+    /// 3. [WRITE]  Fund Collateral Associated Token Account
+    /// 4. []   Lending Program
+    /// 5. [WRITE]  Reserve Account, refreshed earlier in the transaction
+    /// 6. [WRITE]  Reserve Liquidity Supply
+    /// 7. [WRITE]  Reserve Collateral Mint
+    /// 8. []   Lending Market
+    /// 9. []   Lending Market Authority
+    /// 10. []  Clock Sysvar
+    /// 11. []  Token Program
+    fn move_liquidity(&self, program_id: &Pubkey, accounts: &[AccountInfo], amount: u64, is_deposit: bool, is_manager: bool) -> Result<usize, ProgramError> {
+        const NUM_FIXED: usize = 12;
+        check!(accounts.len() >= NUM_FIXED, ProgramError::NotEnoughAccountKeys);
+        let [
+            fund_account_ai,
+            signer_ai,
+            liquidity_vault_ai,
+            collateral_ai,
+            lending_prog_ai,
+            reserve_ai,
+            reserve_supply_ai,
+            collateral_mint_ai,
+            lending_market_ai,
+            market_authority_ai,
+            clock_ai,
+            token_prog_ai,
+        ] = array_ref![accounts, 0, NUM_FIXED];
+
+        check!(signer_ai.is_signer, ProgramError::MissingRequiredSignature);
+        check!(is_lending_program(lending_prog_ai.key) && *reserve_ai.owner == *lending_prog_ai.key, FundError::IncorrectProgramId);
+        check_eq!(*token_prog_ai.key, spl_token::ID);
+        let fund_data = FundAccount::load_checked(fund_account_ai, program_id)?;
+        if is_manager {
+            check!(fund_data.manager_account == *signer_ai.key, FundError::ManagerMismatch);
+        }
+        let position = fund_data.get_strategy_index(STRATEGY_TOKEN_LENDING, reserve_ai.key).ok_or(FundError::InvalidStrategy)?;
+        let tsi = fund_data.strategy_position(position).ul_token_slot as usize;
+        check_eq!(fund_data.tokens[tsi].vault, *liquidity_vault_ai.key);
+        if is_deposit {
+            check!(fund_data.tokens[tsi].balance.checked_sub(fund_data.tokens[tsi].debt).unwrap() >= amount, ProgramError::InsufficientFunds);
+        }
+        check_collateral_account(&fund_data, fund_account_ai.key, reserve_ai, collateral_ai)?;
+        let manager_account = fund_data.manager_account;
+        let nonce = fund_data.signer_nonce;
+        drop(fund_data);
+
+        let (source_ai, destination_ai) = if is_deposit { (liquidity_vault_ai, collateral_ai) } else { (collateral_ai, liquidity_vault_ai) };
+        let (account_3, account_4) = if is_deposit { (reserve_supply_ai, collateral_mint_ai) } else { (collateral_mint_ai, reserve_supply_ai) };
+        let mut data = vec![if is_deposit { LENDING_DEPOSIT_RESERVE_LIQUIDITY } else { LENDING_REDEEM_RESERVE_COLLATERAL }];
+        data.extend_from_slice(&amount.to_le_bytes());
+        invoke_signed(
+            &Instruction {
+                program_id: *lending_prog_ai.key,
+                accounts: vec![
+                    AccountMeta::new(*source_ai.key, false),
+                    AccountMeta::new(*destination_ai.key, false),
+                    AccountMeta::new(*reserve_ai.key, false),
+                    AccountMeta::new(*account_3.key, false),
+                    AccountMeta::new(*account_4.key, false),
+                    AccountMeta::new_readonly(*lending_market_ai.key, false),
+                    AccountMeta::new_readonly(*market_authority_ai.key, false),
+                    AccountMeta::new_readonly(*fund_account_ai.key, true),
+                    AccountMeta::new_readonly(*clock_ai.key, false),
+                    AccountMeta::new_readonly(*token_prog_ai.key, false),
+                ],
+                data,
+            },
+            &[
+                source_ai.clone(),
+                destination_ai.clone(),
+                reserve_ai.clone(),
+                account_3.clone(),
+                account_4.clone(),
+                lending_market_ai.clone(),
+                market_authority_ai.clone(),
+                fund_account_ai.clone(),
+                clock_ai.clone(),
+                token_prog_ai.clone(),
+                lending_prog_ai.clone(),
+            ],
+            &[&[manager_account.as_ref(), bytes_of(&nonce)]]
+        )?;
+
+        let mut fund_data = FundAccount::load_mut_checked(fund_account_ai, program_id)?;
+        fund_data.tokens[tsi].balance = parse_token_account(liquidity_vault_ai)?.amount;
+        refresh_lending_position(&mut fund_data, position, reserve_ai, collateral_ai)?;
+        Ok(position)
+    }
+}
+
+impl StrategyAdapter for LendingAdapter {
+    /// Accounts expected
+    /// 0. []   Platform State Account
+    /// 1. [SIGNER] Manager Account
+    /// 2. [WRITE]  Fund State Account
+    /// 3. []   Reserve Account
+    fn add(&self, program_id: &Pubkey, accounts: &[AccountInfo], ul_token_slot: u8) -> Result<(), ProgramError> {
+        let accounts_iter = &mut accounts.iter();
+        let platform_ai = next_account_info(accounts_iter)?;
+        let platform_data = PlatformData::load_checked(platform_ai, program_id)?;
+        let manager_ai = next_account_info(accounts_iter)?;
+        check!(manager_ai.is_signer, ProgramError::MissingRequiredSignature);
+        let fund_account_ai = next_account_info(accounts_iter)?;
+        let mut fund_data = FundAccount::load_mut_checked(fund_account_ai, program_id)?;
+        check!(fund_data.manager_account == *manager_ai.key, FundError::ManagerMismatch);
+        let reserve_ai = next_account_info(accounts_iter)?;
+        check!(is_lending_program(reserve_ai.owner), FundError::IncorrectProgramId);
+
+        let tsi = ul_token_slot as usize;
+        check!(tsi < fund_data.tokens.len() && fund_data.tokens[tsi].is_active, ProgramError::InvalidArgument);
+        let token_info = platform_data.token_list[fund_data.tokens[tsi].get_active_index()];
+        check!(token_info.mint == read_pubkey(&reserve_ai.try_borrow_data()?, RESERVE_LIQUIDITY_MINT_OFFSET)?, FundError::InvalidTokenAccount);
+        check!(fund_data.get_strategy_index(STRATEGY_TOKEN_LENDING, reserve_ai.key).is_none(), FundError::InvalidStateAccount);
+        // from the back, friktion volts are limited to the first positions
+        let position = (0..MAX_STRATEGY_POSITIONS).rev().find(|i| !fund_data.strategy_position(*i).is_active).ok_or(FundError::InvalidStateAccount)?;
+
+        let info = fund_data.strategy_position_mut(position);
+        *info = bytemuck::Zeroable::zeroed();
+        info.venue = *reserve_ai.key;
+        info.kind = STRATEGY_TOKEN_LENDING;
+        info.ul_token_slot = ul_token_slot;
+        info.is_active = true;
+        Ok(())
+    }
+
+    /// Accounts expected
+    /// 0. [SIGNER] Manager Account
+    /// 1. [WRITE]  Fund State Account
+    /// 2. []   Reserve Account
+    fn remove(&self, program_id: &Pubkey, accounts: &[AccountInfo]) -> Result<(), ProgramError> {
+        let accounts_iter = &mut accounts.iter();
+        let manager_ai = next_account_info(accounts_iter)?;
+        check!(manager_ai.is_signer, ProgramError::MissingRequiredSignature);
+        let fund_account_ai = next_account_info(accounts_iter)?;
+        let mut fund_data = FundAccount::load_mut_checked(fund_account_ai, program_id)?;
+        check!(fund_data.manager_account == *manager_ai.key, FundError::ManagerMismatch);
+        let reserve_ai = next_account_info(accounts_iter)?;
+        let position = fund_data.get_strategy_index(STRATEGY_TOKEN_LENDING, reserve_ai.key).ok_or(FundError::InvalidStrategy)?;
+        let info = fund_data.strategy_position_mut(position);
+        check!(info.receipt_balance == 0 && info.ul_debt == 0, FundError::InvalidStateAccount);
+        *info = bytemuck::Zeroable::zeroed();
+        info.ul_token_slot = 255;
+        Ok(())
+    }
+
+    /// Supplies `amount` of the liquidity token, accounts as in `move_liquidity`
+    fn deposit(&self, program_id: &Pubkey, accounts: &[AccountInfo], amount: u64) -> Result<(), ProgramError> {
+        check!(amount > 0, ProgramError::InvalidArgument);
+        let position = self.move_liquidity(program_id, accounts, amount, true, true)?;
+        let fund_data = FundAccount::load_checked(&accounts[0], program_id)?;
+        let tsi = fund_data.strategy_position(position).ul_token_slot as usize;
+        check!(fund_data.tokens[tsi].balance >= fund_data.tokens[tsi].debt, ProgramError::InsufficientFunds);
+        Ok(())
+    }
+
+    /// Redeems `amount` of collateral tokens, accounts as in `move_liquidity`
+    fn withdraw(&self, program_id: &Pubkey, accounts: &[AccountInfo], amount: u64) -> Result<(), ProgramError> {
+        check!(amount > 0, ProgramError::InvalidArgument);
+        let position = self.move_liquidity(program_id, accounts, amount, false, true)?;
+        // what investors are still owed stays supplied until unwound
+        let fund_data = FundAccount::load_checked(&accounts[0], program_id)?;
+        let info = fund_data.strategy_position(position);
+        check!(info.value_in_ul >= info.ul_debt, ProgramError::InsufficientFunds);
+        Ok(())
+    }
+
+    /// Permissionless refresh of the collateral balance and exchange rate
+    ///
+    /// Accounts expected
+    /// 0. [WRITE]  Fund State Account
+    /// 1. []   Reserve Account, refreshed earlier in the transaction
+    /// 2. []   Fund Collateral Associated Token Account
+    fn update_value(&self, program_id: &Pubkey, accounts: &[AccountInfo]) -> Result<(), ProgramError> {
+        let accounts_iter = &mut accounts.iter();
+        let fund_account_ai = next_account_info(accounts_iter)?;
+        let reserve_ai = next_account_info(accounts_iter)?;
+        let collateral_ai = next_account_info(accounts_iter)?;
+        let mut fund_data = FundAccount::load_mut_checked(fund_account_ai, program_id)?;
+        let position = fund_data.get_strategy_index(STRATEGY_TOKEN_LENDING, reserve_ai.key).ok_or(FundError::InvalidStrategy)?;
+        check_collateral_account(&fund_data, fund_account_ai.key, reserve_ai, collateral_ai)?;
+        refresh_lending_position(&mut fund_data, position, reserve_ai, collateral_ai)?;
+        msg!("collateral value in ul: {:?}", fund_data.strategy_position(position).value_in_ul);
+        Ok(())
+    }
+
+    /// Permissionless: redeems the collateral settled investors are owed into the token slot's
+    /// vault, their token debts on that slot are then paid as usual. Accounts as in `move_liquidity`.
+    fn investor_exit(&self, program_id: &Pubkey, accounts: &[AccountInfo]) -> Result<(), ProgramError> {
+        check!(accounts.len() > 5, ProgramError::NotEnoughAccountKeys);
+        let fund_data = FundAccount::load_checked(&accounts[0], program_id)?;
+        let position = fund_data.get_strategy_index(STRATEGY_TOKEN_LENDING, accounts[5].key).ok_or(FundError::InvalidStrategy)?;
+        let info = *fund_data.strategy_position(position);
+        drop(fund_data);
+        check!(info.ul_debt > 0, FundError::InvalidStateAccount);
+        let rate = get_collateral_rate(&accounts[5])?;
+        // round up so the redeemed liquidity covers the debt
+        let collateral_amount: u64 = U64F64::to_num(U64F64::from_num(info.ul_debt).checked_div(rate).unwrap().ceil());
+        self.move_liquidity(program_id, accounts, collateral_amount.min(info.receipt_balance), false, false)?;
+
+        let mut fund_data = FundAccount::load_mut_checked(&accounts[0], program_id)?;
+        let tsi = info.ul_token_slot as usize;
+        fund_data.tokens[tsi].debt = fund_data.tokens[tsi].debt.checked_add(info.ul_debt).unwrap();
+        check!(fund_data.tokens[tsi].balance >= fund_data.tokens[tsi].debt, ProgramError::InsufficientFunds);
+        fund_data.strategy_position_mut(position).ul_debt = 0;
+        Ok(())
+    }
+}
@@ -0,0 +1,542 @@
+use bytemuck::bytes_of;
+use arrayref::array_ref;
+use fixed::types::U64F64;
+
+use solana_program::{
+    account_info::{AccountInfo, next_account_info},
+    instruction::{AccountMeta, Instruction},
+    msg,
+    program::invoke_signed,
+    program_error::ProgramError,
+    pubkey::Pubkey,
+    sysvar::{Sysvar, clock::Clock},
+};
+
+use crate::error::FundError;
+use crate::state::{NUM_TOKENS, STRATEGY_TOKEN_LENDING, FundAccount, InvestorData, PlatformData};
+use crate::processor::parse_token_account;
+use crate::jup_utils::get_slot_price;
+use crate::exit_utils::take_exit_deficit;
+use crate::lending_utils::*;
+use crate::data_utils::{read_pubkey, read_u64, read_u128};
+
+macro_rules! check {
+    ($cond:expr, $err:expr) => {
+        if !($cond) {
+            return Err(($err).into())
+        }
+    }
+}
+macro_rules! check_eq {
+    ($x:expr, $y:expr) => {
+        if ($x != $y) {
+            return Err(FundError::Default.into())
+        }
+    }
+}
+
+// about 2.5x, managers pick anything below
+pub const MAX_LTV_BPS: u16 = 6000;
+const BPS: u64 = 10_000;
+
+// token-lending Obligation layout, after last_update
+const OBLIGATION_LENDING_MARKET_OFFSET: usize = 10;
+const OBLIGATION_OWNER_OFFSET: usize = 42;
+const OBLIGATION_DEPOSITED_VALUE_OFFSET: usize = 74;
+const OBLIGATION_BORROWED_VALUE_OFFSET: usize = 90;
+// obligation values are WAD scaled USD, fund values USDC native
+const WADS_PER_USDC_NATIVE: u128 = 1_000_000_000_000;
+
+fn is_over_ltv(borrowed_value: U64F64, deposited_value: U64F64, max_ltv_bps: u16) -> bool {
+    borrowed_value.checked_mul(U64F64::from_num(BPS)).unwrap() > deposited_value.checked_mul(U64F64::from_num(max_ltv_bps)).unwrap()
+}
+
+/// Copies the obligation's values, it has to be refreshed earlier in the transaction
+fn refresh_leverage(fund_data: &mut FundAccount, fund_key: &Pubkey, obligation_ai: &AccountInfo) -> Result<(), ProgramError> {
+    check_eq!(fund_data.leverage.is_active, true);
+    check_eq!(fund_data.leverage.obligation, *obligation_ai.key);
+    check!(is_lending_program(obligation_ai.owner), FundError::IncorrectProgramId);
+    let data = obligation_ai.try_borrow_data()?;
+    check_eq!(read_pubkey(&data, OBLIGATION_OWNER_OFFSET)?, *fund_key);
+    let is_stale = *data.get(LAST_UPDATE_STALE_OFFSET).ok_or(ProgramError::InvalidAccountData)? != 0;
+    check!(!is_stale && read_u64(&data, LAST_UPDATE_SLOT_OFFSET)? == Clock::get()?.slot, FundError::PriceStaleInAccount);
+    fund_data.leverage.deposited_value = U64F64::from_num(read_u128(&data, OBLIGATION_DEPOSITED_VALUE_OFFSET)? / WADS_PER_USDC_NATIVE);
+    fund_data.leverage.borrowed_value = U64F64::from_num(read_u128(&data, OBLIGATION_BORROWED_VALUE_OFFSET)? / WADS_PER_USDC_NATIVE);
+    fund_data.leverage.last_updated = Clock::get()?.unix_timestamp;
+    Ok(())
+}
+
+// token slot holding the vault, the reserve has to lend that token
+fn get_reserve_slot(platform_data: &PlatformData, fund_data: &FundAccount, vault_ai: &AccountInfo, reserve_ai: &AccountInfo) -> Result<usize, ProgramError> {
+    let slot = (0..NUM_TOKENS).find(|i| fund_data.tokens[*i].is_active && fund_data.tokens[*i].vault == *vault_ai.key)
+        .ok_or(FundError::InvalidTokenAccount)?;
+    let mint = platform_data.token_list[fund_data.tokens[slot].get_active_index()].mint;
+    check!(mint == read_pubkey(&reserve_ai.try_borrow_data()?, RESERVE_LIQUIDITY_MINT_OFFSET)?, FundError::InvalidTokenAccount);
+    Ok(slot)
+}
+
+/// Refused while borrowing above the fund's max LTV, the obligation values must be fresh
+pub fn check_leverage(fund_data: &FundAccount, now_ts: i64) -> Result<(), ProgramError> {
+    let leverage = &fund_data.leverage;
+    if !leverage.is_active || leverage.borrowed_value == U64F64::from_num(0) {
+        return Ok(());
+    }
+    check!(now_ts - leverage.last_updated <= 100, FundError::PriceStaleInAccount);
+    check!(!is_over_ltv(leverage.borrowed_value, leverage.deposited_value, leverage.max_ltv_bps), FundError::LeverageLimitExceeded);
+    Ok(())
+}
+
+/// Net value of the obligation for the NAV, the liabilities are subtracted from `fund_val`
+pub fn add_leverage_value(fund_data: &FundAccount, fund_val: U64F64, now_ts: i64) -> Result<U64F64, ProgramError> {
+    let leverage = &fund_data.leverage;
+    if !leverage.is_active || (leverage.deposited_value == U64F64::from_num(0) && leverage.borrowed_value == U64F64::from_num(0)) {
+        return Ok(fund_val);
+    }
+    if now_ts - leverage.last_updated > 100 {
+        msg!("obligation not up-to-date...");
+        return Err(FundError::PriceStaleInAccount.into())
+    }
+    fund_val.checked_add(leverage.deposited_value).unwrap()
+        .checked_sub(leverage.borrowed_value).ok_or(FundError::LeverageLimitExceeded.into())
+}
+
+/// Investor's part of the obligation equity, in USDC: (owed to the investor, owed by the investor)
+pub fn get_leverage_exit(fund_data: &FundAccount, share: U64F64) -> (u64, u64) {
+    let leverage = &fund_data.leverage;
+    if !leverage.is_active {
+        return (0, 0);
+    }
+    if leverage.deposited_value >= leverage.borrowed_value {
+        (U64F64::to_num(leverage.deposited_value.checked_sub(leverage.borrowed_value).unwrap().checked_mul(share).unwrap()), 0)
+    } else {
+        (0, U64F64::to_num(leverage.borrowed_value.checked_sub(leverage.deposited_value).unwrap().checked_mul(share).unwrap()))
+    }
+}
+
+/// The obligation stays whole on settle, the investor's part of its equity is paid in USDC instead.
+/// A part of an underwater obligation is taken from the investor's token debts.
+pub fn book_leverage_exit(
+    platform_data: &PlatformData,
+    fund_data: &mut FundAccount,
+    investor_data: &mut InvestorData,
+    share: U64F64,
+    now_ts: i64,
+) -> Result<(), ProgramError> {
+    let (owed, deficit) = get_leverage_exit(fund_data, share);
+    investor_data.token_debts[0] = investor_data.token_debts[0].checked_add(owed).unwrap();
+    fund_data.tokens[0].debt = fund_data.tokens[0].debt.checked_add(owed).unwrap();
+    check!(fund_data.tokens[0].balance >= fund_data.tokens[0].debt, ProgramError::InsufficientFunds);
+    take_exit_deficit(platform_data, fund_data, investor_data, deficit, now_ts)
+}
+
+/// Opens the fund's obligation, or changes the max LTV. Raising it needs the borrows repaid.
+///
+/// Accounts expected
+/// 0. [WRITE]  Fund State Account
+/// 1. [SIGNER] Manager Account
+/// 2. []   Lending Program
+/// 3. [WRITE]  Obligation Account, allocated for the lending program
+/// 4. []   Lending Market
+/// 5. []   Clock Sysvar
+/// 6. []   Rent Sysvar
+/// 7. []   Token Program
+pub fn init_leverage(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    max_ltv_bps: u16
+) -> Result<(), ProgramError> {
+    const NUM_FIXED: usize = 8;
+    check!(accounts.len() >= NUM_FIXED, ProgramError::NotEnoughAccountKeys);
+    let [
+        fund_account_ai,
+        manager_ai,
+        lending_prog_ai,
+        obligation_ai,
+        lending_market_ai,
+        clock_ai,
+        rent_ai,
+        token_prog_ai,
+    ] = array_ref![accounts, 0, NUM_FIXED];
+
+    check!(manager_ai.is_signer, ProgramError::MissingRequiredSignature);
+    check!(max_ltv_bps <= MAX_LTV_BPS, ProgramError::InvalidArgument);
+    let mut fund_data = FundAccount::load_mut_checked(fund_account_ai, program_id)?;
+    check!(fund_data.manager_account == *manager_ai.key, FundError::ManagerMismatch);
+
+    if fund_data.leverage.is_active {
+        check_eq!(fund_data.leverage.obligation, *obligation_ai.key);
+        check!(max_ltv_bps <= fund_data.leverage.max_ltv_bps || fund_data.leverage.borrowed_value == U64F64::from_num(0), FundError::LeverageLimitExceeded);
+        fund_data.leverage.max_ltv_bps = max_ltv_bps;
+        return Ok(());
+    }
+
+    check!(is_lending_program(lending_prog_ai.key) && *obligation_ai.owner == *lending_prog_ai.key, FundError::IncorrectProgramId);
+    check_eq!(*token_prog_ai.key, spl_token::ID);
+    let manager_account = fund_data.manager_account;
+    let nonce = fund_data.signer_nonce;
+    drop(fund_data);
+
+    invoke_signed(
+        &Instruction {
+            program_id: *lending_prog_ai.key,
+            accounts: vec![
+                AccountMeta::new(*obligation_ai.key, false),
+                AccountMeta::new_readonly(*lending_market_ai.key, false),
+                AccountMeta::new_readonly(*fund_account_ai.key, true),
+                AccountMeta::new_readonly(*clock_ai.key, false),
+                AccountMeta::new_readonly(*rent_ai.key, false),
+                AccountMeta::new_readonly(*token_prog_ai.key, false),
+            ],
+            data: vec![LENDING_INIT_OBLIGATION],
+        },
+        &[
+            obligation_ai.clone(),
+            lending_market_ai.clone(),
+            fund_account_ai.clone(),
+            clock_ai.clone(),
+            rent_ai.clone(),
+            token_prog_ai.clone(),
+            lending_prog_ai.clone(),
+        ],
+        &[&[manager_account.as_ref(), bytes_of(&nonce)]]
+    )?;
+
+    let mut fund_data = FundAccount::load_mut_checked(fund_account_ai, program_id)?;
+    check_eq!(read_pubkey(&obligation_ai.try_borrow_data()?, OBLIGATION_LENDING_MARKET_OFFSET)?, *lending_market_ai.key);
+    fund_data.leverage = bytemuck::Zeroable::zeroed();
+    fund_data.leverage.obligation = *obligation_ai.key;
+    fund_data.leverage.max_ltv_bps = max_ltv_bps;
+    fund_data.leverage.is_active = true;
+    Ok(())
+}
+
+/// Moves collateral tokens of a lending strategy position into or out of the obligation.
+/// Withdrawals have to keep the obligation under the max LTV.
+///
+/// Accounts expected
+/// 0. [WRITE]  Fund State Account
+/// 1. [SIGNER] Manager Account
+/// 2. []   Platform State Account
+/// 3. [WRITE]  Fund Collateral Associated Token Account
+/// 4. []   Lending Program
+/// 5. []   Reserve Account
+/// 6. [WRITE]  Reserve Collateral Supply
+/// 7. [WRITE]  Obligation Account, refreshed earlier in the transaction
+/// 8. []   Lending Market
+/// 9. []   Lending Market Authority
+/// 10. []  Clock Sysvar
+/// 11. []  Token Program
+pub fn move_leverage_collateral(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    amount: u64,
+    is_deposit: bool
+) -> Result<(), ProgramError> {
+    const NUM_FIXED: usize = 12;
+    check!(accounts.len() >= NUM_FIXED, ProgramError::NotEnoughAccountKeys);
+    let [
+        fund_account_ai,
+        manager_ai,
+        platform_ai,
+        collateral_ai,
+        lending_prog_ai,
+        reserve_ai,
+        reserve_collateral_ai,
+        obligation_ai,
+        lending_market_ai,
+        market_authority_ai,
+        clock_ai,
+        token_prog_ai,
+    ] = array_ref![accounts, 0, NUM_FIXED];
+
+    check!(manager_ai.is_signer, ProgramError::MissingRequiredSignature);
+    check!(amount > 0, ProgramError::InvalidArgument);
+    check!(is_lending_program(lending_prog_ai.key) && *obligation_ai.owner == *lending_prog_ai.key, FundError::IncorrectProgramId);
+    check_eq!(*token_prog_ai.key, spl_token::ID);
+    let platform_data = PlatformData::load_checked(platform_ai, program_id)?;
+    let mut fund_data = FundAccount::load_mut_checked(fund_account_ai, program_id)?;
+    check!(fund_data.manager_account == *manager_ai.key, FundError::ManagerMismatch);
+    let position = fund_data.get_strategy_index(STRATEGY_TOKEN_LENDING, reserve_ai.key).ok_or(FundError::InvalidStrategy)?;
+    check_collateral_account(&fund_data, fund_account_ai.key, reserve_ai, collateral_ai)?;
+    refresh_leverage(&mut fund_data, fund_account_ai.key, obligation_ai)?;
+    let ul_slot = fund_data.strategy_position(position).ul_token_slot as usize;
+    let moved_value = U64F64::from_num(amount).checked_mul(get_collateral_rate(reserve_ai)?).unwrap()
+        .checked_mul(get_slot_price(&platform_data, &fund_data, ul_slot, Clock::get()?.unix_timestamp)?).unwrap();
+    let deposited_after = if is_deposit {
+        fund_data.leverage.deposited_value.checked_add(moved_value).unwrap()
+    } else {
+        fund_data.leverage.deposited_value.checked_sub(moved_value).ok_or(ProgramError::InsufficientFunds)?
+    };
+    check!(!is_over_ltv(fund_data.leverage.borrowed_value, deposited_after, fund_data.leverage.max_ltv_bps), FundError::LeverageLimitExceeded);
+    let manager_account = fund_data.manager_account;
+    let nonce = fund_data.signer_nonce;
+    drop(fund_data);
+
+    let (opcode, metas) = if is_deposit {
+        (LENDING_DEPOSIT_OBLIGATION_COLLATERAL, vec![
+            AccountMeta::new(*collateral_ai.key, false),
+            AccountMeta::new(*reserve_collateral_ai.key, false),
+            AccountMeta::new_readonly(*reserve_ai.key, false),
+            AccountMeta::new(*obligation_ai.key, false),
+            AccountMeta::new_readonly(*lending_market_ai.key, false),
+            AccountMeta::new_readonly(*fund_account_ai.key, true),
+            AccountMeta::new_readonly(*fund_account_ai.key, true),
+            AccountMeta::new_readonly(*clock_ai.key, false),
+            AccountMeta::new_readonly(*token_prog_ai.key, false),
+        ])
+    } else {
+        (LENDING_WITHDRAW_OBLIGATION_COLLATERAL, vec![
+            AccountMeta::new(*reserve_collateral_ai.key, false),
+            AccountMeta::new(*collateral_ai.key, false),
+            AccountMeta::new_readonly(*reserve_ai.key, false),
+            AccountMeta::new(*obligation_ai.key, false),
+            AccountMeta::new_readonly(*lending_market_ai.key, false),
+            AccountMeta::new_readonly(*market_authority_ai.key, false),
+            AccountMeta::new_readonly(*fund_account_ai.key, true),
+            AccountMeta::new_readonly(*clock_ai.key, false),
+            AccountMeta::new_readonly(*token_prog_ai.key, false),
+        ])
+    };
+    let mut data = vec![opcode];
+    data.extend_from_slice(&amount.to_le_bytes());
+    invoke_signed(
+        &Instruction { program_id: *lending_prog_ai.key, accounts: metas, data },
+        &[
+            collateral_ai.clone(),
+            reserve_collateral_ai.clone(),
+            reserve_ai.clone(),
+            obligation_ai.clone(),
+            lending_market_ai.clone(),
+            market_authority_ai.clone(),
+            fund_account_ai.clone(),
+            clock_ai.clone(),
+            token_prog_ai.clone(),
+            lending_prog_ai.clone(),
+        ],
+        &[&[manager_account.as_ref(), bytes_of(&nonce)]]
+    )?;
+
+    // the position reads its token account, the obligation value moves by the same amount
+    let mut fund_data = FundAccount::load_mut_checked(fund_account_ai, program_id)?;
+    refresh_lending_position(&mut fund_data, position, reserve_ai, collateral_ai)?;
+    let info = fund_data.strategy_position(position);
+    check!(info.value_in_ul >= info.ul_debt, ProgramError::InsufficientFunds);
+    fund_data.leverage.deposited_value = deposited_after;
+    Ok(())
+}
+
+/// Borrows `amount` into a token slot vault, up to the max LTV
+///
+/// Accounts expected
+/// 0. [WRITE]  Fund State Account
+/// 1. [SIGNER] Manager Account
+/// 2. []   Platform State Account
+/// 3. [WRITE]  Fund Token Vault receiving the borrow
+/// 4. []   Lending Program
+/// 5. [WRITE]  Borrow Reserve Account, refreshed earlier in the transaction
+/// 6. [WRITE]  Reserve Liquidity Supply
+/// 7. [WRITE]  Reserve Liquidity Fee Receiver
+/// 8. [WRITE]  Obligation Account, refreshed earlier in the transaction
+/// 9. []   Lending Market
+/// 10. []  Lending Market Authority
+/// 11. []  Clock Sysvar
+/// 12. []  Token Program
+pub fn leverage_borrow(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    amount: u64
+) -> Result<(), ProgramError> {
+    const NUM_FIXED: usize = 13;
+    check!(accounts.len() >= NUM_FIXED, ProgramError::NotEnoughAccountKeys);
+    let [
+        fund_account_ai,
+        manager_ai,
+        platform_ai,
+        vault_ai,
+        lending_prog_ai,
+        reserve_ai,
+        reserve_supply_ai,
+        fee_receiver_ai,
+        obligation_ai,
+        lending_market_ai,
+        market_authority_ai,
+        clock_ai,
+        token_prog_ai,
+    ] = array_ref![accounts, 0, NUM_FIXED];
+
+    check!(manager_ai.is_signer, ProgramError::MissingRequiredSignature);
+    check!(amount > 0, ProgramError::InvalidArgument);
+    check!(is_lending_program(lending_prog_ai.key) && *reserve_ai.owner == *lending_prog_ai.key, FundError::IncorrectProgramId);
+    check_eq!(*token_prog_ai.key, spl_token::ID);
+    let platform_data = PlatformData::load_checked(platform_ai, program_id)?;
+    let mut fund_data = FundAccount::load_mut_checked(fund_account_ai, program_id)?;
+    check!(fund_data.manager_account == *manager_ai.key, FundError::ManagerMismatch);
+    refresh_leverage(&mut fund_data, fund_account_ai.key, obligation_ai)?;
+    let slot = get_reserve_slot(&platform_data, &fund_data, vault_ai, reserve_ai)?;
+    let borrow_value = U64F64::from_num(amount).checked_mul(get_slot_price(&platform_data, &fund_data, slot, Clock::get()?.unix_timestamp)?).unwrap();
+    let borrowed_after = fund_data.leverage.borrowed_value.checked_add(borrow_value).unwrap();
+    check!(!is_over_ltv(borrowed_after, fund_data.leverage.deposited_value, fund_data.leverage.max_ltv_bps), FundError::LeverageLimitExceeded);
+    let manager_account = fund_data.manager_account;
+    let nonce = fund_data.signer_nonce;
+    drop(fund_data);
+
+    let mut data = vec![LENDING_BORROW_OBLIGATION_LIQUIDITY];
+    data.extend_from_slice(&amount.to_le_bytes());
+    invoke_signed(
+        &Instruction {
+            program_id: *lending_prog_ai.key,
+            accounts: vec![
+                AccountMeta::new(*reserve_supply_ai.key, false),
+                AccountMeta::new(*vault_ai.key, false),
+                AccountMeta::new(*reserve_ai.key, false),
+                AccountMeta::new(*fee_receiver_ai.key, false),
+                AccountMeta::new(*obligation_ai.key, false),
+                AccountMeta::new_readonly(*lending_market_ai.key, false),
+                AccountMeta::new_readonly(*market_authority_ai.key, false),
+                AccountMeta::new_readonly(*fund_account_ai.key, true),
+                AccountMeta::new_readonly(*clock_ai.key, false),
+                AccountMeta::new_readonly(*token_prog_ai.key, false),
+            ],
+            data,
+        },
+        &[
+            reserve_supply_ai.clone(),
+            vault_ai.clone(),
+            reserve_ai.clone(),
+            fee_receiver_ai.clone(),
+            obligation_ai.clone(),
+            lending_market_ai.clone(),
+            market_authority_ai.clone(),
+            fund_account_ai.clone(),
+            clock_ai.clone(),
+            token_prog_ai.clone(),
+            lending_prog_ai.clone(),
+        ],
+        &[&[manager_account.as_ref(), bytes_of(&nonce)]]
+    )?;
+
+    let mut fund_data = FundAccount::load_mut_checked(fund_account_ai, program_id)?;
+    fund_data.tokens[slot].balance = parse_token_account(vault_ai)?.amount;
+    fund_data.leverage.borrowed_value = borrowed_after;
+    Ok(())
+}
+
+/// Repays borrowed liquidity from a token slot vault, by the manager, or by any keeper
+/// while the fund is above its max LTV and then only down to it.
+///
+/// Accounts expected
+/// 0. [WRITE]  Fund State Account
+/// 1. [SIGNER] Manager or Keeper Account
+/// 2. []   Platform State Account
+/// 3. [WRITE]  Fund Token Vault repaying
+/// 4. []   Lending Program
+/// 5. [WRITE]  Repay Reserve Account, refreshed earlier in the transaction
+/// 6. [WRITE]  Reserve Liquidity Supply
+/// 7. [WRITE]  Obligation Account, refreshed earlier in the transaction
+/// 8. []   Lending Market
+/// 9. []   Clock Sysvar
+/// 10. []  Token Program
+pub fn leverage_repay(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    amount: u64,
+    is_keeper: bool
+) -> Result<(), ProgramError> {
+    const NUM_FIXED: usize = 11;
+    check!(accounts.len() >= NUM_FIXED, ProgramError::NotEnoughAccountKeys);
+    let [
+        fund_account_ai,
+        signer_ai,
+        platform_ai,
+        vault_ai,
+        lending_prog_ai,
+        reserve_ai,
+        reserve_supply_ai,
+        obligation_ai,
+        lending_market_ai,
+        clock_ai,
+        token_prog_ai,
+    ] = array_ref![accounts, 0, NUM_FIXED];
+
+    check!(signer_ai.is_signer, ProgramError::MissingRequiredSignature);
+    check!(is_lending_program(lending_prog_ai.key) && *reserve_ai.owner == *lending_prog_ai.key, FundError::IncorrectProgramId);
+    check_eq!(*token_prog_ai.key, spl_token::ID);
+    let platform_data = PlatformData::load_checked(platform_ai, program_id)?;
+    let mut fund_data = FundAccount::load_mut_checked(fund_account_ai, program_id)?;
+    if !is_keeper {
+        check!(fund_data.manager_account == *signer_ai.key, FundError::ManagerMismatch);
+    }
+    refresh_leverage(&mut fund_data, fund_account_ai.key, obligation_ai)?;
+    let slot = get_reserve_slot(&platform_data, &fund_data, vault_ai, reserve_ai)?;
+    let price = get_slot_price(&platform_data, &fund_data, slot, Clock::get()?.unix_timestamp)?;
+    let leverage = fund_data.leverage;
+    let mut repay_amount = amount.min(fund_data.tokens[slot].balance.checked_sub(fund_data.tokens[slot].debt).unwrap());
+    if is_keeper {
+        check!(is_over_ltv(leverage.borrowed_value, leverage.deposited_value, leverage.max_ltv_bps), FundError::LeverageLimitExceeded);
+        let target = leverage.deposited_value.checked_mul(U64F64::from_num(leverage.max_ltv_bps)).unwrap()
+            .checked_div(U64F64::from_num(BPS)).unwrap();
+        let excess: u64 = U64F64::to_num(leverage.borrowed_value.checked_sub(target).unwrap().checked_div(price).unwrap().ceil());
+        repay_amount = repay_amount.min(excess);
+    }
+    check!(repay_amount > 0, ProgramError::InsufficientFunds);
+    let manager_account = fund_data.manager_account;
+    let nonce = fund_data.signer_nonce;
+    drop(fund_data);
+
+    let mut data = vec![LENDING_REPAY_OBLIGATION_LIQUIDITY];
+    data.extend_from_slice(&repay_amount.to_le_bytes());
+    invoke_signed(
+        &Instruction {
+            program_id: *lending_prog_ai.key,
+            accounts: vec![
+                AccountMeta::new(*vault_ai.key, false),
+                AccountMeta::new(*reserve_supply_ai.key, false),
+                AccountMeta::new(*reserve_ai.key, false),
+                AccountMeta::new(*obligation_ai.key, false),
+                AccountMeta::new_readonly(*lending_market_ai.key, false),
+                AccountMeta::new_readonly(*fund_account_ai.key, true),
+                AccountMeta::new_readonly(*clock_ai.key, false),
+                AccountMeta::new_readonly(*token_prog_ai.key, false),
+            ],
+            data,
+        },
+        &[
+            vault_ai.clone(),
+            reserve_supply_ai.clone(),
+            reserve_ai.clone(),
+            obligation_ai.clone(),
+            lending_market_ai.clone(),
+            fund_account_ai.clone(),
+            clock_ai.clone(),
+            token_prog_ai.clone(),
+            lending_prog_ai.clone(),
+        ],
+        &[&[manager_account.as_ref(), bytes_of(&nonce)]]
+    )?;
+
+    let mut fund_data = FundAccount::load_mut_checked(fund_account_ai, program_id)?;
+    let repaid = fund_data.tokens[slot].balance.checked_sub(parse_token_account(vault_ai)?.amount).unwrap();
+    fund_data.tokens[slot].balance = fund_data.tokens[slot].balance.checked_sub(repaid).unwrap();
+    check!(fund_data.tokens[slot].balance >= fund_data.tokens[slot].debt, ProgramError::InsufficientFunds);
+    let repaid_value = U64F64::from_num(repaid).checked_mul(price).unwrap();
+    fund_data.leverage.borrowed_value = fund_data.leverage.borrowed_value.checked_sub(repaid_value).unwrap_or(U64F64::from_num(0));
+    msg!("repaid {:?}", repaid);
+    Ok(())
+}
+
+/// Permissionless refresh of the obligation values used in the NAV
+///
+/// Accounts expected
+/// 0. [WRITE]  Fund State Account
+/// 1. []   Obligation Account, refreshed earlier in the transaction
+pub fn update_leverage_value(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo]
+) -> Result<(), ProgramError> {
+    let accounts_iter = &mut accounts.iter();
+    let fund_account_ai = next_account_info(accounts_iter)?;
+    let obligation_ai = next_account_info(accounts_iter)?;
+    let mut fund_data = FundAccount::load_mut_checked(fund_account_ai, program_id)?;
+    refresh_leverage(&mut fund_data, fund_account_ai.key, obligation_ai)?;
+    Ok(())
+}
@@ -5,6 +5,23 @@ pub mod state;
 pub mod jup_utils;
 pub mod mango_utils;
 pub mod friktion_utils;
+pub mod migration;
+pub mod quote_utils;
+pub mod events;
+pub mod nav_utils;
+pub mod clmm_utils;
+pub mod serum_utils;
+pub mod risk_utils;
+pub mod rebalance_utils;
+pub mod execution_utils;
+pub mod trigger_utils;
+pub mod strategy_utils;
+pub mod staking_utils;
+pub mod lending_utils;
+pub mod data_utils;
+pub mod leverage_utils;
+pub mod reimbursement_utils;
+pub mod exit_utils;
 mod tokens;
 #[cfg(not(feature = "no-entrypoint"))]
 pub mod entrypoint;
\ No newline at end of file
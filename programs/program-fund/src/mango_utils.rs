@@ -1,1145 +1,1040 @@
-// use bytemuck::bytes_of;
-// use fixed::types::I80F48;
-// use fixed::types::U64F64;
-// use fixed_macro::types::I80F48;
-// use mango::instruction::cancel_perp_order;
-// use mango::instruction::cancel_perp_order_by_client_id;
-// use solana_program::{
-//     account_info::AccountInfo,
-//     msg,
-//     instruction:: {AccountMeta, Instruction},
-//     program_error::ProgramError,
-//     program_pack::Pack,
-//     pubkey::Pubkey,
-//     program::invoke_signed,
-//     rent::Rent,
-// };
-// use num_enum::TryFromPrimitive;
-// use std::convert::TryFrom;
-// use std::convert::TryInto;
-// use std::cell::RefMut;
-// use std::i128;
-
-// use fixed_macro::types::U64F64;
-// pub const ONE_U64F64: U64F64 = U64F64!(1);
-// pub const ZERO_U64F64: U64F64 = U64F64!(0);
-
-// use std::num::NonZeroU64;
-
-
-// use arrayref::{array_ref, array_refs};
-
-// use crate::error::FundError;
-// use crate::state::MAX_LIMIT_ORDERS;
-// use crate::state::{MAX_INVESTORS_WITHDRAW, NUM_MARGIN, FundAccount, InvestorData};
-// use crate::state::Loadable;
-// use crate::processor::{ parse_token_account, close_investor_account };
-// use serum_dex::state::MarketState;
-// use serum_dex::instruction::{NewOrderInstructionV3, SelfTradeBehavior};
-// use serum_dex::matching::{Side as SerumSide, OrderType as SerumOrderType};
-// use mango::state::{MangoAccount, MangoGroup, MangoCache, MAX_PAIRS, QUOTE_INDEX};
-// // use mango::state::Loadable as OtherLoadable;
-// use mango::instruction::{deposit, withdraw, place_perp_order, place_perp_order2, init_spot_open_orders, settle_pnl, MangoInstruction};
-
-// use mango::matching::{Side, OrderType};
-// use spl_token::state::Account;
-
-// macro_rules! check {
-//     ($cond:expr, $err:expr) => {
-//         if !($cond) {
-//             return Err(($err).into())
-//         }
-//     }
-// }
-
-// macro_rules! check_eq {
-//     ($x:expr, $y:expr) => {
-//         if ($x != $y) {
-//             return Err(FundError::Default.into())
-//         }
-//     }
-// }
-
-// // pub fn place_spot_order2(
-// //     program_id: &Pubkey,
-// //     mango_group_pk: &Pubkey,
-// //     mango_account_pk: &Pubkey,
-// //     owner_pk: &Pubkey,
-// //     mango_cache_pk: &Pubkey,
-// //     dex_prog_pk: &Pubkey,
-// //     spot_market_pk: &Pubkey,
-// //     bids_pk: &Pubkey,
-// //     asks_pk: &Pubkey,
-// //     dex_request_queue_pk: &Pubkey,
-// //     dex_event_queue_pk: &Pubkey,
-// //     dex_base_pk: &Pubkey,
-// //     dex_quote_pk: &Pubkey,
-// //     base_root_bank_pk: &Pubkey,
-// //     base_node_bank_pk: &Pubkey,
-// //     base_vault_pk: &Pubkey,
-// //     quote_root_bank_pk: &Pubkey,
-// //     quote_node_bank_pk: &Pubkey,
-// //     quote_vault_pk: &Pubkey,
-// //     signer_pk: &Pubkey,
-// //     dex_signer_pk: &Pubkey,
-// //     msrm_or_srm_vault_pk: &Pubkey,
-// //     open_orders_pks: &[Pubkey],
-// //     order: serum_dex::instruction::NewOrderInstructionV3,
-// // ) -> Result<Instruction, ProgramError> {
-// //     msg!("Calling Mango...");
-// //     let mut accounts = vec![
-// //         AccountMeta::new_readonly(*mango_group_pk, false),
-// //         AccountMeta::new(*mango_account_pk, false),
-// //         AccountMeta::new_readonly(*owner_pk, true),
-// //         AccountMeta::new_readonly(*mango_cache_pk, false),
-// //         AccountMeta::new_readonly(*dex_prog_pk, false),
-// //         AccountMeta::new(*spot_market_pk, false),
-// //         AccountMeta::new(*bids_pk, false),
-// //         AccountMeta::new(*asks_pk, false),
-// //         AccountMeta::new(*dex_request_queue_pk, false),
-// //         AccountMeta::new(*dex_event_queue_pk, false),
-// //         AccountMeta::new(*dex_base_pk, false),
-// //         AccountMeta::new(*dex_quote_pk, false),
-// //         AccountMeta::new_readonly(*base_root_bank_pk, false),
-// //         AccountMeta::new(*base_node_bank_pk, false),
-// //         AccountMeta::new(*base_vault_pk, false),
-// //         AccountMeta::new_readonly(*quote_root_bank_pk, false),
-// //         AccountMeta::new(*quote_node_bank_pk, false),
-// //         AccountMeta::new(*quote_vault_pk, false),
-// //         AccountMeta::new_readonly(spl_token::ID, false),
-// //         AccountMeta::new_readonly(*signer_pk, false),
-// //         AccountMeta::new_readonly(*dex_signer_pk, false),
-// //         AccountMeta::new_readonly(*msrm_or_srm_vault_pk, false),
-// //     ];
-
-// //     accounts.extend(open_orders_pks.iter().map(
-// //         |pk| 
-// //         if *pk == Pubkey::default(){
-// //             AccountMeta::new_readonly(*pk, false)
-// //         } else {
-// //             AccountMeta::new(*pk, false)
-// //         })
-// //     );
-
-// //     let instr = MangoInstruction::PlaceSpotOrder2 { order };
-// //     let data = instr.pack();
-
-// //     Ok(Instruction { program_id: *program_id, accounts, data })
-// // }
-
-// pub fn init_mango_account(
-//     program_id: &Pubkey,
-//     mango_group_pk: &Pubkey,
-//     mango_account_pk: &Pubkey,
-//     owner_pk: &Pubkey,
-// ) -> Result<Instruction, ProgramError> {
-//     let accounts = vec![
-//         AccountMeta::new_readonly(*mango_group_pk, false),
-//         AccountMeta::new(*mango_account_pk, false),
-//         AccountMeta::new_readonly(*owner_pk, true),
-//     ];
-
-//     let instr = MangoInstruction::InitMangoAccount;
-//     let data = instr.pack();
-//     Ok(Instruction { program_id: *program_id, accounts, data })
-// }
-
-// pub mod mango_v3_id {
-//     use solana_program::declare_id;
-//     // #[cfg(feature = "devnet")]
-//     // declare_id!("4skJ85cdxQAFVKbcGgfun8iZPL7BadVYXG3kGEGkufqA");
-//     // #[cfg(not(feature = "devnet"))]
-//     declare_id!("mv3ekLzLbnVPNxjSKvqBpU3ZeZXPQdEC3bp5MDEBG68");
-// }
-
-// pub fn mango_init_mango_account(
-//     program_id: &Pubkey,
-//     accounts: &[AccountInfo],
-// ) -> Result<(), ProgramError> {
-//     const NUM_FIXED: usize = 5;
-//     let accounts = array_ref![accounts, 0, NUM_FIXED];
-
-//     let [
-//         fund_account_ai,
-//         manager_ai,
-//         mango_prog_ai,
-//         mango_group_ai,
-//         mango_account_ai,
-//     ] = accounts;
-
-//     let mut fund_data = FundAccount::load_mut_checked(fund_account_ai, program_id)?;
-//     //Check for Mango v3 ID 
-//     check_eq!(*mango_prog_ai.key, mango_v3_id::ID);
-//     check!(manager_ai.is_signer, ProgramError::MissingRequiredSignature);
-//     check_eq!(*mango_account_ai.owner, mango_v3_id::ID);
-//     check_eq!(fund_data.manager_account, *manager_ai.key);
-//     check_eq!(fund_data.mango_positions.mango_account, Pubkey::default());
-//     fund_data.mango_positions.mango_account = *mango_account_ai.key;
-//     let nonce = fund_data.signer_nonce;
-//     drop(fund_data);
-//     invoke_signed(
-//         &init_mango_account(mango_prog_ai.key, mango_group_ai.key, mango_account_ai.key, fund_account_ai.key)?,
-//         &[
-//             mango_prog_ai.clone(),
-//             mango_group_ai.clone(),
-//             mango_account_ai.clone(),
-//             fund_account_ai.clone()
-//         ],
-//         &[&[&*manager_ai.key.as_ref(), bytes_of(&nonce)]]
-//         )?;    
-//     Ok(())
-// }
-
-// pub fn mango_deposit(
-//     program_id: &Pubkey,
-//     accounts: &[AccountInfo],
-//     token_slot_index: u8,
-//     mango_token_index: u8,
-//     quantity: u64
-// ) -> Result<(), ProgramError> {
-//     const NUM_FIXED: usize = 11;
-//     let accounts = array_ref![accounts, 0, NUM_FIXED];
-//     let [
-//         fund_account_ai,
-//         manager_ai, // or delegate
-//         mango_prog_ai,
-//         mango_group_ai,         // read
-//         mango_account_ai,       // write
-//         mango_cache_ai,         // read
-//         root_bank_ai,           // read
-//         node_bank_ai,           // write
-//         vault_ai,               // write
-//         token_prog_ai,          // read
-//         owner_token_account_ai, // write
-//     ] = accounts;
-
-//     let mut fund_data = FundAccount::load_mut_checked(fund_account_ai, program_id)?;
-    
-//     check_eq!(fund_data.tokens[token_slot_index as usize].vault, *owner_token_account_ai.key); 
-//     check_eq!(*mango_prog_ai.key, mango_v3_id::ID);
-//     check!(fund_data.mango_positions.mango_account != Pubkey::default(), FundError::MangoNotInitialized);
-//     check_eq!(*mango_account_ai.key, fund_data.mango_positions.mango_account);
-//     // check_eq!(mango_group.tokens[mango_token_index].root_bank, )   
-//     check_eq!(mango_token_index as usize, QUOTE_INDEX);
-//     // if(mango_token_index as usize != QUOTE_INDEX){
-//     //     check!(fund_data.mango_positions.deposit_index == mango_token_index || 
-//     //         fund_data.mango_positions.deposit_index == u8::MAX, FundError::InvalidMangoState);
-//     //     fund_data.mango_positions.deposit_index = mango_token_index;
-//     //     fund_data.tokens[token_slot_index as usize].is_on_mango = 1;
-//     // }
-//     check!(fund_data.is_initialized, ProgramError::InvalidAccountData);
-//     check!(manager_ai.is_signer, ProgramError::MissingRequiredSignature);
-    
-//     // check_eq!(fund_data.manager_account, *manager_ai.key);
-//     check!((fund_data.manager_account == *manager_ai.key), FundError::ManagerMismatch);
-
-//     // // check fund vault
-//     // check_eq!(fund_data.vault_key, *owner_token_account_ai.key); 
-//     let nonce = fund_data.signer_nonce;
-//     drop(fund_data);
-//     invoke_signed(
-//         &deposit(mango_prog_ai.key, mango_group_ai.key, mango_account_ai.key, fund_account_ai.key,
-//             mango_cache_ai.key, root_bank_ai.key, node_bank_ai.key, vault_ai.key, owner_token_account_ai.key, quantity)?,
-//         &[
-//             mango_prog_ai.clone(),
-//             mango_group_ai.clone(),
-//             mango_account_ai.clone(),
-//             fund_account_ai.clone(),
-//             mango_cache_ai.clone(),
-//             root_bank_ai.clone(),
-//             node_bank_ai.clone(),
-//             vault_ai.clone(),
-//             owner_token_account_ai.clone(),
-//             token_prog_ai.clone()
-//         ],
-//         &[&[&*manager_ai.key.as_ref(), bytes_of(&nonce)]]
-//     )?;
-
-//     msg!("invoke done");
-
-//     let token_info = parse_token_account(owner_token_account_ai)?;
-//     fund_data = FundAccount::load_mut_checked(fund_account_ai, program_id)?;
-//     fund_data.tokens[token_slot_index as usize].balance = token_info.amount;
-//     check!(fund_data.tokens[token_slot_index as usize].balance >= fund_data.tokens[token_slot_index as usize].debt, ProgramError::InsufficientFunds);
-
-//     Ok(())
-// }
-
-// pub fn mango_place_perp_order(
-//     program_id: &Pubkey,
-//     accounts: &[AccountInfo],
-//     perp_market_id: u8,
-//     side: Side,
-//     mut price: i64,
-//     quantity: i64, 
-//     mut reduce_only: bool
-// ) -> Result<(), ProgramError> {
-//     const NUM_FIXED: usize = 12;
-//     let accounts = array_ref![accounts, 0, NUM_FIXED];
-
-//     let [
-//         fund_account_ai,
-//         manager_ai,
-//         mango_prog_ai,
-//         mango_group_ai,     // read
-//         mango_account_ai,   // write
-//         mango_cache_ai,     // read
-//         perp_market_ai,     // write
-//         bids_ai,            // write
-//         asks_ai,            // write
-//         event_queue_ai,    // write
-//         referrer_mango_account_ai,
-//         default_ai,
-//     ] = accounts;
-
-//     let mut fund_data = FundAccount::load_mut_checked(fund_account_ai, program_id)?;
-//     //Check for Mango v3 ID 
-//     check_eq!(*mango_prog_ai.key, mango_v3_id::ID);
-//     check!(manager_ai.is_signer, ProgramError::MissingRequiredSignature);
-
-//     // check_eq!(fund_data.manager_account, *manager_ai.key);
-//     check!((fund_data.manager_account == *manager_ai.key), FundError::ManagerMismatch);
-//     let fund_perp_market_index = fund_data.get_mango_perp_index(perp_market_id);
-//     if fund_perp_market_index == None {
-//         let new_fund_perp_market_index = fund_data.get_mango_perp_index(u8::MAX).unwrap();
-//         fund_data.mango_positions.perp_markets[new_fund_perp_market_index] = perp_market_id;
-//     }
-//     let open_orders_accs = [Pubkey::default(); MAX_PAIRS];
-//     let nonce = fund_data.signer_nonce;
-//     if perp_market_id == 13 {
-//         reduce_only = true;
-//         if price == 0 {
-//             if side == Side::Bid{
-//                 price = i64::MAX;
-//             } else {
-//                 price = 1;
-//             }
-//         }
-//     }
-//     drop(fund_data);
-//     invoke_signed(
-//         &place_perp_order(mango_prog_ai.key,
-//             mango_group_ai.key, mango_account_ai.key, fund_account_ai.key,
-//             mango_cache_ai.key,perp_market_ai.key, bids_ai.key, asks_ai.key, event_queue_ai.key, Some(referrer_mango_account_ai.key), &open_orders_accs,
-//             side, price, quantity, 0, OrderType::ImmediateOrCancel, reduce_only)?,
-//         &[
-//             mango_prog_ai.clone(),
-//             mango_group_ai.clone(),
-//             mango_account_ai.clone(),
-//             fund_account_ai.clone(),
-//             mango_cache_ai.clone(),
-//             perp_market_ai.clone(),
-//             bids_ai.clone(),
-//             asks_ai.clone(),
-//             event_queue_ai.clone(),
-//             referrer_mango_account_ai.clone(),
-//             default_ai.clone(), 
-//         ],
-//         &[&[&*manager_ai.key.as_ref(), bytes_of(&nonce)]]
-//     )?;
-
-//     let mango_group_data = MangoGroup::load_checked(mango_group_ai, mango_prog_ai.key)?;
-//     check_eq!(mango_group_data.perp_markets[perp_market_id as usize].perp_market, *perp_market_ai.key);
-//     Ok(())
-
-// }
-
-
-// //currently only for Limit Orders
-// pub fn mango_place_perp_order2(
-//     program_id: &Pubkey,
-//     accounts: &[AccountInfo],
-//     perp_market_id: u8,
-//     side: Side,
-//     price: i64,
-//     max_base_quantity: i64,
-//     max_quote_quantity: i64,
-//     client_order_id: u64,
-//     _order_type: OrderType,
-//     reduce_only: bool,
-//     expiry_timestamp: u64,
-//     limit: u8,
-// ) -> Result<(), ProgramError> {
-
-//     msg!("yooyo: perpId-{:?} side- {:?}, p-{},bq-{}, qq-{}, coi-{}, et{:?}, l-{} ", perp_market_id,side,price,max_base_quantity, max_quote_quantity, client_order_id, expiry_timestamp, limit );
-
-//     check!(client_order_id > 0, FundError::InvalidInstruction);
-
-//     // disAllow clientOrderid = 0
-
-//     const NUM_FIXED: usize = 12;
-//     let accounts = array_ref![accounts, 0, NUM_FIXED];
-
-//     let [
-//         fund_account_ai,
-//         manager_ai,
-//         mango_prog_ai,
-//         mango_group_ai,     // read
-//         mango_account_ai,   // write
-//         mango_cache_ai,     // read
-//         perp_market_ai,     // write
-//         bids_ai,            // write
-//         asks_ai,            // write
-//         event_queue_ai,    // write
-//         referrer_mango_account_ai,
-//         default_ai,
-//     ] = accounts;
-
-//     let mut fund_data = FundAccount::load_mut_checked(fund_account_ai, program_id)?;
-//     //Check for Mango v3 ID 
-//     check_eq!(*mango_prog_ai.key, mango_v3_id::ID);
-//     check!(manager_ai.is_signer, ProgramError::MissingRequiredSignature);
-
-//     // check_eq!(fund_data.manager_account, *manager_ai.key);
-//     check!((fund_data.manager_account == *manager_ai.key), FundError::ManagerMismatch);
-//     let fund_perp_market_index = fund_data.get_mango_perp_index(perp_market_id);
-//     if fund_perp_market_index == None {
-//         let new_fund_perp_market_index = fund_data.get_mango_perp_index(u8::MAX).unwrap(); //getting empty Index
-//         fund_data.mango_positions.perp_markets[new_fund_perp_market_index] = perp_market_id;
-//     }
-//     let open_orders_accs = [Pubkey::default(); MAX_PAIRS];
-//     let nonce = fund_data.signer_nonce;
-
-//     // first check all slots and clear if some already executed
-//     for i in 0..MAX_LIMIT_ORDERS {
-//         msg!("check sl-{:?}",i);
-//         if  fund_data.limit_orders[i].client_order_id == 0 {
-//             msg!("ignore 0");
-//             continue;
-//         }
-//         // let limit_order_slot = fund_data.find_slot_by_client_id(client_order_id).unwrap();
-//         //check if order has already execueted
-//         let mango_account = MangoAccount::load_checked(mango_account_ai, mango_prog_ai.key, mango_group_ai.key)?;
-//         let valid =  mango_account.find_order_with_client_id(fund_data.limit_orders[i].perp_market_id as usize,fund_data.limit_orders[i].client_order_id);
-//             match valid {
-//                 None => {
-//                     msg!("order already executed");
-//                     //order already executed
-//                     fund_data.limit_orders[i].client_order_id = 0; 
-//                 }
-//                 _ => {
-//                     // move on still listed
-//                     msg!("still listed");
-//                 }
-//             }
-//     }
-
-//     //get free slot or Panic
-//     let free_slot = fund_data.find_slot_by_client_id(0).unwrap();
-
-//     // update structs 
-//     fund_data.limit_orders[free_slot].price = price;
-//     fund_data.limit_orders[free_slot].max_base_quantity = max_base_quantity;
-//     fund_data.limit_orders[free_slot].max_quote_quantity = max_quote_quantity;
-//     fund_data.limit_orders[free_slot].client_order_id = client_order_id;
-//     fund_data.limit_orders[free_slot].perp_market_id = perp_market_id;
-//     fund_data.limit_orders[free_slot].side = side;
-//     fund_data.limit_orders[free_slot].expiry_timestamp = expiry_timestamp;
-//     fund_data.limit_orders[free_slot].reduce_only = reduce_only;
-//     fund_data.limit_orders[free_slot].limit = limit;
-
-//     drop(fund_data);
-
-//     invoke_signed(
-//         &place_perp_order2(mango_prog_ai.key,
-//             mango_group_ai.key, mango_account_ai.key, fund_account_ai.key,
-//             mango_cache_ai.key,perp_market_ai.key, bids_ai.key, asks_ai.key, event_queue_ai.key, 
-//             Some(referrer_mango_account_ai.key), &open_orders_accs,
-//             side, price, max_base_quantity, max_quote_quantity, client_order_id, OrderType::Limit, reduce_only, Some(expiry_timestamp),limit)?,
-//             &[
-//                 mango_prog_ai.clone(),
-//                 mango_group_ai.clone(),
-//                 mango_account_ai.clone(),
-//                 fund_account_ai.clone(),
-//                 mango_cache_ai.clone(),
-//                 perp_market_ai.clone(),
-//                 bids_ai.clone(),
-//                 asks_ai.clone(),
-//                 event_queue_ai.clone(),
-//                 referrer_mango_account_ai.clone(),
-//                 default_ai.clone(), 
-//             ],
-//         &[&[&*manager_ai.key.as_ref(), bytes_of(&nonce)]]
-//     )?;
-
-    
-//     let mango_group_data = MangoGroup::load_checked(mango_group_ai, mango_prog_ai.key)?;
-//     check_eq!(mango_group_data.perp_markets[perp_market_id as usize].perp_market, *perp_market_ai.key);
-//     Ok(())
-
-// }
-
-// pub fn mango_cancel_perp_order(
-//     program_id: &Pubkey,
-//     accounts: &[AccountInfo],
-//     client_order_id: u64,
-// ) -> Result<(), ProgramError> {
-//     msg!("client_order_id: {:?}",client_order_id);
-//     check!(client_order_id > 0, FundError::InvalidInstruction);
-
-//     const NUM_FIXED: usize = 8;
-//     let accounts = array_ref![accounts, 0, NUM_FIXED];
-
-//     let [
-//         fund_account_ai,
-//         manager_ai,
-//         mango_prog_ai,
-//         mango_group_ai,     // read
-//         mango_account_ai,   // write
-//         perp_market_ai,     // write
-//         bids_ai,            // write
-//         asks_ai,            // write
-//     ] = accounts;
-
-//     let mut fund_data = FundAccount::load_mut_checked(fund_account_ai, program_id)?;
-//     //Check for Mango v3 ID 
-//     check_eq!(*mango_prog_ai.key, mango_v3_id::ID);
-//     check!(manager_ai.is_signer, ProgramError::MissingRequiredSignature);
-//     let nonce = fund_data.signer_nonce;
-
-//     // clear data and clientOrderid = 0
-//     let limit_order_slot = fund_data.find_slot_by_client_id(client_order_id).unwrap();
-//     msg!("limit_order_slot {:?}",limit_order_slot);
-
-//     //check if order has already execueted
-//     let mango_account = MangoAccount::load_checked(mango_account_ai, mango_prog_ai.key, mango_group_ai.key)?;
-//     let valid = mango_account.find_order_with_client_id(fund_data.limit_orders[limit_order_slot].perp_market_id as usize,client_order_id);
-//     drop(mango_account);
-
-//         match valid {
-//             Some(_) => {
-//                 fund_data.limit_orders[limit_order_slot].client_order_id = 0;
-//                 fund_data.limit_orders[limit_order_slot].max_base_quantity = 0 as i64;
-//                 fund_data.limit_orders[limit_order_slot].max_quote_quantity = 0 as i64;
-//                 fund_data.limit_orders[limit_order_slot].perp_market_id = 0 as u8;
-//                 fund_data.limit_orders[limit_order_slot].side = Side::Bid;
-//                 fund_data.limit_orders[limit_order_slot].expiry_timestamp = 0 as u64;
-//                 fund_data.limit_orders[limit_order_slot].reduce_only = false;
-
-//                 drop(fund_data);
-    
-//                 invoke_signed(
-//                     &cancel_perp_order_by_client_id(mango_prog_ai.key,
-//                         mango_group_ai.key, mango_account_ai.key, fund_account_ai.key,
-//                         perp_market_ai.key, bids_ai.key, asks_ai.key,client_order_id ,false)?,
-//                     &[
-//                         mango_prog_ai.clone(),
-//                         mango_group_ai.clone(),
-//                         mango_account_ai.clone(),
-//                         fund_account_ai.clone(),
-//                         perp_market_ai.clone(),
-//                         bids_ai.clone(),
-//                         asks_ai.clone(), 
-//                     ],
-//                     &[&[&*manager_ai.key.as_ref(), bytes_of(&nonce)]]
-//                 )?;
-//             }
-//             None => {
-//                 //order already executed
-//                 fund_data.limit_orders[limit_order_slot].client_order_id = 0; 
-//                 //clear data if needed
-//                 fund_data.limit_orders[limit_order_slot].max_base_quantity = 0 as i64;
-//                 fund_data.limit_orders[limit_order_slot].max_quote_quantity = 0 as i64;
-//                 fund_data.limit_orders[limit_order_slot].perp_market_id = 0 as u8;
-//                 fund_data.limit_orders[limit_order_slot].side = Side::Bid;
-//                 fund_data.limit_orders[limit_order_slot].expiry_timestamp = 0 as u64;
-//                 fund_data.limit_orders[limit_order_slot].reduce_only = false;
-//                 msg!("order already executed");
-//             }
-//         }
-
-    
-
-   
-//     Ok(())
-
-// }
-
-// // deprecated
-// pub fn mango_cancel_perp_by_order_id(
-//     program_id: &Pubkey,
-//     accounts: &[AccountInfo],
-//     order_id: i128,
-// ) -> Result<(), ProgramError> {
-//     msg!("order_id: {:?}",order_id);
-//     const NUM_FIXED: usize = 8;
-//     let accounts = array_ref![accounts, 0, NUM_FIXED];
-
-//     let [
-//         fund_account_ai,
-//         manager_ai,
-//         mango_prog_ai,
-//         mango_group_ai,     // read
-//         mango_account_ai,   // write
-//         perp_market_ai,     // write
-//         bids_ai,            // write
-//         asks_ai,            // write
-//     ] = accounts;
-
-//     let mut fund_data = FundAccount::load_mut_checked(fund_account_ai, program_id)?;
-//     //Check for Mango v3 ID 
-//     check_eq!(*mango_prog_ai.key, mango_v3_id::ID);
-//     check!(manager_ai.is_signer, ProgramError::MissingRequiredSignature);
-//     let nonce = fund_data.signer_nonce;
-//     drop(fund_data);
-    
-//     invoke_signed(
-//         &cancel_perp_order(mango_prog_ai.key,
-//             mango_group_ai.key, mango_account_ai.key, fund_account_ai.key,
-//             perp_market_ai.key, bids_ai.key, asks_ai.key,order_id ,false)?,
-//         &[
-//             mango_prog_ai.clone(),
-//             mango_group_ai.clone(),
-//             mango_account_ai.clone(),
-//             fund_account_ai.clone(),
-//             perp_market_ai.clone(),
-//             bids_ai.clone(),
-//             asks_ai.clone(), 
-//         ],
-//         &[&[&*manager_ai.key.as_ref(), bytes_of(&nonce)]]
-//     )?;
-
-   
-//     Ok(())
-
-// }
-
-
-// pub fn mango_remove_perp_index(
-//     program_id: &Pubkey,
-//     accounts: &[AccountInfo],
-//     perp_market_id: u8
-// ) -> Result<(), ProgramError> {
-//     const NUM_FIXED: usize = 6;
-//     let accounts = array_ref![accounts, 0, NUM_FIXED];
-
-//     let [
-//         fund_account_ai,
-//         manager_ai,
-//         mango_prog_ai,
-//         mango_group_ai,     // read
-//         mango_account_ai,   // write
-//         mango_cache_ai,     // write
-//     ] = accounts;
-
-//     let mut fund_data = FundAccount::load_mut_checked(fund_account_ai, program_id)?;
-//     //Check for Mango v3 ID 
-//     check_eq!(*mango_prog_ai.key, mango_v3_id::ID);
-//     check!(manager_ai.is_signer, ProgramError::MissingRequiredSignature);
-//     check_eq!(*mango_account_ai.key, fund_data.mango_positions.mango_account); //Add this chreck elsewhere
-//     // check_eq!(fund_data.manager_account, *manager_ai.key);
-//     check!((fund_data.manager_account == *manager_ai.key), FundError::ManagerMismatch);
-
-//     let mango_group = MangoGroup::load_checked(mango_group_ai, mango_prog_ai.key)?;
-//     let mango_account = MangoAccount::load_checked(mango_account_ai, mango_prog_ai.key, mango_group_ai.key)?;
-//     let mango_cache = MangoCache::load_checked(mango_cache_ai, mango_prog_ai.key, &mango_group)?;
-    
-//     let (base_val, quote_val) = mango_account.perp_accounts[perp_market_id as usize].get_val(&mango_group.perp_markets[perp_market_id as usize],
-//         &mango_cache.perp_market_cache[perp_market_id as usize], mango_cache.price_cache[perp_market_id as usize].price)?;
-
-//     let perp_pnl = base_val.checked_add(quote_val).unwrap();
-    
-//     let base_pos = mango_account.perp_accounts[perp_market_id as usize].base_position;
-
-//     check!(perp_pnl == I80F48!(0) && base_pos == I80F48!(0), ProgramError::InsufficientFunds);
-
-//     //Check No pending limit orders
-//     for i in 0..MAX_LIMIT_ORDERS {
-//         if fund_data.limit_orders[i].perp_market_id == perp_market_id {
-//         let valid =  mango_account.find_order_with_client_id(fund_data.limit_orders[i].perp_market_id as usize,fund_data.limit_orders[i].client_order_id);
-//         check!(valid == None, FundError::LimitOrderProcessing);
-
-//         }
-//     }
-//     let fund_perp_makret_index = fund_data.get_mango_perp_index(perp_market_id).unwrap();
-//     fund_data.mango_positions.perp_markets[fund_perp_makret_index as usize] = u8::MAX;
-    
-//     //Settle PnL to be executed right after place_perp_order...
-
-//     Ok(())
-
-// }
-
-// //TODO::Update!!!
-// // pub fn mango_settle_pnl(
-// //     program_id: &Pubkey,
-// //     accounts: &[AccountInfo],
-// //     perp_market_id: u8
-// // ) -> Result<(), ProgramError>
-// // {
-// //     const NUM_FIXED: usize = 9;
-// //     let accounts = array_ref![accounts, 0, NUM_FIXED];
-
-// //     let [
-// //         fund_account_ai,
-// //         manager_ai,
-// //         mango_prog_ai,
-// //         mango_group_ai,
-// //         mango_account_a_ai,
-// //         mango_account_b_ai,
-// //         mango_cache_ai,
-// //         root_bank_ai,
-// //         node_bank_ai,
-// //     ] = accounts;
-// //     let mut fund_data = FundAccount::load_mut_checked(fund_account_ai, program_id)?;
-// //     // check!(manager_ai.is_signer, ProgramError::MissingRequiredSignature);
-// //     // check!((fund_data.manager_account == *manager_ai.key), FundError::ManagerMismatch);
-// //     invoke_signed(
-// //         &settle_pnl(mango_prog_ai.key, mango_group_ai.key, mango_account_a_ai.key, mango_account_a_ai.key, 
-// //             mango_cache_ai.key, root_bank_ai.key, node_bank_ai.key, perp_market_id as usize)?,
-// //         &[
-// //             mango_prog_ai.clone(),
-// //             mango_group_ai.clone(),
-// //             mango_account_a_ai.clone(),
-// //             mango_account_b_ai.clone(),
-// //             mango_cache_ai.clone(),
-// //             root_bank_ai.clone(),
-// //             node_bank_ai.clone()
-// //         ],
-// //         &[&[fund_data.manager_account.as_ref(), bytes_of(&fund_data.signer_nonce)]]
-// //     )?;
-// //     Ok(())
-// // }
-
-
-// pub fn mango_withdraw(
-//     program_id: &Pubkey,
-//     accounts: &[AccountInfo],
-//     token_slot_index: u8,
-//     mango_token_index: u8,
-//     quantity: u64
-// ) -> Result<(), ProgramError>
-// {
-//     const NUM_FIXED: usize = 13;
-//     let accounts = array_ref![accounts, 0, NUM_FIXED];
-//     let [
-//         fund_account_ai,
-//         manager_ai,
-//         mango_prog_ai,
-//         mango_group_ai,     // read
-//         mango_account_ai,   // write
-//         mango_cache_ai,     // read
-//         root_bank_ai,       // read
-//         node_bank_ai,       // write
-//         vault_ai,           // write
-//         fund_token_ai,   // write
-//         signer_ai,          // read
-//         token_prog_ai,      // read
-//         default_ai
-//     ] = accounts;
-
-//     let mut fund_data = FundAccount::load_mut_checked(fund_account_ai, program_id)?;
-
-//     check!(fund_data.is_initialized, ProgramError::InvalidAccountData);
-//     check!(manager_ai.is_signer, ProgramError::MissingRequiredSignature);
-//     check!(fund_data.mango_positions.mango_account != Pubkey::default(), FundError::MangoNotInitialized);
-//     //Check for Mango v3 ID 
-//     check_eq!(*mango_prog_ai.key, mango_v3_id::ID);
-
-//     check_eq!(fund_data.tokens[token_slot_index as usize].vault, *fund_token_ai.key);
-
-    
-//     // check_eq!(mango_group.tokens[mango_token_index].root_bank, )
-//     check!((fund_data.manager_account == *manager_ai.key), FundError::ManagerMismatch);
-    
-//     // withdraw USDC from mango account
-//     let open_orders_accs = [Pubkey::default(); MAX_PAIRS];
-//     let nonce = fund_data.signer_nonce;
-//     drop(fund_data);
-//     invoke_signed(
-//         &withdraw(mango_prog_ai.key, mango_group_ai.key, mango_account_ai.key, fund_account_ai.key,
-//             mango_cache_ai.key, root_bank_ai.key, node_bank_ai.key, vault_ai.key, fund_token_ai.key,
-//             signer_ai.key, &open_orders_accs, quantity, false)?,
-//         &[
-//             mango_prog_ai.clone(),
-//             mango_group_ai.clone(),
-//             mango_account_ai.clone(),
-//             fund_account_ai.clone(),
-//             mango_cache_ai.clone(),
-//             root_bank_ai.clone(),
-//             node_bank_ai.clone(),
-//             vault_ai.clone(),
-//             fund_token_ai.clone(),
-//             signer_ai.clone(),
-//             default_ai.clone(),
-//             token_prog_ai.clone()
-//         ],
-//         &[&[&*manager_ai.key.as_ref(), bytes_of(&nonce)]]
-//     )?;
-
-//     msg!("invoke done");
-//     fund_data = FundAccount::load_mut_checked(fund_account_ai, program_id)?;
-//     let dest_info = parse_token_account(fund_token_ai)?;
-//     check_eq!(dest_info.owner, fund_data.fund_pda);
-//     fund_data.tokens[token_slot_index as usize].balance = dest_info.amount;
-//     // let mango_group = MangoGroup::load_checked(mango_group_ai, mango_prog_ai.key)?;
-//     let mango_group = MangoGroup::load_checked(mango_group_ai, mango_prog_ai.key)?;
-//     let mango_account = MangoAccount::load_checked(mango_account_ai, mango_prog_ai.key, mango_group_ai.key)?;
-//     let mango_cache = MangoCache::load_checked(mango_cache_ai, mango_prog_ai.key, &mango_group)?;
-//     // account for native USDC deposits
-//     let usdc_deposits  = mango_account.get_native_deposit(&mango_cache.root_bank_cache[QUOTE_INDEX], QUOTE_INDEX)?;
-    
-//     //.checked_sub(mango_account.get_native_borrow(&mango_cache.root_bank_cache[QUOTE_INDEX], QUOTE_INDEX)?).unwrap();
-//     check!(I80F48::from_num(fund_data.mango_positions.investor_debts[0]) <= usdc_deposits, ProgramError::InsufficientFunds);
-//     // .checked_sub(I80F48::from_num(fund_data.mango_positions.investor_debts[0])).unwrap();
-//     // if mango_token_index as usize != QUOTE_INDEX {
-//     //     check!(deposits_after >= fund_data.mango_positions.investor_debts[1] , FundError::InvalidAmount);
-//     //     if deposits_after < 0.00001 {
-//     //         fund_data.mango_positions.deposit_index = u8::MAX;
-//     //         fund_data.tokens[token_slot_index as usize].is_on_mango = 0;
-//     //     }
-//     // } else {
-//         // check!(deposits_after >= fund_data.mango_positions.investor_debts[0] , FundError::InvalidAmount);
-//     // }
-    
-//     // fund_data.tokens[0].balance = parse_token_account(fund_token_ai)?.amount;
-
-//     Ok(())
-// }
-
-// // pub fn mango_place_spot_order2(
-// //     program_id: &Pubkey,
-// //     accounts: &[AccountInfo],
-// //     side: u8,
-// //     price: u64,
-// //     trade_size: u64,
-// //     call_init: bool
-// // ) -> Result<(), ProgramError> {
-// //     const NUM_FIXED: usize = 26;
-// //     let fixed_ais = array_ref![accounts, 0, NUM_FIXED];
-// //     let [
-// //         fund_account_ai,
-// //         manager_ai,
-// //         mango_prog_ai,
-// //         mango_group_ai,         // read
-// //         mango_account_ai,       // write
-// //         mango_cache_ai,         // read
-// //         dex_prog_ai,            // read
-// //         spot_market_ai,         // write
-// //         bids_ai,                // write
-// //         asks_ai,                // write
-// //         dex_request_queue_ai,   // write
-// //         dex_event_queue_ai,     // write
-// //         dex_base_ai,            // write
-// //         dex_quote_ai,           // write
-// //         base_root_bank_ai,      // read
-// //         base_node_bank_ai,      // write
-// //         base_vault_ai,          // write
-// //         quote_root_bank_ai,     // read
-// //         quote_node_bank_ai,     // write
-// //         quote_vault_ai,         // write
-// //         token_prog_ai,          // read
-// //         signer_ai,              // read
-// //         dex_signer_ai,          // read
-// //         msrm_or_srm_vault_ai,   // read
-// //         packed_open_orders_ais,
-// //         rent_ai
-// //     ] = fixed_ais;
-
-// //     let fund_data = FundAccount::load_mut_checked(fund_account_ai, program_id)?;
-// //     check!(fund_data.is_initialized, ProgramError::InvalidAccountData);
-// //     // check!(manager_ai.is_signer, ProgramError::MissingRequiredSignature);
-// //     check!(fund_data.mango_positions.mango_account != Pubkey::default(), FundError::MangoNotInitialized);
-// //     //Check for Mango v3 ID 
-// //     check_eq!(*mango_prog_ai.key, mango_v3_id::ID);
-// //     //TODO Check for qotue params to match USDC
-// //     //drop(fund_data);
-// //     if call_init {
-// //         invoke_signed(
-// //             &init_spot_open_orders(
-// //                 mango_prog_ai.key,
-// //                 mango_group_ai.key, 
-// //                 mango_account_ai.key, 
-// //                 fund_account_ai.key, 
-// //                 dex_prog_ai.key,
-// //                 packed_open_orders_ais.key, 
-// //                 spot_market_ai.key, 
-// //                 signer_ai.key
-// //             )?,
-// //             &[
-// //                 mango_prog_ai.clone(),
-// //                 mango_group_ai.clone(),
-// //                 mango_account_ai.clone(),
-// //                 fund_account_ai.clone(),
-// //                 dex_prog_ai.clone(),
-// //                 packed_open_orders_ais.clone(),
-// //                 spot_market_ai.clone(),
-// //                 signer_ai.clone(),
-// //                 rent_ai.clone()
-// //             ], 
-// //             &[&[&*manager_ai.key.as_ref(), bytes_of(&fund_data.signer_nonce)]]
-// //         )?;
-// //     }
-    
-// //     let coin_lots = convert_size_to_lots(spot_market_ai, dex_prog_ai.key, trade_size, false)?;
-// //     msg!("coin_lots:: {:?} ", coin_lots);
-
-// //     let pc_qty = convert_size_to_lots(spot_market_ai, dex_prog_ai.key, trade_size * price, true)?;
-// //     msg!("pc_qty:: {:?}", pc_qty);
-// //     let fee_rate:U64F64 = U64F64!(0.0022); // fee_bps = 22; BASE
-
-// //     let exact_fee: u64 = U64F64::to_num(fee_rate.checked_mul(U64F64::from_num(pc_qty)).unwrap());
-
-// //     let pc_qty_including_fees = pc_qty + exact_fee;
-// //     msg!("pc_qty:: {:?}", pc_qty_including_fees);
-
-// //     let order_side = serum_dex::matching::Side::try_from_primitive(side.try_into().unwrap()).unwrap();
-// //     // let mut open_orders_accs = [Pubkey::default(); MAX_PAIRS];
-// //     // open_orders_accs[3] = *packed_open_orders_ais[3].key;
-// //     let order: NewOrderInstructionV3 = NewOrderInstructionV3 {
-// //         side: order_side,
-// //         limit_price: NonZeroU64::new(price).unwrap(),
-// //         max_coin_qty: NonZeroU64::new(coin_lots).unwrap(),
-// //         max_native_pc_qty_including_fees: NonZeroU64::new(pc_qty_including_fees).unwrap(),
-// //         self_trade_behavior: SelfTradeBehavior::AbortTransaction,
-// //         order_type: SerumOrderType::ImmediateOrCancel,
-// //         client_order_id: 1,
-// //         limit: 65535,
-// //     };
-
-// //         invoke_signed(
-// //             &place_spot_order2(
-// //                 mango_prog_ai.key,
-// //                 mango_group_ai.key, 
-// //                 mango_account_ai.key, 
-// //                 fund_account_ai.key, 
-// //                 mango_cache_ai.key, 
-// //                 dex_prog_ai.key, 
-// //                 spot_market_ai.key, 
-// //                 bids_ai.key, 
-// //                 asks_ai.key, 
-// //                 dex_request_queue_ai.key, 
-// //                 dex_event_queue_ai.key, 
-// //                 dex_base_ai.key, 
-// //                 dex_quote_ai.key, 
-// //                 base_root_bank_ai.key, 
-// //                 base_node_bank_ai.key, 
-// //                 base_vault_ai.key, 
-// //                 quote_root_bank_ai.key, 
-// //                 quote_node_bank_ai.key, 
-// //                 quote_vault_ai.key, 
-// //                 signer_ai.key, 
-// //                 dex_signer_ai.key, 
-// //                 msrm_or_srm_vault_ai.key, 
-// //                 &[*packed_open_orders_ais.key],
-// //                 order)?,
-// //             &[
-// //                 mango_prog_ai.clone(),
-// //                 mango_group_ai.clone(), 
-// //                 mango_account_ai.clone(), 
-// //                 fund_account_ai.clone(), 
-// //                 mango_cache_ai.clone(), 
-// //                 dex_prog_ai.clone(), 
-// //                 spot_market_ai.clone(), 
-// //                 bids_ai.clone(), 
-// //                 asks_ai.clone(), 
-// //                 dex_request_queue_ai.clone(), 
-// //                 dex_event_queue_ai.clone(), 
-// //                 dex_base_ai.clone(), 
-// //                 dex_quote_ai.clone(), 
-// //                 base_root_bank_ai.clone(), 
-// //                 base_node_bank_ai.clone(), 
-// //                 base_vault_ai.clone(), 
-// //                 quote_root_bank_ai.clone(), 
-// //                 quote_node_bank_ai.clone(), 
-// //                 quote_vault_ai.clone(), 
-// //                 signer_ai.clone(), 
-// //                 dex_signer_ai.clone(), 
-// //                 msrm_or_srm_vault_ai.clone(),
-// //                 packed_open_orders_ais.clone(),
-// //             ],
-// //             &[&[fund_data.manager_account.as_ref(), bytes_of(&fund_data.signer_nonce)]]
-// //         )?;
-
-// //     Ok(())
-// // }
-
-// pub fn mango_withdraw_investor(
-//     program_id: &Pubkey,
-//     accounts: &[AccountInfo],
-// ) -> Result<(), ProgramError> {
-//     const NUM_FIXED: usize = 18;
-//     let accounts = array_ref![accounts, 0, NUM_FIXED];
-//     let [
-//         fund_account_ai,      //write
-//         investor_state_ai,  //write
-//         investor_ai,        //signer
-//         mango_prog_ai,      //
-//         mango_group_ai,     // read
-//         mango_account_ai,   // write
-//         mango_cache_ai,     // read
-//         usdc_root_bank_ai,       // read
-//         usdc_node_bank_ai,       // write
-//         usdc_vault_ai,           // write
-//         usdc_investor_token_ai,   // write
-//         token_root_bank_ai,       // read
-//         token_node_bank_ai,       // write
-//         token_vault_ai,           // write
-//         token_investor_token_ai,   // write
-//         signer_ai,          // read
-//         token_prog_ai,      // read
-//         default_ai
-//     ] = accounts;
-//     let mut fund_data = FundAccount::load_mut_checked(fund_account_ai, program_id)?;
-//     let mut investor_data = InvestorData::load_mut_checked(investor_state_ai, program_id)?;
-
-//     check!(investor_data.owner == *investor_ai.key, ProgramError::MissingRequiredSignature);
-//     check!(investor_ai.is_signer, ProgramError::MissingRequiredSignature);
-//     check_eq!(investor_data.manager, fund_data.manager_account);
-//     check!(investor_data.has_withdrawn == true && investor_data.withdrawn_from_margin == false, FundError::InvalidStateAccount);
-//     check_eq!(*mango_prog_ai.key, mango_v3_id::ID);
-//     fund_data.mango_positions.investor_debts[0] = fund_data.mango_positions.investor_debts[0].checked_sub(U64F64::to_num(investor_data.margin_debt[0])).unwrap();
-//     let manager_account = fund_data.manager_account;
-//     let nonce = fund_data.signer_nonce;
-//     let open_orders_accs = [Pubkey::default(); MAX_PAIRS];
-//     let usdc_quantity:u64 =  U64F64::to_num(investor_data.margin_debt[0]);
-//     msg!("usdc {:?}", usdc_quantity);
-//     drop(fund_data);
-//     if usdc_quantity > 0 {
-//         invoke_signed(
-//             &withdraw(mango_prog_ai.key, mango_group_ai.key, mango_account_ai.key, fund_account_ai.key,
-//                 mango_cache_ai.key, usdc_root_bank_ai.key, usdc_node_bank_ai.key, usdc_vault_ai.key, usdc_investor_token_ai.key,
-//                 signer_ai.key, &open_orders_accs, usdc_quantity, false)?,
-//             &[
-//                 mango_prog_ai.clone(),
-//                 mango_group_ai.clone(),
-//                 mango_account_ai.clone(),
-//                 fund_account_ai.clone(),
-//                 mango_cache_ai.clone(),
-//                 usdc_root_bank_ai.clone(),
-//                 usdc_node_bank_ai.clone(),
-//                 usdc_vault_ai.clone(),
-//                 usdc_investor_token_ai.clone(),
-//                 signer_ai.clone(),
-//                 default_ai.clone(),
-//                 token_prog_ai.clone()
-//             ],
-//             &[&[bytes_of(&manager_account), bytes_of(&nonce)]]
-//         )?;
-//     }
-
-//     // let token_quantity:u64 =  U64F64::to_num(investor_data.margin_debt[1]);
-//     // if token_quantity > 0 {
-//     //     invoke_signed(
-//     //         &withdraw(mango_prog_ai.key, mango_group_ai.key, mango_account_ai.key, fund_account_ai.key,
-//     //             mango_cache_ai.key, token_root_bank_ai.key, token_node_bank_ai.key, token_vault_ai.key, token_investor_token_ai.key,
-//     //             signer_ai.key, &open_orders_accs, token_quantity, false)?,
-//     //         &[
-//     //             mango_prog_ai.clone(),
-//     //             mango_group_ai.clone(),
-//     //             mango_account_ai.clone(),
-//     //             fund_account_ai.clone(),
-//     //             mango_cache_ai.clone(),
-//     //             token_root_bank_ai.clone(),
-//     //             token_node_bank_ai.clone(),
-//     //             token_vault_ai.clone(),
-//     //             token_investor_token_ai.clone(),
-//     //             signer_ai.clone(),
-//     //             default_ai.clone(),
-//     //             token_prog_ai.clone()
-//     //         ],
-//     //         &[&[fund_data.manager_account.as_ref(), bytes_of(&fund_data.signer_nonce)]]
-//     //     )?;
-//     // }
-    
-//     msg!("invoke done");
-//     // fund_data.mango_positions.investor_debts[1] = fund_data.mango_positions.investor_debts[1].checked_sub(U64F64::to_num(investor_data.margin_debt[1])).unwrap();
-//     investor_data.margin_debt = [ZERO_U64F64; 2];
-//     investor_data.withdrawn_from_margin = true;
-//     Ok(())
-// }
-
-// // pub fn convert_size_to_lots(
-// //     spot_market_acc: &AccountInfo,
-// //     dex_program_id: &Pubkey,
-// //     size: u64,
-// //     pc: bool
-// // ) -> Result <u64, ProgramError> {
-// //     let market = MarketState::load(spot_market_acc, dex_program_id)?;
-// //     if pc {
-// //         Ok(size * market.pc_lot_size / market.coin_lot_size)
-// //     }
-// //     else {
-// //         Ok(size / market.coin_lot_size)
-// //     }
-// // }
-
-// // pub fn convert_size_to_lots(
-// //     spot_market_acc: &AccountInfo,
-// //     dex_program_id: &Pubkey,
-// //     size: u64,
-// //     pc: bool
-// // ) -> Result <u64, ProgramError> {
-// //     let market = MarketState::load(spot_market_ai, dex_program_id)?;
-// //     if pc {
-// //         Ok(size * market.pc_lot_size / market.coin_lot_size)
-// //     }
-// //     else {
-// //         Ok(size / market.coin_lot_size)
-// //     }
-// // }
-
-// // fn get_withdraw_lots(
-// //     spot_market_acc: &AccountInfo,
-// //     dex_program_id: &Pubkey,
-// //     size: u64,
-// //     side: u8,
-// // ) -> Result <u64, ProgramError> {
-// //     let market = MarketState::load(spot_market_ai, dex_program_id)?;
-// //     Ok((size / market.coin_lot_size) + side as u64)
-// // }
-
-// // pub fn get_investor_withdraw_lots(
-// //     spot_market_acc: &AccountInfo,
-// //     dex_program_id: &Pubkey,
-// //     size: u64,
-// //     pos_size: u64,
-// //     side: u8
-// // ) -> Result <u64, ProgramError> {
-// //     let market = MarketState::load(spot_market_ai, dex_program_id)?;
-// //     //if size + market.coin_lot_size > pos_size {
-// //     if (pos_size - size) / market.coin_lot_size == 0 {
-// //         Ok((size / market.coin_lot_size) + side as u64) // same as manager close case
-// //     }
-// //     else {
-// //         Ok((size / market.coin_lot_size) + 1)
-// //     }
-// //     // Ok((size / market.coin_lot_size) + side as u64)
-// // }
-
-// // pub fn update_investor_debts(
-// //     fund_data: &FundAccount,
-// //     investor_accs: &[AccountInfo],
-// //     withdraw_amount: u64,
-// //     index: usize
-// // ) -> Result<(u64, U64F64), ProgramError> {
-    
-// //     let mut debts: u64 = 0;
-// //     let mut debts_share = U64F64!(0);
-
-// //     for i in 0..MAX_INVESTORS_WITHDRAW {
-// //         if *investor_accs[i].key == Pubkey::default() {
-// //             continue;
-// //         }
-// //         let mut investor_data = InvestorData::load_mut(&investor_accs[i])?;
-// //         if investor_data.margin_position_id[index] == fund_data.mango_positions[index].position_id as u64 {
-// //             // update
-// //             let debt_valuation: u64 = U64F64::to_num(U64F64::from_num(withdraw_amount)
-// //             .checked_mul(investor_data.margin_debt[index] / fund_data.mango_positions[index].share_ratio).unwrap());
-// //             debts += debt_valuation;
-// //             debts_share += investor_data.margin_debt[index] / fund_data.mango_positions[index].share_ratio;
-
-// //             // update investor debts; add to USDC debt
-// //             investor_data.margin_debt[index] = U64F64!(0);
-// //             investor_data.token_debts[0] += debt_valuation;
-// //             investor_data.has_withdrawn = true;
-// //             investor_data.withdrawn_from_margin = false;
-// //             investor_data.margin_position_id[index] = 0; // remove position id
-// //         }
-// //     }
-// //     Ok((debts, debts_share))
-// // }
-
-
-
-
+use bytemuck::bytes_of;
+use arrayref::array_ref;
+use fixed::types::{I80F48, U64F64};
+
+use solana_program::{
+    account_info::{AccountInfo, next_account_info},
+    instruction::{AccountMeta, Instruction},
+    msg,
+    program::invoke_signed,
+    program_error::ProgramError,
+    pubkey::Pubkey,
+    sysvar::{Sysvar, clock::Clock, instructions::{load_current_index_checked, load_instruction_at_checked}},
+};
+
+use crate::error::FundError;
+use crate::state::{NUM_PERP, FundAccount, InvestorData, PlatformData};
+use crate::jup_utils::{get_slot_price, get_slippage_bps};
+use crate::processor::parse_token_account;
+use crate::processor::update_amount_and_performance;
+use crate::instruction::FundInstruction;
+use crate::data_utils::{read_pubkey, read_u16, read_u32, read_i64, read_i80f48};
+use crate::nav_utils::find_nav_history;
+use crate::exit_utils::{refresh_exit_status, take_exit_deficit};
+
+macro_rules! check {
+    ($cond:expr, $err:expr) => {
+        if !($cond) {
+            return Err(($err).into())
+        }
+    }
+}
+macro_rules! check_eq {
+    ($x:expr, $y:expr) => {
+        if ($x != $y) {
+            return Err(FundError::Default.into())
+        }
+    }
+}
+
+pub mod mango_v4_id {
+    use solana_program::declare_id;
+    declare_id!("4MangoMjqJ2firMokCjjGgoK8d4MXcrgL7XJaL3w6fVg");
+}
+
+// anchor discriminators, sha256("global:<name>")[..8]
+const ACCOUNT_CREATE: [u8; 8] = [198, 95, 39, 197, 41, 214, 157, 18];
+const TOKEN_DEPOSIT: [u8; 8] = [117, 255, 154, 71, 245, 58, 95, 89];
+const TOKEN_WITHDRAW: [u8; 8] = [63, 223, 42, 59, 15, 128, 102, 66];
+const PERP_PLACE_ORDER: [u8; 8] = [189, 196, 225, 201, 114, 172, 25, 166];
+const PERP_CANCEL_ORDER_BY_CLIENT_ORDER_ID: [u8; 8] = [74, 250, 56, 79, 206, 173, 163, 102];
+const PERP_CANCEL_ALL_ORDERS: [u8; 8] = [96, 16, 226, 181, 107, 145, 224, 213];
+const PERP_SETTLE_PNL: [u8; 8] = [245, 98, 85, 179, 230, 215, 130, 57];
+
+// the fund's account only holds USDC collateral and perps settled in USDC
+pub const MANGO_QUOTE_TOKEN_INDEX: u16 = 0;
+const TOKEN_COUNT: u8 = 4;
+const PERP_OO_COUNT: u8 = 8;
+const ORDER_LIMIT: u8 = 10;
+// PlaceOrderType::ImmediateOrCancel
+const ORDER_TYPE_IOC: u8 = 1;
+
+// MangoAccount: 400 byte fixed part, then header version and padding, then u32 padded vecs
+const ACCOUNT_GROUP_OFFSET: usize = 8;
+const ACCOUNT_OWNER_OFFSET: usize = 40;
+const ACCOUNT_TOKENS_LEN_OFFSET: usize = 420;
+const TOKEN_POSITION_SIZE: usize = 184;
+const TOKEN_POSITION_INDEX_OFFSET: usize = 16;
+const PERP_POSITION_SIZE: usize = 304;
+const PERP_POSITION_BASE_LOTS_OFFSET: usize = 16;
+const PERP_POSITION_QUOTE_OFFSET: usize = 24;
+const PERP_POSITION_LONG_SETTLED_FUNDING_OFFSET: usize = 48;
+const PERP_POSITION_SHORT_SETTLED_FUNDING_OFFSET: usize = 64;
+const PERP_POSITION_BIDS_OFFSET: usize = 80;
+const PERP_POSITION_ASKS_OFFSET: usize = 88;
+
+// Bank
+const BANK_GROUP_OFFSET: usize = 8;
+const BANK_MINT_OFFSET: usize = 56;
+const BANK_DEPOSIT_INDEX_OFFSET: usize = 536;
+const BANK_BORROW_INDEX_OFFSET: usize = 552;
+
+// PerpMarket
+const PERP_GROUP_OFFSET: usize = 8;
+const PERP_SETTLE_TOKEN_INDEX_OFFSET: usize = 40;
+const PERP_MARKET_INDEX_OFFSET: usize = 42;
+const PERP_BASE_DECIMALS_OFFSET: usize = 47;
+const PERP_QUOTE_LOT_SIZE_OFFSET: usize = 576;
+const PERP_BASE_LOT_SIZE_OFFSET: usize = 584;
+const PERP_LONG_FUNDING_OFFSET: usize = 720;
+const PERP_SHORT_FUNDING_OFFSET: usize = 736;
+
+// perp positions follow the token positions, the fund's account is created without serum3 slots
+fn perp_positions_offset(data: &[u8]) -> Result<(usize, usize), ProgramError> {
+    let tokens_end = ACCOUNT_TOKENS_LEN_OFFSET + 4 + read_u32(data, ACCOUNT_TOKENS_LEN_OFFSET)? as usize * TOKEN_POSITION_SIZE;
+    // padding, serum3 len, padding, perps len
+    check_eq!(read_u32(data, tokens_end + 4)?, 0);
+    let perp_count = read_u32(data, tokens_end + 12)?;
+    Ok((tokens_end + 16, perp_count as usize))
+}
+
+fn find_perp_position(data: &[u8], market_index: u8) -> Result<Option<usize>, ProgramError> {
+    let (start, count) = perp_positions_offset(data)?;
+    for i in 0..count {
+        let offset = start + i * PERP_POSITION_SIZE;
+        if read_u16(data, offset)? == market_index as u16 {
+            return Ok(Some(offset));
+        }
+    }
+    Ok(None)
+}
+
+/// (base lots, quote native, resting bid and ask lots) of the account's position on a perp market
+fn get_perp_position(data: &[u8], market_index: u8) -> Result<(i64, I80F48, i64), ProgramError> {
+    match find_perp_position(data, market_index)? {
+        Some(offset) => {
+            let open_lots = read_i64(data, offset + PERP_POSITION_BIDS_OFFSET)?.checked_add(read_i64(data, offset + PERP_POSITION_ASKS_OFFSET)?).unwrap();
+            Ok((
+                read_i64(data, offset + PERP_POSITION_BASE_LOTS_OFFSET)?,
+                read_i80f48(data, offset + PERP_POSITION_QUOTE_OFFSET)?,
+                open_lots
+            ))
+        }
+        None => Ok((0, I80F48::from_num(0), 0))
+    }
+}
+
+/// Funding accrued on the position since mango last settled it, in quote native.
+/// Positive is owed by the account, mango takes it from the quote position on the next settle.
+fn get_unsettled_funding(data: &[u8], market_index: u8, perp_market_ai: &AccountInfo) -> Result<I80F48, ProgramError> {
+    let offset = match find_perp_position(data, market_index)? {
+        Some(offset) => offset,
+        None => return Ok(I80F48::from_num(0))
+    };
+    let base_lots = read_i64(data, offset + PERP_POSITION_BASE_LOTS_OFFSET)?;
+    let market_data = perp_market_ai.try_borrow_data()?;
+    let (funding, settled_funding) = if base_lots >= 0 {
+        (read_i80f48(&market_data, PERP_LONG_FUNDING_OFFSET)?, read_i80f48(data, offset + PERP_POSITION_LONG_SETTLED_FUNDING_OFFSET)?)
+    } else {
+        (read_i80f48(&market_data, PERP_SHORT_FUNDING_OFFSET)?, read_i80f48(data, offset + PERP_POSITION_SHORT_SETTLED_FUNDING_OFFSET)?)
+    };
+    Ok(funding.checked_sub(settled_funding).unwrap().checked_mul(I80F48::from_num(base_lots)).unwrap())
+}
+
+fn check_mango_account(fund_data: &FundAccount, mango_account_ai: &AccountInfo) -> Result<(), ProgramError> {
+    check!(fund_data.mango_positions.is_active, FundError::InvalidStateAccount);
+    check_eq!(fund_data.mango_positions.mango_account, *mango_account_ai.key);
+    check_eq!(*mango_account_ai.owner, mango_v4_id::ID);
+    Ok(())
+}
+
+// the fund's USDC bank in the account's group
+fn check_quote_bank(platform_data: &PlatformData, fund_data: &FundAccount, group: &Pubkey, bank_ai: &AccountInfo) -> Result<(), ProgramError> {
+    check_eq!(*bank_ai.owner, mango_v4_id::ID);
+    let data = bank_ai.try_borrow_data()?;
+    check_eq!(read_pubkey(&data, BANK_GROUP_OFFSET)?, *group);
+    check_eq!(read_pubkey(&data, BANK_MINT_OFFSET)?, platform_data.token_list[fund_data.tokens[0].get_active_index()].mint);
+    Ok(())
+}
+
+fn invoke_mango<'a>(
+    fund_data: &FundAccount,
+    mango_prog_ai: &AccountInfo<'a>,
+    metas: Vec<AccountMeta>,
+    data: Vec<u8>,
+    account_infos: &[AccountInfo<'a>],
+) -> Result<(), ProgramError> {
+    check_eq!(*mango_prog_ai.key, mango_v4_id::ID);
+    let manager_account = fund_data.manager_account;
+    let nonce = fund_data.signer_nonce;
+    let mut infos = account_infos.to_vec();
+    infos.push(mango_prog_ai.clone());
+    invoke_signed(
+        &Instruction { program_id: mango_v4_id::ID, accounts: metas, data },
+        &infos,
+        &[&[manager_account.as_ref(), bytes_of(&nonce)]]
+    )
+}
+
+// health accounts Mango expects after the instruction's own accounts
+fn health_metas(health_accs: &[AccountInfo]) -> Vec<AccountMeta> {
+    health_accs.iter().map(|ai| AccountMeta::new_readonly(*ai.key, false)).collect()
+}
+
+/// Creates the fund's Mango v4 account, owned by the fund PDA
+///
+/// Accounts expected
+/// 0. [WRITE]  Fund State Account
+/// 1. [SIGNER, WRITE] Manager Account, pays for the mango account
+/// 2. []   Mango v4 Program
+/// 3. []   Mango Group
+/// 4. [WRITE]  Mango Account, the mango PDA for the fund and `account_num`
+/// 5. []   System Program
+pub fn init_mango_account(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    account_num: u32
+) -> Result<(), ProgramError> {
+    const NUM_FIXED: usize = 6;
+    check!(accounts.len() >= NUM_FIXED, ProgramError::NotEnoughAccountKeys);
+    let [
+        fund_account_ai,
+        manager_ai,
+        mango_prog_ai,
+        mango_group_ai,
+        mango_account_ai,
+        system_prog_ai,
+    ] = array_ref![accounts, 0, NUM_FIXED];
+
+    check!(manager_ai.is_signer, ProgramError::MissingRequiredSignature);
+    let fund_data = FundAccount::load_checked(fund_account_ai, program_id)?;
+    check!(fund_data.manager_account == *manager_ai.key, FundError::ManagerMismatch);
+    check!(!fund_data.mango_positions.is_active, FundError::InvalidStateAccount);
+
+    let mut data = ACCOUNT_CREATE.to_vec();
+    data.extend_from_slice(&account_num.to_le_bytes());
+    data.extend_from_slice(&[TOKEN_COUNT, 0, NUM_PERP as u8, PERP_OO_COUNT]);
+    let name = b"investin fund";
+    data.extend_from_slice(&(name.len() as u32).to_le_bytes());
+    data.extend_from_slice(name);
+    invoke_mango(
+        &fund_data,
+        mango_prog_ai,
+        vec![
+            AccountMeta::new_readonly(*mango_group_ai.key, false),
+            AccountMeta::new(*mango_account_ai.key, false),
+            AccountMeta::new_readonly(*fund_account_ai.key, true),
+            AccountMeta::new(*manager_ai.key, true),
+            AccountMeta::new_readonly(*system_prog_ai.key, false),
+        ],
+        data,
+        &[mango_group_ai.clone(), mango_account_ai.clone(), fund_account_ai.clone(), manager_ai.clone(), system_prog_ai.clone()]
+    )?;
+    drop(fund_data);
+
+    let mut fund_data = FundAccount::load_mut_checked(fund_account_ai, program_id)?;
+    check_eq!(read_pubkey(&mango_account_ai.try_borrow_data()?, ACCOUNT_OWNER_OFFSET)?, *fund_account_ai.key);
+    fund_data.mango_positions.mango_account = *mango_account_ai.key;
+    fund_data.mango_positions.perp_markets = [u8::MAX; NUM_PERP];
+    fund_data.mango_positions.is_active = true;
+    fund_data.mango_positions.equity = 0;
+    fund_data.mango_positions.last_updated = Clock::get()?.unix_timestamp;
+    Ok(())
+}
+
+/// Enables a perp market settled in USDC, its base priced by the token slot `price_slot`
+///
+/// Accounts expected
+/// 0. [WRITE]  Fund State Account
+/// 1. [SIGNER] Manager Account
+/// 2. []   Platform State Account
+/// 3. []   Mango Account
+/// 4. []   Perp Market
+pub fn add_mango_perp_market(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    price_slot: u8
+) -> Result<(), ProgramError> {
+    const NUM_FIXED: usize = 5;
+    check!(accounts.len() >= NUM_FIXED, ProgramError::NotEnoughAccountKeys);
+    let [
+        fund_account_ai,
+        manager_ai,
+        platform_ai,
+        mango_account_ai,
+        perp_market_ai,
+    ] = array_ref![accounts, 0, NUM_FIXED];
+
+    check!(manager_ai.is_signer, ProgramError::MissingRequiredSignature);
+    let platform_data = PlatformData::load_checked(platform_ai, program_id)?;
+    let mut fund_data = FundAccount::load_mut_checked(fund_account_ai, program_id)?;
+    check!(fund_data.manager_account == *manager_ai.key, FundError::ManagerMismatch);
+    check_mango_account(&fund_data, mango_account_ai)?;
+    let ps = price_slot as usize;
+    check!(ps > 0 && ps < fund_data.tokens.len() && fund_data.tokens[ps].is_active, FundError::InvalidTokenAccount);
+
+    check_eq!(*perp_market_ai.owner, mango_v4_id::ID);
+    let perp_data = perp_market_ai.try_borrow_data()?;
+    check_eq!(read_pubkey(&perp_data, PERP_GROUP_OFFSET)?, read_pubkey(&mango_account_ai.try_borrow_data()?, ACCOUNT_GROUP_OFFSET)?);
+    check_eq!(read_u16(&perp_data, PERP_SETTLE_TOKEN_INDEX_OFFSET)?, MANGO_QUOTE_TOKEN_INDEX);
+    let market_index = read_u16(&perp_data, PERP_MARKET_INDEX_OFFSET)?;
+    check!(market_index < u8::MAX as u16, FundError::InvalidStateAccount);
+    let base_decimals = *perp_data.get(PERP_BASE_DECIMALS_OFFSET).ok_or(ProgramError::InvalidAccountData)?;
+    check_eq!(base_decimals as u64, platform_data.token_list[fund_data.tokens[ps].get_active_index()].decimals);
+
+    let mango = &mut fund_data.mango_positions;
+    check!(!mango.perp_markets.contains(&(market_index as u8)), FundError::InvalidStateAccount);
+    let i = mango.perp_markets.iter().position(|m| *m == u8::MAX).ok_or(FundError::InvalidStateAccount)?;
+    mango.perp_markets[i] = market_index as u8;
+    mango.perp_price_slots[i] = price_slot;
+    Ok(())
+}
+
+/// Frees the perp market's slot once the position is closed
+///
+/// Accounts expected
+/// 0. [WRITE]  Fund State Account
+/// 1. [SIGNER] Manager Account
+/// 2. []   Mango Account
+pub fn remove_mango_perp_market(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    perp_slot: u8
+) -> Result<(), ProgramError> {
+    let accounts_iter = &mut accounts.iter();
+    let fund_account_ai = next_account_info(accounts_iter)?;
+    let manager_ai = next_account_info(accounts_iter)?;
+    let mango_account_ai = next_account_info(accounts_iter)?;
+
+    check!(manager_ai.is_signer, ProgramError::MissingRequiredSignature);
+    let mut fund_data = FundAccount::load_mut_checked(fund_account_ai, program_id)?;
+    check!(fund_data.manager_account == *manager_ai.key, FundError::ManagerMismatch);
+    check_mango_account(&fund_data, mango_account_ai)?;
+    let i = perp_slot as usize;
+    check!(i < NUM_PERP && fund_data.mango_positions.perp_markets[i] != u8::MAX, FundError::InvalidStateAccount);
+    let (base_lots, _, open_lots) = get_perp_position(&mango_account_ai.try_borrow_data()?, fund_data.mango_positions.perp_markets[i])?;
+    check!(base_lots == 0 && open_lots == 0, FundError::InvalidStateAccount);
+    fund_data.mango_positions.perp_markets[i] = u8::MAX;
+    fund_data.mango_positions.perp_price_slots[i] = 0;
+    Ok(())
+}
+
+/// Moves USDC between the fund's vault and its mango account. Withdrawals keep what investors
+/// are owed from the account and need a recent UpdateMangoValue.
+///
+/// Accounts expected
+/// 0. [WRITE]  Fund State Account
+/// 1. [SIGNER] Manager Account
+/// 2. []   Platform State Account
+/// 3. []   Mango v4 Program
+/// 4. []   Mango Group
+/// 5. [WRITE]  Mango Account
+/// 6. [WRITE]  USDC Bank
+/// 7. [WRITE]  USDC Bank Vault
+/// 8. []   USDC Oracle
+/// 9. [WRITE]  Fund USDC Vault
+/// 10. []  Token Program
+/// 11.. []  Mango health accounts
+pub fn move_mango_collateral(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    amount: u64,
+    is_deposit: bool
+) -> Result<(), ProgramError> {
+    const NUM_FIXED: usize = 11;
+    check!(accounts.len() >= NUM_FIXED, ProgramError::NotEnoughAccountKeys);
+    let (fixed_accs, health_accs) = accounts.split_at(NUM_FIXED);
+    let [
+        fund_account_ai,
+        manager_ai,
+        platform_ai,
+        mango_prog_ai,
+        mango_group_ai,
+        mango_account_ai,
+        bank_ai,
+        bank_vault_ai,
+        oracle_ai,
+        fund_vault_ai,
+        token_prog_ai,
+    ] = array_ref![fixed_accs, 0, NUM_FIXED];
+
+    check!(manager_ai.is_signer, ProgramError::MissingRequiredSignature);
+    check!(amount > 0, ProgramError::InvalidArgument);
+    check_eq!(*token_prog_ai.key, spl_token::ID);
+    let platform_data = PlatformData::load_checked(platform_ai, program_id)?;
+    let fund_data = FundAccount::load_checked(fund_account_ai, program_id)?;
+    check!(fund_data.manager_account == *manager_ai.key, FundError::ManagerMismatch);
+    check_mango_account(&fund_data, mango_account_ai)?;
+    check_eq!(fund_data.tokens[0].vault, *fund_vault_ai.key);
+    check_quote_bank(&platform_data, &fund_data, mango_group_ai.key, bank_ai)?;
+    let mango = fund_data.mango_positions;
+    if is_deposit {
+        check!(fund_data.tokens[0].balance.checked_sub(fund_data.tokens[0].debt).unwrap() >= amount, ProgramError::InsufficientFunds);
+    } else {
+        check!(Clock::get()?.unix_timestamp - mango.last_updated <= 100, FundError::PriceStaleInAccount);
+        check!(mango.equity.checked_sub(amount as i64).unwrap() >= mango.investor_debts[0] as i64, ProgramError::InsufficientFunds);
+    }
+
+    let mut metas = vec![
+        AccountMeta::new_readonly(*mango_group_ai.key, false),
+        AccountMeta::new(*mango_account_ai.key, false),
+        AccountMeta::new_readonly(*fund_account_ai.key, true),
+        AccountMeta::new(*bank_ai.key, false),
+        AccountMeta::new(*bank_vault_ai.key, false),
+        AccountMeta::new_readonly(*oracle_ai.key, false),
+        AccountMeta::new(*fund_vault_ai.key, false),
+    ];
+    let mut data = if is_deposit { TOKEN_DEPOSIT.to_vec() } else { TOKEN_WITHDRAW.to_vec() };
+    data.extend_from_slice(&amount.to_le_bytes());
+    // reduce_only on deposits, allow_borrow on withdrawals
+    data.push(0);
+    if is_deposit {
+        metas.push(AccountMeta::new_readonly(*fund_account_ai.key, true));
+    }
+    metas.push(AccountMeta::new_readonly(*token_prog_ai.key, false));
+    metas.extend(health_metas(health_accs));
+    let mut infos = fixed_accs.to_vec();
+    infos.extend_from_slice(health_accs);
+    invoke_mango(&fund_data, mango_prog_ai, metas, data, &infos)?;
+    drop(fund_data);
+
+    let mut fund_data = FundAccount::load_mut_checked(fund_account_ai, program_id)?;
+    fund_data.tokens[0].balance = parse_token_account(fund_vault_ai)?.amount;
+    check!(fund_data.tokens[0].balance >= fund_data.tokens[0].debt, ProgramError::InsufficientFunds);
+    fund_data.mango_positions.equity = if is_deposit {
+        mango.equity.checked_add(amount as i64).unwrap()
+    } else {
+        mango.equity.checked_sub(amount as i64).unwrap()
+    };
+    Ok(())
+}
+
+/// Places a perp order, priced within the fund's slippage bound of the base token slot price
+///
+/// Accounts expected
+/// 0. [WRITE]  Fund State Account
+/// 1. [SIGNER] Manager Account
+/// 2. []   Platform State Account
+/// 3. []   Mango v4 Program
+/// 4. []   Mango Group
+/// 5. [WRITE]  Mango Account
+/// 6. [WRITE]  Perp Market
+/// 7. [WRITE]  Bids
+/// 8. [WRITE]  Asks
+/// 9. [WRITE]  Event Queue
+/// 10. []  Perp Oracle
+/// 11.. []  Mango health accounts
+pub fn place_mango_perp_order(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    perp_slot: u8,
+    side: u8,
+    price_lots: i64,
+    max_base_lots: i64,
+    client_order_id: u64,
+    order_type: u8,
+    reduce_only: bool
+) -> Result<(), ProgramError> {
+    const NUM_FIXED: usize = 11;
+    check!(accounts.len() >= NUM_FIXED, ProgramError::NotEnoughAccountKeys);
+    let (fixed_accs, health_accs) = accounts.split_at(NUM_FIXED);
+    let [
+        fund_account_ai,
+        manager_ai,
+        platform_ai,
+        mango_prog_ai,
+        mango_group_ai,
+        mango_account_ai,
+        perp_market_ai,
+        bids_ai,
+        asks_ai,
+        event_queue_ai,
+        oracle_ai,
+    ] = array_ref![fixed_accs, 0, NUM_FIXED];
+
+    check!(manager_ai.is_signer, ProgramError::MissingRequiredSignature);
+    check!(side <= 1 && price_lots > 0 && max_base_lots > 0, ProgramError::InvalidArgument);
+    let platform_data = PlatformData::load_checked(platform_ai, program_id)?;
+    let fund_data = FundAccount::load_checked(fund_account_ai, program_id)?;
+    check!(fund_data.manager_account == *manager_ai.key, FundError::ManagerMismatch);
+    check_mango_account(&fund_data, mango_account_ai)?;
+    check_perp_market(&fund_data, perp_slot, perp_market_ai)?;
+    check_perp_order_price(&platform_data, &fund_data, perp_market_ai, perp_slot as usize, side == 0, price_lots)?;
+
+    invoke_perp_order(
+        &fund_data,
+        mango_prog_ai,
+        &[mango_group_ai, mango_account_ai, fund_account_ai, perp_market_ai, bids_ai, asks_ai, event_queue_ai, oracle_ai],
+        health_accs,
+        perp_order_data(side, price_lots, max_base_lots, client_order_id, order_type, reduce_only)
+    )
+}
+
+fn perp_order_data(side: u8, price_lots: i64, max_base_lots: i64, client_order_id: u64, order_type: u8, reduce_only: bool) -> Vec<u8> {
+    let mut data = PERP_PLACE_ORDER.to_vec();
+    data.push(side);
+    data.extend_from_slice(&price_lots.to_le_bytes());
+    data.extend_from_slice(&max_base_lots.to_le_bytes());
+    data.extend_from_slice(&i64::MAX.to_le_bytes());
+    data.extend_from_slice(&client_order_id.to_le_bytes());
+    data.push(order_type);
+    data.push(reduce_only as u8);
+    data.extend_from_slice(&0u64.to_le_bytes());
+    data.push(ORDER_LIMIT);
+    data
+}
+
+// group, mango account, fund, perp market, bids, asks, event queue, oracle
+fn invoke_perp_order<'a>(
+    fund_data: &FundAccount,
+    mango_prog_ai: &AccountInfo<'a>,
+    order_accs: &[&AccountInfo<'a>; 8],
+    health_accs: &[AccountInfo<'a>],
+    data: Vec<u8>
+) -> Result<(), ProgramError> {
+    let [mango_group_ai, mango_account_ai, fund_account_ai, perp_market_ai, bids_ai, asks_ai, event_queue_ai, oracle_ai] = *order_accs;
+    let mut metas = vec![
+        AccountMeta::new_readonly(*mango_group_ai.key, false),
+        AccountMeta::new(*mango_account_ai.key, false),
+        AccountMeta::new_readonly(*fund_account_ai.key, true),
+        AccountMeta::new(*perp_market_ai.key, false),
+        AccountMeta::new(*bids_ai.key, false),
+        AccountMeta::new(*asks_ai.key, false),
+        AccountMeta::new(*event_queue_ai.key, false),
+        AccountMeta::new_readonly(*oracle_ai.key, false),
+    ];
+    metas.extend(health_metas(health_accs));
+    let mut infos: Vec<AccountInfo> = order_accs.iter().map(|ai| (*ai).clone()).collect();
+    infos.extend_from_slice(health_accs);
+    invoke_mango(fund_data, mango_prog_ai, metas, data, &infos)
+}
+
+/// Cancels a perp order by client id, or every order on the market for id 0
+///
+/// Accounts expected
+/// 0. []   Fund State Account
+/// 1. [SIGNER] Manager Account
+/// 2. []   Mango v4 Program
+/// 3. []   Mango Group
+/// 4. [WRITE]  Mango Account
+/// 5. [WRITE]  Perp Market
+/// 6. [WRITE]  Bids
+/// 7. [WRITE]  Asks
+pub fn cancel_mango_perp_orders(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    perp_slot: u8,
+    client_order_id: u64
+) -> Result<(), ProgramError> {
+    const NUM_FIXED: usize = 8;
+    check!(accounts.len() >= NUM_FIXED, ProgramError::NotEnoughAccountKeys);
+    let [
+        fund_account_ai,
+        manager_ai,
+        mango_prog_ai,
+        mango_group_ai,
+        mango_account_ai,
+        perp_market_ai,
+        bids_ai,
+        asks_ai,
+    ] = array_ref![accounts, 0, NUM_FIXED];
+
+    check!(manager_ai.is_signer, ProgramError::MissingRequiredSignature);
+    let fund_data = FundAccount::load_checked(fund_account_ai, program_id)?;
+    check!(fund_data.manager_account == *manager_ai.key, FundError::ManagerMismatch);
+    check_mango_account(&fund_data, mango_account_ai)?;
+    check_perp_market(&fund_data, perp_slot, perp_market_ai)?;
+
+    invoke_perp_cancel(&fund_data, mango_prog_ai, &[mango_group_ai, mango_account_ai, fund_account_ai, perp_market_ai, bids_ai, asks_ai], client_order_id)
+}
+
+// group, mango account, fund, perp market, bids, asks
+fn invoke_perp_cancel<'a>(
+    fund_data: &FundAccount,
+    mango_prog_ai: &AccountInfo<'a>,
+    cancel_accs: &[&AccountInfo<'a>; 6],
+    client_order_id: u64
+) -> Result<(), ProgramError> {
+    let [mango_group_ai, mango_account_ai, fund_account_ai, perp_market_ai, bids_ai, asks_ai] = *cancel_accs;
+    let data = if client_order_id == 0 {
+        let mut data = PERP_CANCEL_ALL_ORDERS.to_vec();
+        data.push(ORDER_LIMIT);
+        data
+    } else {
+        let mut data = PERP_CANCEL_ORDER_BY_CLIENT_ORDER_ID.to_vec();
+        data.extend_from_slice(&client_order_id.to_le_bytes());
+        data
+    };
+    invoke_mango(
+        fund_data,
+        mango_prog_ai,
+        vec![
+            AccountMeta::new_readonly(*mango_group_ai.key, false),
+            AccountMeta::new(*mango_account_ai.key, false),
+            AccountMeta::new_readonly(*fund_account_ai.key, true),
+            AccountMeta::new(*perp_market_ai.key, false),
+            AccountMeta::new(*bids_ai.key, false),
+            AccountMeta::new(*asks_ai.key, false),
+        ],
+        data,
+        &[mango_group_ai.clone(), mango_account_ai.clone(), fund_account_ai.clone(), perp_market_ai.clone(), bids_ai.clone(), asks_ai.clone()]
+    )
+}
+
+fn check_perp_market(fund_data: &FundAccount, perp_slot: u8, perp_market_ai: &AccountInfo) -> Result<(), ProgramError> {
+    let i = perp_slot as usize;
+    check!(i < NUM_PERP && fund_data.mango_positions.perp_markets[i] != u8::MAX, FundError::InvalidStateAccount);
+    check_eq!(*perp_market_ai.owner, mango_v4_id::ID);
+    check_eq!(read_u16(&perp_market_ai.try_borrow_data()?, PERP_MARKET_INDEX_OFFSET)?, fund_data.mango_positions.perp_markets[i] as u16);
+    Ok(())
+}
+
+// (base lot size, quote lot size) of a perp market
+fn get_perp_lot_sizes(perp_market_ai: &AccountInfo) -> Result<(i64, i64), ProgramError> {
+    let data = perp_market_ai.try_borrow_data()?;
+    Ok((read_i64(&data, PERP_BASE_LOT_SIZE_OFFSET)?, read_i64(&data, PERP_QUOTE_LOT_SIZE_OFFSET)?))
+}
+
+/// Bids may not pay more, and asks not take less, than the base slot price within the slippage bound
+fn check_perp_order_price(
+    platform_data: &PlatformData,
+    fund_data: &FundAccount,
+    perp_market_ai: &AccountInfo,
+    perp_slot: usize,
+    is_bid: bool,
+    price_lots: i64,
+) -> Result<(), ProgramError> {
+    let (base_lot_size, quote_lot_size) = get_perp_lot_sizes(perp_market_ai)?;
+    // USDC native per base native
+    let order_price = U64F64::from_num(price_lots).checked_mul(U64F64::from_num(quote_lot_size)).unwrap()
+        .checked_div(U64F64::from_num(base_lot_size)).unwrap();
+    let limit_price = get_perp_limit_price(platform_data, fund_data, perp_slot, is_bid)?;
+    msg!("perp order price {:?}, limit price {:?}", order_price, limit_price);
+    if is_bid {
+        check!(order_price <= limit_price, FundError::MinAmountFailed);
+    } else {
+        check!(order_price >= limit_price, FundError::MinAmountFailed);
+    }
+    Ok(())
+}
+
+// base slot price moved against the order by the slippage bound, USDC native per base native
+fn get_perp_limit_price(platform_data: &PlatformData, fund_data: &FundAccount, perp_slot: usize, is_bid: bool) -> Result<U64F64, ProgramError> {
+    let price_slot = fund_data.mango_positions.perp_price_slots[perp_slot] as usize;
+    let oracle_price = get_slot_price(platform_data, fund_data, price_slot, Clock::get()?.unix_timestamp)?;
+    let base_info = platform_data.token_list[fund_data.tokens[price_slot].get_active_index()];
+    let usdc_info = platform_data.token_list[fund_data.tokens[0].get_active_index()];
+    let bps = U64F64::from_num(get_slippage_bps(platform_data, &base_info, &[usdc_info], fund_data.max_slippage_bps, 0)).checked_div(U64F64::from_num(10_000)).unwrap();
+    let factor = if is_bid {
+        U64F64::from_num(1).checked_add(bps).unwrap()
+    } else {
+        U64F64::from_num(1).checked_sub(bps).unwrap()
+    };
+    Ok(oracle_price.checked_mul(factor).unwrap())
+}
+
+/// Permissionless: refreshes the cached account equity valued by update_amount_and_performance,
+/// USDC deposits less borrows and each perp position at the base slot price with its unsettled pnl
+///
+/// Accounts expected
+/// 0. [WRITE]  Fund State Account
+/// 1. []   Platform State Account
+/// 2. []   Mango Account
+/// 3. []   USDC Bank
+/// 4.. []  Perp Markets of the active perp slots, in fund order
+pub fn update_mango_value(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo]
+) -> Result<(), ProgramError> {
+    let accounts_iter = &mut accounts.iter();
+    let fund_account_ai = next_account_info(accounts_iter)?;
+    let platform_ai = next_account_info(accounts_iter)?;
+    let mango_account_ai = next_account_info(accounts_iter)?;
+    let bank_ai = next_account_info(accounts_iter)?;
+
+    let platform_data = PlatformData::load_checked(platform_ai, program_id)?;
+    let mut fund_data = FundAccount::load_mut_checked(fund_account_ai, program_id)?;
+    check_mango_account(&fund_data, mango_account_ai)?;
+    let mut perp_market_ais = vec![];
+    for _ in fund_data.mango_positions.perp_markets.iter().filter(|m| **m != u8::MAX) {
+        perp_market_ais.push(next_account_info(accounts_iter)?);
+    }
+    let now_ts = Clock::get()?.unix_timestamp;
+    let equity = get_mango_equity(&platform_data, &fund_data, mango_account_ai, bank_ai, &perp_market_ais, now_ts)?;
+    fund_data.mango_positions.equity = equity.to_num();
+    fund_data.mango_positions.last_updated = now_ts;
+    msg!("mango equity {:?}", fund_data.mango_positions.equity);
+    Ok(())
+}
+
+/// Equity of the mango account at the token slot prices, net of unsettled funding.
+/// `perp_market_ais` are those of the active perp slots.
+fn get_mango_equity(
+    platform_data: &PlatformData,
+    fund_data: &FundAccount,
+    mango_account_ai: &AccountInfo,
+    bank_ai: &AccountInfo,
+    perp_market_ais: &[&AccountInfo],
+    now_ts: i64
+) -> Result<I80F48, ProgramError> {
+    let account_data = mango_account_ai.try_borrow_data()?;
+    check_quote_bank(platform_data, fund_data, &read_pubkey(&account_data, ACCOUNT_GROUP_OFFSET)?, bank_ai)?;
+
+    let mut equity = I80F48::from_num(0);
+    let token_count = read_u32(&account_data, ACCOUNT_TOKENS_LEN_OFFSET)? as usize;
+    for i in 0..token_count {
+        let offset = ACCOUNT_TOKENS_LEN_OFFSET + 4 + i * TOKEN_POSITION_SIZE;
+        let indexed_position = read_i80f48(&account_data, offset)?;
+        if indexed_position == I80F48::from_num(0) {
+            continue;
+        }
+        check!(read_u16(&account_data, offset + TOKEN_POSITION_INDEX_OFFSET)? == MANGO_QUOTE_TOKEN_INDEX, FundError::InvalidStateAccount);
+        let bank_data = bank_ai.try_borrow_data()?;
+        let index = read_i80f48(&bank_data, if indexed_position > 0 { BANK_DEPOSIT_INDEX_OFFSET } else { BANK_BORROW_INDEX_OFFSET })?;
+        equity = equity.checked_add(indexed_position.checked_mul(index).unwrap()).unwrap();
+    }
+    let mut perp_market_ais = perp_market_ais.iter();
+    for i in 0..NUM_PERP {
+        let market_index = fund_data.mango_positions.perp_markets[i];
+        if market_index == u8::MAX {
+            continue;
+        }
+        let perp_market_ai = perp_market_ais.next().ok_or(ProgramError::NotEnoughAccountKeys)?;
+        check_perp_market(fund_data, i as u8, perp_market_ai)?;
+        let (base_lots, quote_native, _) = get_perp_position(&account_data, market_index)?;
+        let (base_lot_size, _) = get_perp_lot_sizes(perp_market_ai)?;
+        let price = get_slot_price(platform_data, fund_data, fund_data.mango_positions.perp_price_slots[i] as usize, now_ts)?;
+        let base_value = I80F48::from_num(base_lots.checked_mul(base_lot_size).unwrap()).checked_mul(I80F48::from_num(price)).unwrap();
+        let funding = get_unsettled_funding(&account_data, market_index, perp_market_ai)?;
+        equity = equity.checked_add(base_value).unwrap().checked_add(quote_native).unwrap().checked_sub(funding).unwrap();
+    }
+    Ok(equity)
+}
+
+/// Account equity not yet owed to investors, added to or, when negative, subtracted from `fund_val`
+pub fn add_mango_value(fund_data: &FundAccount, fund_val: U64F64, now_ts: i64) -> Result<U64F64, ProgramError> {
+    let mango = &fund_data.mango_positions;
+    if !mango.is_active {
+        return Ok(fund_val);
+    }
+    if now_ts - mango.last_updated > 100 {
+        msg!("mango account not up-to-date...");
+        return Err(FundError::PriceStaleInAccount.into())
+    }
+    let net = mango.equity.checked_sub(mango.investor_debts[0] as i64).unwrap();
+    if net >= 0 {
+        Ok(fund_val.checked_add(U64F64::from_num(net)).unwrap())
+    } else {
+        fund_val.checked_sub(U64F64::from_num(net.unsigned_abs())).ok_or(ProgramError::InsufficientFunds)
+    }
+}
+
+/// Investor's part of the account equity in USDC: (owed from the account, deficit paid from the vault)
+pub fn get_mango_exit(fund_data: &FundAccount, share: U64F64) -> (u64, u64) {
+    let mango = &fund_data.mango_positions;
+    if !mango.is_active {
+        return (0, 0);
+    }
+    let net = mango.equity.checked_sub(mango.investor_debts[0] as i64).unwrap();
+    let part: u64 = U64F64::to_num(U64F64::from_num(net.unsigned_abs()).checked_mul(share).unwrap());
+    if net >= 0 { (part, 0) } else { (0, part) }
+}
+
+/// Books the investor's part of the account equity on settle, withdrawn with MangoInvestorWithdraw.
+/// With perps open the part is booked by MangoInvestorReduce instead, once they are reduced.
+pub fn book_mango_exit(
+    platform_data: &PlatformData,
+    fund_data: &mut FundAccount,
+    investor_data: &mut InvestorData,
+    share: U64F64,
+    now_ts: i64,
+) -> Result<(), ProgramError> {
+    investor_data.margin_position_id[0] = MANGO_QUOTE_TOKEN_INDEX as u64;
+    investor_data.margin_debt = U64F64::from_num(0);
+    investor_data.withdrawn_from_margin = !fund_data.mango_positions.is_active;
+    if has_perp_markets(fund_data) {
+        return Ok(());
+    }
+    let (owed, deficit) = get_mango_exit(fund_data, share);
+    book_mango_part(platform_data, fund_data, investor_data, owed as i64 - deficit as i64, now_ts)
+}
+
+// a positive part is owed from the mango account, a deficit is taken from the investor's token debts
+fn book_mango_part(
+    platform_data: &PlatformData,
+    fund_data: &mut FundAccount,
+    investor_data: &mut InvestorData,
+    part: i64,
+    now_ts: i64,
+) -> Result<(), ProgramError> {
+    if part >= 0 {
+        investor_data.margin_debt = U64F64::from_num(part);
+        investor_data.withdrawn_from_margin = part == 0;
+        fund_data.mango_positions.investor_debts[0] = fund_data.mango_positions.investor_debts[0].checked_add(part as u64).unwrap();
+        Ok(())
+    } else {
+        investor_data.withdrawn_from_margin = true;
+        take_exit_deficit(platform_data, fund_data, investor_data, part.unsigned_abs(), now_ts)
+    }
+}
+
+pub fn has_perp_markets(fund_data: &FundAccount) -> bool {
+    fund_data.mango_positions.is_active && fund_data.mango_positions.perp_markets.iter().any(|m| *m != u8::MAX)
+}
+
+/// Permissionless: withdraws a settled investor's booked USDC from the mango account into the
+/// fund's USDC vault, where it is owed to the investor like the other token debts
+///
+/// Accounts expected
+/// 0. [WRITE]  Fund State Account
+/// 1. [WRITE]  Investor State Account
+/// 2. []   Platform State Account
+/// 3. []   Mango v4 Program
+/// 4. []   Mango Group
+/// 5. [WRITE]  Mango Account
+/// 6. [WRITE]  USDC Bank
+/// 7. [WRITE]  USDC Bank Vault
+/// 8. []   USDC Oracle
+/// 9. [WRITE]  Fund USDC Vault
+/// 10. []  Token Program
+/// 11.. []  Mango health accounts
+pub fn mango_investor_withdraw(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo]
+) -> Result<(), ProgramError> {
+    const NUM_FIXED: usize = 11;
+    check!(accounts.len() >= NUM_FIXED, ProgramError::NotEnoughAccountKeys);
+    let (fixed_accs, health_accs) = accounts.split_at(NUM_FIXED);
+    let [
+        fund_account_ai,
+        investor_state_ai,
+        platform_ai,
+        mango_prog_ai,
+        mango_group_ai,
+        mango_account_ai,
+        bank_ai,
+        bank_vault_ai,
+        oracle_ai,
+        fund_vault_ai,
+        token_prog_ai,
+    ] = array_ref![fixed_accs, 0, NUM_FIXED];
+
+    check_eq!(*token_prog_ai.key, spl_token::ID);
+    let platform_data = PlatformData::load_checked(platform_ai, program_id)?;
+    let fund_data = FundAccount::load_checked(fund_account_ai, program_id)?;
+    let mut investor_data = InvestorData::load_mut_checked(investor_state_ai, program_id)?;
+    check_eq!(investor_data.manager, fund_data.manager_account);
+    check!(investor_data.has_withdrawn && !investor_data.withdrawn_from_margin, FundError::InvalidStateAccount);
+    check_mango_account(&fund_data, mango_account_ai)?;
+    check_eq!(fund_data.tokens[0].vault, *fund_vault_ai.key);
+    check_quote_bank(&platform_data, &fund_data, mango_group_ai.key, bank_ai)?;
+    let amount: u64 = U64F64::to_num(investor_data.margin_debt);
+
+    let mut data = TOKEN_WITHDRAW.to_vec();
+    data.extend_from_slice(&amount.to_le_bytes());
+    data.push(0);
+    let mut metas = vec![
+        AccountMeta::new_readonly(*mango_group_ai.key, false),
+        AccountMeta::new(*mango_account_ai.key, false),
+        AccountMeta::new_readonly(*fund_account_ai.key, true),
+        AccountMeta::new(*bank_ai.key, false),
+        AccountMeta::new(*bank_vault_ai.key, false),
+        AccountMeta::new_readonly(*oracle_ai.key, false),
+        AccountMeta::new(*fund_vault_ai.key, false),
+        AccountMeta::new_readonly(*token_prog_ai.key, false),
+    ];
+    metas.extend(health_metas(health_accs));
+    let mut infos = fixed_accs.to_vec();
+    infos.extend_from_slice(health_accs);
+    invoke_mango(&fund_data, mango_prog_ai, metas, data, &infos)?;
+    drop(fund_data);
+
+    let mut fund_data = FundAccount::load_mut_checked(fund_account_ai, program_id)?;
+    let received = parse_token_account(fund_vault_ai)?.amount.checked_sub(fund_data.tokens[0].balance).unwrap();
+    check_eq!(received, amount);
+    fund_data.tokens[0].balance = fund_data.tokens[0].balance.checked_add(amount).unwrap();
+    fund_data.tokens[0].debt = fund_data.tokens[0].debt.checked_add(amount).unwrap();
+    fund_data.mango_positions.investor_debts[0] = fund_data.mango_positions.investor_debts[0].checked_sub(amount).unwrap();
+    fund_data.mango_positions.equity = fund_data.mango_positions.equity.checked_sub(amount as i64).unwrap();
+    investor_data.token_debts[0] = investor_data.token_debts[0].checked_add(amount).unwrap();
+    investor_data.margin_debt = U64F64::from_num(0);
+    investor_data.withdrawn_from_margin = true;
+    refresh_exit_status(&fund_data, &mut investor_data);
+    Ok(())
+}
+
+/// Runs right after the investor's InvestorWithdrawSettleFunds when the fund has perps: cancels the
+/// resting orders and reduces every perp position by the investor's share with reduce-only IOC orders
+/// within the slippage bound, then books the investor's part of the equity less the reduction cost
+///
+/// Accounts expected
+/// 0. [WRITE]  Fund State Account
+/// 1. [WRITE]  Investor State Account
+/// 2. []   Platform State Account
+/// 3. []   Instructions Sysvar
+/// 4. []   Mango v4 Program
+/// 5. []   Mango Group
+/// 6. [WRITE]  Mango Account
+/// 7. []   USDC Bank
+/// 8.. for each active perp slot, in fund order:
+///     [WRITE] Perp Market, [WRITE] Bids, [WRITE] Asks, [WRITE] Event Queue, [] Perp Oracle
+/// then the Mango health accounts, followed by the NAV History Account if the fund has one
+pub fn mango_investor_reduce(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo]
+) -> Result<(), ProgramError> {
+    const NUM_FIXED: usize = 8;
+    check!(accounts.len() >= NUM_FIXED, ProgramError::NotEnoughAccountKeys);
+    let (fixed_accs, other_accs) = accounts.split_at(NUM_FIXED);
+    let [
+        fund_account_ai,
+        investor_state_ai,
+        platform_ai,
+        ix_sysvar_ai,
+        mango_prog_ai,
+        mango_group_ai,
+        mango_account_ai,
+        bank_ai,
+    ] = array_ref![fixed_accs, 0, NUM_FIXED];
+
+    let platform_data = PlatformData::load_checked(platform_ai, program_id)?;
+    let fund_data = FundAccount::load_checked(fund_account_ai, program_id)?;
+    let investor_data = InvestorData::load_checked(investor_state_ai, program_id)?;
+    check_eq!(investor_data.manager, fund_data.manager_account);
+    check!(investor_data.has_withdrawn && !investor_data.withdrawn_from_margin, FundError::InvalidStateAccount);
+    check!(has_perp_markets(&fund_data), FundError::InvalidStateAccount);
+    check_mango_account(&fund_data, mango_account_ai)?;
+
+    // the investor's settle must be the previous instruction, their share is valued from it
+    let current = load_current_index_checked(ix_sysvar_ai)?;
+    check!(current > 0, FundError::InvalidInstruction);
+    let ix = load_instruction_at_checked(current as usize - 1, ix_sysvar_ai)?;
+    check!(ix.program_id == *program_id && FundInstruction::unpack(&ix.data) == Some(FundInstruction::InvestorWithdrawSettleFunds), FundError::InvalidInstruction);
+    check!(ix.accounts.get(2).map(|meta| meta.pubkey) == Some(*investor_state_ai.key), FundError::InvalidInstruction);
+    let share = investor_data.share;
+    drop(investor_data);
+
+    let active_slots: Vec<usize> = (0..NUM_PERP).filter(|i| fund_data.mango_positions.perp_markets[*i] != u8::MAX).collect();
+    check!(other_accs.len() >= 5 * active_slots.len(), ProgramError::NotEnoughAccountKeys);
+    let (perp_accs, health_accs) = other_accs.split_at(5 * active_slots.len());
+    let nav_history_key = fund_data.nav_history;
+    let health_accs: Vec<AccountInfo> = health_accs.iter().filter(|ai| *ai.key != nav_history_key).cloned().collect();
+    let perp_market_ais: Vec<&AccountInfo> = perp_accs.iter().step_by(5).collect();
+
+    let now_ts = Clock::get()?.unix_timestamp;
+    let equity_before = get_mango_equity(&platform_data, &fund_data, mango_account_ai, bank_ai, &perp_market_ais, now_ts)?;
+    for (n, i) in active_slots.iter().enumerate() {
+        let [perp_market_ai, bids_ai, asks_ai, event_queue_ai, oracle_ai] = array_ref![perp_accs, 5 * n, 5];
+        check_perp_market(&fund_data, *i as u8, perp_market_ai)?;
+        let market_index = fund_data.mango_positions.perp_markets[*i];
+        invoke_perp_cancel(&fund_data, mango_prog_ai, &[mango_group_ai, mango_account_ai, fund_account_ai, perp_market_ai, bids_ai, asks_ai], 0)?;
+
+        let (base_lots, _, _) = get_perp_position(&mango_account_ai.try_borrow_data()?, market_index)?;
+        if base_lots == 0 {
+            continue;
+        }
+        // rounded up so the fund keeps no more than its own part
+        let exact = U64F64::from_num(base_lots.unsigned_abs()).checked_mul(share).unwrap();
+        let reduce_lots: i64 = exact.ceil().to_num();
+        let reduce_lots = reduce_lots.min(base_lots.abs());
+        if reduce_lots == 0 {
+            continue;
+        }
+        // longs are reduced with asks, shorts with bids
+        let is_bid = base_lots < 0;
+        let (base_lot_size, quote_lot_size) = get_perp_lot_sizes(perp_market_ai)?;
+        let limit_lots = get_perp_limit_price(&platform_data, &fund_data, *i, is_bid)?
+            .checked_mul(U64F64::from_num(base_lot_size)).unwrap()
+            .checked_div(U64F64::from_num(quote_lot_size)).unwrap();
+        let price_lots: i64 = if is_bid { limit_lots.floor().to_num() } else { limit_lots.ceil().to_num() };
+        check!(price_lots > 0, FundError::MinAmountFailed);
+        invoke_perp_order(
+            &fund_data,
+            mango_prog_ai,
+            &[mango_group_ai, mango_account_ai, fund_account_ai, perp_market_ai, bids_ai, asks_ai, event_queue_ai, oracle_ai],
+            &health_accs,
+            perp_order_data(is_bid as u8 ^ 1, price_lots, reduce_lots, 0, ORDER_TYPE_IOC, true)
+        )?;
+
+        // taker fills are applied to the position right away, partial fills are rejected
+        let (base_after, _, _) = get_perp_position(&mango_account_ai.try_borrow_data()?, market_index)?;
+        check_eq!(base_after.abs(), base_lots.abs().checked_sub(reduce_lots).unwrap());
+        msg!("perp slot {} reduced by {} lots", i, reduce_lots);
+    }
+    let equity_after = get_mango_equity(&platform_data, &fund_data, mango_account_ai, bank_ai, &perp_market_ais, now_ts)?;
+    drop(fund_data);
+
+    let mut fund_data = FundAccount::load_mut_checked(fund_account_ai, program_id)?;
+    let mut investor_data = InvestorData::load_mut_checked(investor_state_ai, program_id)?;
+    // fees and slippage of the reduction are the investor's
+    let cost = equity_before.checked_sub(equity_after).unwrap();
+    let net_before = equity_before.checked_sub(I80F48::from_num(fund_data.mango_positions.investor_debts[0])).unwrap();
+    let part = net_before.checked_mul(I80F48::from_num(share)).unwrap().checked_sub(cost).unwrap();
+    msg!("mango equity {:?} -> {:?}, investor part {:?}", equity_before, equity_after, part);
+    fund_data.mango_positions.equity = equity_after.to_num();
+    fund_data.mango_positions.last_updated = now_ts;
+    book_mango_part(&platform_data, &mut fund_data, &mut investor_data, part.to_num(), now_ts)?;
+    refresh_exit_status(&fund_data, &mut investor_data);
+
+    let mut nav_history = find_nav_history(program_id, &fund_data, other_accs)?;
+    update_amount_and_performance(&platform_data, &mut fund_data, false, nav_history.as_deref_mut())
+}
+
+/// Permissionless: settles the pnl between the fund's mango account and a counterparty on a perp
+/// market, moving realized perp pnl into the USDC position
+///
+/// Accounts expected
+/// 0. []   Fund State Account
+/// 1. []   Mango v4 Program
+/// 2. []   Mango Group
+/// 3. [WRITE]  Mango Account of the fund, the settler
+/// 4. [WRITE]  Perp Market
+/// 5. [WRITE]  Mango Account A, with positive pnl
+/// 6. [WRITE]  Mango Account B, with negative pnl
+/// 7. []   Perp Oracle
+/// 8. [WRITE]  Settle Bank
+/// 9. []   Settle Oracle
+/// 10.. []  Mango health accounts of accounts A and B
+pub fn mango_settle_perp_pnl(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    perp_slot: u8
+) -> Result<(), ProgramError> {
+    const NUM_FIXED: usize = 10;
+    check!(accounts.len() >= NUM_FIXED, ProgramError::NotEnoughAccountKeys);
+    let (fixed_accs, health_accs) = accounts.split_at(NUM_FIXED);
+    let [
+        fund_account_ai,
+        mango_prog_ai,
+        mango_group_ai,
+        mango_account_ai,
+        perp_market_ai,
+        account_a_ai,
+        account_b_ai,
+        perp_oracle_ai,
+        settle_bank_ai,
+        settle_oracle_ai,
+    ] = array_ref![fixed_accs, 0, NUM_FIXED];
+
+    let fund_data = FundAccount::load_checked(fund_account_ai, program_id)?;
+    check_mango_account(&fund_data, mango_account_ai)?;
+    check_perp_market(&fund_data, perp_slot, perp_market_ai)?;
+    check!(*account_a_ai.key == *mango_account_ai.key || *account_b_ai.key == *mango_account_ai.key, FundError::InvalidStateAccount);
+
+    let mut metas = vec![
+        AccountMeta::new_readonly(*mango_group_ai.key, false),
+        AccountMeta::new(*mango_account_ai.key, false),
+        AccountMeta::new_readonly(*fund_account_ai.key, true),
+        AccountMeta::new(*perp_market_ai.key, false),
+        AccountMeta::new(*account_a_ai.key, false),
+        AccountMeta::new(*account_b_ai.key, false),
+        AccountMeta::new_readonly(*perp_oracle_ai.key, false),
+        AccountMeta::new(*settle_bank_ai.key, false),
+        AccountMeta::new_readonly(*settle_oracle_ai.key, false),
+    ];
+    metas.extend(health_metas(health_accs));
+    let mut infos = fixed_accs[2..].to_vec();
+    infos.push(fund_account_ai.clone());
+    infos.extend_from_slice(health_accs);
+    invoke_mango(&fund_data, mango_prog_ai, metas, PERP_SETTLE_PNL.to_vec(), &infos)
+}
@@ -0,0 +1,410 @@
+use std::mem::size_of;
+
+use bytemuck::{from_bytes_mut, Pod, Zeroable};
+use solana_program::{
+    account_info::{AccountInfo, next_account_info},
+    msg,
+    program_error::ProgramError,
+    program_pack::IsInitialized,
+    pubkey::Pubkey,
+};
+
+use crate::error::FundError;
+use fixed::types::U64F64;
+
+use crate::state::{
+    NUM_TOKENS, MAX_INVESTORS, NUM_MARGIN, MAX_LIMIT_ORDERS, MAX_SERUM_MARKETS, MAX_STRATEGY_POSITIONS,
+    FundAccount, InvestorData, PlatformData, NavHistory, ExecutionOrder, Loadable,
+    TokenSlot, MangoInfo, SwapGuard, LimitOrderInfo, StrategyPosition, SerumMarketInfo, RiskLimits,
+    Mandate, RebalanceConfig, SpotTrigger, LeverageInfo,
+};
+
+macro_rules! check {
+    ($cond:expr, $err:expr) => {
+        if !($cond) {
+            return Err(($err).into())
+        }
+    }
+}
+macro_rules! check_eq {
+    ($x:expr, $y:expr) => {
+        if ($x != $y) {
+            return Err(FundError::Default.into())
+        }
+    }
+}
+
+/// Fails to compile if `$t` is not exactly `$size` bytes
+macro_rules! assert_size {
+    ($t:ty, $size:expr) => {
+        const _: [(); $size] = [(); size_of::<$t>()];
+    }
+}
+
+// Layout versions written by this program.
+//
+// Accounts never get resized, so every version of a struct is as large as the one before it.
+// New fields are carved out of the trailing reserved bytes, the assertions below catch a wrong carve.
+// A new field whose zero value is a valid default needs no version bump (reserved bytes are zeroed
+// from fund v2 on). Anything that moves or reinterprets existing bytes bumps the version, freezes
+// the previous layout as `<Struct>V<n>` below and adds a step to the matching `migrate_*`.
+pub const FUND_ACCOUNT_VERSION: u8 = 3;
+pub const INVESTOR_DATA_VERSION: u8 = 2;
+pub const PLATFORM_DATA_VERSION: u8 = 1;
+pub const NAV_HISTORY_VERSION: u8 = 1;
+pub const EXECUTION_ORDER_VERSION: u8 = 1;
+pub const REIMBURSEMENT_TABLE_VERSION: u8 = 1;
+
+pub const FUND_ACCOUNT_SIZE: usize = size_of::<FundAccount>();
+pub const INVESTOR_DATA_SIZE: usize = size_of::<InvestorData>();
+pub const PLATFORM_DATA_SIZE: usize = size_of::<PlatformData>();
+
+// On-chain sizes, u128 is 8-byte aligned on bpf so host builds differ
+#[cfg(target_arch = "bpf")]
+assert_size!(FundAccount, 3160);
+#[cfg(target_arch = "bpf")]
+assert_size!(InvestorData, 256);
+#[cfg(target_arch = "bpf")]
+assert_size!(PlatformData, 6904);
+#[cfg(target_arch = "bpf")]
+assert_size!(NavHistory, 10136);
+#[cfg(target_arch = "bpf")]
+assert_size!(ExecutionOrder, 80);
+
+// Frozen layouts of older versions, field for field. Structs whose bytes kept their meaning are
+// shared with the current layout.
+
+/// Fund v1: the reserved tail may still hold bytes of the deprecated MarginInfo
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct FundAccountV1 {
+    pub is_initialized: bool,
+    pub number_of_active_investments: u8,
+    pub no_of_investments: u8,
+    pub signer_nonce: u8,
+    pub no_of_margin_positions: u8,
+    pub no_of_assets: u8,
+    pub position_count: u16,
+    pub version: u8,
+    pub is_private: bool,
+    pub fund_v3_index: u16,
+    pub padding: [u8; 4],
+    pub min_amount: u64,
+    pub mr_padding: [u8; 16],
+    pub performance_fee_percentage: U64F64,
+    pub total_amount: U64F64,
+    pub prev_performance: U64F64,
+    pub amount_in_router: u64,
+    pub performance_fee: U64F64,
+    pub manager_account: Pubkey,
+    pub fund_pda: Pubkey,
+    pub tokens: [TokenSlot; NUM_TOKENS],
+    pub investors: [Pubkey; MAX_INVESTORS],
+    pub mango_positions: MangoInfoV2,
+    pub guard: SwapGuard,
+    pub limit_orders: [LimitOrderInfo; MAX_LIMIT_ORDERS],
+    pub friktion_vault: StrategyPosition,
+    pub reimbursement_init: bool,
+    pub reimbursement_claimed: bool,
+    pub reserved: [u8; FUND_V1_RESERVED],
+}
+const FUND_V1_RESERVED: usize = 1854;
+assert_size!(FundAccountV1, FUND_ACCOUNT_SIZE);
+
+/// Fund v2: Mango v3 margin info, v3 reads these bytes as the Mango v4 account state
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct FundAccountV2 {
+    pub is_initialized: bool,
+    pub number_of_active_investments: u8,
+    pub no_of_investments: u8,
+    pub signer_nonce: u8,
+    pub no_of_margin_positions: u8,
+    pub no_of_assets: u8,
+    pub position_count: u16,
+    pub version: u8,
+    pub is_private: bool,
+    pub fund_v3_index: u16,
+    pub padding: [u8; 4],
+    pub min_amount: u64,
+    pub mr_padding: [u8; 16],
+    pub performance_fee_percentage: U64F64,
+    pub total_amount: U64F64,
+    pub prev_performance: U64F64,
+    pub amount_in_router: u64,
+    pub performance_fee: U64F64,
+    pub manager_account: Pubkey,
+    pub fund_pda: Pubkey,
+    pub tokens: [TokenSlot; NUM_TOKENS],
+    pub investors: [Pubkey; MAX_INVESTORS],
+    pub mango_positions: MangoInfoV2,
+    pub guard: SwapGuard,
+    pub limit_orders: [LimitOrderInfo; MAX_LIMIT_ORDERS],
+    pub friktion_vault: StrategyPosition,
+    pub reimbursement_init: bool,
+    pub reimbursement_claimed: bool,
+    pub nav_history: Pubkey,
+    pub max_slippage_bps: u16,
+    pub serum_padding: [u8; 4],
+    pub serum_markets: [SerumMarketInfo; MAX_SERUM_MARKETS],
+    pub risk_limits: RiskLimits,
+    pub mandate: Mandate,
+    pub rebalance: RebalanceConfig,
+    pub stop_loss: [SpotTrigger; NUM_TOKENS],
+    pub take_profit: [SpotTrigger; NUM_TOKENS],
+    pub strategy_positions_ext: [StrategyPosition; MAX_STRATEGY_POSITIONS - 1],
+    pub leverage: LeverageInfo,
+    pub migration_additonal_padding: [u8; 224],
+}
+assert_size!(FundAccountV2, FUND_ACCOUNT_SIZE);
+
+/// Mango v3 margin info of funds up to v2
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct MangoInfoV2 {
+    pub mango_account: Pubkey,
+    pub perp_markets: [u8; 3],
+    pub perp_padding: u8,
+    pub deposit_index: u8,
+    pub markets_active: u8,
+    pub deposits_active: u8,
+    pub xpadding: u8,
+    pub investor_debts: [u64; 2],
+    pub padding: [u8; 24],
+}
+assert_size!(MangoInfoV2, size_of::<MangoInfo>());
+
+/// Investor v0: created before versioning, `version` was padding and reads 0
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct InvestorDataV0 {
+    pub is_initialized: bool,
+    pub has_withdrawn: bool,
+    pub withdrawn_from_margin: bool,
+    pub withdrawn_ul_from_friktion: bool,
+    pub withdrawn_ftokens_from_friktion: bool,
+    pub pending_deposit_on_friktion: bool,
+    pub padding: [u8; 2],
+    pub owner: Pubkey,
+    pub amount: u64,
+    pub start_performance: U64F64,
+    pub amount_in_router: u64,
+    pub manager: Pubkey,
+    pub margin_debt: [U64F64; NUM_MARGIN],
+    pub margin_position_id: [u64; NUM_MARGIN],
+    pub token_indexes: [u8; NUM_TOKENS],
+    pub token_debts: [u64; NUM_TOKENS],
+    pub share: U64F64,
+    pub friktion_ul_debt: u64,
+    pub friktion_fc_debt: u64,
+}
+assert_size!(InvestorDataV0, INVESTOR_DATA_SIZE);
+
+/// Investor v1: the byte after `version` and `margin_debt[1]` were reserved,
+/// v2 keeps the second friktion volt's flags and debts there
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct InvestorDataV1 {
+    pub is_initialized: bool,
+    pub has_withdrawn: bool,
+    pub withdrawn_from_margin: bool,
+    pub withdrawn_ul_from_friktion: bool,
+    pub withdrawn_ftokens_from_friktion: bool,
+    pub pending_deposit_on_friktion: bool,
+    pub version: u8,
+    pub padding: [u8; 1],
+    pub owner: Pubkey,
+    pub amount: u64,
+    pub start_performance: U64F64,
+    pub amount_in_router: u64,
+    pub manager: Pubkey,
+    pub margin_debt: [U64F64; NUM_MARGIN],
+    pub margin_position_id: [u64; NUM_MARGIN],
+    pub token_indexes: [u8; NUM_TOKENS],
+    pub token_debts: [u64; NUM_TOKENS],
+    pub share: U64F64,
+    pub friktion_ul_debt: u64,
+    pub friktion_fc_debt: u64,
+}
+assert_size!(InvestorDataV1, INVESTOR_DATA_SIZE);
+
+unsafe impl Zeroable for FundAccountV1 {}
+unsafe impl Pod for FundAccountV1 {}
+unsafe impl Zeroable for FundAccountV2 {}
+unsafe impl Pod for FundAccountV2 {}
+unsafe impl Zeroable for MangoInfoV2 {}
+unsafe impl Pod for MangoInfoV2 {}
+unsafe impl Zeroable for InvestorDataV0 {}
+unsafe impl Pod for InvestorDataV0 {}
+unsafe impl Zeroable for InvestorDataV1 {}
+unsafe impl Pod for InvestorDataV1 {}
+
+// byte offset of `version`, stable across all layouts
+const FUND_VERSION_OFFSET: usize = 8;
+const INVESTOR_VERSION_OFFSET: usize = 6;
+const PLATFORM_VERSION_OFFSET: usize = 1;
+
+/// Upgrades platform, fund and investor accounts to the current layouts in place.
+/// Permissionless, every step is deterministic.
+pub fn migrate_fund(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+) -> Result<(), ProgramError> {
+    let accounts_iter = &mut accounts.iter();
+    let platform_ai = next_account_info(accounts_iter)?;
+    let fund_account_ai = next_account_info(accounts_iter)?;
+
+    check_eq!(platform_ai.owner, program_id);
+    check_eq!(platform_ai.data_len(), size_of::<PlatformData>());
+    migrate_platform_data(&mut platform_ai.try_borrow_mut_data()?)?;
+
+    check_eq!(fund_account_ai.owner, program_id);
+    check_eq!(fund_account_ai.data_len(), size_of::<FundAccount>());
+    migrate_fund_data(&mut fund_account_ai.try_borrow_mut_data()?)?;
+
+    let fund_data = FundAccount::load_checked(fund_account_ai, program_id)?;
+    check!(fund_data.is_initialized(), FundError::InvalidStateAccount);
+
+    for investor_state_ai in accounts_iter {
+        check_eq!(investor_state_ai.owner, program_id);
+        check_eq!(investor_state_ai.data_len(), size_of::<InvestorData>());
+        check_eq!(InvestorData::load(investor_state_ai)?.manager, fund_data.manager_account);
+        migrate_investor_data(&mut investor_state_ai.try_borrow_mut_data()?)?;
+    }
+    Ok(())
+}
+
+pub fn migrate_fund_data(data: &mut [u8]) -> Result<(), ProgramError> {
+    loop {
+        let version = data[FUND_VERSION_OFFSET];
+        match version {
+            FUND_ACCOUNT_VERSION => return Ok(()),
+            1 => fund_v1_to_v2(from_bytes_mut(data)),
+            2 => fund_v2_to_v3(from_bytes_mut(data)),
+            _ => {
+                msg!("unsupported fund version {}", version);
+                return Err(FundError::UnsupportedAccountVersion.into())
+            }
+        }
+        msg!("fund migrated v{} -> v{}", version, data[FUND_VERSION_OFFSET]);
+    }
+}
+
+pub fn migrate_investor_data(data: &mut [u8]) -> Result<(), ProgramError> {
+    loop {
+        let version = data[INVESTOR_VERSION_OFFSET];
+        match version {
+            INVESTOR_DATA_VERSION => return Ok(()),
+            0 => investor_v0_to_v1(from_bytes_mut(data)),
+            1 => investor_v1_to_v2(from_bytes_mut(data)),
+            _ => {
+                msg!("unsupported investor version {}", version);
+                return Err(FundError::UnsupportedAccountVersion.into())
+            }
+        }
+    }
+}
+
+pub fn migrate_platform_data(data: &mut [u8]) -> Result<(), ProgramError> {
+    let version = data[PLATFORM_VERSION_OFFSET];
+    match version {
+        PLATFORM_DATA_VERSION => Ok(()),
+        _ => {
+            msg!("unsupported platform version {}", version);
+            Err(FundError::UnsupportedAccountVersion.into())
+        }
+    }
+}
+
+fn fund_v1_to_v2(fund: &mut FundAccountV1) {
+    fund.reserved = [0; FUND_V1_RESERVED];
+    fund.version = 2;
+}
+
+// the v4 account is set up again with InitMangoAccount, `is_active` gates everything else
+fn fund_v2_to_v3(fund: &mut FundAccountV2) {
+    let mango = &mut fund.mango_positions;
+    mango.perp_padding = 0;
+    mango.deposit_index = 0;
+    mango.markets_active = 0;
+    mango.deposits_active = 0;
+    mango.padding = [0; 24];
+    fund.version = 3;
+}
+
+fn investor_v0_to_v1(investor: &mut InvestorDataV0) {
+    // the first padding byte becomes `version`
+    investor.padding = [1, 0];
+}
+
+fn investor_v1_to_v2(investor: &mut InvestorDataV1) {
+    investor.padding = [0];
+    investor.margin_debt[1] = U64F64::from_num(0);
+    investor.version = 2;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytemuck::bytes_of_mut;
+
+    #[test]
+    fn fund_v1_migrates_to_current() {
+        let mut fund = FundAccountV1::zeroed();
+        fund.version = 1;
+        fund.reserved = [0xab; FUND_V1_RESERVED];
+        migrate_fund_data(bytes_of_mut(&mut fund)).unwrap();
+        assert_eq!(fund.version, FUND_ACCOUNT_VERSION);
+        assert!(fund.reserved.iter().all(|&b| b == 0));
+    }
+
+    #[test]
+    fn fund_v2_clears_mango_fields() {
+        let mut fund = FundAccountV2::zeroed();
+        fund.version = 2;
+        fund.mango_positions.perp_padding = 1;
+        fund.mango_positions.deposit_index = 2;
+        fund.mango_positions.markets_active = 3;
+        fund.mango_positions.deposits_active = 4;
+        fund.mango_positions.padding = [5; 24];
+        migrate_fund_data(bytes_of_mut(&mut fund)).unwrap();
+        let mango = &fund.mango_positions;
+        assert_eq!(fund.version, FUND_ACCOUNT_VERSION);
+        assert_eq!((mango.perp_padding, mango.deposit_index, mango.markets_active, mango.deposits_active), (0, 0, 0, 0));
+        assert_eq!(mango.padding, [0; 24]);
+    }
+
+    #[test]
+    fn current_fund_is_untouched() {
+        let mut fund = FundAccountV2::zeroed();
+        fund.version = FUND_ACCOUNT_VERSION;
+        fund.mango_positions.deposit_index = 2;
+        migrate_fund_data(bytes_of_mut(&mut fund)).unwrap();
+        assert_eq!(fund.mango_positions.deposit_index, 2);
+    }
+
+    #[test]
+    fn investor_v0_migrates_to_current() {
+        let mut investor = InvestorDataV0::zeroed();
+        investor.margin_debt[1] = U64F64::from_num(7);
+        migrate_investor_data(bytes_of_mut(&mut investor)).unwrap();
+        let investor: &InvestorDataV1 = bytemuck::from_bytes(bytemuck::bytes_of(&investor));
+        assert_eq!(investor.version, INVESTOR_DATA_VERSION);
+        assert_eq!(investor.padding, [0]);
+        assert_eq!(investor.margin_debt[1], U64F64::from_num(0));
+    }
+
+    #[test]
+    fn unknown_versions_are_rejected() {
+        let unsupported = Err(FundError::UnsupportedAccountVersion.into());
+        let mut fund = FundAccountV2::zeroed();
+        fund.version = FUND_ACCOUNT_VERSION + 1;
+        assert_eq!(migrate_fund_data(bytes_of_mut(&mut fund)), unsupported);
+        let mut investor = InvestorDataV1::zeroed();
+        investor.version = INVESTOR_DATA_VERSION + 1;
+        assert_eq!(migrate_investor_data(bytes_of_mut(&mut investor)), unsupported);
+        let mut platform = [0u8; 8];
+        platform[PLATFORM_VERSION_OFFSET] = PLATFORM_DATA_VERSION + 1;
+        assert_eq!(migrate_platform_data(&mut platform), unsupported);
+    }
+}
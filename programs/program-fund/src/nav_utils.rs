@@ -0,0 +1,121 @@
+use std::cell::RefMut;
+use std::mem::size_of;
+
+use bytemuck::bytes_of;
+use solana_program::{
+    account_info::{AccountInfo, next_account_info},
+    entrypoint::MAX_PERMITTED_DATA_INCREASE,
+    msg,
+    program::invoke_signed,
+    program_error::ProgramError,
+    program_pack::IsInitialized,
+    pubkey::Pubkey,
+    system_instruction::create_account,
+    sysvar::{Sysvar, rent::Rent},
+};
+
+use crate::error::FundError;
+use crate::migration::NAV_HISTORY_VERSION;
+use crate::state::{FundAccount, NavEntry, NavHistory};
+
+macro_rules! check {
+    ($cond:expr, $err:expr) => {
+        if !($cond) {
+            return Err(($err).into())
+        }
+    }
+}
+macro_rules! check_eq {
+    ($x:expr, $y:expr) => {
+        if ($x != $y) {
+            return Err(FundError::Default.into())
+        }
+    }
+}
+
+pub const NAV_HISTORY_SEED: &[u8] = b"nav_history";
+// hourly at most, a full buffer then spans a week or more
+pub const MIN_NAV_INTERVAL: i64 = 3600;
+
+// created through CPI, must fit in one allocation
+#[cfg(target_arch = "bpf")]
+const _: () = assert!(size_of::<NavHistory>() <= MAX_PERMITTED_DATA_INCREASE);
+
+/// Accounts expected
+/// 0. [WRITE]  Fund State Account
+/// 1. [SIGNER, WRITE] Manager Account
+/// 2. [WRITE]  NAV History Account, PDA of [fund, "nav_history"]
+/// 3. []   System Program
+pub fn init_nav_history(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    interval: i64,
+) -> Result<(), ProgramError> {
+    let accounts_iter = &mut accounts.iter();
+    let fund_account_ai = next_account_info(accounts_iter)?;
+    let manager_ai = next_account_info(accounts_iter)?;
+    let nav_history_ai = next_account_info(accounts_iter)?;
+    let system_program_ai = next_account_info(accounts_iter)?;
+
+    let mut fund_data = FundAccount::load_mut_checked(fund_account_ai, program_id)?;
+    check!(fund_data.is_initialized(), FundError::InvalidStateAccount);
+    check!(manager_ai.is_signer, ProgramError::MissingRequiredSignature);
+    check!(fund_data.manager_account == *manager_ai.key, FundError::ManagerMismatch);
+    check!(fund_data.nav_history == Pubkey::default(), ProgramError::AccountAlreadyInitialized);
+    check!(interval >= MIN_NAV_INTERVAL, ProgramError::InvalidArgument);
+
+    let (pda, nonce) = Pubkey::find_program_address(&[fund_account_ai.key.as_ref(), NAV_HISTORY_SEED], program_id);
+    check!(*nav_history_ai.key == pda, FundError::IncorrectPDA);
+    let size = size_of::<NavHistory>();
+    invoke_signed(
+        &create_account(
+            manager_ai.key,
+            nav_history_ai.key,
+            Rent::get()?.minimum_balance(size),
+            size as u64,
+            program_id,
+        ),
+        &[manager_ai.clone(), nav_history_ai.clone(), system_program_ai.clone()],
+        &[&[fund_account_ai.key.as_ref(), NAV_HISTORY_SEED, bytes_of(&nonce)]]
+    )?;
+
+    let mut nav_history = NavHistory::load_mut_checked(nav_history_ai, program_id)?;
+    nav_history.is_initialized = true;
+    nav_history.version = NAV_HISTORY_VERSION;
+    nav_history.fund = *fund_account_ai.key;
+    nav_history.interval = interval;
+    fund_data.nav_history = *nav_history_ai.key;
+    Ok(())
+}
+
+/// The fund's NAV history, if it has one, which must then be passed in `accounts`
+pub fn find_nav_history<'a, 'b>(
+    program_id: &Pubkey,
+    fund_data: &FundAccount,
+    accounts: &'a [AccountInfo<'b>],
+) -> Result<Option<RefMut<'a, NavHistory>>, ProgramError> {
+    if fund_data.nav_history == Pubkey::default() {
+        return Ok(None)
+    }
+    match accounts.iter().find(|ai| *ai.key == fund_data.nav_history) {
+        Some(nav_history_ai) => {
+            let nav_history = NavHistory::load_mut_checked(nav_history_ai, program_id)?;
+            check_eq!(nav_history.fund, fund_data.fund_pda);
+            Ok(Some(nav_history))
+        }
+        None => {
+            msg!("NAV history account missing");
+            Err(ProgramError::NotEnoughAccountKeys)
+        }
+    }
+}
+
+/// Appends the current valuation, skipped while within `interval` of the last entry
+pub fn record_nav(nav_history: &mut NavHistory, fund_data: &FundAccount, timestamp: i64) {
+    nav_history.push(NavEntry {
+        timestamp,
+        total_amount: fund_data.total_amount,
+        prev_performance: fund_data.prev_performance,
+        performance_fee: fund_data.performance_fee,
+    });
+}
@@ -25,7 +25,7 @@ use anchor_lang::{prelude::CpiContext, AnchorDeserialize};
 use arrayref::{array_ref, array_refs};
 use spl_token::state::{Account, Mint};
 
-use mango::{state::{MangoAccount, MangoGroup, MangoCache, PerpMarket, MAX_TOKENS, MAX_PAIRS, QUOTE_INDEX}, instruction::{cancel_perp_order_by_client_id, place_perp_order2}};
+use mango::{state::{MangoAccount, MangoGroup, MangoCache, PerpMarket, MAX_TOKENS, MAX_PAIRS}, instruction::{cancel_perp_order_by_client_id, place_perp_order2}};
 use mango::instruction::{ cancel_all_perp_orders,cancel_perp_order, withdraw, place_perp_order, consume_events };
 use mango::matching::{Side, OrderType, Book};
 
@@ -34,11 +34,25 @@ use volt_abi::*;
 
 use crate::{error::FundError, jup_utils::jupiter_pid::check_id};
 use crate::instruction::{FundInstruction, Data};
-use crate::state::{NUM_TOKENS, MAX_INVESTORS,MAX_LIMIT_ORDERS, NUM_PERP, FundAccount, InvestorData, PlatformData};
-// use crate::mango_utils::*;
+use crate::state::{NUM_TOKENS, MAX_INVESTORS,MAX_LIMIT_ORDERS, NUM_PERP, FundAccount, InvestorData, PlatformData, NavHistory};
 use crate::jup_utils::*;
 use crate::tokens::*;
 use crate::friktion_utils::*;
+use crate::strategy_utils::{book_strategy_exits, get_strategy_adapter, get_strategy_value, strategy_exits_unwound};
+use crate::leverage_utils::*;
+use crate::reimbursement_utils::*;
+use crate::exit_utils::*;
+use crate::mango_utils::*;
+use crate::migration::*;
+use crate::quote_utils::*;
+use crate::nav_utils::*;
+use crate::clmm_utils::*;
+use crate::serum_utils::*;
+use crate::risk_utils::*;
+use crate::rebalance_utils::*;
+use crate::execution_utils::*;
+use crate::trigger_utils::*;
+use crate::events::{emit_event, FundEvent};
 
 pub const CREATE_REIMBURSEMENT_ACCOUNT_OPCODE: u64 = 0x6f91dd5910a34ca5;
 pub const REIMBURSE_OPCODE: u64 = 0xa05c7dbb20b37258;
@@ -186,27 +200,7 @@ impl Fund {
             check_eq!(asset_vault.owner, fund_data.fund_pda);
             check_eq!(asset_vault.mint, *mint_ai.key); // check for  mint
 
-            let token_index_1 = platform_data.get_token_index(mint_ai.key, 0);
-            let token_index_2 = platform_data.get_token_index(mint_ai.key, 1);
-
-            // both indexes cant be None
-            check!(((token_index_1 != None) || (token_index_2 != None)), ProgramError::InvalidAccountData);
-
-            if token_index_1 != None {
-                fund_data.tokens[index as usize].mux = 0;
-                fund_data.tokens[index as usize].index[0] = token_index_1.unwrap() as u8;
-            }
-            else {
-                fund_data.tokens[index as usize].index[0] = 255; // Max u8
-            }
-
-            if token_index_2 != None {
-                fund_data.tokens[index as usize].mux = 1;
-                fund_data.tokens[index as usize].index[1] = token_index_2.unwrap() as u8;
-            }
-            else {
-                fund_data.tokens[index as usize].index[1] = 255;
-            }
+            set_token_slot_indexes(&platform_data, &mut fund_data.tokens[index as usize], mint_ai.key)?;
 
             fund_data.tokens[index as usize].is_active = true;
             fund_data.tokens[index as usize].balance = 0;
@@ -223,13 +217,11 @@ impl Fund {
         fund_data.number_of_active_investments = 0;
         fund_data.no_of_investments = 0;
         fund_data.mango_positions.mango_account = Pubkey::default();
-        fund_data.mango_positions.perp_markets = [u8::MAX; 3];
-        fund_data.mango_positions.deposit_index = u8::MAX;
-        fund_data.mango_positions.markets_active = 0;
-        fund_data.mango_positions.deposits_active = 0;
+        fund_data.mango_positions.perp_markets = [u8::MAX; NUM_PERP];
+        fund_data.mango_positions.is_active = false;
         fund_data.mango_positions.investor_debts = [0; 2];
         fund_data.is_initialized = true;
-        fund_data.version = 1; // v1 funds
+        fund_data.version = FUND_ACCOUNT_VERSION;
         fund_data.is_private = is_private; //set from params
         fund_data.fund_v3_index = platform_data.total_v3_funds;
 
@@ -275,6 +267,7 @@ impl Fund {
         check!(!investor_data.is_initialized(), FundError::InvestorAccountAlreadyInit);
 
         investor_data.is_initialized = true;
+        investor_data.version = INVESTOR_DATA_VERSION;
         investor_data.owner = *investor_ai.key;
         // Store manager's address
         investor_data.manager = fund_data.manager_account;
@@ -314,6 +307,12 @@ impl Fund {
         investor_data.amount_in_router += amount;
         fund_data.amount_in_router += amount;
 
+        emit_event(&FundEvent::Deposit {
+            fund: *fund_account_ai.key,
+            investor: *investor_ai.key,
+            investor_state: *investor_state_ai.key,
+            amount,
+        });
         Ok(())
     }
 
@@ -323,17 +322,13 @@ impl Fund {
         accounts: &[AccountInfo]
     ) -> Result<(), ProgramError> {
 
-        const NUM_FIXED:usize = 13;
+        const NUM_FIXED:usize = 9;
 
         let(fixed_accs, investor_state_accs) = array_refs![accounts, NUM_FIXED; ..;];
 
         let [
             platform_ai,
             fund_account_ai,
-            mango_account_ai,
-            mango_group_ai,
-            mango_cache_ai,
-            mango_prog_ai,
             manager_ai,
             router_btoken_ai,
             fund_btoken_ai,
@@ -350,32 +345,29 @@ impl Fund {
         check!(manager_ai.is_signer, FundError::IncorrectProgramId);
         check_eq!(fund_data.manager_account, *manager_ai.key);
         check!(fund_data.is_initialized(), ProgramError::AccountAlreadyInitialized);
+        // the investors of an unsealed reimbursement snapshot are fixed
+        check!(!fund_data.reimbursement_snapshot_pending, FundError::ReimbursementSnapshotPending);
 
         // check if router PDA matches
         check!(*pda_router_ai.key == platform_data.router, FundError::IncorrectPDA);
+        // passed after the investor state accounts when the fund has one
+        let mut nav_history = find_nav_history(program_id, &fund_data, investor_state_accs)?;
+        let nav_history_key = fund_data.nav_history;
 
-        // update start performance for investors
-        // let (perp_pnl, usdc_deposits) = get_mango_valuation(
-        //     &fund_data,
-        //     &mango_account_ai,
-        //     &mango_group_ai,
-        //     &mango_cache_ai,
-        //     &mango_prog_ai
-        // )?;
-        // let mango_val = U64F64::from_fixed(usdc_deposits.checked_add(token_deposits_val).unwrap().checked_add(perp_pnl).unwrap());
-        // let mango_val = U64F64::from_fixed(usdc_deposits.checked_add(perp_pnl).unwrap());
-        // msg!("perp pnl:: {}, usdc_deposits:: {}, mango_val:: {}", perp_pnl, usdc_deposits, mango_val);
-        
+        // update start performance for investors, mango equity is valued from UpdateMangoValue
         update_amount_and_performance(
             &platform_data,
             &mut fund_data,
-            true
+            true,
+            nav_history.as_deref_mut()
         )?;
 
         let mut transferable_amount: u64 = 0;
+        let investor_state_accs: Vec<&AccountInfo> = investor_state_accs.iter().filter(|ai| *ai.key != nav_history_key).collect();
+        let investor_count = investor_state_accs.len() as u8;
         // let mut fee: u64 = 0;
 
-        for investor_state_ai in investor_state_accs.iter() {
+        for investor_state_ai in investor_state_accs {
             let index = fund_data.get_investor_index(investor_state_ai.key).unwrap();
             let mut investor_data = InvestorData::load_mut_checked(investor_state_ai, program_id)?;
 
@@ -469,9 +461,16 @@ impl Fund {
         update_amount_and_performance(
             &platform_data,
             &mut fund_data,
-            false
+            false,
+            nav_history.as_deref_mut()
         )?;
-        
+
+        emit_event(&FundEvent::Transfer {
+            fund: *fund_account_ai.key,
+            amount: transferable_amount,
+            investor_count,
+            performance: fund_data.prev_performance.to_bits(),
+        });
         Ok(())
     }
 
@@ -504,12 +503,12 @@ impl Fund {
         let mut fund_data = FundAccount::load_mut_checked(fund_account_ai, program_id)?;
         let mut investor_data = InvestorData::load_mut_checked(investor_state_ai, program_id)?;
 
-        check!(investor_ai.is_signer, FundError::IncorrectSignature);
         check_eq!(investor_data.owner, *investor_ai.key);
         check_eq!(investor_data.manager, fund_data.manager_account);
 
         // Manager has not transferred to vault
         if investor_data.amount_in_router != 0  {
+            check!(investor_ai.is_signer, FundError::IncorrectSignature);
             invoke_signed(
                 &(spl_token::instruction::transfer(
                     token_prog_ai.key,
@@ -529,6 +528,13 @@ impl Fund {
             )?;
             fund_data.amount_in_router = fund_data.amount_in_router.checked_sub(investor_data.amount_in_router).unwrap();
             fund_data.no_of_investments = fund_data.no_of_investments.checked_sub(1).unwrap();
+            emit_event(&FundEvent::Withdraw {
+                fund: *fund_account_ai.key,
+                investor_state: *investor_state_ai.key,
+                share: 0,
+                token_amounts: [0; NUM_TOKENS],
+                amount_in_router: investor_data.amount_in_router,
+            });
             investor_data.amount_in_router = 0;
             investor_data.is_initialized = false;
             let index = fund_data.get_investor_index(investor_state_ai.key).unwrap();
@@ -536,18 +542,17 @@ impl Fund {
             // close investor account
             close_investor_account(investor_ai, investor_state_ai)?;
         } else {
-            check!(investor_data.has_withdrawn == true && 
-                (investor_data.withdrawn_from_margin == true || investor_data.margin_debt[0] == 0) ,
-                // &&
-                // (investor_data.withdrawn_ul_from_friktion == true || investor_data.withdrawn_ftokens_from_friktion == true),
-                 FundError::InvalidInstruction);
+            // last exit step, anyone can crank it once the others are done, the investor is paid as is
+            check!(investor_data.has_withdrawn, FundError::InvalidInstruction);
+            check!(refresh_exit_status(&fund_data, &mut investor_data) == ExitStatus::ReadyToWithdraw, FundError::ExitStepPending);
             for i in 0..NUM_TOKENS {
                 // TODO:: check if fund_debt on inv_acc <= fund_debt on fund
                 if investor_data.token_debts[i] < 10 {
                     continue;
                 }
+                check_owner(&inv_token_accs[i], investor_ai.key)?;
                 let mint_1 = platform_data.token_list[investor_data.token_indexes[i] as usize].mint;
-                let mint_2 = platform_data.token_list[fund_data.tokens[i].index[fund_data.tokens[i].mux as usize] as usize].mint;
+                let mint_2 = platform_data.token_list[fund_data.tokens[i].get_active_index()].mint;
                 check_eq!(mint_1, mint_2);
                 let manager_account = fund_data.manager_account;
                 let nonce = fund_data.signer_nonce;
@@ -581,8 +586,8 @@ impl Fund {
             // check_eq!(investor_data.margin_debt[1], 0);
             // close investor account
             //&& investor_data.margin_debt[1] == 0
-            if investor_data.margin_debt[0] != 0  {
-                fund_data.mango_positions.investor_debts[0] = fund_data.mango_positions.investor_debts[0].checked_sub(U64F64::to_num(investor_data.margin_debt[0])).unwrap();
+            if investor_data.margin_debt != 0  {
+                fund_data.mango_positions.investor_debts[0] = fund_data.mango_positions.investor_debts[0].checked_sub(U64F64::to_num(investor_data.margin_debt)).unwrap();
             }
             investor_data.amount = 0;
             investor_data.start_performance = U64F64!(0);
@@ -600,201 +605,87 @@ impl Fund {
         program_id: &Pubkey,
         accounts: &[AccountInfo],
     ) -> Result<(), ProgramError> {
-        const NUM_FIXED:usize = 11;
-        let accounts = array_ref![accounts, 0, NUM_FIXED + 4*NUM_PERP];
-        let (
-            fixed_accs,
-            perp_accs,
-        ) = array_refs![accounts, NUM_FIXED, 4*NUM_PERP];
-
+        const NUM_FIXED:usize = 5;
+        check!(accounts.len() >= NUM_FIXED, ProgramError::NotEnoughAccountKeys);
+        // the NAV history account goes last when the fund has one
+        let (fixed_accs, nav_history_accs) = accounts.split_at(NUM_FIXED);
         let [
             platform_ai,
             fund_account_ai,
             investor_state_ai,
             investor_ai,
-            mango_account_ai,
-            mango_group_ai,
-            mango_cache_ai,
-            mango_prog_ai,
-            referrer_mango_account_ai,
             ix_sysvar_ai,
-            default_ai,
-        ] = fixed_accs;
+        ] = array_ref![fixed_accs, 0, NUM_FIXED];
 
         let platform_data = PlatformData::load_mut_checked(platform_ai, program_id)?;
         let mut fund_data = FundAccount::load_mut_checked(fund_account_ai, program_id)?;
         let mut investor_data = InvestorData::load_mut_checked(investor_state_ai, program_id)?;
-        // check_eq!(*mango_prog_ai.key, mango_v3_id::ID);
         check!(investor_data.owner == *investor_ai.key, ProgramError::MissingRequiredSignature);
         check!(investor_ai.is_signer, ProgramError::MissingRequiredSignature);
         check_eq!(investor_data.manager, fund_data.manager_account);
         check_eq!(investor_data.has_withdrawn, false);
-        let ix = solana_program::sysvar::instructions::load_instruction_at_checked(1, ix_sysvar_ai)?;
-        check!(ix.program_id == *program_id && ix.data[0] == 30, FundError::InvalidInstruction); 
-        
+        check!(!fund_data.reimbursement_snapshot_pending, FundError::ReimbursementSnapshotPending);
+        // with perps open, MangoInvestorReduce must follow to reduce them and book the investor's part
+        let defer_mango = has_perp_markets(&fund_data);
+        if defer_mango {
+            let current = solana_program::sysvar::instructions::load_current_index_checked(ix_sysvar_ai)?;
+            let ix = solana_program::sysvar::instructions::load_instruction_at_checked(current as usize + 1, ix_sysvar_ai)?;
+            check!(ix.program_id == *program_id && FundInstruction::unpack(&ix.data) == Some(FundInstruction::MangoInvestorReduce), FundError::InvalidInstruction);
+            check!(ix.accounts.get(1).map(|meta| meta.pubkey) == Some(*investor_state_ai.key), FundError::InvalidInstruction);
+        }
+        let mut nav_history = find_nav_history(program_id, &fund_data, nav_history_accs)?;
 
         if investor_data.amount != 0 && investor_data.start_performance != ZERO_U64F64 {
-            // let (perp_pnl_before, usdc_deposits_before) = get_mango_valuation(
-            //     &fund_data,
-            //     &mango_account_ai,
-            //     &mango_group_ai,
-            //     &mango_cache_ai,
-            //     &mango_prog_ai
-            // )?;
-            // let mango_val_before = U64F64::from_fixed(usdc_deposits_before.checked_add(perp_pnl_before).unwrap());
-            // msg!("pnl: {:?}, val: {:?}", perp_pnl_before, mango_val_before);
             update_amount_and_performance(
                 &platform_data,
                 &mut fund_data,
-                true
+                true,
+                nav_history.as_deref_mut()
             )?;
             let share = get_share(&mut fund_data, &mut investor_data)?;
-            // msg!("share {:?}", share);
             investor_data.share = share;
 
             for i in 0..NUM_TOKENS {
-                let mut withdraw_amount: u64 = U64F64::to_num(
-                    U64F64::from_num(fund_data.tokens[i].balance.checked_sub(fund_data.tokens[i].debt).unwrap())
-                .checked_mul(share).unwrap());
-                investor_data.token_indexes[i] = fund_data.tokens[i].index[fund_data.tokens[i].mux as usize];
-                if fund_data.number_of_active_investments == 1 { // ceil for last investor
-                    withdraw_amount += 1; // ceil
-                    if withdraw_amount + fund_data.tokens[i].debt > fund_data.tokens[i].balance {
-                        withdraw_amount -= 1;
-                    }
-                }
+                let withdraw_amount = get_token_withdraw_amount(&fund_data, i, share);
+                investor_data.token_indexes[i] = fund_data.tokens[i].get_active_index() as u8;
                 investor_data.token_debts[i] = withdraw_amount;
                 fund_data.tokens[i].debt += withdraw_amount;
                 check!(fund_data.tokens[i].balance >= fund_data.tokens[i].debt, ProgramError::InvalidAccountData);
             }
 
-            if fund_data.friktion_vault.is_active {
-                let ul_withdraw_amount: u64 = U64F64::to_num(
-                    U64F64::from_num(fund_data.friktion_vault.ul_token_balance.checked_sub(fund_data.friktion_vault.ul_token_debt).unwrap())
-                .checked_mul(share).unwrap());
-                investor_data.friktion_ul_debt = ul_withdraw_amount;
-                fund_data.friktion_vault.ul_token_debt = fund_data.friktion_vault.ul_token_debt.checked_add(ul_withdraw_amount).unwrap();
-                check!(fund_data.friktion_vault.ul_token_balance >= fund_data.friktion_vault.ul_token_debt, ProgramError::InsufficientFunds);
-                
-                let fc_withdraw_amount: u64 = U64F64::to_num(
-                    U64F64::from_num(fund_data.friktion_vault.fc_token_balance.checked_sub(fund_data.friktion_vault.fc_token_debt).unwrap())
-                .checked_mul(share).unwrap());
-                investor_data.friktion_fc_debt = fc_withdraw_amount;
-                fund_data.friktion_vault.fc_token_debt = fund_data.friktion_vault.fc_token_debt.checked_add(fc_withdraw_amount).unwrap();
-                check!(fund_data.friktion_vault.fc_token_balance >= fund_data.friktion_vault.fc_token_debt, ProgramError::InsufficientFunds);
-            
+            // one investor's parts unwind at a time, so the venue fees come out of their debts
+            check!(strategy_exits_unwound(&fund_data), FundError::ExitStepPending);
+            book_strategy_exits(&mut fund_data, &mut investor_data, share)?;
+            if !strategy_exits_unwound(&fund_data) {
+                fund_data.strategy_exit_investor = *investor_state_ai.key;
             }
+            let now_ts = Clock::get()?.unix_timestamp;
+            book_leverage_exit(&platform_data, &mut fund_data, &mut investor_data, share, now_ts)?;
+            book_mango_exit(&platform_data, &mut fund_data, &mut investor_data, share, now_ts)?;
+            // after the deficits, those only come out of what the vaults hold
+            book_serum_exits(&mut fund_data, &mut investor_data, share)?;
 
-            
-
-            // if mango_val_before > 0 {
-            //     let mut perp_vals: [i64; 4] = get_perp_vals(&fund_data, &mango_account_ai, &mango_prog_ai, &mango_group_ai).unwrap();
-            //     // msg!("closing perps: {:?}", perp_vals);
-            //     let manager_account = fund_data.manager_account;
-            //     let nonce = fund_data.signer_nonce;
-                
-            //     drop(fund_data);
-                
-            //     for i in 0..3 {
-            //         if perp_vals[i] != 0 {
-            //             let mut side:Side = Side::Ask;
-            //             if perp_vals[i] < 0 {
-            //                 side = Side::Bid;
-            //                 perp_vals[i] = perp_vals[i].checked_mul(-1).unwrap();
-            //             }
-            //             let mut perp_close_amount: i64 = U64F64::to_num(U64F64::from_num(perp_vals[i]).checked_mul(share).unwrap());
-            //             // msg!("side: {:?}, qty: {:?}", side, perp_close_amount);
-            //             if perp_vals[i] > perp_close_amount {
-            //                 perp_close_amount = perp_close_amount.checked_add(1).unwrap();
-            //             }
-            //             let open_orders_accs = [Pubkey::default(); MAX_PAIRS];
-            //             invoke_signed(
-            //                 &place_perp_order(mango_prog_ai.key,
-            //                     mango_group_ai.key, mango_account_ai.key, fund_account_ai.key,
-            //                     mango_cache_ai.key, perp_accs[i*4].key, perp_accs[(i*4) + 1].key, perp_accs[(i*4) + 2].key, perp_accs[(i*4) + 3].key, Some(referrer_mango_account_ai.key), &open_orders_accs,
-            //                     side, i64::MAX, perp_close_amount, 0, OrderType::Market, true)?,
-            //                 &[
-            //                     mango_prog_ai.clone(),
-            //                     mango_group_ai.clone(),
-            //                     mango_account_ai.clone(),
-            //                     fund_account_ai.clone(),
-            //                     mango_cache_ai.clone(),
-            //                     perp_accs[i*4].clone(),
-            //                     perp_accs[i*4 + 1].clone(),
-            //                     perp_accs[i*4 + 2].clone(),
-            //                     perp_accs[i*4 + 3].clone(),
-            //                     referrer_mango_account_ai.clone(),
-            //                     default_ai.clone(),
-            //                 ],
-            //                 &[&[bytes_of(&manager_account), bytes_of(&nonce)]]
-            //             )?;
-    
-            //             // mango_place_perp_order_investor(&fund_data, instruction_accounts, mango_perp_index, side, perp_close_amount);
-            //             // pub fn mango_place_perp_order_investor(fund_data: &FundAccount,accounts: &[AccountInfo],perp_market_id: u8,side: Side,quantity: i64
-            //         }
-            //     }
-            //     fund_data = FundAccount::load_mut_checked(fund_account_ai, program_id)?;
-            // } 
-
-            // msg!("usdc {:?}, tok {:?}", usdc_deposits_before, token_deposits_before);
-            // let (perp_pnl_after, usdc_deposits_after) = get_mango_valuation(
-            //     &fund_data,
-            //     &mango_account_ai,
-            //     &mango_group_ai,
-            //     &mango_cache_ai,
-            //     &mango_prog_ai
-            // )?;
-            // let mut mango_val_after = U64F64::from_fixed(usdc_deposits_after.checked_add(token_deposits_val_after).unwrap().checked_add(perp_pnl_after).unwrap());
-            // let mut mango_val_after = U64F64::from_fixed(usdc_deposits_after.checked_add(perp_pnl_after).unwrap());
-            // let mut investor_mango_value = mango_val_after.checked_mul(share).unwrap();
-            // mango_val_after = mango_val_after.checked_sub(investor_mango_value).unwrap();
-            // msg!("usdc* {:?}, tok* {:?}", usdc_deposits_after, token_deposits_after);
-            
-            // if perp_pnl_after < 0 {
-            //     // let pnl_ratio:U64F64 = mango_val_after.checked_div(U64F64::from_num(
-            //     //     usdc_deposits_after
-            //     //     //.checked_add(token_deposits_val_after).unwrap()
-            //     // )).unwrap();
-            //     // msg!("pnl -ve {:?}", pnl_ratio);
-            //     usdc_deposits_after = usdc_deposits_after.checked_sub(perp_pnl_after).unwrap();
-            //     // usdc_deposits_after = usdc_deposits_after.checked_mul(I80F48::from_fixed(pnl_ratio)).unwrap();
-            //     // token_deposits_after = token_deposits_after.checked_mul(I80F48::from_fixed(pnl_ratio)).unwrap();
-            // } else {
-            //     usdc_deposits_after = usdc_deposits_after.checked_add(perp_pnl_after).unwrap();
-            // }
-            // msg!("*pnl: {:?}, mango_val {:?}", perp_pnl_after, mango_val_after);
-            // let pnl_diff = perp_pnl_before.checked_sub(perp_pnl_after).unwrap();
-            // msg!("pnl_diff {:?}", pnl_diff);
-            // let mut pnl_diff_ratio = U64F64!(1);
-            // if pnl_diff > 0 {
-            //     // pnl_diff_ratio = pnl_diff_ratio.checked_sub(U64F64::from_fixed(pnl_diff).checked_div(investor_mango_value).unwrap()).unwrap();
-            //     let comp = U64F64::from_num(pnl_diff);
-            //     mango_val_after = mango_val_after.checked_add(comp).unwrap();
-            //     investor_mango_value = investor_mango_value.checked_sub(comp).unwrap();
-            // }
-            
-            investor_data.margin_position_id[0] = QUOTE_INDEX as u64;
-            // investor_data.margin_position_id[1] = fund_data.mango_positions.deposit_index as u64;
-            // investor_data.margin_debt[0] = U64F64::from_fixed(investor_mango_value.checked_mul(I80F48::from_fixed(share)).unwrap()).checked_mul(pnl_diff_ratio).unwrap();
-            // investor_data.margin_debt[0] = investor_mango_value;
-            
-            // investor_data.margin_debt[1] = U64F64::from_fixed(token_deposits_after.checked_mul(I80F48::from_fixed(share)).unwrap()).checked_mul(pnl_diff_ratio).unwrap();
-            // msg!("investor debts: {:?}", investor_data.margin_debt);
-            // fund_data.mango_positions.investor_debts[0] = fund_data.mango_positions.investor_debts[0].checked_add(U64F64::to_num(investor_mango_value)).unwrap();
-            // fund_data.mango_positions.investor_debts[1] = fund_data.mango_positions.investor_debts[1].checked_add(U64F64::to_num(investor_data.margin_debt[1])).unwrap();
             fund_data.number_of_active_investments -= 1;
             fund_data.no_of_investments -= 1;
             investor_data.has_withdrawn = true;
-            let nonce = fund_data.signer_nonce;
-            let manager_account = fund_data.manager_account;
-            // let mut mango_val_after = U64F64::from_fixed(usdc_deposits_after.checked_add(token_deposits_val_after).unwrap().checked_add(perp_pnl_after).unwrap());
-
-            update_amount_and_performance(
-                &platform_data,
-                &mut fund_data,
-                false
-            )?;
-
+            refresh_exit_status(&fund_data, &mut investor_data);
+            emit_event(&FundEvent::Withdraw {
+                fund: *fund_account_ai.key,
+                investor_state: *investor_state_ai.key,
+                share: share.to_bits(),
+                token_amounts: investor_data.token_debts,
+                amount_in_router: 0,
+            });
+
+            // the fund is revalued by MangoInvestorReduce once the mango part is booked
+            if !defer_mango {
+                update_amount_and_performance(
+                    &platform_data,
+                    &mut fund_data,
+                    false,
+                    nav_history.as_deref_mut()
+                )?;
+            }
         }
         Ok(())
     }
@@ -975,23 +866,20 @@ impl Fund {
         check!(manager_ai.is_signer, ProgramError::MissingRequiredSignature);
         check_eq!(fund_data.manager_account, *manager_ai.key);
         
-        check!(swap_index < 2, ProgramError::InvalidArgument);
+        check!(swap_index < 2 || is_clmm(swap_index), ProgramError::InvalidArgument);
         
         let nonce = fund_data.signer_nonce;
         drop(fund_data);
         let (source_info, dest_info) = match swap_index {
             0 => swap_instruction_raydium(&data, nonce, accounts)?,
             1 => swap_instruction_orca(&data, nonce, accounts)?,
+            WHIRLPOOL_TOKEN_ID => swap_instruction_whirlpool(&data, nonce, accounts)?,
+            RAYDIUM_CLMM_TOKEN_ID => swap_instruction_raydium_clmm(&data, nonce, accounts)?,
             _ => return Err(ProgramError::InvalidArgument)
         };
 
         let source_index = platform_data.get_token_index(&source_info.mint, swap_index);
         let dest_index = platform_data.get_token_index(&dest_info.mint, swap_index);
-        msg!("source_index:: {:?} ", source_index);
-        msg!("dest index:: {:?}", dest_index);
-
-        msg!("source mint:: {:?}", source_info.mint);
-        msg!("dest mint:: {:?}", dest_info.mint);
 
         fund_data = FundAccount::load_mut_checked(fund_account_ai, program_id)?;
 
@@ -999,39 +887,50 @@ impl Fund {
         check_eq!(source_info.owner, fund_data.fund_pda);
         check_eq!(dest_info.owner, fund_data.fund_pda);
 
-        if source_info.mint == usdc_mint::ID {
-            let di = fund_data.get_token_slot(dest_index.unwrap(), swap_index as usize).unwrap();
-            fund_data.tokens[0].balance = source_info.amount;
-            fund_data.tokens[di].balance = dest_info.amount;
-            fund_data.tokens[di].mux = swap_index;
-            // check the balance validity
-            check!(fund_data.tokens[di].balance >= fund_data.tokens[di].debt, ProgramError::InsufficientFunds);
-        }
-        else if dest_info.mint == usdc_mint::ID {
-            let si = fund_data.get_token_slot(source_index.unwrap(), swap_index as usize).unwrap();
-            fund_data.tokens[0].balance = dest_info.amount;
-            fund_data.tokens[si].balance = source_info.amount;
-            fund_data.tokens[si].mux = swap_index;
-            // check balance validity
-            check!(fund_data.tokens[si].balance >= fund_data.tokens[si].debt, ProgramError::InsufficientFunds);
-        }
-        else {
-            let si = fund_data.get_token_slot(source_index.unwrap(), swap_index as usize).unwrap();
-            let di = fund_data.get_token_slot(dest_index.unwrap(), swap_index as usize).unwrap();
-
-            fund_data.tokens[si].balance = source_info.amount;
-            fund_data.tokens[di].balance = dest_info.amount;
+        // USDC always sits in slot 0 and keeps its mux, slots listed on the venue after they were
+        // added need RefreshTokenSlotIndexes first
+        let si = if source_info.mint == usdc_mint::ID { 0 } else {
+            source_index.and_then(|index| fund_data.get_token_slot(index, swap_index as usize)).ok_or(FundError::InvalidTokenAccount)?
+        };
+        let di = if dest_info.mint == usdc_mint::ID { 0 } else {
+            dest_index.and_then(|index| fund_data.get_token_slot(index, swap_index as usize)).ok_or(FundError::InvalidTokenAccount)?
+        };
+        let amount_in = fund_data.tokens[si].balance.saturating_sub(source_info.amount);
+        let amount_out = dest_info.amount.saturating_sub(fund_data.tokens[di].balance);
 
+        fund_data.tokens[si].balance = source_info.amount;
+        fund_data.tokens[di].balance = dest_info.amount;
+        if si != 0 {
             fund_data.tokens[si].mux = swap_index;
+        }
+        if di != 0 {
             fund_data.tokens[di].mux = swap_index;
-
-            check!(fund_data.tokens[si].balance >= fund_data.tokens[si].debt, ProgramError::InsufficientFunds);
-            check!(fund_data.tokens[di].balance >= fund_data.tokens[di].debt, ProgramError::InsufficientFunds);
         }
 
+        // check balance validity
+        check!(fund_data.tokens[si].balance >= fund_data.tokens[si].debt, ProgramError::InsufficientFunds);
+        check!(fund_data.tokens[di].balance >= fund_data.tokens[di].debt, ProgramError::InsufficientFunds);
         // check USDC balance validity
         check!(fund_data.tokens[0].balance >= fund_data.tokens[0].debt, ProgramError::InsufficientFunds);
 
+        // pricing is only needed, and prices only need to be fresh, when limits are set
+        let mut input_value = U64F64!(0);
+        if has_risk_limits(&fund_data) {
+            let now_ts = Clock::get()?.unix_timestamp;
+            input_value = U64F64::from_num(amount_in).checked_mul(get_slot_price(&platform_data, &fund_data, si, now_ts)?).unwrap();
+            check_trade_limits(&mut fund_data, input_value, now_ts, true)?;
+        }
+        check_mandate(&platform_data, &mut fund_data, si, &[di], Clock::get()?.unix_timestamp)?;
+        check_leverage(&fund_data, Clock::get()?.unix_timestamp)?;
+
+        emit_event(&FundEvent::Swap {
+            fund: *fund_account_ai.key,
+            token_in_slot: si as u8,
+            token_out_slot: di as u8,
+            amount_in,
+            amount_out,
+            input_value: input_value.to_bits(),
+        });
         Ok(())
     }
 
@@ -1054,16 +953,14 @@ impl Fund {
         program_id: &Pubkey,
         accounts: &[AccountInfo],
     ) -> Result<(), ProgramError> {
-        const NUM_FIXED:usize = 11;
+        const NUM_FIXED:usize = 7;
+        // the NAV history account goes last when the fund has one
+        let nav_history_accs = accounts.get(NUM_FIXED..).unwrap_or_default();
         let accounts = array_ref![accounts, 0, NUM_FIXED];
 
         let [
             platform_ai,
             fund_account_ai,
-            mango_account_ai,
-            mango_group_ai,
-            mango_cache_ai,
-            mango_prog_ai,
             manager_ai,
             fund_btoken_ai,
             manager_btoken_ai,
@@ -1076,18 +973,12 @@ impl Fund {
         // check if manager signed the tx
         check!(manager_ai.is_signer, FundError::IncorrectSignature);
         check_eq!(fund_data.manager_account, *manager_ai.key);
-        // let (perp_pnl, usdc_deposits) = get_mango_valuation(
-        //     &fund_data,
-        //     &mango_account_ai,
-        //     &mango_group_ai,
-        //     &mango_cache_ai,
-        //     &mango_prog_ai
-        // )?;
-        // let mango_val = U64F64::from_fixed(usdc_deposits.checked_add(perp_pnl).unwrap());
+        let mut nav_history = find_nav_history(program_id, &fund_data, nav_history_accs)?;
         update_amount_and_performance(
             &platform_data,
             &mut fund_data,
-            true
+            true,
+            nav_history.as_deref_mut()
         )?;
 
         let performance_fee_manager: u64 = U64F64::to_num(U64F64::from_num(fund_data.performance_fee)
@@ -1133,6 +1024,11 @@ impl Fund {
         ];
         invoke_signed(&transfer_instruction, &transfer_accs, &[&signer_seeds])?;
         msg!("Transfer Complete");
+        emit_event(&FundEvent::FeeClaim {
+            fund: *fund_account_ai.key,
+            manager_fee: performance_fee_manager,
+            investin_fee: performance_fee_investin,
+        });
 
         fund_data = FundAccount::load_mut_checked(fund_account_ai, program_id)?;
 
@@ -1142,7 +1038,8 @@ impl Fund {
         update_amount_and_performance(
             &platform_data,
             &mut fund_data,
-            false
+            false,
+            nav_history.as_deref_mut()
         )?;
 
         Ok(())
@@ -1175,7 +1072,9 @@ impl Fund {
         check_eq!(fund_data.tokens[index as usize].is_active, true);
         check_eq!(fund_data.tokens[index as usize].vault, *vault_ai.key);
 
-        let token_mint = platform_data.token_list[fund_data.tokens[index as usize].index[fund_data.tokens[index as usize].mux as usize] as usize].mint;
+        let token_mint = platform_data.token_list[fund_data.tokens[index as usize].get_active_index()].mint;
+        check!(strategy_exits_unwound(&fund_data), FundError::InvalidStateAccount);
+        check!(get_serum_pending(&fund_data).is_none(), FundError::ExitStepPending);
         
         let nonce = fund_data.signer_nonce;
         
@@ -1252,7 +1151,7 @@ impl Fund {
             
             // check_eq!(platform_data.is_initialized(), false);  REVERT-MAINNET
             platform_data.is_initialized = true;
-            platform_data.version = 1;
+            platform_data.version = PLATFORM_DATA_VERSION;
             platform_data.no_of_active_funds = 0;
             
             // add router pda
@@ -1315,8 +1214,9 @@ pub fn init_mango_reimbursement(
     program_id: &Pubkey,
     accounts: &[AccountInfo],
 ) -> Result<(), ProgramError> {
-    const NUM_FIXED: usize = 7;
-    let accounts = array_ref![accounts, 0, NUM_FIXED];
+    const NUM_FIXED: usize = 9;
+    check!(accounts.len() >= NUM_FIXED, ProgramError::NotEnoughAccountKeys);
+    let (fixed_accs, rest_accs) = accounts.split_at(NUM_FIXED);
     let [
         fund_pda_ai,
         mango_reimbursement_program_ai,
@@ -1325,14 +1225,22 @@ pub fn init_mango_reimbursement(
         payer_ai,
         system_program_ai,
         sysvar_rent_ai,
-    ] = accounts;
+        reimbursement_table_ai,
+        platform_ai,
+    ] = array_ref![fixed_accs, 0, NUM_FIXED];
+    let accounts = array_ref![fixed_accs, 0, NUM_FIXED - 2];
 
     assert_eq!(*system_program_ai.key, solana_program::system_program::id(), "System Program ID mismatch");
     
 
     let mut fund_data = FundAccount::load_mut_checked(fund_pda_ai, program_id)?;
+    check!(!fund_data.reimbursement_init, ProgramError::AccountAlreadyInitialized);
     fund_data.reimbursement_init = true;
+    fund_data.has_reimbursement_table = true;
     let (manager_account, signer_nonce) = (fund_data.manager_account, fund_data.signer_nonce);
+    // a first page of investors, then the NAV history account if the fund has one
+    let page_len = if fund_data.nav_history == Pubkey::default() { rest_accs.len() } else { rest_accs.len().saturating_sub(1) };
+    let (investor_state_accs, nav_history_accs) = rest_accs.split_at(page_len);
 
     let signer_seeds = [
         &manager_account.as_ref(),
@@ -1353,7 +1261,17 @@ pub fn init_mango_reimbursement(
         &[&signer_seeds]
     )?;
 
-    Ok(())
+    // the snapshot shares are valued at the current NAV
+    {
+        let platform_data = PlatformData::load_checked(platform_ai, program_id)?;
+        let mut fund_data = FundAccount::load_mut_checked(fund_pda_ai, program_id)?;
+        let mut nav_history = find_nav_history(program_id, &fund_data, nav_history_accs)?;
+        update_amount_and_performance(&platform_data, &mut fund_data, true, nav_history.as_deref_mut())?;
+    }
+
+    // investors in the fund now are the ones the reimbursement is paid out to,
+    // the rest of them are added by AddReimbursementInvestors
+    snapshot_reimbursement(program_id, fund_pda_ai, payer_ai, system_program_ai, reimbursement_table_ai, investor_state_accs)
 }
 
 pub fn mango_reimbursement(
@@ -1363,7 +1281,7 @@ pub fn mango_reimbursement(
     index_into_table: usize, 
 ) -> Result<(), ProgramError> {
     const NUM_FIXED: usize = 12;
-    let accounts = array_ref![accounts, 0, NUM_FIXED];
+    check!(accounts.len() >= NUM_FIXED, ProgramError::NotEnoughAccountKeys);
     let [
         fund_pda_ai,
         mango_reimbursement_program_ai,
@@ -1377,13 +1295,17 @@ pub fn mango_reimbursement(
         token_program_ai,
         system_program_ai,
         sysvar_rent_ai,
-    ] = accounts;
+    ] = array_ref![accounts, 0, NUM_FIXED];
+    // funds that ran InitReimbursement before the table existed have none
+    let reimbursement_table_ai = accounts.get(NUM_FIXED);
+    let accounts = array_ref![accounts, 0, NUM_FIXED];
 
     assert_eq!(*system_program_ai.key, solana_program::system_program::id(), "System Program ID mismatch");
     assert_eq!(*token_program_ai.key, spl_token::id(), "Token Program ID mismatch");
 
     let fund_data = FundAccount::load_checked(fund_pda_ai, program_id)?;
     assert_eq!(*fund_usdc_vault_ai.key, fund_data.tokens[0].vault);
+    check!(!fund_data.has_reimbursement_table || reimbursement_table_ai.is_some(), ProgramError::NotEnoughAccountKeys);
     let (manager_account, signer_nonce) = (fund_data.manager_account, fund_data.signer_nonce);
 
     let signer_seeds = [
@@ -1415,8 +1337,13 @@ pub fn mango_reimbursement(
 
     let fund_usdc_vault_data = parse_token_account(fund_usdc_vault_ai)?;
     let mut fund_data = FundAccount::load_mut_checked(fund_pda_ai, program_id)?;
+    let received = fund_usdc_vault_data.amount.checked_sub(fund_data.tokens[0].balance).ok_or(FundError::InvalidStateAccount)?;
     fund_data.tokens[0].balance = fund_usdc_vault_data.amount;
     fund_data.reimbursement_claimed = true;
+    // without a table the reimbursement stays in the fund NAV, as it did before snapshots
+    if fund_data.has_reimbursement_table {
+        credit_reimbursement(program_id, fund_pda_ai, &mut fund_data, reimbursement_table_ai.unwrap(), received)?;
+    }
 
     Ok(())
 }
@@ -1570,23 +1497,21 @@ pub fn mango_reimbursement(
                 msg!("FundInstruction::FlushDebts");
                 return Self::flush_debts(program_id, accounts, index, count);
             }
-            FundInstruction::JupiterSwap => {
+            FundInstruction::JupiterSwap { data } => {
                 msg!("FundInstruction::JupiterSwap");
-                let (&_op, op_data) = array_refs![data, 1; ..;];
-                return jup_swap(program_id, accounts, op_data);
+                return jup_swap(program_id, accounts, &data);
             }
             FundInstruction::SetSwapGuard {token_in_fund_slot, token_out_fund_slot, amount_in} => {
                 msg!("FundInstruction::SetSwapGuard");
-                return set_swap_guard(program_id, accounts, token_in_fund_slot, token_out_fund_slot, amount_in);
+                return set_swap_guard(program_id, accounts, token_in_fund_slot, token_out_fund_slot, amount_in, 0);
             }
             FundInstruction::CheckSwapGuard => {
                 msg!("FundInstruction::CheckSwapGuard");
                 return check_swap_guard(program_id, accounts);
             }
-            FundInstruction::InitOpenOrderAccounts => {
+            FundInstruction::InitOpenOrderAccounts { data } => {
                 msg!("FundInstruction::InitOpenOrders");
-                let (&_op, op_data) = array_refs![data, 1; ..;];
-                return init_open_order_accs(program_id, accounts, op_data);
+                return init_open_order_accs(program_id, accounts, &data);
             }
             FundInstruction::FriktionDeposit { deposit_amount} => {
                 msg!("FundInstruction::FriktionDeposit");
@@ -1650,7 +1575,219 @@ pub fn mango_reimbursement(
             }
             FundInstruction::Reimburse {token_index, index_into_table} => {
                 msg!("FundInstruction::Reimburse");
-                return Self::mango_reimbursement(program_id, accounts, token_index, index_into_table);
+                return Self::mango_reimbursement(program_id, accounts, token_index as usize, index_into_table as usize);
+            }
+            FundInstruction::MigrateFund => {
+                msg!("FundInstruction::MigrateFund");
+                return migrate_fund(program_id, accounts);
+            }
+            FundInstruction::QuoteFundNav => {
+                msg!("FundInstruction::QuoteFundNav");
+                return quote_fund_nav(program_id, accounts);
+            }
+            FundInstruction::QuoteInvestorWithdrawal => {
+                msg!("FundInstruction::QuoteInvestorWithdrawal");
+                return quote_investor_withdrawal(program_id, accounts);
+            }
+            FundInstruction::InitNavHistory { interval } => {
+                msg!("FundInstruction::InitNavHistory");
+                return init_nav_history(program_id, accounts, interval);
+            }
+            FundInstruction::SetTokenSlippage { token_index, max_slippage_bps } => {
+                msg!("FundInstruction::SetTokenSlippage");
+                return set_token_slippage(program_id, accounts, token_index, max_slippage_bps);
+            }
+            FundInstruction::SetFundSlippage { max_slippage_bps } => {
+                msg!("FundInstruction::SetFundSlippage");
+                return set_fund_slippage(program_id, accounts, max_slippage_bps);
+            }
+            FundInstruction::SetSwapGuardWithSlippage { token_in_fund_slot, token_out_fund_slot, amount_in, max_slippage_bps } => {
+                msg!("FundInstruction::SetSwapGuardWithSlippage");
+                return set_swap_guard(program_id, accounts, token_in_fund_slot, token_out_fund_slot, amount_in, max_slippage_bps);
+            }
+            FundInstruction::SetRouteSwapGuard { token_in_fund_slot, hop_fund_slots, token_out_fund_slots, amount_in, max_slippage_bps } => {
+                msg!("FundInstruction::SetRouteSwapGuard");
+                return set_route_swap_guard(program_id, accounts, token_in_fund_slot, &hop_fund_slots, &token_out_fund_slots, amount_in, max_slippage_bps);
+            }
+            FundInstruction::AddSerumMarket { base_slot, quote_slot } => {
+                msg!("FundInstruction::AddSerumMarket");
+                return add_serum_market(program_id, accounts, base_slot, quote_slot);
+            }
+            FundInstruction::RemoveSerumMarket => {
+                msg!("FundInstruction::RemoveSerumMarket");
+                return remove_serum_market(program_id, accounts);
+            }
+            FundInstruction::SerumOrder { data } => {
+                msg!("FundInstruction::SerumOrder");
+                return serum_order(program_id, accounts, &data);
+            }
+            FundInstruction::UpdateSerumValue => {
+                msg!("FundInstruction::UpdateSerumValue");
+                return update_serum_value(program_id, accounts);
+            }
+            FundInstruction::SetFundRiskLimits { max_trade_bps, max_daily_turnover_bps } => {
+                msg!("FundInstruction::SetFundRiskLimits");
+                return set_fund_risk_limits(program_id, accounts, max_trade_bps, max_daily_turnover_bps);
+            }
+            FundInstruction::InitializeWithMandate {
+                min_amount, performance_fee_percentage, no_of_tokens, is_private,
+                max_token_weight_bps, min_usdc_buffer_bps, is_locked, banned_mints
+            } => {
+                msg!("FundInstruction::InitializeWithMandate");
+                Self::initialize(program_id, accounts, min_amount, performance_fee_percentage, no_of_tokens, is_private)?;
+                return init_fund_mandate(program_id, accounts, max_token_weight_bps, min_usdc_buffer_bps, is_locked, &banned_mints);
+            }
+            FundInstruction::SetFundMandate { max_token_weight_bps, min_usdc_buffer_bps, banned_mints } => {
+                msg!("FundInstruction::SetFundMandate");
+                return set_fund_mandate(program_id, accounts, max_token_weight_bps, min_usdc_buffer_bps, &banned_mints);
+            }
+            FundInstruction::SetTargetWeights { target_weights_bps, tolerance_bps, max_trade_bps } => {
+                msg!("FundInstruction::SetTargetWeights");
+                return set_target_weights(program_id, accounts, &target_weights_bps, tolerance_bps, max_trade_bps);
+            }
+            FundInstruction::RebalanceSwapGuard { token_in_fund_slot, token_out_fund_slot, amount_in } => {
+                msg!("FundInstruction::RebalanceSwapGuard");
+                return rebalance_swap_guard(program_id, accounts, token_in_fund_slot, token_out_fund_slot, amount_in);
+            }
+            FundInstruction::CreateExecutionOrder { token_in_fund_slot, token_out_fund_slot, total_amount_in, slices, interval, max_slippage_bps } => {
+                msg!("FundInstruction::CreateExecutionOrder");
+                return create_execution_order(program_id, accounts, token_in_fund_slot, token_out_fund_slot, total_amount_in, slices, interval, max_slippage_bps);
+            }
+            FundInstruction::CancelExecutionOrder => {
+                msg!("FundInstruction::CancelExecutionOrder");
+                return cancel_execution_order(program_id, accounts);
+            }
+            FundInstruction::ExecuteOrderSlice => {
+                msg!("FundInstruction::ExecuteOrderSlice");
+                return execute_order_slice(program_id, accounts);
+            }
+            FundInstruction::SetSpotTrigger { token_fund_slot, is_stop_loss, trigger_price, amount, token_out_fund_slot } => {
+                msg!("FundInstruction::SetSpotTrigger");
+                return set_spot_trigger(program_id, accounts, token_fund_slot, is_stop_loss, U64F64::from_bits(trigger_price), amount, token_out_fund_slot);
+            }
+            FundInstruction::ExecuteSpotTrigger { token_fund_slot, is_stop_loss } => {
+                msg!("FundInstruction::ExecuteSpotTrigger");
+                return execute_spot_trigger(program_id, accounts, token_fund_slot, is_stop_loss);
+            }
+            FundInstruction::StrategyAdd { kind, ul_token_slot } => {
+                msg!("FundInstruction::StrategyAdd");
+                return get_strategy_adapter(kind)?.add(program_id, accounts, ul_token_slot);
+            }
+            FundInstruction::StrategyRemove { kind } => {
+                msg!("FundInstruction::StrategyRemove");
+                return get_strategy_adapter(kind)?.remove(program_id, accounts);
+            }
+            FundInstruction::StrategyDeposit { kind, amount } => {
+                msg!("FundInstruction::StrategyDeposit");
+                return get_strategy_adapter(kind)?.deposit(program_id, accounts, amount);
+            }
+            FundInstruction::StrategyWithdraw { kind, amount } => {
+                msg!("FundInstruction::StrategyWithdraw");
+                return get_strategy_adapter(kind)?.withdraw(program_id, accounts, amount);
+            }
+            FundInstruction::StrategyClaim { kind } => {
+                msg!("FundInstruction::StrategyClaim");
+                return get_strategy_adapter(kind)?.claim(program_id, accounts);
+            }
+            FundInstruction::StrategyUpdateValue { kind } => {
+                msg!("FundInstruction::StrategyUpdateValue");
+                return get_strategy_adapter(kind)?.update_value(program_id, accounts);
+            }
+            FundInstruction::StrategyInvestorExit { kind } => {
+                msg!("FundInstruction::StrategyInvestorExit");
+                return get_strategy_adapter(kind)?.investor_exit(program_id, accounts);
+            }
+            FundInstruction::InitLeverage { max_ltv_bps } => {
+                msg!("FundInstruction::InitLeverage");
+                return init_leverage(program_id, accounts, max_ltv_bps);
+            }
+            FundInstruction::MoveLeverageCollateral { amount, is_deposit } => {
+                msg!("FundInstruction::MoveLeverageCollateral");
+                return move_leverage_collateral(program_id, accounts, amount, is_deposit);
+            }
+            FundInstruction::LeverageBorrow { amount } => {
+                msg!("FundInstruction::LeverageBorrow");
+                return leverage_borrow(program_id, accounts, amount);
+            }
+            FundInstruction::LeverageRepay { amount } => {
+                msg!("FundInstruction::LeverageRepay");
+                return leverage_repay(program_id, accounts, amount, false);
+            }
+            FundInstruction::Deleverage { amount } => {
+                msg!("FundInstruction::Deleverage");
+                return leverage_repay(program_id, accounts, amount, true);
+            }
+            FundInstruction::UpdateLeverageValue => {
+                msg!("FundInstruction::UpdateLeverageValue");
+                return update_leverage_value(program_id, accounts);
+            }
+            FundInstruction::MangoInit { account_num } => {
+                msg!("FundInstruction::MangoInit");
+                return init_mango_account(program_id, accounts, account_num);
+            }
+            FundInstruction::MangoAddPerpMarket { price_slot } => {
+                msg!("FundInstruction::MangoAddPerpMarket");
+                return add_mango_perp_market(program_id, accounts, price_slot);
+            }
+            FundInstruction::MangoRemovePerpMarket { perp_slot } => {
+                msg!("FundInstruction::MangoRemovePerpMarket");
+                return remove_mango_perp_market(program_id, accounts, perp_slot);
+            }
+            FundInstruction::MangoDepositCollateral { amount } => {
+                msg!("FundInstruction::MangoDepositCollateral");
+                return move_mango_collateral(program_id, accounts, amount, true);
+            }
+            FundInstruction::MangoWithdrawCollateral { amount } => {
+                msg!("FundInstruction::MangoWithdrawCollateral");
+                return move_mango_collateral(program_id, accounts, amount, false);
+            }
+            FundInstruction::MangoPlacePerpOrder { perp_slot, side, price_lots, max_base_lots, client_order_id, order_type, reduce_only } => {
+                msg!("FundInstruction::MangoPlacePerpOrder");
+                return place_mango_perp_order(program_id, accounts, perp_slot, side, price_lots, max_base_lots, client_order_id, order_type, reduce_only);
+            }
+            FundInstruction::MangoCancelPerpOrders { perp_slot, client_order_id } => {
+                msg!("FundInstruction::MangoCancelPerpOrders");
+                return cancel_mango_perp_orders(program_id, accounts, perp_slot, client_order_id);
+            }
+            FundInstruction::UpdateMangoValue => {
+                msg!("FundInstruction::UpdateMangoValue");
+                return update_mango_value(program_id, accounts);
+            }
+            FundInstruction::MangoInvestorWithdraw => {
+                msg!("FundInstruction::MangoInvestorWithdraw");
+                return mango_investor_withdraw(program_id, accounts);
+            }
+            FundInstruction::MangoInvestorReduce => {
+                msg!("FundInstruction::MangoInvestorReduce");
+                return mango_investor_reduce(program_id, accounts);
+            }
+            FundInstruction::MangoSettlePerpPnl { perp_slot } => {
+                msg!("FundInstruction::MangoSettlePerpPnl");
+                return mango_settle_perp_pnl(program_id, accounts, perp_slot);
+            }
+            FundInstruction::ClaimReimbursement => {
+                msg!("FundInstruction::ClaimReimbursement");
+                return claim_reimbursement(program_id, accounts);
+            }
+            FundInstruction::FriktionReconcile => {
+                msg!("FundInstruction::FriktionReconcile");
+                return friktion_reconcile(program_id, accounts);
+            }
+            FundInstruction::QuoteInvestorExit => {
+                msg!("FundInstruction::QuoteInvestorExit");
+                return quote_investor_exit(program_id, accounts);
+            }
+            FundInstruction::RefreshTokenSlotIndexes { index } => {
+                msg!("FundInstruction::RefreshTokenSlotIndexes");
+                return refresh_token_slot_indexes(program_id, accounts, index);
+            }
+            FundInstruction::SetTokenFundSlippageCeiling { token_index, max_fund_slippage_bps } => {
+                msg!("FundInstruction::SetTokenFundSlippageCeiling");
+                return set_token_fund_slippage_ceiling(program_id, accounts, token_index, max_fund_slippage_bps);
+            }
+            FundInstruction::AddReimbursementInvestors => {
+                msg!("FundInstruction::AddReimbursementInvestors");
+                return add_reimbursement_investors(program_id, accounts);
             }
         }
     }
@@ -1722,7 +1859,8 @@ pub fn update_amount_and_performance(
     platform_data: &PlatformData,
     fund_data: &mut FundAccount,
     // mango_val: U64F64,
-    update_perf: bool
+    update_perf: bool,
+    nav_history: Option<&mut NavHistory>
 ) -> Result<(), ProgramError> {
     // let mut usdc_deposits: I80F48 = ZERO_I80F48;
     // let mut token_deposits: I80F48 = ZERO_I80F48;
@@ -1741,7 +1879,7 @@ pub fn update_amount_and_performance(
         
         // get last mux
         // get index of token
-        let token_info = platform_data.token_list[fund_data.tokens[i].index[fund_data.tokens[i].mux as usize] as usize];
+        let token_info = platform_data.token_list[fund_data.tokens[i].get_active_index()];
         
         if Clock::get()?.unix_timestamp.checked_sub(token_info.last_updated).unwrap() > 100 {
             msg!("{} price not up-to-date", i);
@@ -1762,31 +1900,17 @@ pub fn update_amount_and_performance(
         fund_val = fund_val.checked_add(val).unwrap();
     }
 
-    if fund_data.friktion_vault.is_active {
+    // friktion volts and other strategy positions, through their adapters
+    fund_val = fund_val.checked_add(get_strategy_value(platform_data, fund_data, Clock::get()?.unix_timestamp)?).unwrap();
 
-        let friktion_ul_token_info = platform_data.token_list[fund_data.tokens[fund_data.friktion_vault.ul_token_slot as usize].index[fund_data.tokens[fund_data.friktion_vault.ul_token_slot as usize].mux as usize] as usize];
-            
-            if Clock::get()?.unix_timestamp.checked_sub(fund_data.friktion_vault.last_updated).unwrap() > 100 {
-                msg!("FKV not up-to-date...");
-                return Err(FundError::PriceStaleInAccount.into())
-            }
-            // calculate price in terms of base token
-            let mut val: U64F64 = U64F64::from_num(fund_data.friktion_vault.ul_token_balance.checked_sub(fund_data.friktion_vault.ul_token_debt).unwrap())
-            .checked_mul(friktion_ul_token_info.pool_price).unwrap();
-    
-             if friktion_ul_token_info.pc_index != 0 {
-                 let underlying_token_info = platform_data.token_list[friktion_ul_token_info.pc_index as usize];
-                 if Clock::get()?.unix_timestamp.checked_sub(underlying_token_info.last_updated).unwrap() > 100 {
-                    msg!("ul price not up-to-date.. aborting");
-                    return Err(FundError::PriceStaleInAccount.into())
-                }
-                 val = val.checked_mul(underlying_token_info.pool_price).unwrap();
-             }
-    
-        fund_val = fund_val.checked_add(val).unwrap();
-    }
-    
+    // collateral and borrows on the lending market obligation
+    fund_val = add_leverage_value(fund_data, fund_val, Clock::get()?.unix_timestamp)?;
+
+    // USDC collateral and perps on Mango v4, net of what settled investors are owed
+    fund_val = add_mango_value(fund_data, fund_val, Clock::get()?.unix_timestamp)?;
 
+    // free and locked balances of open orders on Serum/OpenBook
+    fund_val = fund_val.checked_add(get_serum_value(platform_data, fund_data, Clock::get()?.unix_timestamp)?).unwrap();
     
     if update_perf {
         let mut perf = U64F64::from_num(fund_data.prev_performance);
@@ -1803,6 +1927,9 @@ pub fn update_amount_and_performance(
         fund_data.prev_performance = U64F64::to_num(perf);
     }
     fund_data.total_amount = fund_val;
+    if let Some(nav_history) = nav_history {
+        record_nav(nav_history, fund_data, Clock::get()?.unix_timestamp);
+    }
     Ok(())
 }
 
@@ -2102,6 +2229,24 @@ pub fn get_share(
 
     Ok(share)
 }
+// investor's part of a token slot for a given share, rounded up for the last investor
+pub fn get_token_withdraw_amount(
+    fund_data: &FundAccount,
+    i: usize,
+    share: U64F64
+) -> u64 {
+    let mut withdraw_amount: u64 = U64F64::to_num(
+        U64F64::from_num(fund_data.tokens[i].balance.checked_sub(fund_data.tokens[i].debt).unwrap())
+    .checked_mul(share).unwrap());
+    if fund_data.number_of_active_investments == 1 { // ceil for last investor
+        withdraw_amount += 1; // ceil
+        if withdraw_amount + fund_data.tokens[i].debt > fund_data.tokens[i].balance {
+            withdraw_amount -= 1;
+        }
+    }
+    withdraw_amount
+}
+
 pub fn get_perp_vals(
     fund_data: &FundAccount,
     mango_account_ai: &AccountInfo,
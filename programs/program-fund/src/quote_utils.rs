@@ -0,0 +1,195 @@
+use borsh::{BorshDeserialize, BorshSerialize};
+use fixed::types::U64F64;
+
+use solana_program::{
+    account_info::{AccountInfo, next_account_info},
+    program::set_return_data,
+    program_error::ProgramError,
+    program_pack::IsInitialized,
+    pubkey::Pubkey,
+    sysvar::{Sysvar, clock::Clock},
+};
+
+use crate::error::FundError;
+use crate::state::{NUM_TOKENS, MAX_FRIKTION_VOLTS, MAX_SERUM_MARKETS, MAX_STRATEGY_POSITIONS, STRATEGY_FRIKTION, FundAccount, InvestorData, PlatformData};
+use crate::processor::{update_amount_and_performance, get_share, get_token_withdraw_amount};
+use crate::strategy_utils::{get_strategy_adapter, FriktionAdapter, StrategyAdapter};
+use crate::leverage_utils::get_leverage_exit;
+use crate::mango_utils::get_mango_exit;
+use crate::exit_utils::get_deficit_split;
+use crate::serum_utils::get_serum_exit;
+
+macro_rules! check {
+    ($cond:expr, $err:expr) => {
+        if !($cond) {
+            return Err(($err).into())
+        }
+    }
+}
+macro_rules! check_eq {
+    ($x:expr, $y:expr) => {
+        if ($x != $y) {
+            return Err(FundError::Default.into())
+        }
+    }
+}
+
+// Read-only quotes returned through `set_return_data`, meant to be simulated.
+// U64F64 values are returned as their raw bits (`U64F64::from_bits`).
+
+#[derive(Clone, Debug, PartialEq, BorshSerialize, BorshDeserialize)]
+pub struct FundNavQuote {
+    pub timestamp: i64,
+    /// fund value in USDC, U64F64 bits
+    pub total_amount: u128,
+    /// U64F64 bits
+    pub performance: u128,
+    /// performance fee accrued and not yet claimed, U64F64 bits
+    pub performance_fee: u128,
+    /// USDC waiting in router for the manager transfer
+    pub amount_in_router: u64,
+}
+
+#[derive(Clone, Debug, PartialEq, BorshSerialize, BorshDeserialize)]
+pub struct InvestorWithdrawalQuote {
+    pub nav: FundNavQuote,
+    /// investor's part of the fund, U64F64 bits
+    pub share: u128,
+    /// performance fee charged on this withdrawal, U64F64 bits
+    pub performance_fee: u128,
+    /// payout per fund token slot, in native units
+    pub token_amounts: [u64; NUM_TOKENS],
+    /// per friktion volt, in native units
+    pub friktion_ul_amount: [u64; MAX_FRIKTION_VOLTS],
+    pub friktion_fc_amount: [u64; MAX_FRIKTION_VOLTS],
+    /// refunded as is when the manager has not transferred yet
+    pub amount_in_router: u64,
+}
+
+/// Accounts expected
+/// 0. []   Platform State Account
+/// 1. []   Fund State Account
+pub fn quote_fund_nav(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+) -> Result<(), ProgramError> {
+    let accounts_iter = &mut accounts.iter();
+    let platform_ai = next_account_info(accounts_iter)?;
+    let fund_account_ai = next_account_info(accounts_iter)?;
+
+    let platform_data = PlatformData::load_checked(platform_ai, program_id)?;
+    // valuation runs on a heap copy, nothing is written back and the account is too large for the stack
+    let mut fund_data = Box::new(*FundAccount::load_checked(fund_account_ai, program_id)?);
+    check!(fund_data.is_initialized(), FundError::InvalidStateAccount);
+
+    update_amount_and_performance(&platform_data, &mut fund_data, true, None)?;
+
+    let quote = get_nav_quote(&fund_data)?;
+    set_return_data(&quote.try_to_vec()?);
+    Ok(())
+}
+
+/// Accounts expected
+/// 0. []   Platform State Account
+/// 1. []   Fund State Account
+/// 2. []   Investor State Account
+pub fn quote_investor_withdrawal(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+) -> Result<(), ProgramError> {
+    let accounts_iter = &mut accounts.iter();
+    let platform_ai = next_account_info(accounts_iter)?;
+    let fund_account_ai = next_account_info(accounts_iter)?;
+    let investor_state_ai = next_account_info(accounts_iter)?;
+
+    let platform_data = PlatformData::load_checked(platform_ai, program_id)?;
+    let mut fund_data = Box::new(*FundAccount::load_checked(fund_account_ai, program_id)?);
+    let mut investor_data = Box::new(*InvestorData::load_checked(investor_state_ai, program_id)?);
+    check!(fund_data.is_initialized(), FundError::InvalidStateAccount);
+    check!(investor_data.is_initialized(), FundError::InvalidStateAccount);
+    check_eq!(investor_data.manager, fund_data.manager_account);
+
+    update_amount_and_performance(&platform_data, &mut fund_data, true, None)?;
+    let nav = get_nav_quote(&fund_data)?;
+
+    let mut quote = InvestorWithdrawalQuote {
+        nav,
+        share: 0,
+        performance_fee: 0,
+        token_amounts: [0; NUM_TOKENS],
+        friktion_ul_amount: [0; MAX_FRIKTION_VOLTS],
+        friktion_fc_amount: [0; MAX_FRIKTION_VOLTS],
+        amount_in_router: investor_data.amount_in_router,
+    };
+
+    // same path as InvestorWithdrawSettleFunds
+    if investor_data.amount_in_router == 0 && investor_data.amount != 0 && investor_data.start_performance != U64F64::from_num(0) && !investor_data.has_withdrawn {
+        let fee_before = fund_data.performance_fee;
+        let share = get_share(&mut fund_data, &mut investor_data)?;
+        quote.share = share.to_bits();
+        quote.performance_fee = fund_data.performance_fee.checked_sub(fee_before).unwrap().to_bits();
+        for i in 0..NUM_TOKENS {
+            quote.token_amounts[i] = get_token_withdraw_amount(&fund_data, i, share);
+        }
+        for volt in 0..MAX_FRIKTION_VOLTS {
+            let info = fund_data.strategy_position(volt);
+            if !info.is_active || info.kind != STRATEGY_FRIKTION { continue; }
+            let (ul_amount, fc_amount) = FriktionAdapter.get_exit_amounts(&fund_data, volt, share);
+            quote.friktion_ul_amount[volt] = ul_amount;
+            quote.friktion_fc_amount[volt] = fc_amount;
+        }
+        // other positions pay out in their underlying token slot
+        for position in 0..MAX_STRATEGY_POSITIONS {
+            let info = fund_data.strategy_position(position);
+            if !info.is_active || info.kind == STRATEGY_FRIKTION { continue; }
+            let (ul_amount, _) = get_strategy_adapter(info.kind)?.get_exit_amounts(&fund_data, position, share);
+            let tsi = info.ul_token_slot as usize;
+            quote.token_amounts[tsi] = quote.token_amounts[tsi].checked_add(ul_amount).unwrap();
+        }
+        // obligation equity is settled in USDC, a deficit comes out of the token amounts
+        let now_ts = Clock::get()?.unix_timestamp;
+        let (owed, deficit) = get_leverage_exit(&fund_data, share);
+        quote.token_amounts[0] = quote.token_amounts[0].checked_add(owed).unwrap();
+        let taken = get_deficit_split(&platform_data, &fund_data, &quote.token_amounts, deficit, now_ts)?;
+        for i in 0..NUM_TOKENS {
+            quote.token_amounts[i] -= taken[i];
+        }
+        // and so is the mango account equity
+        let (owed, deficit) = get_mango_exit(&fund_data, share);
+        quote.token_amounts[0] = quote.token_amounts[0].checked_add(owed).unwrap();
+        let taken = get_deficit_split(&platform_data, &fund_data, &quote.token_amounts, deficit, now_ts)?;
+        for i in 0..NUM_TOKENS {
+            quote.token_amounts[i] -= taken[i];
+        }
+        // open orders pay out in their base and quote slots once settled
+        for market in 0..MAX_SERUM_MARKETS {
+            let info = fund_data.serum_markets[market];
+            if !info.is_active { continue; }
+            let (base_amount, quote_amount) = get_serum_exit(&fund_data, market, share);
+            quote.token_amounts[info.base_slot as usize] = quote.token_amounts[info.base_slot as usize].checked_add(base_amount).unwrap();
+            quote.token_amounts[info.quote_slot as usize] = quote.token_amounts[info.quote_slot as usize].checked_add(quote_amount).unwrap();
+        }
+    } else if investor_data.has_withdrawn {
+        // settled already, report what is still owed
+        quote.share = investor_data.share.to_bits();
+        quote.token_amounts = investor_data.token_debts;
+        quote.token_amounts[0] = quote.token_amounts[0].checked_add(U64F64::to_num(investor_data.margin_debt)).unwrap();
+        for volt in 0..MAX_FRIKTION_VOLTS {
+            quote.friktion_ul_amount[volt] = investor_data.get_friktion_ul_debt(volt);
+            quote.friktion_fc_amount[volt] = investor_data.get_friktion_fc_debt(volt);
+        }
+    }
+
+    set_return_data(&quote.try_to_vec()?);
+    Ok(())
+}
+
+fn get_nav_quote(fund_data: &FundAccount) -> Result<FundNavQuote, ProgramError> {
+    Ok(FundNavQuote {
+        timestamp: Clock::get()?.unix_timestamp,
+        total_amount: fund_data.total_amount.to_bits(),
+        performance: fund_data.prev_performance.to_bits(),
+        performance_fee: fund_data.performance_fee.to_bits(),
+        amount_in_router: fund_data.amount_in_router,
+    })
+}
@@ -0,0 +1,126 @@
+use fixed::types::U64F64;
+
+use solana_program::{
+    account_info::{AccountInfo, next_account_info},
+    msg,
+    program_error::ProgramError,
+    program_pack::IsInitialized,
+    pubkey::Pubkey,
+    sysvar::{Sysvar, clock::Clock},
+};
+
+use crate::error::FundError;
+use crate::state::{NUM_TOKENS, FundAccount, PlatformData};
+use crate::jup_utils::{arm_swap_guard, check_guard_pairing, check_price_slots, get_slot_price};
+use crate::risk_utils::get_token_values;
+
+macro_rules! check {
+    ($cond:expr, $err:expr) => {
+        if !($cond) {
+            return Err(($err).into())
+        }
+    }
+}
+
+const BPS: u64 = 10_000;
+
+/// Publishes the target weights, all zero turns the crank off
+///
+/// Accounts expected
+/// 0. [WRITE]  Fund State Account
+/// 1. [SIGNER] Manager Account
+pub fn set_target_weights(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    target_weights_bps: &[u16],
+    tolerance_bps: u16,
+    max_trade_bps: u16,
+) -> Result<(), ProgramError> {
+    let accounts_iter = &mut accounts.iter();
+    let fund_account_ai = next_account_info(accounts_iter)?;
+    let manager_ai = next_account_info(accounts_iter)?;
+
+    let mut fund_data = FundAccount::load_mut_checked(fund_account_ai, program_id)?;
+    check!(fund_data.is_initialized(), FundError::InvalidStateAccount);
+    check!(manager_ai.is_signer, ProgramError::MissingRequiredSignature);
+    check!(fund_data.manager_account == *manager_ai.key, FundError::ManagerMismatch);
+    check!(target_weights_bps.len() == NUM_TOKENS, ProgramError::InvalidArgument);
+    check!(tolerance_bps as u64 <= BPS && max_trade_bps as u64 <= BPS, ProgramError::InvalidArgument);
+
+    let total_bps: u64 = target_weights_bps.iter().map(|w| *w as u64).sum();
+    check!(total_bps == 0 || total_bps == BPS, ProgramError::InvalidArgument);
+    for i in 0..NUM_TOKENS {
+        check!(target_weights_bps[i] == 0 || fund_data.tokens[i].is_active, ProgramError::InvalidArgument);
+    }
+
+    let rebalance = &mut fund_data.rebalance;
+    rebalance.target_weights_bps.copy_from_slice(target_weights_bps);
+    rebalance.tolerance_bps = tolerance_bps;
+    rebalance.max_trade_bps = max_trade_bps;
+    rebalance.is_active = total_bps != 0;
+    Ok(())
+}
+
+/// Arms the swap guard for a keeper trade from an overweight slot into an underweight one.
+/// The keeper then fires JupiterSwap and CheckSwapGuard in the same transaction, like the manager would.
+/// Min out is bound by the token and fund slippage, the legs are held to the route vaults.
+/// Every token price must be from an earlier slot than this one.
+///
+/// Accounts expected
+/// 0. []   Instructions Sysvar
+/// 1. []   Platform State Account
+/// 2. [SIGNER] Keeper Account
+/// 3. [WRITE]  Fund State Account
+pub fn rebalance_swap_guard(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    token_in_fund_slot: u8,
+    token_out_fund_slot: u8,
+    amount_in: u64,
+) -> Result<(), ProgramError> {
+    let accounts_iter = &mut accounts.iter();
+    let sysvar_ix_ai = next_account_info(accounts_iter)?;
+    check!(*sysvar_ix_ai.key == solana_program::sysvar::instructions::id(), ProgramError::InvalidArgument);
+    let platform_ai = next_account_info(accounts_iter)?;
+    let keeper_ai = next_account_info(accounts_iter)?;
+    let fund_pda_ai = next_account_info(accounts_iter)?;
+
+    let platform_data = PlatformData::load_checked(platform_ai, program_id)?;
+    let mut fund_data = FundAccount::load_mut_checked(fund_pda_ai, program_id)?;
+    check!(fund_data.is_initialized(), FundError::InvalidStateAccount);
+    check!(keeper_ai.is_signer, ProgramError::MissingRequiredSignature);
+    check!(fund_data.rebalance.is_active, FundError::InvalidInstruction);
+    let si = token_in_fund_slot as usize;
+    let di = token_out_fund_slot as usize;
+    check!(si < NUM_TOKENS && di < NUM_TOKENS, ProgramError::InvalidArgument);
+
+    // a live guard is never overridden
+    let clock = Clock::get()?;
+    let now_ts = clock.unix_timestamp;
+    check!(!fund_data.guard.is_active || now_ts - fund_data.guard.triggered_at >= 100, FundError::InvalidInstruction);
+    // the weights and the trade are valued with every token price
+    let slots: Vec<usize> = (0..NUM_TOKENS).collect();
+    check_price_slots(&platform_data, &fund_data, &slots, clock.slot)?;
+
+    let (values, total) = get_token_values(&platform_data, &fund_data, now_ts)?;
+    let config = fund_data.rebalance;
+    let bps_of_total = |bps: u16| total.checked_mul(U64F64::from_num(bps)).unwrap().checked_div(U64F64::from_num(BPS)).unwrap();
+    let target_in = bps_of_total(config.target_weights_bps[si]);
+    let target_out = bps_of_total(config.target_weights_bps[di]);
+    let band = bps_of_total(config.tolerance_bps);
+    msg!("in value {:?} target {:?}, out value {:?} target {:?}", values[si], target_in, values[di], target_out);
+    check!(values[si] > target_in.checked_add(band).unwrap(), FundError::InvalidInstruction);
+    check!(values[di].checked_add(band).unwrap() < target_out, FundError::InvalidInstruction);
+
+    // never past either target, and within the per call cap
+    let trade_value = U64F64::from_num(amount_in).checked_mul(get_slot_price(&platform_data, &fund_data, si, now_ts)?).unwrap();
+    check!(trade_value <= values[si].checked_sub(target_in).unwrap(), ProgramError::InvalidArgument);
+    check!(trade_value <= target_out.checked_sub(values[di]).unwrap(), ProgramError::InvalidArgument);
+    if config.max_trade_bps != 0 {
+        check!(trade_value <= bps_of_total(config.max_trade_bps), ProgramError::InvalidArgument);
+    }
+
+    arm_swap_guard(&platform_data, &mut fund_data, token_in_fund_slot, &[], &[token_out_fund_slot], amount_in, 0, *keeper_ai.key, now_ts)?;
+    // a keeper guard never outlives its transaction
+    check_guard_pairing(program_id, sysvar_ix_ai, &fund_data, fund_pda_ai.key, keeper_ai.key)
+}
@@ -0,0 +1,236 @@
+use std::mem::size_of;
+
+use bytemuck::bytes_of;
+use fixed::types::U64F64;
+use solana_program::{
+    account_info::{AccountInfo, next_account_info},
+    msg,
+    program::invoke_signed,
+    program_error::ProgramError,
+    pubkey::Pubkey,
+    system_instruction::create_account,
+    sysvar::{Sysvar, rent::Rent},
+};
+
+use crate::error::FundError;
+use crate::migration::REIMBURSEMENT_TABLE_VERSION;
+use crate::processor::parse_token_account;
+use crate::state::{MAX_REIMBURSED_INVESTORS, FundAccount, InvestorData, ReimbursementTable};
+
+macro_rules! check {
+    ($cond:expr, $err:expr) => {
+        if !($cond) {
+            return Err(($err).into())
+        }
+    }
+}
+macro_rules! check_eq {
+    ($x:expr, $y:expr) => {
+        if ($x != $y) {
+            return Err(FundError::Default.into())
+        }
+    }
+}
+
+pub const REIMBURSEMENT_SEED: &[u8] = b"reimbursement";
+
+/// Creates the reimbursement table with the fund valuation every investor's part is taken at,
+/// the fund must have just been revalued, then records the first page of investors.
+/// Investors can neither enter nor leave the fund until the table is sealed.
+pub fn snapshot_reimbursement<'a>(
+    program_id: &Pubkey,
+    fund_account_ai: &AccountInfo<'a>,
+    payer_ai: &AccountInfo<'a>,
+    system_program_ai: &AccountInfo<'a>,
+    table_ai: &AccountInfo<'a>,
+    investor_state_accs: &[AccountInfo<'a>],
+) -> Result<(), ProgramError> {
+    let (pda, nonce) = Pubkey::find_program_address(&[fund_account_ai.key.as_ref(), REIMBURSEMENT_SEED], program_id);
+    check!(*table_ai.key == pda, FundError::IncorrectPDA);
+    let size = size_of::<ReimbursementTable>();
+    invoke_signed(
+        &create_account(
+            payer_ai.key,
+            table_ai.key,
+            Rent::get()?.minimum_balance(size),
+            size as u64,
+            program_id,
+        ),
+        &[payer_ai.clone(), table_ai.clone(), system_program_ai.clone()],
+        &[&[fund_account_ai.key.as_ref(), REIMBURSEMENT_SEED, bytes_of(&nonce)]]
+    )?;
+
+    {
+        let mut fund_data = FundAccount::load_mut_checked(fund_account_ai, program_id)?;
+        let mut table = ReimbursementTable::load_mut_checked(table_ai, program_id)?;
+        check!(fund_data.number_of_active_investments as usize <= MAX_REIMBURSED_INVESTORS, ProgramError::InvalidArgument);
+        table.is_initialized = true;
+        table.version = REIMBURSEMENT_TABLE_VERSION;
+        table.fund = *fund_account_ai.key;
+        table.expected_count = fund_data.number_of_active_investments;
+        table.is_sealed = table.expected_count == 0;
+        table.total_amount = fund_data.total_amount;
+        table.prev_performance = fund_data.prev_performance;
+        fund_data.reimbursement_snapshot_pending = !table.is_sealed;
+    }
+
+    if investor_state_accs.is_empty() {
+        return Ok(())
+    }
+    record_reimbursement_investors(program_id, fund_account_ai, table_ai, investor_state_accs)
+}
+
+/// Permissionless: adds the next page of investors to an unsealed reimbursement table
+///
+/// Accounts expected
+/// 0. [WRITE]  Fund State Account
+/// 1. [WRITE]  Reimbursement Table, PDA of [fund, "reimbursement"]
+/// 2.. []  Investor State Accounts not in the table yet
+pub fn add_reimbursement_investors(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+) -> Result<(), ProgramError> {
+    check!(accounts.len() > 2, ProgramError::NotEnoughAccountKeys);
+    let (fixed_accs, investor_state_accs) = accounts.split_at(2);
+    record_reimbursement_investors(program_id, &fixed_accs[0], &fixed_accs[1], investor_state_accs)
+}
+
+/// Records each investor's part of the fund NAV at the snapshot valuation, valued like `get_share`
+/// before the performance fee, and seals the table once every active investor is in.
+/// The fund keeps the rest (manager fees, later entrants).
+fn record_reimbursement_investors(
+    program_id: &Pubkey,
+    fund_account_ai: &AccountInfo,
+    table_ai: &AccountInfo,
+    investor_state_accs: &[AccountInfo],
+) -> Result<(), ProgramError> {
+    let mut fund_data = FundAccount::load_mut_checked(fund_account_ai, program_id)?;
+    let mut table = ReimbursementTable::load_mut_checked(table_ai, program_id)?;
+    check!(table.is_initialized && !table.is_sealed, FundError::InvalidStateAccount);
+    check_eq!(table.fund, *fund_account_ai.key);
+    check!(table.count as usize + investor_state_accs.len() <= table.expected_count as usize, ProgramError::InvalidArgument);
+
+    for investor_state_ai in investor_state_accs {
+        let count = table.count as usize;
+        check!(table.entries[..count].iter().all(|entry| entry.investor_state != *investor_state_ai.key), ProgramError::InvalidArgument);
+        let investor_data = InvestorData::load_checked(investor_state_ai, program_id)?;
+        check_eq!(investor_data.manager, fund_data.manager_account);
+        check!(!investor_data.has_withdrawn && investor_data.amount != 0 && investor_data.start_performance != 0, FundError::InvalidStateAccount);
+        let value = U64F64::from_num(investor_data.amount)
+            .checked_mul(table.prev_performance).unwrap()
+            .checked_div(investor_data.start_performance).unwrap();
+        let share = value.checked_div(table.total_amount).unwrap();
+        table.entries[count].owner = investor_data.owner;
+        table.entries[count].investor_state = *investor_state_ai.key;
+        table.entries[count].share = share;
+        table.count += 1;
+    }
+    let mut total_share = U64F64::from_num(0);
+    for i in 0..table.count as usize {
+        total_share = total_share.checked_add(table.entries[i].share).unwrap();
+    }
+    check!(total_share <= U64F64::from_num(1), FundError::InvalidStateAccount);
+
+    if table.count == table.expected_count {
+        table.is_sealed = true;
+        fund_data.reimbursement_snapshot_pending = false;
+    }
+    msg!("reimbursement snapshot of {}/{} investors, {:?} of the fund", table.count, table.expected_count, total_share);
+    Ok(())
+}
+
+/// Credits `received` USDC to the snapshot investors and books their part as USDC debt,
+/// so it stays out of `update_amount_and_performance` until claimed
+pub fn credit_reimbursement(
+    program_id: &Pubkey,
+    fund_account_ai: &AccountInfo,
+    fund_data: &mut FundAccount,
+    table_ai: &AccountInfo,
+    received: u64,
+) -> Result<(), ProgramError> {
+    let mut table = ReimbursementTable::load_mut_checked(table_ai, program_id)?;
+    check!(table.is_initialized, FundError::InvalidStateAccount);
+    check!(table.is_sealed, FundError::ReimbursementSnapshotPending);
+    check_eq!(table.fund, *fund_account_ai.key);
+    let mut entitled: u64 = 0;
+    for i in 0..table.count as usize {
+        let amount: u64 = U64F64::to_num(U64F64::from_num(received).checked_mul(table.entries[i].share).unwrap());
+        table.entries[i].amount = table.entries[i].amount.checked_add(amount).unwrap();
+        entitled = entitled.checked_add(amount).unwrap();
+    }
+    table.total_reimbursed = table.total_reimbursed.checked_add(received).unwrap();
+    table.total_entitled = table.total_entitled.checked_add(entitled).unwrap();
+    fund_data.tokens[0].debt = fund_data.tokens[0].debt.checked_add(entitled).unwrap();
+    check!(fund_data.tokens[0].balance >= fund_data.tokens[0].debt, ProgramError::InsufficientFunds);
+    msg!("reimbursed {}, credited to investors {}", received, entitled);
+    Ok(())
+}
+
+/// Pays out everything credited to the investor wallet in the reimbursement table,
+/// also after the investor has left the fund
+///
+/// Accounts expected
+/// 0. [WRITE]  Fund State Account
+/// 1. [WRITE]  Reimbursement Table, PDA of [fund, "reimbursement"]
+/// 2. [SIGNER] Investor Wallet Account
+/// 3. [WRITE]  Fund USDC Vault
+/// 4. [WRITE]  Investor USDC Token Account
+/// 5. []   Token Program
+pub fn claim_reimbursement(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+) -> Result<(), ProgramError> {
+    let accounts_iter = &mut accounts.iter();
+    let fund_account_ai = next_account_info(accounts_iter)?;
+    let table_ai = next_account_info(accounts_iter)?;
+    let investor_ai = next_account_info(accounts_iter)?;
+    let fund_vault_ai = next_account_info(accounts_iter)?;
+    let investor_token_ai = next_account_info(accounts_iter)?;
+    let token_prog_ai = next_account_info(accounts_iter)?;
+
+    check!(investor_ai.is_signer, ProgramError::MissingRequiredSignature);
+    check_eq!(*token_prog_ai.key, spl_token::ID);
+    let fund_data = FundAccount::load_checked(fund_account_ai, program_id)?;
+    check_eq!(fund_data.tokens[0].vault, *fund_vault_ai.key);
+    check_eq!(parse_token_account(investor_token_ai)?.mint, parse_token_account(fund_vault_ai)?.mint);
+
+    let mut table = ReimbursementTable::load_mut_checked(table_ai, program_id)?;
+    check!(table.is_initialized, FundError::InvalidStateAccount);
+    check_eq!(table.fund, *fund_account_ai.key);
+    let mut amount: u64 = 0;
+    for i in 0..table.count as usize {
+        if table.entries[i].owner == *investor_ai.key {
+            amount = amount.checked_add(table.entries[i].amount).unwrap();
+            table.entries[i].amount = 0;
+        }
+    }
+    check!(amount > 0, ProgramError::InsufficientFunds);
+    table.total_claimed = table.total_claimed.checked_add(amount).unwrap();
+
+    let manager_account = fund_data.manager_account;
+    let nonce = fund_data.signer_nonce;
+    drop(fund_data);
+    invoke_signed(
+        &(spl_token::instruction::transfer(
+            token_prog_ai.key,
+            fund_vault_ai.key,
+            investor_token_ai.key,
+            fund_account_ai.key,
+            &[fund_account_ai.key],
+            amount
+        ))?,
+        &[
+            fund_vault_ai.clone(),
+            investor_token_ai.clone(),
+            fund_account_ai.clone(),
+            token_prog_ai.clone()
+        ],
+        &[&[bytes_of(&manager_account), bytes_of(&nonce)]]
+    )?;
+
+    let mut fund_data = FundAccount::load_mut_checked(fund_account_ai, program_id)?;
+    fund_data.tokens[0].balance = parse_token_account(fund_vault_ai)?.amount;
+    fund_data.tokens[0].debt = fund_data.tokens[0].debt.checked_sub(amount).unwrap();
+    msg!("reimbursement claimed {}", amount);
+    Ok(())
+}
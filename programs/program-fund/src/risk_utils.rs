@@ -0,0 +1,364 @@
+use fixed::types::U64F64;
+use fixed_macro::types::U64F64;
+
+use solana_program::{
+    account_info::{AccountInfo, next_account_info},
+    msg,
+    program_error::ProgramError,
+    program_pack::IsInitialized,
+    pubkey::Pubkey,
+    sysvar::{Sysvar, clock::Clock},
+};
+
+use crate::error::FundError;
+use crate::state::{TURNOVER_BUCKETS, MAX_BANNED_TOKENS, MAX_STRATEGY_POSITIONS, NUM_TOKENS, FundAccount, Mandate, PlatformData, RiskLimits};
+use crate::jup_utils::get_slot_price;
+use crate::strategy_utils::get_strategy_adapter;
+use crate::leverage_utils::add_leverage_value;
+use crate::mango_utils::add_mango_value;
+
+macro_rules! check {
+    ($cond:expr, $err:expr) => {
+        if !($cond) {
+            return Err(($err).into())
+        }
+    }
+}
+
+// delay before looser limits apply, investors get the time to exit
+pub const RISK_LIMITS_TIMELOCK: i64 = 2 * 24 * 3600;
+const BPS: u64 = 10_000;
+
+// 0 is unlimited, the loosest
+fn is_tighter_or_equal(new: u16, current: u16) -> bool {
+    current == 0 || (new != 0 && new <= current)
+}
+
+/// Tighter limits apply at once, looser ones after RISK_LIMITS_TIMELOCK
+///
+/// Accounts expected
+/// 0. [WRITE]  Fund State Account
+/// 1. [SIGNER] Manager Account
+pub fn set_fund_risk_limits(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    max_trade_bps: u16,
+    max_daily_turnover_bps: u16,
+) -> Result<(), ProgramError> {
+    let accounts_iter = &mut accounts.iter();
+    let fund_account_ai = next_account_info(accounts_iter)?;
+    let manager_ai = next_account_info(accounts_iter)?;
+
+    let mut fund_data = FundAccount::load_mut_checked(fund_account_ai, program_id)?;
+    check!(fund_data.is_initialized(), FundError::InvalidStateAccount);
+    check!(manager_ai.is_signer, ProgramError::MissingRequiredSignature);
+    check!(fund_data.manager_account == *manager_ai.key, FundError::ManagerMismatch);
+    check!(max_trade_bps as u64 <= BPS, ProgramError::InvalidArgument);
+
+    let now_ts = Clock::get()?.unix_timestamp;
+    let limits = &mut fund_data.risk_limits;
+    apply_pending_limits(limits, now_ts);
+    if is_tighter_or_equal(max_trade_bps, limits.max_trade_bps) && is_tighter_or_equal(max_daily_turnover_bps, limits.max_daily_turnover_bps) {
+        limits.max_trade_bps = max_trade_bps;
+        limits.max_daily_turnover_bps = max_daily_turnover_bps;
+        limits.pending_effective_at = 0;
+    } else {
+        limits.pending_max_trade_bps = max_trade_bps;
+        limits.pending_max_daily_turnover_bps = max_daily_turnover_bps;
+        limits.pending_effective_at = now_ts + RISK_LIMITS_TIMELOCK;
+        msg!("looser limits apply at {:?}", limits.pending_effective_at);
+    }
+    Ok(())
+}
+
+fn apply_pending_limits(limits: &mut RiskLimits, now_ts: i64) {
+    if limits.pending_effective_at != 0 && now_ts >= limits.pending_effective_at {
+        limits.max_trade_bps = limits.pending_max_trade_bps;
+        limits.max_daily_turnover_bps = limits.pending_max_daily_turnover_bps;
+        limits.pending_effective_at = 0;
+    }
+}
+
+// drops the buckets older than 24h
+fn roll_turnover(limits: &mut RiskLimits, now_ts: i64) {
+    let hour = now_ts / 3600;
+    let elapsed = (hour - limits.turnover_hour).max(0) as usize;
+    for h in 1..=elapsed.min(TURNOVER_BUCKETS) {
+        limits.turnover_buckets[(limits.turnover_hour as usize + h) % TURNOVER_BUCKETS] = 0;
+    }
+    limits.turnover_hour = limits.turnover_hour.max(hour);
+}
+
+pub fn has_risk_limits(fund_data: &FundAccount) -> bool {
+    let limits = &fund_data.risk_limits;
+    limits.max_trade_bps != 0 || limits.max_daily_turnover_bps != 0 || limits.pending_effective_at != 0
+}
+
+/// Checks a trade of `value` (USDC) against the fund limits, and counts it in the
+/// 24h turnover when `record` is set
+pub fn check_trade_limits(fund_data: &mut FundAccount, value: U64F64, now_ts: i64, record: bool) -> Result<(), ProgramError> {
+    let total_amount = fund_data.total_amount;
+    let limits = &mut fund_data.risk_limits;
+    apply_pending_limits(limits, now_ts);
+    roll_turnover(limits, now_ts);
+    let value: u64 = value.to_num();
+
+    let bps_of_total = |bps: u16| U64F64::to_num::<u64>(total_amount.checked_mul(U64F64::from_num(bps)).unwrap().checked_div(U64F64::from_num(BPS)).unwrap());
+    if limits.max_trade_bps != 0 {
+        msg!("trade value {:?}, max {:?}", value, bps_of_total(limits.max_trade_bps));
+        check!(value <= bps_of_total(limits.max_trade_bps), FundError::RiskLimitExceeded);
+    }
+    let turnover = limits.turnover_buckets.iter().sum::<u64>().checked_add(value).unwrap();
+    if limits.max_daily_turnover_bps != 0 {
+        msg!("24h turnover {:?}, max {:?}", turnover, bps_of_total(limits.max_daily_turnover_bps));
+        check!(turnover <= bps_of_total(limits.max_daily_turnover_bps), FundError::RiskLimitExceeded);
+    }
+    if record {
+        let bucket = limits.turnover_hour as usize % TURNOVER_BUCKETS;
+        limits.turnover_buckets[bucket] = limits.turnover_buckets[bucket].checked_add(value).unwrap();
+    }
+    Ok(())
+}
+
+fn to_banned_list(banned_mints: &[Pubkey]) -> Result<[Pubkey; MAX_BANNED_TOKENS], ProgramError> {
+    check!(banned_mints.len() <= MAX_BANNED_TOKENS, ProgramError::InvalidArgument);
+    let mut list = [Pubkey::default(); MAX_BANNED_TOKENS];
+    list[..banned_mints.len()].copy_from_slice(banned_mints);
+    Ok(list)
+}
+
+/// Sets the mandate of a newly initialized fund, `is_locked` makes it permanent
+///
+/// Accounts expected, as in Initialize
+/// 0. []   Platform State Account
+/// 1. [WRITE]  Fund State Account
+pub fn init_fund_mandate(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    max_token_weight_bps: u16,
+    min_usdc_buffer_bps: u16,
+    is_locked: bool,
+    banned_mints: &[Pubkey],
+) -> Result<(), ProgramError> {
+    let accounts_iter = &mut accounts.iter();
+    let _platform_ai = next_account_info(accounts_iter)?;
+    let fund_account_ai = next_account_info(accounts_iter)?;
+    let mut fund_data = FundAccount::load_mut_checked(fund_account_ai, program_id)?;
+    check!(max_token_weight_bps as u64 <= BPS && min_usdc_buffer_bps as u64 <= BPS, ProgramError::InvalidArgument);
+
+    let mandate = &mut fund_data.mandate;
+    mandate.max_token_weight_bps = max_token_weight_bps;
+    mandate.min_usdc_buffer_bps = min_usdc_buffer_bps;
+    mandate.banned_mints = to_banned_list(banned_mints)?;
+    mandate.is_locked = is_locked;
+    Ok(())
+}
+
+/// Tighter mandates apply at once, looser ones after RISK_LIMITS_TIMELOCK.
+/// A stricter mandate caps weights lower, keeps a larger USDC buffer and bans at least the same mints.
+///
+/// Accounts expected
+/// 0. [WRITE]  Fund State Account
+/// 1. [SIGNER] Manager Account
+pub fn set_fund_mandate(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    max_token_weight_bps: u16,
+    min_usdc_buffer_bps: u16,
+    banned_mints: &[Pubkey],
+) -> Result<(), ProgramError> {
+    let accounts_iter = &mut accounts.iter();
+    let fund_account_ai = next_account_info(accounts_iter)?;
+    let manager_ai = next_account_info(accounts_iter)?;
+
+    let mut fund_data = FundAccount::load_mut_checked(fund_account_ai, program_id)?;
+    check!(fund_data.is_initialized(), FundError::InvalidStateAccount);
+    check!(manager_ai.is_signer, ProgramError::MissingRequiredSignature);
+    check!(fund_data.manager_account == *manager_ai.key, FundError::ManagerMismatch);
+    check!(!fund_data.mandate.is_locked, FundError::MandateLocked);
+    check!(max_token_weight_bps as u64 <= BPS && min_usdc_buffer_bps as u64 <= BPS, ProgramError::InvalidArgument);
+    let banned_list = to_banned_list(banned_mints)?;
+
+    let now_ts = Clock::get()?.unix_timestamp;
+    let mandate = &mut fund_data.mandate;
+    apply_pending_mandate(mandate, now_ts);
+    let is_stricter = is_tighter_or_equal(max_token_weight_bps, mandate.max_token_weight_bps)
+        && min_usdc_buffer_bps >= mandate.min_usdc_buffer_bps
+        && mandate.banned_mints.iter().all(|m| *m == Pubkey::default() || banned_list.contains(m));
+    if is_stricter {
+        mandate.max_token_weight_bps = max_token_weight_bps;
+        mandate.min_usdc_buffer_bps = min_usdc_buffer_bps;
+        mandate.banned_mints = banned_list;
+        mandate.pending_effective_at = 0;
+    } else {
+        mandate.pending_max_token_weight_bps = max_token_weight_bps;
+        mandate.pending_min_usdc_buffer_bps = min_usdc_buffer_bps;
+        mandate.pending_banned_mints = banned_list;
+        mandate.pending_effective_at = now_ts + RISK_LIMITS_TIMELOCK;
+        msg!("looser mandate applies at {:?}", mandate.pending_effective_at);
+    }
+    Ok(())
+}
+
+fn apply_pending_mandate(mandate: &mut Mandate, now_ts: i64) {
+    if mandate.pending_effective_at != 0 && now_ts >= mandate.pending_effective_at {
+        mandate.max_token_weight_bps = mandate.pending_max_token_weight_bps;
+        mandate.min_usdc_buffer_bps = mandate.pending_min_usdc_buffer_bps;
+        mandate.banned_mints = mandate.pending_banned_mints;
+        mandate.pending_banned_mints = [Pubkey::default(); MAX_BANNED_TOKENS];
+        mandate.pending_effective_at = 0;
+    }
+}
+
+/// USDC exposure of each token slot, and the fund value the weights are taken of.
+/// Slots hold their vault balance net of debt, strategy positions in their underlying slot and
+/// open orders in their market slots. The obligation and Mango equity only count in the total,
+/// the USDC buffer is what the vault holds.
+pub fn get_token_values(
+    platform_data: &PlatformData,
+    fund_data: &FundAccount,
+    now_ts: i64,
+) -> Result<([U64F64; NUM_TOKENS], U64F64), ProgramError> {
+    let mut values = [U64F64!(0); NUM_TOKENS];
+    for i in 0..NUM_TOKENS {
+        let amount = fund_data.tokens[i].balance.checked_sub(fund_data.tokens[i].debt).unwrap();
+        if !fund_data.tokens[i].is_active || amount == 0 { continue; }
+        values[i] = U64F64::from_num(amount).checked_mul(get_slot_price(platform_data, fund_data, i, now_ts)?).unwrap();
+    }
+    for position in 0..MAX_STRATEGY_POSITIONS {
+        let info = fund_data.strategy_position(position);
+        if !info.is_active { continue; }
+        let slot = info.ul_token_slot as usize;
+        values[slot] = values[slot].checked_add(get_strategy_adapter(info.kind)?.get_value(platform_data, fund_data, position, now_ts)?).unwrap();
+    }
+    for (info, debt) in fund_data.serum_markets.iter().zip(fund_data.serum_debts.iter()).filter(|(info, _)| info.is_active) {
+        for (slot, amount) in [(info.base_slot as usize, info.base_total.saturating_sub(debt.base_debt)), (info.quote_slot as usize, info.quote_total.saturating_sub(debt.quote_debt))] {
+            if amount == 0 { continue; }
+            if now_ts - info.last_updated > 100 {
+                msg!("open orders not up-to-date...");
+                return Err(FundError::PriceStaleInAccount.into())
+            }
+            values[slot] = values[slot].checked_add(U64F64::from_num(amount).checked_mul(get_slot_price(platform_data, fund_data, slot, now_ts)?).unwrap()).unwrap();
+        }
+    }
+    let mut total = values.iter().try_fold(U64F64!(0), |total, value| total.checked_add(*value)).unwrap();
+    total = add_leverage_value(fund_data, total, now_ts)?;
+    total = add_mango_value(fund_data, total, now_ts)?;
+    Ok((values, total))
+}
+
+/// Checks the token weights after a swap from `token_in_slot` into `token_out_slots`.
+/// Only what the trade increased is checked, so trades back towards the mandate always pass.
+pub fn check_mandate(
+    platform_data: &PlatformData,
+    fund_data: &mut FundAccount,
+    token_in_slot: usize,
+    token_out_slots: &[usize],
+    now_ts: i64,
+) -> Result<(), ProgramError> {
+    apply_pending_mandate(&mut fund_data.mandate, now_ts);
+    if !fund_data.mandate.is_set() {
+        return Ok(())
+    }
+
+    let (values, total) = get_token_values(platform_data, fund_data, now_ts)?;
+    let bps_of_total = |bps: u16| total.checked_mul(U64F64::from_num(bps)).unwrap().checked_div(U64F64::from_num(BPS)).unwrap();
+
+    let mandate = fund_data.mandate;
+    for &slot in token_out_slots {
+        if slot == 0 { continue; }
+        let mint = platform_data.token_list[fund_data.tokens[slot].get_active_index()].mint;
+        check!(!mandate.is_banned(&mint), FundError::MandateViolation);
+        if mandate.max_token_weight_bps != 0 {
+            msg!("slot {:?} value {:?}, max {:?}", slot, values[slot], bps_of_total(mandate.max_token_weight_bps));
+            check!(values[slot] <= bps_of_total(mandate.max_token_weight_bps), FundError::MandateViolation);
+        }
+    }
+    if token_in_slot == 0 && mandate.min_usdc_buffer_bps != 0 {
+        msg!("USDC value {:?}, min {:?}", values[0], bps_of_total(mandate.min_usdc_buffer_bps));
+        check!(values[0] >= bps_of_total(mandate.min_usdc_buffer_bps), FundError::MandateViolation);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytemuck::Zeroable;
+
+    const HOUR: i64 = 3600;
+    const NOW: i64 = 1_000 * HOUR;
+
+    fn fund_with_limits(max_trade_bps: u16, max_daily_turnover_bps: u16) -> FundAccount {
+        let mut fund_data = FundAccount::zeroed();
+        fund_data.total_amount = U64F64::from_num(10_000);
+        fund_data.risk_limits.max_trade_bps = max_trade_bps;
+        fund_data.risk_limits.max_daily_turnover_bps = max_daily_turnover_bps;
+        fund_data.risk_limits.turnover_hour = NOW / HOUR;
+        fund_data
+    }
+
+    #[test]
+    fn roll_turnover_drops_old_buckets() {
+        let mut limits = RiskLimits::zeroed();
+        limits.turnover_hour = 10;
+        limits.turnover_buckets = [1; TURNOVER_BUCKETS];
+        roll_turnover(&mut limits, 10 * HOUR + HOUR - 1);
+        assert_eq!(limits.turnover_buckets.iter().sum::<u64>(), TURNOVER_BUCKETS as u64);
+
+        roll_turnover(&mut limits, 12 * HOUR);
+        assert_eq!(limits.turnover_hour, 12);
+        assert_eq!(limits.turnover_buckets[11 % TURNOVER_BUCKETS], 0);
+        assert_eq!(limits.turnover_buckets[12 % TURNOVER_BUCKETS], 0);
+        assert_eq!(limits.turnover_buckets.iter().sum::<u64>(), TURNOVER_BUCKETS as u64 - 2);
+
+        // a stale clock does not move the window back
+        roll_turnover(&mut limits, 5 * HOUR);
+        assert_eq!(limits.turnover_hour, 12);
+
+        roll_turnover(&mut limits, 100 * HOUR);
+        assert_eq!(limits.turnover_hour, 100);
+        assert_eq!(limits.turnover_buckets, [0; TURNOVER_BUCKETS]);
+    }
+
+    #[test]
+    fn trade_size_limit() {
+        let mut fund_data = fund_with_limits(100, 0);
+        assert!(check_trade_limits(&mut fund_data, U64F64::from_num(100), NOW, true).is_ok());
+        assert_eq!(check_trade_limits(&mut fund_data, U64F64::from_num(101), NOW, true), Err(FundError::RiskLimitExceeded.into()));
+    }
+
+    #[test]
+    fn daily_turnover_limit() {
+        let mut fund_data = fund_with_limits(0, 500);
+        for h in 0..5 {
+            check_trade_limits(&mut fund_data, U64F64::from_num(100), NOW + h * HOUR, true).unwrap();
+        }
+        assert_eq!(check_trade_limits(&mut fund_data, U64F64::from_num(1), NOW + 5 * HOUR, true), Err(FundError::RiskLimitExceeded.into()));
+        // unrecorded checks leave the turnover as is
+        let mut fund_data = fund_with_limits(0, 500);
+        for _ in 0..10 {
+            check_trade_limits(&mut fund_data, U64F64::from_num(500), NOW, false).unwrap();
+        }
+        assert_eq!(fund_data.risk_limits.turnover_buckets.iter().sum::<u64>(), 0);
+    }
+
+    #[test]
+    fn daily_turnover_frees_up_after_24h() {
+        let mut fund_data = fund_with_limits(0, 500);
+        check_trade_limits(&mut fund_data, U64F64::from_num(500), NOW, true).unwrap();
+        assert!(check_trade_limits(&mut fund_data, U64F64::from_num(1), NOW + 23 * HOUR, true).is_err());
+        assert!(check_trade_limits(&mut fund_data, U64F64::from_num(500), NOW + 24 * HOUR, true).is_ok());
+    }
+
+    #[test]
+    fn pending_limits_apply_once_effective() {
+        let mut fund_data = fund_with_limits(100, 0);
+        fund_data.risk_limits.pending_max_trade_bps = 200;
+        fund_data.risk_limits.pending_effective_at = NOW + HOUR;
+        assert!(check_trade_limits(&mut fund_data, U64F64::from_num(150), NOW, false).is_err());
+        assert!(check_trade_limits(&mut fund_data, U64F64::from_num(150), NOW + HOUR, false).is_ok());
+        assert_eq!(fund_data.risk_limits.max_trade_bps, 200);
+        assert_eq!(fund_data.risk_limits.pending_effective_at, 0);
+    }
+}
@@ -0,0 +1,431 @@
+use bytemuck::bytes_of;
+use arrayref::array_ref;
+use fixed::types::U64F64;
+use std::convert::TryInto;
+
+use solana_program::{
+    account_info::{AccountInfo, next_account_info},
+    instruction::{AccountMeta, Instruction},
+    msg,
+    program::invoke_signed,
+    program_error::ProgramError,
+    program_pack::IsInitialized,
+    pubkey::Pubkey,
+    sysvar::{Sysvar, clock::Clock},
+};
+
+use crate::error::FundError;
+use crate::state::{MAX_SERUM_MARKETS, FundAccount, InvestorData, PlatformData, TokenSlot};
+use crate::jup_utils::{serum_dex, get_slot_price, get_slippage_bps};
+use crate::processor::parse_token_account;
+use crate::risk_utils::{has_risk_limits, check_trade_limits, check_mandate};
+use crate::tokens::load_open_orders;
+
+macro_rules! check {
+    ($cond:expr, $err:expr) => {
+        if !($cond) {
+            return Err(($err).into())
+        }
+    }
+}
+macro_rules! check_eq {
+    ($x:expr, $y:expr) => {
+        if ($x != $y) {
+            return Err(FundError::Default.into())
+        }
+    }
+}
+
+pub mod openbook_id {
+    use solana_program::declare_id;
+    declare_id!("srmqPvymJeFKQ4zGQed1GFppgkRHL9kaELCbyksJtPX");
+}
+
+// dex instruction tags, data is [version: u8, tag: u32 LE, args]
+const SETTLE_FUNDS: u32 = 5;
+const NEW_ORDER_V3: u32 = 10;
+const CANCEL_ORDER_V2: u32 = 11;
+const CANCEL_ORDER_BY_CLIENT_ID_V2: u32 = 12;
+
+// MarketState offsets, the 5 byte "serum" head padding included
+const MARKET_COIN_MINT: usize = 53;
+const MARKET_PC_MINT: usize = 85;
+const MARKET_COIN_LOT_SIZE: usize = 349;
+const MARKET_PC_LOT_SIZE: usize = 357;
+
+fn is_dex_program(key: &Pubkey) -> bool {
+    *key == serum_dex::ID || *key == openbook_id::ID
+}
+
+/// Registers a market and the fund's open orders account on it (created with InitOpenOrderAccounts)
+///
+/// Accounts expected
+/// 0. []   Platform State Account
+/// 1. [WRITE]  Fund State Account
+/// 2. [SIGNER] Manager Account
+/// 3. []   Market
+/// 4. []   Open Orders Account
+pub fn add_serum_market(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    base_slot: u8,
+    quote_slot: u8,
+) -> Result<(), ProgramError> {
+    let accounts_iter = &mut accounts.iter();
+    let platform_ai = next_account_info(accounts_iter)?;
+    let fund_account_ai = next_account_info(accounts_iter)?;
+    let manager_ai = next_account_info(accounts_iter)?;
+    let market_ai = next_account_info(accounts_iter)?;
+    let open_orders_ai = next_account_info(accounts_iter)?;
+
+    let platform_data = PlatformData::load_checked(platform_ai, program_id)?;
+    let mut fund_data = FundAccount::load_mut_checked(fund_account_ai, program_id)?;
+    check!(fund_data.is_initialized(), FundError::InvalidStateAccount);
+    check!(manager_ai.is_signer, ProgramError::MissingRequiredSignature);
+    check!(fund_data.manager_account == *manager_ai.key, FundError::ManagerMismatch);
+    check!(fund_data.get_serum_market_index(market_ai.key) == None, ProgramError::InvalidArgument);
+    check!((base_slot as usize) < fund_data.tokens.len() && (quote_slot as usize) < fund_data.tokens.len(), ProgramError::InvalidArgument);
+    check!(fund_data.tokens[base_slot as usize].is_active && fund_data.tokens[quote_slot as usize].is_active, ProgramError::InvalidArgument);
+
+    check!(is_dex_program(market_ai.owner), FundError::IncorrectProgramId);
+    {
+        let market_data = market_ai.try_borrow_data()?;
+        check!(market_data.len() > MARKET_PC_LOT_SIZE + 8, ProgramError::InvalidAccountData);
+        check_eq!(*array_ref![market_data, MARKET_COIN_MINT, 32], platform_data.token_list[fund_data.tokens[base_slot as usize].get_active_index()].mint.to_bytes());
+        check_eq!(*array_ref![market_data, MARKET_PC_MINT, 32], platform_data.token_list[fund_data.tokens[quote_slot as usize].get_active_index()].mint.to_bytes());
+    }
+    check_eq!(open_orders_ai.owner, market_ai.owner);
+    {
+        let open_orders = load_open_orders(open_orders_ai)?;
+        check_eq!(bytes_of(&open_orders.market), market_ai.key.as_ref());
+        check_eq!(bytes_of(&open_orders.owner), fund_data.fund_pda.as_ref());
+    }
+
+    let i = fund_data.serum_markets.iter().position(|info| !info.is_active).ok_or(ProgramError::InvalidArgument)?;
+    let info = &mut fund_data.serum_markets[i];
+    info.market = *market_ai.key;
+    info.open_orders = *open_orders_ai.key;
+    info.base_slot = base_slot;
+    info.quote_slot = quote_slot;
+    info.is_active = true;
+    info.base_total = 0;
+    info.quote_total = 0;
+    info.last_updated = Clock::get()?.unix_timestamp;
+    Ok(())
+}
+
+/// Accounts expected
+/// 0. [WRITE]  Fund State Account
+/// 1. [SIGNER] Manager Account
+/// 2. []   Market
+/// 3. []   Open Orders Account
+pub fn remove_serum_market(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+) -> Result<(), ProgramError> {
+    let accounts_iter = &mut accounts.iter();
+    let fund_account_ai = next_account_info(accounts_iter)?;
+    let manager_ai = next_account_info(accounts_iter)?;
+    let market_ai = next_account_info(accounts_iter)?;
+    let open_orders_ai = next_account_info(accounts_iter)?;
+
+    let mut fund_data = FundAccount::load_mut_checked(fund_account_ai, program_id)?;
+    check!(manager_ai.is_signer, ProgramError::MissingRequiredSignature);
+    check!(fund_data.manager_account == *manager_ai.key, FundError::ManagerMismatch);
+    let i = fund_data.get_serum_market_index(market_ai.key).ok_or(ProgramError::InvalidArgument)?;
+    check_eq!(fund_data.serum_markets[i].open_orders, *open_orders_ai.key);
+    check!(fund_data.serum_debts[i].base_debt == 0 && fund_data.serum_debts[i].quote_debt == 0, FundError::ExitStepPending);
+    {
+        // everything settled back to the vaults
+        let open_orders = load_open_orders(open_orders_ai)?;
+        check!(open_orders.native_coin_total == 0 && open_orders.native_pc_total == 0, FundError::InvalidStateAccount);
+    }
+    fund_data.serum_markets[i] = bytemuck::Zeroable::zeroed();
+    Ok(())
+}
+
+/// Relays NewOrderV3, CancelOrderV2, CancelOrderByClientIdV2 and SettleFunds for the fund.
+/// The manager may place orders; an investor may only cancel and settle, a settled one only while
+/// the open orders still owe settled investors. Orders are priced against the oracle within the
+/// fund's slippage bound and count against the risk limits and mandate, payers and settle
+/// destinations must be the fund vaults of the market.
+///
+/// Accounts expected
+/// 0. [SIGNER] Manager or Investor Account
+/// 1. [WRITE]  Fund State Account
+/// 2. []   Platform State Account
+/// 3. []   Investor State Account, or the Fund State Account again when the manager signs
+/// 4. []   Dex Program
+/// 5.. []  Dex instruction accounts, in the dex order
+pub fn serum_order(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    data: &[u8]
+) -> Result<(), ProgramError> {
+    let accounts_iter = &mut accounts.iter();
+    let signer_ai = next_account_info(accounts_iter)?;
+    let fund_account_ai = next_account_info(accounts_iter)?;
+    let platform_ai = next_account_info(accounts_iter)?;
+    let investor_state_ai = next_account_info(accounts_iter)?;
+    let dex_prog_ai = next_account_info(accounts_iter)?;
+    let dex_accs = accounts_iter.as_slice();
+
+    check!(signer_ai.is_signer, ProgramError::MissingRequiredSignature);
+    check!(is_dex_program(dex_prog_ai.key), FundError::IncorrectProgramId);
+    let platform_data = PlatformData::load_checked(platform_ai, program_id)?;
+    let mut fund_data = FundAccount::load_mut_checked(fund_account_ai, program_id)?;
+    check!(fund_data.is_initialized(), FundError::InvalidStateAccount);
+
+    let is_manager = fund_data.manager_account == *signer_ai.key;
+    if !is_manager {
+        let investor_data = InvestorData::load_checked(investor_state_ai, program_id)?;
+        check_eq!(investor_data.owner, *signer_ai.key);
+        check_eq!(investor_data.manager, fund_data.manager_account);
+        check!(investor_data.amount != 0, FundError::InvalidStateAccount);
+        // only a settled investor, to unwind orders that still owe the settled investors
+        check!(investor_data.has_withdrawn && get_serum_pending(&fund_data).is_some(), FundError::InvalidStateAccount);
+    }
+
+    let tag = u32::from_le_bytes(data.get(1..5).ok_or(ProgramError::InvalidInstructionData)?.try_into().unwrap());
+    msg!("Dex Discrim:: {:?}", tag);
+    // market and open orders positions in the dex account list
+    let (market_pos, open_orders_pos) = match tag {
+        NEW_ORDER_V3 => (0, 1),
+        CANCEL_ORDER_V2 | CANCEL_ORDER_BY_CLIENT_ID_V2 => (0, 3),
+        SETTLE_FUNDS => (0, 1),
+        _ => return Err(FundError::InvalidInstruction.into())
+    };
+    let market_ai = dex_accs.get(market_pos).ok_or(ProgramError::NotEnoughAccountKeys)?;
+    let open_orders_ai = dex_accs.get(open_orders_pos).ok_or(ProgramError::NotEnoughAccountKeys)?;
+    let mi = fund_data.get_serum_market_index(market_ai.key).ok_or(ProgramError::InvalidArgument)?;
+    check_eq!(fund_data.serum_markets[mi].open_orders, *open_orders_ai.key);
+    check_eq!(market_ai.owner, dex_prog_ai.key);
+    let base_slot = fund_data.serum_markets[mi].base_slot as usize;
+    let quote_slot = fund_data.serum_markets[mi].quote_slot as usize;
+
+    match tag {
+        NEW_ORDER_V3 => {
+            check!(is_manager, FundError::ManagerMismatch);
+            check!(data.len() >= 33, ProgramError::InvalidInstructionData);
+            let is_bid = u32::from_le_bytes(*array_ref![data, 5, 4]) == 0;
+            let limit_price = u64::from_le_bytes(*array_ref![data, 9, 8]);
+            let max_coin_qty = u64::from_le_bytes(*array_ref![data, 17, 8]);
+            let max_native_pc_qty = u64::from_le_bytes(*array_ref![data, 25, 8]);
+            // order payer
+            let payer_ai = dex_accs.get(6).ok_or(ProgramError::NotEnoughAccountKeys)?;
+            let pay_slot = if is_bid { quote_slot } else { base_slot };
+            check_eq!(fund_data.tokens[pay_slot].vault, *payer_ai.key);
+            check_order_price(&platform_data, &fund_data, market_ai, base_slot, quote_slot, is_bid, limit_price)?;
+            if has_risk_limits(&fund_data) {
+                // at most what the order can pay
+                let now_ts = Clock::get()?.unix_timestamp;
+                let max_pay = if is_bid { max_native_pc_qty } else { max_coin_qty.checked_mul(get_lot_sizes(market_ai)?.0).unwrap() };
+                let value = U64F64::from_num(max_pay).checked_mul(get_slot_price(&platform_data, &fund_data, pay_slot, now_ts)?).unwrap();
+                check_trade_limits(&mut fund_data, value, now_ts, true)?;
+            }
+        }
+        SETTLE_FUNDS => {
+            let coin_wallet_ai = dex_accs.get(5).ok_or(ProgramError::NotEnoughAccountKeys)?;
+            let pc_wallet_ai = dex_accs.get(6).ok_or(ProgramError::NotEnoughAccountKeys)?;
+            check_eq!(fund_data.tokens[base_slot].vault, *coin_wallet_ai.key);
+            check_eq!(fund_data.tokens[quote_slot].vault, *pc_wallet_ai.key);
+            // no referrer, rebates stay with the fund
+            check!(dex_accs.len() <= 9, ProgramError::InvalidArgument);
+        }
+        _ => {}
+    }
+
+    let mut meta_accounts = vec![];
+    meta_accounts.extend(dex_accs.iter().map(|a| {
+        if *a.key == fund_data.fund_pda { // pda will sign
+            AccountMeta::new(*a.key, true)
+        } else if a.is_writable {
+            AccountMeta::new(*a.key, a.is_signer)
+        } else {
+            AccountMeta::new_readonly(*a.key, a.is_signer)
+        }
+    }));
+    let relay_instruction = Instruction {
+        program_id: *dex_prog_ai.key,
+        accounts: meta_accounts,
+        data: data.to_vec(),
+    };
+    let nonce = fund_data.signer_nonce;
+    let manager_account = fund_data.manager_account;
+    drop(fund_data);
+    invoke_signed(
+        &relay_instruction,
+        accounts,
+        &[&[&*manager_account.as_ref(), bytes_of(&nonce)]]
+    )?;
+
+    fund_data = FundAccount::load_mut_checked(fund_account_ai, program_id)?;
+    refresh_serum_market(&mut fund_data, mi, open_orders_ai)?;
+    // vault balances move on place and settle, account positions were checked above
+    let moved: &[(usize, usize)] = match tag {
+        NEW_ORDER_V3 => &[(base_slot, 6), (quote_slot, 6)],
+        SETTLE_FUNDS => &[(base_slot, 5), (quote_slot, 6)],
+        _ => &[]
+    };
+    for (slot, pos) in moved.iter().copied() {
+        if dex_accs[pos].key != &fund_data.tokens[slot].vault {
+            continue;
+        }
+        fund_data.tokens[slot].balance = parse_token_account(&dex_accs[pos])?.amount;
+        check!(fund_data.tokens[slot].balance >= fund_data.tokens[slot].debt, ProgramError::InsufficientFunds);
+    }
+    if tag == NEW_ORDER_V3 {
+        let pay_slot = if u32::from_le_bytes(*array_ref![data, 5, 4]) == 0 { quote_slot } else { base_slot };
+        let recv_slot = if pay_slot == quote_slot { base_slot } else { quote_slot };
+        check_mandate(&platform_data, &mut fund_data, pay_slot, &[recv_slot], Clock::get()?.unix_timestamp)?;
+    }
+    unwind_serum_debt(&mut fund_data, mi);
+    Ok(())
+}
+
+/// Permissionless: refreshes the cached open orders totals valued by update_amount_and_performance,
+/// and moves what settled investors are owed into the token debts once the vaults hold it
+///
+/// Accounts expected
+/// 0. [WRITE]  Fund State Account
+/// 1.. []  Open Orders Accounts of the active markets, in fund order
+pub fn update_serum_value(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+) -> Result<(), ProgramError> {
+    let accounts_iter = &mut accounts.iter();
+    let fund_account_ai = next_account_info(accounts_iter)?;
+    let mut fund_data = FundAccount::load_mut_checked(fund_account_ai, program_id)?;
+    for i in 0..MAX_SERUM_MARKETS {
+        if !fund_data.serum_markets[i].is_active {
+            continue;
+        }
+        let open_orders_ai = next_account_info(accounts_iter)?;
+        check_eq!(fund_data.serum_markets[i].open_orders, *open_orders_ai.key);
+        refresh_serum_market(&mut fund_data, i, open_orders_ai)?;
+        unwind_serum_debt(&mut fund_data, i);
+    }
+    Ok(())
+}
+
+fn refresh_serum_market(fund_data: &mut FundAccount, i: usize, open_orders_ai: &AccountInfo) -> Result<(), ProgramError> {
+    let open_orders = load_open_orders(open_orders_ai)?;
+    let info = &mut fund_data.serum_markets[i];
+    info.base_total = open_orders.native_coin_total;
+    info.quote_total = open_orders.native_pc_total;
+    info.last_updated = Clock::get()?.unix_timestamp;
+    msg!("open orders base {:?}, quote {:?}", info.base_total, info.quote_total);
+    Ok(())
+}
+
+fn get_lot_sizes(market_ai: &AccountInfo) -> Result<(u64, u64), ProgramError> {
+    let market_data = market_ai.try_borrow_data()?;
+    check!(market_data.len() > MARKET_PC_LOT_SIZE + 8, ProgramError::InvalidAccountData);
+    Ok((u64::from_le_bytes(*array_ref![market_data, MARKET_COIN_LOT_SIZE, 8]), u64::from_le_bytes(*array_ref![market_data, MARKET_PC_LOT_SIZE, 8])))
+}
+
+/// Bids may not pay more, and asks not take less, than the oracle price within the slippage bound
+fn check_order_price(
+    platform_data: &PlatformData,
+    fund_data: &FundAccount,
+    market_ai: &AccountInfo,
+    base_slot: usize,
+    quote_slot: usize,
+    is_bid: bool,
+    limit_price: u64,
+) -> Result<(), ProgramError> {
+    let (coin_lot_size, pc_lot_size) = get_lot_sizes(market_ai)?;
+    // quote native per base native
+    let order_price = U64F64::from_num(limit_price).checked_mul(U64F64::from_num(pc_lot_size)).unwrap()
+        .checked_div(U64F64::from_num(coin_lot_size)).unwrap();
+    let now_ts = Clock::get()?.unix_timestamp;
+    let oracle_price = get_slot_price(platform_data, fund_data, base_slot, now_ts)?
+        .checked_div(get_slot_price(platform_data, fund_data, quote_slot, now_ts)?).unwrap();
+
+    let base_info = platform_data.token_list[fund_data.tokens[base_slot].get_active_index()];
+    let quote_info = platform_data.token_list[fund_data.tokens[quote_slot].get_active_index()];
+    let bps = U64F64::from_num(get_slippage_bps(platform_data, &base_info, &[quote_info], fund_data.max_slippage_bps, 0)).checked_div(U64F64::from_num(10_000)).unwrap();
+    msg!("order price {:?}, oracle price {:?}", order_price, oracle_price);
+    if is_bid {
+        check!(order_price <= oracle_price.checked_mul(U64F64::from_num(1).checked_add(bps).unwrap()).unwrap(), FundError::MinAmountFailed);
+    } else {
+        check!(order_price >= oracle_price.checked_mul(U64F64::from_num(1).checked_sub(bps).unwrap()).unwrap(), FundError::MinAmountFailed);
+    }
+    Ok(())
+}
+
+/// USDC value of the fund's open orders net of what settled investors are owed on them,
+/// errors if a market was not refreshed recently
+pub fn get_serum_value(platform_data: &PlatformData, fund_data: &FundAccount, now_ts: i64) -> Result<U64F64, ProgramError> {
+    let mut val = U64F64::from_num(0);
+    let mut debt_val = U64F64::from_num(0);
+    for (info, debt) in fund_data.serum_markets.iter().zip(fund_data.serum_debts.iter()).filter(|(info, _)| info.is_active) {
+        if info.base_total == 0 && info.quote_total == 0 && debt.base_debt == 0 && debt.quote_debt == 0 {
+            continue;
+        }
+        if now_ts - info.last_updated > 100 {
+            msg!("open orders not up-to-date...");
+            return Err(FundError::PriceStaleInAccount.into())
+        }
+        let base_price = get_slot_price(platform_data, fund_data, info.base_slot as usize, now_ts)?;
+        let quote_price = get_slot_price(platform_data, fund_data, info.quote_slot as usize, now_ts)?;
+        val = val.checked_add(U64F64::from_num(info.base_total).checked_mul(base_price).unwrap()).unwrap()
+            .checked_add(U64F64::from_num(info.quote_total).checked_mul(quote_price).unwrap()).unwrap();
+        // fills can leave a side owing more than it holds, the other side then covers it
+        debt_val = debt_val.checked_add(U64F64::from_num(debt.base_debt).checked_mul(base_price).unwrap()).unwrap()
+            .checked_add(U64F64::from_num(debt.quote_debt).checked_mul(quote_price).unwrap()).unwrap();
+    }
+    Ok(val.saturating_sub(debt_val))
+}
+
+/// Investor's part of a market's open orders, in base and quote native units
+pub fn get_serum_exit(fund_data: &FundAccount, i: usize, share: U64F64) -> (u64, u64) {
+    let info = &fund_data.serum_markets[i];
+    let debt = &fund_data.serum_debts[i];
+    (
+        U64F64::to_num(U64F64::from_num(info.base_total.saturating_sub(debt.base_debt)).checked_mul(share).unwrap()),
+        U64F64::to_num(U64F64::from_num(info.quote_total.saturating_sub(debt.quote_debt)).checked_mul(share).unwrap()),
+    )
+}
+
+/// Books the investor's part of the open orders on settle. What the free vault balances cover goes
+/// to the token debts right away, the rest waits in `serum_debts` until the orders are settled.
+pub fn book_serum_exits(fund_data: &mut FundAccount, investor_data: &mut InvestorData, share: U64F64) -> Result<(), ProgramError> {
+    for i in 0..MAX_SERUM_MARKETS {
+        let info = fund_data.serum_markets[i];
+        if !info.is_active {
+            continue;
+        }
+        let (base_amount, quote_amount) = get_serum_exit(fund_data, i, share);
+        let debt = &mut fund_data.serum_debts[i];
+        debt.base_debt = debt.base_debt.checked_add(base_amount).unwrap();
+        debt.quote_debt = debt.quote_debt.checked_add(quote_amount).unwrap();
+        let (base_slot, quote_slot) = (info.base_slot as usize, info.quote_slot as usize);
+        investor_data.token_debts[base_slot] = investor_data.token_debts[base_slot].checked_add(base_amount).unwrap();
+        investor_data.token_debts[quote_slot] = investor_data.token_debts[quote_slot].checked_add(quote_amount).unwrap();
+        unwind_serum_debt(fund_data, i);
+    }
+    Ok(())
+}
+
+/// Moves what the free vault balances cover of a market's investor debts into the token debts
+fn unwind_serum_debt(fund_data: &mut FundAccount, i: usize) {
+    let (base_slot, quote_slot) = (fund_data.serum_markets[i].base_slot as usize, fund_data.serum_markets[i].quote_slot as usize);
+    let debt = &mut fund_data.serum_debts[i];
+    pay_serum_debt(&mut fund_data.tokens[base_slot], &mut debt.base_debt);
+    pay_serum_debt(&mut fund_data.tokens[quote_slot], &mut debt.quote_debt);
+}
+
+fn pay_serum_debt(token: &mut TokenSlot, debt: &mut u64) {
+    let paid = (*debt).min(token.balance.saturating_sub(token.debt));
+    token.debt += paid;
+    *debt -= paid;
+}
+
+/// First market still owing settled investors, investors are paid once none does
+pub fn get_serum_pending(fund_data: &FundAccount) -> Option<usize> {
+    (0..MAX_SERUM_MARKETS).find(|i| {
+        let debt = &fund_data.serum_debts[*i];
+        fund_data.serum_markets[*i].is_active && (debt.base_debt > 0 || debt.quote_debt > 0)
+    })
+}
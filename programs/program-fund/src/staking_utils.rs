@@ -0,0 +1,543 @@
+use bytemuck::bytes_of;
+use arrayref::array_ref;
+use fixed::types::U64F64;
+
+use solana_program::{
+    account_info::{AccountInfo, next_account_info},
+    instruction::{AccountMeta, Instruction},
+    msg,
+    program::{invoke, invoke_signed},
+    program_error::ProgramError,
+    program_pack::Pack,
+    pubkey::Pubkey,
+    system_instruction::{allocate, assign, create_account, transfer},
+    sysvar::{Sysvar, clock::Clock, rent::Rent},
+};
+use spl_associated_token_account::get_associated_token_address;
+use spl_token::state::Account;
+
+use crate::error::FundError;
+use crate::data_utils::{read_pubkey, read_u64};
+use crate::state::{MAX_STRATEGY_POSITIONS, STRATEGY_MARINADE, FundAccount, InvestorData, PlatformData};
+use crate::processor::parse_token_account;
+use crate::strategy_utils::StrategyAdapter;
+use crate::exit_utils::refresh_exit_status;
+
+macro_rules! check {
+    ($cond:expr, $err:expr) => {
+        if !($cond) {
+            return Err(($err).into())
+        }
+    }
+}
+macro_rules! check_eq {
+    ($x:expr, $y:expr) => {
+        if ($x != $y) {
+            return Err(FundError::Default.into())
+        }
+    }
+}
+
+pub mod stake_pool_program_id {
+    use solana_program::declare_id;
+    declare_id!("SPoo1Ku8WFXoNDMHPsrGSTSG1Y47rzgn41SLUNakuHy");
+}
+
+pub mod marinade_program_id {
+    use solana_program::declare_id;
+    declare_id!("MarBmsSgKXdrN1egZf5sqe1TMai9K1rChYNDJgjq7aD");
+}
+
+// system account of the fund the venues take lamports from and pay them to
+pub const STAKE_SOL_SEED: &[u8] = b"stake_sol";
+// short-lived wSOL account closed into the stake_sol account to unwrap
+pub const STAKE_WSOL_SEED: &[u8] = b"stake_wsol";
+
+pub const STAKE_POOL_DEPOSIT_SOL: u8 = 14;
+pub const STAKE_POOL_WITHDRAW_SOL: u8 = 16;
+pub const MARINADE_DEPOSIT_OPCODE: u64 = 0xf223c68952e1f2b6;
+pub const MARINADE_LIQUID_UNSTAKE_OPCODE: u64 = 0x1e1e77f0bfe30c10;
+
+// spl stake-pool StakePool layout
+const STAKE_POOL_MINT_OFFSET: usize = 162;
+const STAKE_POOL_TOTAL_LAMPORTS_OFFSET: usize = 258;
+const STAKE_POOL_TOKEN_SUPPLY_OFFSET: usize = 266;
+const STAKE_POOL_LAST_UPDATE_EPOCH_OFFSET: usize = 274;
+// marinade State layout, after the anchor discriminator
+const MARINADE_MSOL_MINT_OFFSET: usize = 8;
+const MARINADE_MSOL_PRICE_OFFSET: usize = 512;
+
+/// SPL stake-pool (jitoSOL and others) or Marinade position, SOL in the wSOL token slot
+/// against the LST in the fund's associated token account.
+///
+/// The LST is valued at the venue's own exchange rate instead of a DEX pool.
+pub struct LiquidStakingAdapter {
+    pub kind: u8,
+}
+
+impl LiquidStakingAdapter {
+    fn venue_program_id(&self) -> Pubkey {
+        if self.kind == STRATEGY_MARINADE { marinade_program_id::ID } else { stake_pool_program_id::ID }
+    }
+
+    fn get_lst_mint(&self, venue_ai: &AccountInfo) -> Result<Pubkey, ProgramError> {
+        check!(*venue_ai.owner == self.venue_program_id(), FundError::IncorrectProgramId);
+        let data = venue_ai.try_borrow_data()?;
+        read_pubkey(&data, if self.kind == STRATEGY_MARINADE { MARINADE_MSOL_MINT_OFFSET } else { STAKE_POOL_MINT_OFFSET })
+    }
+
+    /// Lamports per native LST unit
+    fn get_lst_rate(&self, venue_ai: &AccountInfo) -> Result<U64F64, ProgramError> {
+        check!(*venue_ai.owner == self.venue_program_id(), FundError::IncorrectProgramId);
+        let data = venue_ai.try_borrow_data()?;
+        if self.kind == STRATEGY_MARINADE {
+            // msol_price is fixed point with 32 fractional bits
+            return Ok(U64F64::from_bits((read_u64(&data, MARINADE_MSOL_PRICE_OFFSET)? as u128) << 32));
+        }
+        // totals are only exact right after the epoch's update crank
+        check!(read_u64(&data, STAKE_POOL_LAST_UPDATE_EPOCH_OFFSET)? == Clock::get()?.epoch, FundError::PriceStaleInAccount);
+        let supply = read_u64(&data, STAKE_POOL_TOKEN_SUPPLY_OFFSET)?;
+        if supply == 0 {
+            return Ok(U64F64::from_num(1));
+        }
+        Ok(U64F64::from_num(read_u64(&data, STAKE_POOL_TOTAL_LAMPORTS_OFFSET)?).checked_div(U64F64::from_num(supply)).unwrap())
+    }
+
+    // the fund's ATA for the LST, never a token slot vault so it is not valued twice
+    fn check_lst_account(&self, fund_data: &FundAccount, fund_key: &Pubkey, venue_ai: &AccountInfo, lst_ai: &AccountInfo) -> Result<(), ProgramError> {
+        check_eq!(*lst_ai.key, get_associated_token_address(fund_key, &self.get_lst_mint(venue_ai)?));
+        check!(fund_data.tokens.iter().all(|t| !t.is_active || t.vault != *lst_ai.key), FundError::InvalidTokenAccount);
+        Ok(())
+    }
+
+    // receipts from the LST account, value from the venue's rate
+    fn refresh_position(&self, fund_data: &mut FundAccount, position: usize, venue_ai: &AccountInfo, lst_ai: &AccountInfo) -> Result<(), ProgramError> {
+        let receipt_balance = parse_token_account(lst_ai)?.amount;
+        let rate = self.get_lst_rate(venue_ai)?;
+        let info = fund_data.strategy_position_mut(position);
+        info.receipt_balance = receipt_balance;
+        info.value_in_ul = U64F64::to_num(U64F64::from_num(receipt_balance).checked_mul(rate).unwrap());
+        info.last_updated = Clock::get()?.unix_timestamp;
+        Ok(())
+    }
+
+    fn deposit_ix(&self, venue_ai: &AccountInfo, venue_accs: &[AccountInfo], sol_vault_ai: &AccountInfo, lst_ai: &AccountInfo, token_prog_ai: &AccountInfo, system_prog_ai: &AccountInfo, lamports: u64) -> Result<Instruction, ProgramError> {
+        let mut data = Vec::<u8>::new();
+        let accounts = if self.kind == STRATEGY_MARINADE {
+            check!(venue_accs.len() >= 6, ProgramError::NotEnoughAccountKeys);
+            let [msol_mint_ai, sol_leg_ai, msol_leg_ai, msol_leg_authority_ai, reserve_ai, msol_mint_authority_ai] = array_ref![venue_accs, 0, 6];
+            data.extend(MARINADE_DEPOSIT_OPCODE.to_be_bytes().to_vec());
+            vec![
+                AccountMeta::new(*venue_ai.key, false),
+                AccountMeta::new(*msol_mint_ai.key, false),
+                AccountMeta::new(*sol_leg_ai.key, false),
+                AccountMeta::new(*msol_leg_ai.key, false),
+                AccountMeta::new_readonly(*msol_leg_authority_ai.key, false),
+                AccountMeta::new(*reserve_ai.key, false),
+                AccountMeta::new(*sol_vault_ai.key, true),
+                AccountMeta::new(*lst_ai.key, false),
+                AccountMeta::new_readonly(*msol_mint_authority_ai.key, false),
+                AccountMeta::new_readonly(*system_prog_ai.key, false),
+                AccountMeta::new_readonly(*token_prog_ai.key, false),
+            ]
+        } else {
+            check!(venue_accs.len() >= 4, ProgramError::NotEnoughAccountKeys);
+            let [withdraw_authority_ai, reserve_stake_ai, manager_fee_ai, pool_mint_ai] = array_ref![venue_accs, 0, 4];
+            data.push(STAKE_POOL_DEPOSIT_SOL);
+            vec![
+                AccountMeta::new(*venue_ai.key, false),
+                AccountMeta::new_readonly(*withdraw_authority_ai.key, false),
+                AccountMeta::new(*reserve_stake_ai.key, false),
+                AccountMeta::new(*sol_vault_ai.key, true),
+                AccountMeta::new(*lst_ai.key, false),
+                AccountMeta::new(*manager_fee_ai.key, false),
+                // no referrer, its share of the fee comes back to the fund
+                AccountMeta::new(*lst_ai.key, false),
+                AccountMeta::new(*pool_mint_ai.key, false),
+                AccountMeta::new_readonly(*system_prog_ai.key, false),
+                AccountMeta::new_readonly(*token_prog_ai.key, false),
+            ]
+        };
+        data.extend_from_slice(&lamports.to_le_bytes());
+        Ok(Instruction { program_id: self.venue_program_id(), accounts, data })
+    }
+
+    fn withdraw_ix(&self, venue_ai: &AccountInfo, venue_accs: &[AccountInfo], fund_ai: &AccountInfo, sol_vault_ai: &AccountInfo, lst_ai: &AccountInfo, token_prog_ai: &AccountInfo, system_prog_ai: &AccountInfo, lst_amount: u64) -> Result<Instruction, ProgramError> {
+        let mut data = Vec::<u8>::new();
+        let accounts = if self.kind == STRATEGY_MARINADE {
+            check!(venue_accs.len() >= 4, ProgramError::NotEnoughAccountKeys);
+            let [msol_mint_ai, sol_leg_ai, msol_leg_ai, treasury_ai] = array_ref![venue_accs, 0, 4];
+            data.extend(MARINADE_LIQUID_UNSTAKE_OPCODE.to_be_bytes().to_vec());
+            vec![
+                AccountMeta::new(*venue_ai.key, false),
+                AccountMeta::new(*msol_mint_ai.key, false),
+                AccountMeta::new(*sol_leg_ai.key, false),
+                AccountMeta::new(*msol_leg_ai.key, false),
+                AccountMeta::new(*treasury_ai.key, false),
+                AccountMeta::new(*lst_ai.key, false),
+                AccountMeta::new_readonly(*fund_ai.key, true),
+                AccountMeta::new(*sol_vault_ai.key, false),
+                AccountMeta::new_readonly(*system_prog_ai.key, false),
+                AccountMeta::new_readonly(*token_prog_ai.key, false),
+            ]
+        } else {
+            check!(venue_accs.len() >= 7, ProgramError::NotEnoughAccountKeys);
+            let [withdraw_authority_ai, reserve_stake_ai, manager_fee_ai, pool_mint_ai, clock_ai, stake_history_ai, stake_prog_ai] = array_ref![venue_accs, 0, 7];
+            data.push(STAKE_POOL_WITHDRAW_SOL);
+            vec![
+                AccountMeta::new(*venue_ai.key, false),
+                AccountMeta::new_readonly(*withdraw_authority_ai.key, false),
+                AccountMeta::new_readonly(*fund_ai.key, true),
+                AccountMeta::new(*lst_ai.key, false),
+                AccountMeta::new(*reserve_stake_ai.key, false),
+                AccountMeta::new(*sol_vault_ai.key, false),
+                AccountMeta::new(*manager_fee_ai.key, false),
+                AccountMeta::new(*pool_mint_ai.key, false),
+                AccountMeta::new_readonly(*clock_ai.key, false),
+                AccountMeta::new_readonly(*stake_history_ai.key, false),
+                AccountMeta::new_readonly(*stake_prog_ai.key, false),
+                AccountMeta::new_readonly(*token_prog_ai.key, false),
+            ]
+        };
+        data.extend_from_slice(&lst_amount.to_le_bytes());
+        Ok(Instruction { program_id: self.venue_program_id(), accounts, data })
+    }
+
+    /// Redeems `lst_amount` and wraps the SOL back into the token slot vault, returns the lamports received
+    fn redeem(&self, program_id: &Pubkey, accounts: &[AccountInfo], lst_amount: u64, is_manager: bool) -> Result<(usize, u64), ProgramError> {
+        const NUM_FIXED: usize = 9;
+        check!(accounts.len() >= NUM_FIXED, ProgramError::NotEnoughAccountKeys);
+        let (fixed_accs, venue_accs) = accounts.split_at(NUM_FIXED);
+        let [
+            fund_account_ai,
+            signer_ai,
+            sol_vault_ai,
+            wsol_vault_ai,
+            lst_ai,
+            venue_program_ai,
+            venue_ai,
+            token_prog_ai,
+            system_prog_ai,
+        ] = array_ref![fixed_accs, 0, NUM_FIXED];
+
+        check!(signer_ai.is_signer, ProgramError::MissingRequiredSignature);
+        check_eq!(*venue_program_ai.key, self.venue_program_id());
+        check_eq!(*token_prog_ai.key, spl_token::ID);
+        let fund_data = FundAccount::load_checked(fund_account_ai, program_id)?;
+        if is_manager {
+            check!(fund_data.manager_account == *signer_ai.key, FundError::ManagerMismatch);
+        }
+        let position = fund_data.get_strategy_index(self.kind, venue_ai.key).ok_or(FundError::InvalidStrategy)?;
+        let tsi = fund_data.strategy_position(position).ul_token_slot as usize;
+        check_eq!(fund_data.tokens[tsi].vault, *wsol_vault_ai.key);
+        self.check_lst_account(&fund_data, fund_account_ai.key, venue_ai, lst_ai)?;
+        let (sol_vault, sol_vault_nonce) = Pubkey::find_program_address(&[fund_account_ai.key.as_ref(), STAKE_SOL_SEED], program_id);
+        check_eq!(*sol_vault_ai.key, sol_vault);
+        let manager_account = fund_data.manager_account;
+        let nonce = fund_data.signer_nonce;
+        drop(fund_data);
+
+        let lamports_before = sol_vault_ai.lamports();
+        let mut account_infos = vec![
+            venue_ai.clone(),
+            fund_account_ai.clone(),
+            sol_vault_ai.clone(),
+            lst_ai.clone(),
+            token_prog_ai.clone(),
+            system_prog_ai.clone(),
+        ];
+        account_infos.extend_from_slice(venue_accs);
+        invoke_signed(
+            &self.withdraw_ix(venue_ai, venue_accs, fund_account_ai, sol_vault_ai, lst_ai, token_prog_ai, system_prog_ai, lst_amount)?,
+            &account_infos,
+            &[&[manager_account.as_ref(), bytes_of(&nonce)]]
+        )?;
+        let received = sol_vault_ai.lamports().checked_sub(lamports_before).unwrap();
+
+        // wrap: lamports into the native token account, then resync its amount
+        invoke_signed(
+            &transfer(sol_vault_ai.key, wsol_vault_ai.key, received),
+            &[sol_vault_ai.clone(), wsol_vault_ai.clone(), system_prog_ai.clone()],
+            &[&[fund_account_ai.key.as_ref(), STAKE_SOL_SEED, bytes_of(&sol_vault_nonce)]]
+        )?;
+        invoke_signed(
+            &spl_token::instruction::sync_native(token_prog_ai.key, wsol_vault_ai.key)?,
+            &[wsol_vault_ai.clone(), token_prog_ai.clone()],
+            &[]
+        )?;
+
+        let mut fund_data = FundAccount::load_mut_checked(fund_account_ai, program_id)?;
+        fund_data.tokens[tsi].balance = parse_token_account(wsol_vault_ai)?.amount;
+        self.refresh_position(&mut fund_data, position, venue_ai, lst_ai)?;
+        Ok((position, received))
+    }
+}
+
+impl StrategyAdapter for LiquidStakingAdapter {
+    /// Accounts expected
+    /// 0. []   Platform State Account
+    /// 1. [SIGNER] Manager Account
+    /// 2. [WRITE]  Fund State Account
+    /// 3. []   Stake Pool / Marinade State Account
+    fn add(&self, program_id: &Pubkey, accounts: &[AccountInfo], ul_token_slot: u8) -> Result<(), ProgramError> {
+        let accounts_iter = &mut accounts.iter();
+        let platform_ai = next_account_info(accounts_iter)?;
+        let platform_data = PlatformData::load_checked(platform_ai, program_id)?;
+        let manager_ai = next_account_info(accounts_iter)?;
+        check!(manager_ai.is_signer, ProgramError::MissingRequiredSignature);
+        let fund_account_ai = next_account_info(accounts_iter)?;
+        let mut fund_data = FundAccount::load_mut_checked(fund_account_ai, program_id)?;
+        check!(fund_data.manager_account == *manager_ai.key, FundError::ManagerMismatch);
+        let venue_ai = next_account_info(accounts_iter)?;
+        self.get_lst_mint(venue_ai)?;
+
+        let tsi = ul_token_slot as usize;
+        check!(tsi < fund_data.tokens.len() && fund_data.tokens[tsi].is_active, ProgramError::InvalidArgument);
+        let token_info = platform_data.token_list[fund_data.tokens[tsi].get_active_index()];
+        check!(token_info.mint == spl_token::native_mint::ID, FundError::InvalidTokenAccount);
+        check!(fund_data.get_strategy_index(self.kind, venue_ai.key).is_none(), FundError::InvalidStateAccount);
+        // from the back, friktion volts are limited to the first positions
+        let position = (0..MAX_STRATEGY_POSITIONS).rev().find(|i| !fund_data.strategy_position(*i).is_active).ok_or(FundError::InvalidStateAccount)?;
+
+        let info = fund_data.strategy_position_mut(position);
+        *info = bytemuck::Zeroable::zeroed();
+        info.venue = *venue_ai.key;
+        info.kind = self.kind;
+        info.ul_token_slot = ul_token_slot;
+        info.is_active = true;
+        Ok(())
+    }
+
+    /// Accounts expected
+    /// 0. [SIGNER] Manager Account
+    /// 1. [WRITE]  Fund State Account
+    /// 2. []   Stake Pool / Marinade State Account
+    fn remove(&self, program_id: &Pubkey, accounts: &[AccountInfo]) -> Result<(), ProgramError> {
+        let accounts_iter = &mut accounts.iter();
+        let manager_ai = next_account_info(accounts_iter)?;
+        check!(manager_ai.is_signer, ProgramError::MissingRequiredSignature);
+        let fund_account_ai = next_account_info(accounts_iter)?;
+        let mut fund_data = FundAccount::load_mut_checked(fund_account_ai, program_id)?;
+        check!(fund_data.manager_account == *manager_ai.key, FundError::ManagerMismatch);
+        let venue_ai = next_account_info(accounts_iter)?;
+        let position = fund_data.get_strategy_index(self.kind, venue_ai.key).ok_or(FundError::InvalidStrategy)?;
+        let info = fund_data.strategy_position_mut(position);
+        check!(info.receipt_balance == 0 && info.ul_debt == 0, FundError::InvalidStateAccount);
+        *info = bytemuck::Zeroable::zeroed();
+        info.ul_token_slot = 255;
+        Ok(())
+    }
+
+    /// Stakes `amount` lamports of the wSOL token slot
+    ///
+    /// Accounts expected
+    /// 0. [WRITE]  Fund State Account
+    /// 1. [SIGNER, WRITE] Manager Account, fronts the rent of the unwrap account
+    /// 2. [WRITE]  Stake SOL Account, PDA of [fund, "stake_sol"]
+    /// 3. [WRITE]  Unwrap Account, PDA of [fund, "stake_wsol"]
+    /// 4. [WRITE]  Fund wSOL Vault
+    /// 5. [WRITE]  Fund LST Associated Token Account
+    /// 6. []   Stake Pool / Marinade Program
+    /// 7. [WRITE]  Stake Pool / Marinade State Account
+    /// 8. []   Native Mint
+    /// 9. []   Rent Sysvar
+    /// 10. []  Token Program
+    /// 11. []  System Program
+    /// 12.. stake pool: withdraw authority, reserve stake, manager fee account, pool mint
+    ///      marinade: msol mint, liq pool sol leg, liq pool msol leg, msol leg authority, reserve, msol mint authority
+    fn deposit(&self, program_id: &Pubkey, accounts: &[AccountInfo], amount: u64) -> Result<(), ProgramError> {
+        const NUM_FIXED: usize = 12;
+        check!(accounts.len() >= NUM_FIXED, ProgramError::NotEnoughAccountKeys);
+        let (fixed_accs, venue_accs) = accounts.split_at(NUM_FIXED);
+        let [
+            fund_account_ai,
+            manager_ai,
+            sol_vault_ai,
+            unwrap_ai,
+            wsol_vault_ai,
+            lst_ai,
+            venue_program_ai,
+            venue_ai,
+            native_mint_ai,
+            rent_ai,
+            token_prog_ai,
+            system_prog_ai,
+        ] = array_ref![fixed_accs, 0, NUM_FIXED];
+
+        check!(manager_ai.is_signer, ProgramError::MissingRequiredSignature);
+        check!(amount > 0, ProgramError::InvalidArgument);
+        check_eq!(*venue_program_ai.key, self.venue_program_id());
+        check_eq!(*token_prog_ai.key, spl_token::ID);
+        check_eq!(*native_mint_ai.key, spl_token::native_mint::ID);
+        let fund_data = FundAccount::load_checked(fund_account_ai, program_id)?;
+        check!(fund_data.manager_account == *manager_ai.key, FundError::ManagerMismatch);
+        let position = fund_data.get_strategy_index(self.kind, venue_ai.key).ok_or(FundError::InvalidStrategy)?;
+        let tsi = fund_data.strategy_position(position).ul_token_slot as usize;
+        check_eq!(fund_data.tokens[tsi].vault, *wsol_vault_ai.key);
+        check!(fund_data.tokens[tsi].balance.checked_sub(fund_data.tokens[tsi].debt).unwrap() >= amount, ProgramError::InsufficientFunds);
+        self.check_lst_account(&fund_data, fund_account_ai.key, venue_ai, lst_ai)?;
+        let (sol_vault, sol_vault_nonce) = Pubkey::find_program_address(&[fund_account_ai.key.as_ref(), STAKE_SOL_SEED], program_id);
+        check_eq!(*sol_vault_ai.key, sol_vault);
+        let (unwrap_pda, unwrap_nonce) = Pubkey::find_program_address(&[fund_account_ai.key.as_ref(), STAKE_WSOL_SEED], program_id);
+        check_eq!(*unwrap_ai.key, unwrap_pda);
+        let manager_account = fund_data.manager_account;
+        let nonce = fund_data.signer_nonce;
+        drop(fund_data);
+        let fund_seeds: &[&[u8]] = &[manager_account.as_ref(), bytes_of(&nonce)];
+        let sol_vault_seeds: &[&[u8]] = &[fund_account_ai.key.as_ref(), STAKE_SOL_SEED, bytes_of(&sol_vault_nonce)];
+
+        // unwrap: a native account of `amount` closed into the stake_sol account.
+        // Lamports sent to the address would fail create_account, it is topped up and allocated instead
+        let rent = Rent::get()?.minimum_balance(Account::LEN);
+        let unwrap_seeds: &[&[u8]] = &[fund_account_ai.key.as_ref(), STAKE_WSOL_SEED, bytes_of(&unwrap_nonce)];
+        let fronted = rent.saturating_sub(unwrap_ai.lamports());
+        if unwrap_ai.lamports() == 0 {
+            invoke_signed(
+                &create_account(manager_ai.key, unwrap_ai.key, rent, Account::LEN as u64, &spl_token::ID),
+                &[manager_ai.clone(), unwrap_ai.clone(), system_prog_ai.clone()],
+                &[unwrap_seeds]
+            )?;
+        } else {
+            if fronted > 0 {
+                invoke(
+                    &transfer(manager_ai.key, unwrap_ai.key, fronted),
+                    &[manager_ai.clone(), unwrap_ai.clone(), system_prog_ai.clone()]
+                )?;
+            }
+            invoke_signed(
+                &allocate(unwrap_ai.key, Account::LEN as u64),
+                &[unwrap_ai.clone(), system_prog_ai.clone()],
+                &[unwrap_seeds]
+            )?;
+            invoke_signed(
+                &assign(unwrap_ai.key, &spl_token::ID),
+                &[unwrap_ai.clone(), system_prog_ai.clone()],
+                &[unwrap_seeds]
+            )?;
+        }
+        invoke_signed(
+            &spl_token::instruction::initialize_account(token_prog_ai.key, unwrap_ai.key, native_mint_ai.key, fund_account_ai.key)?,
+            &[unwrap_ai.clone(), native_mint_ai.clone(), fund_account_ai.clone(), rent_ai.clone(), token_prog_ai.clone()],
+            &[]
+        )?;
+        invoke_signed(
+            &spl_token::instruction::transfer(token_prog_ai.key, wsol_vault_ai.key, unwrap_ai.key, fund_account_ai.key, &[fund_account_ai.key], amount)?,
+            &[wsol_vault_ai.clone(), unwrap_ai.clone(), fund_account_ai.clone(), token_prog_ai.clone()],
+            &[fund_seeds]
+        )?;
+        invoke_signed(
+            &spl_token::instruction::close_account(token_prog_ai.key, unwrap_ai.key, sol_vault_ai.key, fund_account_ai.key, &[fund_account_ai.key])?,
+            &[unwrap_ai.clone(), sol_vault_ai.clone(), fund_account_ai.clone(), token_prog_ai.clone()],
+            &[fund_seeds]
+        )?;
+        // only what the manager fronted goes back, lamports sent to the address stay unstaked
+        invoke_signed(
+            &transfer(sol_vault_ai.key, manager_ai.key, fronted),
+            &[sol_vault_ai.clone(), manager_ai.clone(), system_prog_ai.clone()],
+            &[sol_vault_seeds]
+        )?;
+
+        let mut account_infos = vec![
+            venue_ai.clone(),
+            sol_vault_ai.clone(),
+            lst_ai.clone(),
+            token_prog_ai.clone(),
+            system_prog_ai.clone(),
+        ];
+        account_infos.extend_from_slice(venue_accs);
+        invoke_signed(
+            &self.deposit_ix(venue_ai, venue_accs, sol_vault_ai, lst_ai, token_prog_ai, system_prog_ai, amount)?,
+            &account_infos,
+            &[sol_vault_seeds]
+        )?;
+
+        let mut fund_data = FundAccount::load_mut_checked(fund_account_ai, program_id)?;
+        fund_data.tokens[tsi].balance = parse_token_account(wsol_vault_ai)?.amount;
+        check!(fund_data.tokens[tsi].balance >= fund_data.tokens[tsi].debt, ProgramError::InsufficientFunds);
+        self.refresh_position(&mut fund_data, position, venue_ai, lst_ai)?;
+        Ok(())
+    }
+
+    /// Unstakes `amount` of the LST at once, through WithdrawSol or Marinade's liquid unstake
+    ///
+    /// Accounts expected
+    /// 0. [WRITE]  Fund State Account
+    /// 1. [SIGNER] Manager Account
+    /// 2. [WRITE]  Stake SOL Account, PDA of [fund, "stake_sol"]
+    /// 3. [WRITE]  Fund wSOL Vault
+    /// 4. [WRITE]  Fund LST Associated Token Account
+    /// 5. []   Stake Pool / Marinade Program
+    /// 6. [WRITE]  Stake Pool / Marinade State Account
+    /// 7. []   Token Program
+    /// 8. []   System Program
+    /// 9.. stake pool: withdraw authority, reserve stake, manager fee account, pool mint, clock, stake history, stake program
+    ///     marinade: msol mint, liq pool sol leg, liq pool msol leg, treasury msol account
+    fn withdraw(&self, program_id: &Pubkey, accounts: &[AccountInfo], amount: u64) -> Result<(), ProgramError> {
+        check!(amount > 0, ProgramError::InvalidArgument);
+        let (position, _) = self.redeem(program_id, accounts, amount, true)?;
+        // what investors are still owed stays staked until unwound
+        let fund_data = FundAccount::load_checked(&accounts[0], program_id)?;
+        let info = fund_data.strategy_position(position);
+        check!(info.value_in_ul >= info.ul_debt, ProgramError::InsufficientFunds);
+        Ok(())
+    }
+
+    /// Permissionless refresh of the LST balance and exchange rate
+    ///
+    /// Accounts expected
+    /// 0. [WRITE]  Fund State Account
+    /// 1. []   Stake Pool / Marinade State Account
+    /// 2. []   Fund LST Associated Token Account
+    fn update_value(&self, program_id: &Pubkey, accounts: &[AccountInfo]) -> Result<(), ProgramError> {
+        let accounts_iter = &mut accounts.iter();
+        let fund_account_ai = next_account_info(accounts_iter)?;
+        let venue_ai = next_account_info(accounts_iter)?;
+        let lst_ai = next_account_info(accounts_iter)?;
+        let mut fund_data = FundAccount::load_mut_checked(fund_account_ai, program_id)?;
+        let position = fund_data.get_strategy_index(self.kind, venue_ai.key).ok_or(FundError::InvalidStrategy)?;
+        self.check_lst_account(&fund_data, fund_account_ai.key, venue_ai, lst_ai)?;
+        self.refresh_position(&mut fund_data, position, venue_ai, lst_ai)?;
+        msg!("LST value in ul: {:?}", fund_data.strategy_position(position).value_in_ul);
+        Ok(())
+    }
+
+    /// Permissionless: unstakes what the settled investor is owed into the wSOL vault, their
+    /// token debt on that slot is then paid as usual. Same accounts as `withdraw`, any signer,
+    /// then the Investor State Account of `strategy_exit_investor`.
+    /// What the venue keeps on the way out comes off that investor's token debt.
+    fn investor_exit(&self, program_id: &Pubkey, accounts: &[AccountInfo]) -> Result<(), ProgramError> {
+        check!(accounts.len() > 6, ProgramError::NotEnoughAccountKeys);
+        let fund_data = FundAccount::load_checked(&accounts[0], program_id)?;
+        // unset for debts booked before exits unwound one investor at a time, the fund keeps the fees of those
+        let investor_state_ai = if fund_data.strategy_exit_investor == Pubkey::default() { None } else { accounts.last() };
+        if let Some(investor_state_ai) = investor_state_ai {
+            check_eq!(fund_data.strategy_exit_investor, *investor_state_ai.key);
+        }
+        let accounts = &accounts[..accounts.len() - investor_state_ai.is_some() as usize];
+        check!(accounts.len() > 6, ProgramError::NotEnoughAccountKeys);
+        let position = fund_data.get_strategy_index(self.kind, accounts[6].key).ok_or(FundError::InvalidStrategy)?;
+        let info = *fund_data.strategy_position(position);
+        drop(fund_data);
+        check!(info.ul_debt > 0, FundError::InvalidStateAccount);
+        let rate = self.get_lst_rate(&accounts[6])?;
+        // round up so the redeemed SOL covers the debt before venue fees
+        let lst_amount: u64 = U64F64::to_num(U64F64::from_num(info.ul_debt).checked_div(rate).unwrap().ceil());
+        let (_, received) = self.redeem(program_id, accounts, lst_amount.min(info.receipt_balance), false)?;
+
+        let mut fund_data = FundAccount::load_mut_checked(&accounts[0], program_id)?;
+        let tsi = info.ul_token_slot as usize;
+        // a shortfall is the investor's, a rounding surplus stays with the fund
+        let booked = if investor_state_ai.is_none() { info.ul_debt } else { received.min(info.ul_debt) };
+        fund_data.tokens[tsi].debt = fund_data.tokens[tsi].debt.checked_add(booked).unwrap();
+        check!(fund_data.tokens[tsi].balance >= fund_data.tokens[tsi].debt, ProgramError::InsufficientFunds);
+        fund_data.strategy_position_mut(position).ul_debt = 0;
+        if let Some(investor_state_ai) = investor_state_ai {
+            let mut investor_data = InvestorData::load_mut_checked(investor_state_ai, program_id)?;
+            check_eq!(investor_data.manager, fund_data.manager_account);
+            investor_data.token_debts[tsi] = investor_data.token_debts[tsi].checked_sub(info.ul_debt - booked).unwrap();
+            refresh_exit_status(&fund_data, &mut investor_data);
+        }
+        msg!("unstaked {:?} of {:?} owed", received, info.ul_debt);
+        Ok(())
+    }
+}
@@ -13,6 +13,7 @@ use bytemuck::{from_bytes, from_bytes_mut, Pod, Zeroable};
 use fixed::types::U64F64;
 use fixed::types::I80F48;
 use crate::error::FundError;
+use crate::migration::{FUND_ACCOUNT_VERSION, INVESTOR_DATA_VERSION};
 
 pub const NUM_TOKENS:usize = 8;
 pub const MAX_TOKENS:usize = 50;
@@ -21,6 +22,28 @@ pub const MAX_INVESTORS_WITHDRAW: usize = 2;
 pub const NUM_MARGIN: usize = 2;
 pub const NUM_PERP: usize = 3;
 pub const MAX_LIMIT_ORDERS:usize = 2;
+pub const NAV_HISTORY_LEN: usize = 180;
+// intermediate tokens of a guarded swap route
+pub const MAX_SWAP_HOPS: usize = 3;
+pub const MAX_SERUM_MARKETS: usize = 2;
+pub const TURNOVER_BUCKETS: usize = 24;
+pub const MAX_BANNED_TOKENS: usize = 4;
+pub const MAX_FRIKTION_VOLTS: usize = 2;
+pub const MAX_STRATEGY_POSITIONS: usize = 4;
+pub const MAX_REIMBURSED_INVESTORS: usize = 32;
+// StrategyPosition.kind
+pub const STRATEGY_FRIKTION: u8 = 0;
+pub const STRATEGY_STAKE_POOL: u8 = 1;
+pub const STRATEGY_MARINADE: u8 = 2;
+pub const STRATEGY_TOKEN_LENDING: u8 = 3;
+// SwapGuard.source
+pub const GUARD_SOURCE_NONE: u8 = 0;
+pub const GUARD_SOURCE_EXECUTION_ORDER: u8 = 1;
+pub const GUARD_SOURCE_STOP_LOSS: u8 = 2;
+pub const GUARD_SOURCE_TAKE_PROFIT: u8 = 3;
+// InvestorData.friktion_ext_flags
+pub const FRIKTION_EXT_UL_WITHDRAWN: u8 = 1;
+pub const FRIKTION_EXT_FC_WITHDRAWN: u8 = 2;
 
 pub trait Loadable: Pod {
     fn load_mut<'a>(account: &'a AccountInfo) -> Result<RefMut<'a, Self>, ProgramError> {
@@ -162,15 +185,52 @@ pub struct FundAccount {
      pub limit_orders : [LimitOrderInfo; MAX_LIMIT_ORDERS], // 48 each = 96 
 
      
-     pub friktion_vault: FriktionVaultInfo, // 96 u8
+     // strategy position 0, first friktion volt before positions were generic
+     pub friktion_vault: StrategyPosition, // 96 u8
 
      pub reimbursement_init: bool,
 
      pub reimbursement_claimed: bool,
-     
-    //  pub margin_update_padding: [u8; 24], //80 Bytes for Depr. MarginInfo Size
 
-    pub migration_additonal_padding: [u8; 1854] // 2024 + 24 - 96 - 96 =  1864
+     // NAV history account, default if the fund has none
+     pub nav_history: Pubkey,
+
+     // manager's swap guard bound, replaces the pair bound within the pair's ceiling, 0 -> unset
+     pub max_slippage_bps: u16,
+     pub serum_padding: [u8; 4],
+
+     pub serum_markets: [SerumMarketInfo; MAX_SERUM_MARKETS], // 96 each
+
+     pub risk_limits: RiskLimits, // 216 u8
+
+     pub mandate: Mandate, // 280 u8
+
+     pub rebalance: RebalanceConfig, // 24 u8
+
+     // per token slot, executed by keepers once the price crosses
+     pub stop_loss: [SpotTrigger; NUM_TOKENS], // 32 each
+     pub take_profit: [SpotTrigger; NUM_TOKENS], // 32 each
+
+     // strategy positions after the first, friktion volts only use 0..MAX_FRIKTION_VOLTS
+     pub strategy_positions_ext: [StrategyPosition; MAX_STRATEGY_POSITIONS - 1], // 96 each
+
+     pub leverage: LeverageInfo, // 80 u8
+
+     // per serum market, open orders booked to settled investors and not yet in the token debts
+     pub serum_debts: [SerumDebt; MAX_SERUM_MARKETS], // 16 each
+
+     // InitReimbursement snapshotted the investors, unset on funds initialized before the table
+     pub has_reimbursement_table: bool,
+
+     // investor state of the settled investor the positions' ul_debt is owed to, while there is one
+     pub strategy_exit_investor: Pubkey,
+
+     // InitReimbursement started the snapshot and AddReimbursementInvestors has not sealed it,
+     // investors can neither enter nor leave meanwhile
+     pub reimbursement_snapshot_pending: bool,
+     
+    // reserved for new fields, carve from here and let the size assertions in migration.rs check it
+    pub migration_additonal_padding: [u8; 158]
 }
 impl_loadable!(FundAccount);
 
@@ -184,7 +244,8 @@ pub struct TokenSlot {
     pub index: [u8; 3],
     pub mux: u8,
     pub is_locked: u8,
-    pub padding: [u8; 2],
+    // index for mux 3 (whirlpool) and 4 (raydium clmm), use get_index / set_index
+    pub clmm_index: [u8; 2],
 
     // token balances & debts
     pub balance: u64,
@@ -195,6 +256,20 @@ pub struct TokenSlot {
 }
 impl_loadable!(TokenSlot);
 
+impl TokenSlot {
+    /// Platform token index of the slot under `mux`
+    pub fn get_index(&self, mux: usize) -> u8 {
+        if mux < 3 { self.index[mux] } else { self.clmm_index[mux - 3] }
+    }
+    pub fn set_index(&mut self, mux: usize, index: u8) {
+        if mux < 3 { self.index[mux] = index } else { self.clmm_index[mux - 3] = index }
+    }
+    /// Platform token index under the current mux
+    pub fn get_active_index(&self) -> usize {
+        self.get_index(self.mux as usize) as usize
+    }
+}
+
 #[repr(C)]
 #[derive(Clone, Copy, Debug)]
 pub struct SwapGuard {
@@ -204,10 +279,19 @@ pub struct SwapGuard {
     pub count: u8,
     pub token_in_slot: u8,
     pub token_out_slot: u8,
-    pub padding: [u8; 2],
+    // keeper order the swap is booked to once checked, GUARD_SOURCE_*
+    pub source: u8,
+    // trigger slot of a stop-loss or take-profit source
+    pub source_slot: u8,
     pub triggered_at: UnixTimestamp,
     pub input_value: U64F64,
-    pub ex_padding: [u8; 40],
+    // intermediate slots of a multi-hop route, `hop` of them are set
+    pub hop_slots: [u8; MAX_SWAP_HOPS],
+    // second output of a split swap
+    pub split_out_slot: u8,
+    // rebalancing keeper allowed to fire and check the swap, default for manager swaps
+    pub keeper: Pubkey,
+    pub ex_padding: [u8; 4],
     pub amount_in: u64,
     pub min_amount_out: u64,
 }
@@ -222,8 +306,12 @@ pub struct InvestorData {
     pub withdrawn_from_margin: bool,
     pub withdrawn_ul_from_friktion: bool,
     pub withdrawn_ftokens_from_friktion: bool,
-    pub pending_deposit_on_friktion: bool,
-    pub padding: [u8; 2],
+    // ExitStatus, in the byte of the never used pending_deposit_on_friktion
+    pub exit_status: u8,
+    // layout version, see migration.rs
+    pub version: u8,
+    // withdrawn flags of the volts after the first, FRIKTION_EXT_* bits
+    pub friktion_ext_flags: u8,
 
     /// Investor wallet address
     pub owner: Pubkey,
@@ -241,7 +329,11 @@ pub struct InvestorData {
     pub manager: Pubkey,
 
     // TODO Debt in Depost Tokens on Mango
-    pub margin_debt: [U64F64; NUM_MARGIN], 
+    pub margin_debt: U64F64,
+
+    // debts on the second friktion volt, in the bytes of the never used margin_debt[1]
+    pub friktion_ext_ul_debt: u64,
+    pub friktion_ext_fc_debt: u64,
 
     // margin position id
     pub margin_position_id: [u64; NUM_MARGIN],
@@ -259,38 +351,165 @@ impl_loadable!(InvestorData);
 #[repr(C)]
 #[derive(Clone, Copy, Debug)]
 pub struct MangoInfo {
-    // margin account pubkey to check if the passed acc is correct
+    // Mango v4 account owned by the fund PDA, see mango_utils
     pub mango_account: Pubkey, 
-    pub perp_markets: [u8; 3], // default u8::MAX
-    pub perp_padding: u8,
-    pub deposit_index: u8,
-    pub markets_active: u8,
-    pub deposits_active: u8,
+    pub perp_markets: [u8; NUM_PERP], // v4 perp market index, default u8::MAX
+    pub is_active: bool,
+    // token slot pricing the base of each perp market
+    pub perp_price_slots: [u8; NUM_PERP],
     pub xpadding: u8,
 
     pub investor_debts: [u64; 2], // cumulative investor debts for each deposit token 
-    pub padding: [u8; 24]
+
+    // USDC collateral and unsettled perp pnl net of funding as of `last_updated`, native USDC
+    pub equity: i64,
+    pub last_updated: UnixTimestamp,
+    pub padding: [u8; 8]
 }
 impl_loadable!(MangoInfo);
 
 #[repr(C)]
 #[derive(Clone, Copy, Debug)]
-pub struct FriktionVaultInfo {
+pub struct StrategyPosition {
     pub last_updated: UnixTimestamp,
-    pub volt_vault_id: Pubkey,
-    pub total_value_in_ul: u64, //including pending w & d
-    pub fc_token_balance: u64,
-    pub ul_token_balance: u64,
-    pub fc_token_debt: u64,    
-    pub ul_token_debt: u64,
+    pub venue: Pubkey, // friktion volt vault, stake pool, lending reserve
+    pub value_in_ul: u64, //including pending w & d
+    pub receipt_balance: u64, // fcTokens, pool tokens, collateral tokens
+    pub ul_balance: u64, // underlying at the venue not yet turned into receipts
+    pub receipt_debt: u64,    
+    pub ul_debt: u64,
     pub ul_token_slot: u8,
     pub is_active: bool,
     pub pending_deposit: bool,
     pub pending_withdrawal: bool,
-    pub padding: [u8; 4],
+    pub kind: u8, // STRATEGY_*, zero so positions written before it read as friktion
+    pub padding: [u8; 3],
     pub deposit_amount: u64,
 }
-impl_loadable!(FriktionVaultInfo);
+impl_loadable!(StrategyPosition);
+
+/// Borrowing on a token-lending market through one obligation owned by the fund
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct LeverageInfo {
+    pub obligation: Pubkey,
+    // USDC values of the obligation as of `last_updated`
+    pub deposited_value: U64F64,
+    pub borrowed_value: U64F64,
+    pub last_updated: UnixTimestamp,
+    // borrowed over deposited value, borrows and swaps are refused above it and keepers may repay
+    pub max_ltv_bps: u16,
+    pub is_active: bool,
+    pub padding: [u8; 5],
+}
+impl_loadable!(LeverageInfo);
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct SerumMarketInfo {
+    pub market: Pubkey,
+    // fund owned open orders account on the market
+    pub open_orders: Pubkey,
+    pub base_slot: u8,
+    pub quote_slot: u8,
+    pub is_active: bool,
+    pub padding: [u8; 5],
+    // free + locked in open orders, refreshed by every order op and UpdateSerumValue
+    pub base_total: u64,
+    pub quote_total: u64,
+    pub last_updated: UnixTimestamp,
+}
+impl_loadable!(SerumMarketInfo);
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct SerumDebt {
+    pub base_debt: u64,
+    pub quote_debt: u64,
+}
+impl_loadable!(SerumDebt);
+
+/// Manager trading limits, 0 is unlimited. Loosening only applies after a timelock.
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct RiskLimits {
+    // max USDC value of one trade, bps of total_amount
+    pub max_trade_bps: u16,
+    // max USDC value traded over the last 24h, bps of total_amount
+    pub max_daily_turnover_bps: u16,
+    pub pending_max_trade_bps: u16,
+    pub pending_max_daily_turnover_bps: u16,
+    // when the pending limits replace the current ones, 0 if none pending
+    pub pending_effective_at: UnixTimestamp,
+    // hour (unix time / 3600) of the newest turnover bucket
+    pub turnover_hour: i64,
+    // USDC traded per hour, ring indexed by hour % TURNOVER_BUCKETS
+    pub turnover_buckets: [u64; TURNOVER_BUCKETS],
+}
+impl_loadable!(RiskLimits);
+
+/// Allocation mandate declared by the manager, 0 / default is unset.
+/// Immutable once locked, otherwise loosening only applies after a timelock.
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct Mandate {
+    // max USDC value of a single non USDC token, bps of the fund value
+    pub max_token_weight_bps: u16,
+    // min USDC share of the fund value, bps
+    pub min_usdc_buffer_bps: u16,
+    pub pending_max_token_weight_bps: u16,
+    pub pending_min_usdc_buffer_bps: u16,
+    // set at initialize, the mandate can never change
+    pub is_locked: bool,
+    pub padding: [u8; 7],
+    // when the pending mandate replaces the current one, 0 if none pending
+    pub pending_effective_at: UnixTimestamp,
+    // mints the fund may not buy
+    pub banned_mints: [Pubkey; MAX_BANNED_TOKENS],
+    pub pending_banned_mints: [Pubkey; MAX_BANNED_TOKENS],
+}
+impl_loadable!(Mandate);
+
+/// Target weights for the permissionless rebalancing crank
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct RebalanceConfig {
+    // bps of the fund value per slot, summing to 10000
+    pub target_weights_bps: [u16; NUM_TOKENS],
+    // drift from the target tolerated before a slot can be traded
+    pub tolerance_bps: u16,
+    // max USDC value of one rebalancing trade, bps of the fund value, 0 is unlimited
+    pub max_trade_bps: u16,
+    pub is_active: bool,
+    pub padding: [u8; 3],
+}
+impl_loadable!(RebalanceConfig);
+
+/// Conditional sale of a spot holding, one-shot
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct SpotTrigger {
+    // USDC price of one native unit, as TokenInfo prices are
+    pub trigger_price: U64F64,
+    // native units to sell
+    pub amount: u64,
+    pub is_active: bool,
+    // slot bought with the proceeds, USDC usually
+    pub token_out_slot: u8,
+    pub padding: [u8; 6],
+}
+impl_loadable!(SpotTrigger);
+
+impl Mandate {
+    pub fn is_set(&self) -> bool {
+        self.max_token_weight_bps != 0 || self.min_usdc_buffer_bps != 0 || self.pending_effective_at != 0
+            || self.banned_mints.iter().any(|m| *m != Pubkey::default())
+    }
+
+    pub fn is_banned(&self, mint: &Pubkey) -> bool {
+        *mint != Pubkey::default() && self.banned_mints.contains(mint)
+    }
+}
 
 #[repr(C)]
 #[derive(Clone, Copy, Debug)]
@@ -310,6 +529,105 @@ pub struct LimitOrderInfo {
 }
 impl_loadable!(LimitOrderInfo);
 
+/// Ring buffer of fund NAV snapshots, PDA of [fund, "nav_history"].
+/// Written by `update_amount_and_performance`, at most once per `interval` seconds.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct NavHistory {
+    pub is_initialized: bool,
+    pub version: u8,
+    pub padding: [u8; 6],
+
+    pub fund: Pubkey,
+    // min seconds between two entries
+    pub interval: i64,
+    // slot of the next entry
+    pub head: u32,
+    pub count: u32,
+
+    pub entries: [NavEntry; NAV_HISTORY_LEN],
+}
+impl_loadable!(NavHistory);
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct NavEntry {
+    pub timestamp: UnixTimestamp,
+    pub total_amount: U64F64,
+    pub prev_performance: U64F64,
+    pub performance_fee: U64F64,
+}
+impl_loadable!(NavEntry);
+
+/// Investors entitled to the fund's Mango v3 reimbursement, PDA of [fund, "reimbursement"].
+/// Snapshot started by InitReimbursement and filled by AddReimbursementInvestors, sealed once every
+/// investor is in, then credited by Reimburse and paid out by ClaimReimbursement.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct ReimbursementTable {
+    pub is_initialized: bool,
+    pub version: u8,
+    pub count: u8,
+    // active investors at the snapshot, sealed once count reaches it
+    pub expected_count: u8,
+    pub is_sealed: bool,
+    pub padding: [u8; 3],
+
+    pub fund: Pubkey,
+    // USDC received from Mango, the part credited to the entries and the part paid out
+    pub total_reimbursed: u64,
+    pub total_entitled: u64,
+    pub total_claimed: u64,
+
+    // fund valuation at the snapshot, every page is valued with it
+    pub total_amount: U64F64,
+    pub prev_performance: U64F64,
+
+    pub entries: [ReimbursementEntry; MAX_REIMBURSED_INVESTORS],
+}
+impl_loadable!(ReimbursementTable);
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ReimbursementEntry {
+    // investor wallet
+    pub owner: Pubkey,
+    // keeps an investor from being added twice across pages
+    pub investor_state: Pubkey,
+    // investor's part of the fund NAV at the snapshot
+    pub share: U64F64,
+    // credited and not yet claimed
+    pub amount: u64,
+    pub padding: [u8; 8],
+}
+impl_loadable!(ReimbursementEntry);
+
+/// DCA / TWAP order of a fund, PDA of [fund, "execution_order"].
+/// Keepers swap one slice per `interval` through the swap guard.
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct ExecutionOrder {
+    pub is_initialized: bool,
+    pub version: u8,
+    pub is_active: bool,
+    pub token_in_slot: u8,
+    pub token_out_slot: u8,
+    pub padding: [u8; 1],
+    pub slices: u16,
+    pub slices_done: u16,
+    // trade bound, only tightens the token and fund bounds, never 0
+    pub max_slippage_bps: u16,
+    pub ex_padding: [u8; 4],
+
+    pub fund: Pubkey,
+    pub total_amount_in: u64,
+    pub amount_in_done: u64,
+    // seconds between two slices
+    pub interval: i64,
+    pub next_slice_at: UnixTimestamp,
+}
+impl_loadable!(ExecutionOrder);
+
 impl Sealed for InvestorData {}
 impl IsInitialized for InvestorData {
     fn is_initialized(&self) -> bool {
@@ -349,15 +667,28 @@ pub struct TokenInfo {
     // last updated timestamp
     pub last_updated: UnixTimestamp,
 
-    pub token_id: u8, // 0 -> raydium, 1-> orca, 2 -> strategy
+    pub token_id: u8, // 0 -> raydium, 1-> orca, 2 -> strategy, 3 -> orca whirlpool, 4 -> raydium clmm
 
     pub pc_index: u8, // 0 -> USDC (index of the pc i.e base's tokenInfo in platformState)
-    // padding for future use
-    pub padding: [u8; 6],
+    // swap guard bound of the whitelisted pair, this token against its pc, 0 -> DEFAULT_SLIPPAGE_BPS
+    pub max_slippage_bps: u16,
+    // ceiling a fund bound may loosen the pair bound to, 0 -> fund bounds only tighten
+    pub max_fund_slippage_bps: u16,
+    // low 16 bits of the slot of `last_updated`, only compared with the current slot
+    pub last_updated_slot: u16,
 }
 impl_loadable!(TokenInfo);
 
 
+/// Initialized accounts must be on the current layout, older ones go through MigrateFund first
+fn check_version(is_initialized: bool, version: u8, current: u8) -> Result<(), ProgramError> {
+    if is_initialized && version != current {
+        msg!("account layout v{}, needs MigrateFund to v{}", version, current);
+        return Err(FundError::UnsupportedAccountVersion.into())
+    }
+    Ok(())
+}
+
 impl PlatformData {
     pub fn load_mut_checked<'a>(
         account: &'a AccountInfo,
@@ -401,6 +732,7 @@ impl FundAccount {
         check_eq!(account.owner, program_id);
 
         let data = Self::load_mut(account)?;
+        check_version(data.is_initialized, data.version, FUND_ACCOUNT_VERSION)?;
         Ok(data)
     }
     pub fn load_checked<'a>(
@@ -411,10 +743,11 @@ impl FundAccount {
         check_eq!(account.owner, program_id);
 
         let data = Self::load(account)?;
+        check_version(data.is_initialized, data.version, FUND_ACCOUNT_VERSION)?;
         Ok(data)
     }
     pub fn get_token_slot(&self, index: usize, mux: usize) -> Option<usize> {
-        self.tokens.iter().position(|token| token.index[mux] as usize == index)
+        self.tokens.iter().position(|token| token.get_index(mux) as usize == index)
     }
     pub fn get_mango_perp_index(&self, mango_perp_index: u8) -> Option<usize> {
         self.mango_positions.perp_markets.iter().position(|pmid| *pmid == mango_perp_index)
@@ -422,12 +755,99 @@ impl FundAccount {
     pub fn get_investor_index(&self, inv_state_pk: &Pubkey) -> Option<usize> {
         self.investors.iter().position(|pos| *pos == *inv_state_pk)
     }
+    pub fn get_serum_market_index(&self, market: &Pubkey) -> Option<usize> {
+        self.serum_markets.iter().position(|info| info.is_active && info.market == *market)
+    }
     pub fn find_slot_by_client_id(&self, client_order_id: u64) -> Option<usize> {
         self.limit_orders.iter().position(|limitOrderInfo| (*limitOrderInfo).client_order_id == client_order_id)
     }
+    // position 0 is `friktion_vault`, the others live in `strategy_positions_ext`
+    pub fn strategy_position(&self, position: usize) -> &StrategyPosition {
+        if position == 0 { &self.friktion_vault } else { &self.strategy_positions_ext[position - 1] }
+    }
+    pub fn strategy_position_mut(&mut self, position: usize) -> &mut StrategyPosition {
+        if position == 0 { &mut self.friktion_vault } else { &mut self.strategy_positions_ext[position - 1] }
+    }
+    pub fn get_strategy_index(&self, kind: u8, venue: &Pubkey) -> Option<usize> {
+        (0..MAX_STRATEGY_POSITIONS).find(|i| {
+            let position = self.strategy_position(*i);
+            position.is_active && position.kind == kind && position.venue == *venue
+        })
+    }
+    // friktion volts keep their investor debts per volt, so they are limited to the first positions
+    pub fn get_friktion_volt_index(&self, volt_vault: &Pubkey) -> Option<usize> {
+        self.get_strategy_index(STRATEGY_FRIKTION, volt_vault).filter(|volt| *volt < MAX_FRIKTION_VOLTS)
+    }
     
 }
 
+impl ExecutionOrder {
+    pub fn load_mut_checked<'a>(
+        account: &'a AccountInfo,
+        program_id: &Pubkey
+    ) -> Result<RefMut<'a, Self>, ProgramError> {
+        check_eq!(account.data_len(), size_of::<Self>());
+        check_eq!(account.owner, program_id);
+        let data = Self::load_mut(account)?;
+        Ok(data)
+    }
+}
+
+impl ReimbursementTable {
+    pub fn load_mut_checked<'a>(
+        account: &'a AccountInfo,
+        program_id: &Pubkey
+    ) -> Result<RefMut<'a, Self>, ProgramError> {
+        check_eq!(account.data_len(), size_of::<Self>());
+        check_eq!(account.owner, program_id);
+        let data = Self::load_mut(account)?;
+        Ok(data)
+    }
+}
+
+impl NavHistory {
+    pub fn load_mut_checked<'a>(
+        account: &'a AccountInfo,
+        program_id: &Pubkey
+    ) -> Result<RefMut<'a, Self>, ProgramError> {
+        check_eq!(account.data_len(), size_of::<Self>());
+        check_eq!(account.owner, program_id);
+        let data = Self::load_mut(account)?;
+        Ok(data)
+    }
+    pub fn load_checked<'a>(
+        account: &'a AccountInfo,
+        program_id: &Pubkey
+    ) -> Result<Ref<'a, Self>, ProgramError> {
+        check_eq!(account.data_len(), size_of::<Self>());
+        check_eq!(account.owner, program_id);
+        let data = Self::load(account)?;
+        Ok(data)
+    }
+    /// Most recent entry
+    pub fn latest(&self) -> Option<&NavEntry> {
+        self.get(0)
+    }
+    /// `age` entries back from the latest one
+    pub fn get(&self, age: usize) -> Option<&NavEntry> {
+        if age >= self.count as usize {
+            return None
+        }
+        Some(&self.entries[(self.head as usize + NAV_HISTORY_LEN - 1 - age) % NAV_HISTORY_LEN])
+    }
+    pub fn push(&mut self, entry: NavEntry) -> bool {
+        if let Some(last) = self.latest() {
+            if entry.timestamp - last.timestamp < self.interval {
+                return false
+            }
+        }
+        self.entries[self.head as usize] = entry;
+        self.head = ((self.head as usize + 1) % NAV_HISTORY_LEN) as u32;
+        self.count = (self.count as usize + 1).min(NAV_HISTORY_LEN) as u32;
+        true
+    }
+}
+
 impl InvestorData {
     pub fn load_mut_checked<'a>(
         account: &'a AccountInfo,
@@ -436,6 +856,7 @@ impl InvestorData {
         check_eq!(account.data_len(), size_of::<Self>());
         check_eq!(account.owner, program_id);
         let data = Self::load_mut(account)?;
+        check_version(data.is_initialized, data.version, INVESTOR_DATA_VERSION)?;
         Ok(data)
     }
     pub fn load_checked<'a>(
@@ -446,6 +867,82 @@ impl InvestorData {
         check_eq!(account.owner, program_id);
 
         let data = Self::load(account)?;
+        check_version(data.is_initialized, data.version, INVESTOR_DATA_VERSION)?;
         Ok(data)
     }
+    // per volt debts and flags, volt 0 keeps the original fields
+    pub fn get_friktion_ul_debt(&self, volt: usize) -> u64 {
+        if volt == 0 { self.friktion_ul_debt } else { self.friktion_ext_ul_debt }
+    }
+    pub fn set_friktion_ul_debt(&mut self, volt: usize, amount: u64) {
+        if volt == 0 { self.friktion_ul_debt = amount } else { self.friktion_ext_ul_debt = amount }
+    }
+    pub fn get_friktion_fc_debt(&self, volt: usize) -> u64 {
+        if volt == 0 { self.friktion_fc_debt } else { self.friktion_ext_fc_debt }
+    }
+    pub fn set_friktion_fc_debt(&mut self, volt: usize, amount: u64) {
+        if volt == 0 { self.friktion_fc_debt = amount } else { self.friktion_ext_fc_debt = amount }
+    }
+    pub fn has_withdrawn_friktion_ul(&self, volt: usize) -> bool {
+        if volt == 0 { self.withdrawn_ul_from_friktion } else { self.friktion_ext_flags & FRIKTION_EXT_UL_WITHDRAWN != 0 }
+    }
+    pub fn set_withdrawn_friktion_ul(&mut self, volt: usize, withdrawn: bool) {
+        if volt == 0 {
+            self.withdrawn_ul_from_friktion = withdrawn;
+        } else if withdrawn {
+            self.friktion_ext_flags |= FRIKTION_EXT_UL_WITHDRAWN;
+        } else {
+            self.friktion_ext_flags &= !FRIKTION_EXT_UL_WITHDRAWN;
+        }
+    }
+    pub fn has_withdrawn_friktion_fc(&self, volt: usize) -> bool {
+        if volt == 0 { self.withdrawn_ftokens_from_friktion } else { self.friktion_ext_flags & FRIKTION_EXT_FC_WITHDRAWN != 0 }
+    }
+    pub fn set_withdrawn_friktion_fc(&mut self, volt: usize, withdrawn: bool) {
+        if volt == 0 {
+            self.withdrawn_ftokens_from_friktion = withdrawn;
+        } else if withdrawn {
+            self.friktion_ext_flags |= FRIKTION_EXT_FC_WITHDRAWN;
+        } else {
+            self.friktion_ext_flags &= !FRIKTION_EXT_FC_WITHDRAWN;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(timestamp: UnixTimestamp) -> NavEntry {
+        NavEntry { timestamp, ..NavEntry::zeroed() }
+    }
+
+    #[test]
+    fn nav_history_respects_interval() {
+        let mut history = NavHistory::zeroed();
+        history.interval = 3600;
+        assert!(history.latest().is_none());
+        assert!(history.push(entry(1000)));
+        assert!(!history.push(entry(1000 + 3599)));
+        assert!(history.push(entry(1000 + 3600)));
+        assert_eq!(history.count, 2);
+        assert_eq!(history.latest().unwrap().timestamp, 4600);
+        assert_eq!(history.get(1).unwrap().timestamp, 1000);
+        assert!(history.get(2).is_none());
+    }
+
+    #[test]
+    fn nav_history_wraps_around() {
+        let mut history = NavHistory::zeroed();
+        history.interval = 1;
+        let pushed = NAV_HISTORY_LEN as i64 + 5;
+        for ts in 1..=pushed {
+            assert!(history.push(entry(ts)));
+        }
+        assert_eq!(history.count as usize, NAV_HISTORY_LEN);
+        assert_eq!(history.head, 5);
+        assert_eq!(history.latest().unwrap().timestamp, pushed);
+        assert_eq!(history.get(NAV_HISTORY_LEN - 1).unwrap().timestamp, 6);
+        assert!(history.get(NAV_HISTORY_LEN).is_none());
+    }
 }
@@ -0,0 +1,220 @@
+use fixed::types::U64F64;
+
+use solana_program::{
+    account_info::AccountInfo,
+    msg,
+    program_error::ProgramError,
+    pubkey::Pubkey,
+};
+
+use crate::error::FundError;
+use crate::state::{MAX_STRATEGY_POSITIONS, STRATEGY_FRIKTION, STRATEGY_STAKE_POOL, STRATEGY_MARINADE, STRATEGY_TOKEN_LENDING, FundAccount, InvestorData, PlatformData};
+use crate::friktion_utils::*;
+use crate::jup_utils::get_slot_price;
+use crate::staking_utils::LiquidStakingAdapter;
+use crate::lending_utils::LendingAdapter;
+
+macro_rules! check {
+    ($cond:expr, $err:expr) => {
+        if !($cond) {
+            return Err(($err).into())
+        }
+    }
+}
+
+/// A yield venue holding part of the fund's underlying through a `StrategyPosition`.
+///
+/// Instruction handlers take the venue's own accounts and find their position from the venue
+/// account, the generic Strategy* instructions only pick the adapter by kind.
+pub trait StrategyAdapter {
+    /// Takes a free position for the venue, backed by `ul_token_slot`
+    fn add(&self, program_id: &Pubkey, accounts: &[AccountInfo], ul_token_slot: u8) -> Result<(), ProgramError>;
+
+    /// Frees an empty position
+    fn remove(&self, program_id: &Pubkey, accounts: &[AccountInfo]) -> Result<(), ProgramError>;
+
+    /// Moves `amount` of the underlying into the venue
+    fn deposit(&self, program_id: &Pubkey, accounts: &[AccountInfo], amount: u64) -> Result<(), ProgramError>;
+
+    /// Redeems receipts for the underlying
+    fn withdraw(&self, program_id: &Pubkey, accounts: &[AccountInfo], amount: u64) -> Result<(), ProgramError>;
+
+    /// Settles deposits or withdrawals the venue left pending
+    fn claim(&self, _program_id: &Pubkey, _accounts: &[AccountInfo]) -> Result<(), ProgramError> {
+        Err(FundError::InvalidInstruction.into())
+    }
+
+    /// Refreshes `value_in_ul` and `last_updated` from the venue accounts
+    fn update_value(&self, program_id: &Pubkey, accounts: &[AccountInfo]) -> Result<(), ProgramError>;
+
+    /// USDC value of the position counted in the fund NAV, the underlying not yet owed to investors
+    fn get_value(&self, platform_data: &PlatformData, fund_data: &FundAccount, position: usize, now_ts: i64) -> Result<U64F64, ProgramError> {
+        let info = fund_data.strategy_position(position);
+        get_position_ul_value(platform_data, fund_data, position, info.value_in_ul.checked_sub(info.ul_debt).unwrap(), now_ts)
+    }
+
+    /// Investor's (underlying, receipt) part of the position for a given share
+    fn get_exit_amounts(&self, fund_data: &FundAccount, position: usize, share: U64F64) -> (u64, u64) {
+        let info = fund_data.strategy_position(position);
+        let ul_amount: u64 = U64F64::to_num(
+            U64F64::from_num(info.value_in_ul.checked_sub(info.ul_debt).unwrap())
+        .checked_mul(share).unwrap());
+        (ul_amount, 0)
+    }
+
+    /// Books the investor's part as debts when the withdrawal settles.
+    ///
+    /// By default the part is owed in the underlying token slot, the position keeps it in `ul_debt`
+    /// until `investor_exit` unwinds it into the slot's vault
+    fn book_investor_exit(&self, fund_data: &mut FundAccount, position: usize, investor_data: &mut InvestorData, share: U64F64) -> Result<(), ProgramError> {
+        let (ul_amount, _) = self.get_exit_amounts(fund_data, position, share);
+        let info = fund_data.strategy_position_mut(position);
+        info.ul_debt = info.ul_debt.checked_add(ul_amount).unwrap();
+        check!(info.value_in_ul >= info.ul_debt, ProgramError::InsufficientFunds);
+        let tsi = info.ul_token_slot as usize;
+        investor_data.token_debts[tsi] = investor_data.token_debts[tsi].checked_add(ul_amount).unwrap();
+        Ok(())
+    }
+
+    /// Hands a settled investor their booked part
+    fn investor_exit(&self, program_id: &Pubkey, accounts: &[AccountInfo]) -> Result<(), ProgramError>;
+}
+
+pub fn get_strategy_adapter(kind: u8) -> Result<&'static dyn StrategyAdapter, ProgramError> {
+    match kind {
+        STRATEGY_FRIKTION => Ok(&FriktionAdapter),
+        STRATEGY_STAKE_POOL => Ok(&LiquidStakingAdapter { kind: STRATEGY_STAKE_POOL }),
+        STRATEGY_MARINADE => Ok(&LiquidStakingAdapter { kind: STRATEGY_MARINADE }),
+        STRATEGY_TOKEN_LENDING => Ok(&LendingAdapter),
+        _ => Err(FundError::InvalidStrategy.into())
+    }
+}
+
+/// USDC value of the position's underlying `amount`, rejects positions not refreshed in the last 100s
+pub fn get_position_ul_value(
+    platform_data: &PlatformData,
+    fund_data: &FundAccount,
+    position: usize,
+    amount: u64,
+    now_ts: i64
+) -> Result<U64F64, ProgramError> {
+    let info = fund_data.strategy_position(position);
+    if amount == 0 {
+        return Ok(U64F64::from_num(0));
+    }
+    if now_ts - info.last_updated > 100 {
+        msg!("strategy {} not up-to-date...", position);
+        return Err(FundError::PriceStaleInAccount.into())
+    }
+    Ok(U64F64::from_num(amount).checked_mul(get_slot_price(platform_data, fund_data, info.ul_token_slot as usize, now_ts)?).unwrap())
+}
+
+/// Value of all active strategy positions for the NAV
+pub fn get_strategy_value(platform_data: &PlatformData, fund_data: &FundAccount, now_ts: i64) -> Result<U64F64, ProgramError> {
+    let mut val = U64F64::from_num(0);
+    for position in 0..MAX_STRATEGY_POSITIONS {
+        let info = fund_data.strategy_position(position);
+        if !info.is_active {
+            continue;
+        }
+        val = val.checked_add(get_strategy_adapter(info.kind)?.get_value(platform_data, fund_data, position, now_ts)?).unwrap();
+    }
+    Ok(val)
+}
+
+/// Books the investor's part of every active position on settle
+pub fn book_strategy_exits(fund_data: &mut FundAccount, investor_data: &mut InvestorData, share: U64F64) -> Result<(), ProgramError> {
+    for position in 0..MAX_STRATEGY_POSITIONS {
+        let info = fund_data.strategy_position(position);
+        if !info.is_active {
+            continue;
+        }
+        get_strategy_adapter(info.kind)?.book_investor_exit(fund_data, position, investor_data, share)?;
+    }
+    Ok(())
+}
+
+/// Investors are paid from the token vaults only once every position has unwound what it owes them
+pub fn strategy_exits_unwound(fund_data: &FundAccount) -> bool {
+    (0..MAX_STRATEGY_POSITIONS).all(|position| {
+        let info = fund_data.strategy_position(position);
+        !info.is_active || info.kind == STRATEGY_FRIKTION || info.ul_debt == 0
+    })
+}
+
+pub struct FriktionAdapter;
+
+impl StrategyAdapter for FriktionAdapter {
+    fn add(&self, program_id: &Pubkey, accounts: &[AccountInfo], ul_token_slot: u8) -> Result<(), ProgramError> {
+        friktion_add_to_fund(program_id, accounts, ul_token_slot)
+    }
+
+    fn remove(&self, program_id: &Pubkey, accounts: &[AccountInfo]) -> Result<(), ProgramError> {
+        friktion_remove_from_fund(program_id, accounts)
+    }
+
+    fn deposit(&self, program_id: &Pubkey, accounts: &[AccountInfo], amount: u64) -> Result<(), ProgramError> {
+        friktion_deposit(program_id, accounts, amount)
+    }
+
+    // volts redeem every fcToken not owed to investors, the amount is not used
+    fn withdraw(&self, program_id: &Pubkey, accounts: &[AccountInfo], _amount: u64) -> Result<(), ProgramError> {
+        friktion_withdraw(program_id, accounts)
+    }
+
+    // both claims take the fund first and the volt vault fifth
+    fn claim(&self, program_id: &Pubkey, accounts: &[AccountInfo]) -> Result<(), ProgramError> {
+        check!(accounts.len() > 4, ProgramError::NotEnoughAccountKeys);
+        let fund_data = FundAccount::load_checked(&accounts[0], program_id)?;
+        let volt = fund_data.get_friktion_volt_index(accounts[4].key).ok_or(FundError::FriktionIncorrectVault)?;
+        let info = *fund_data.strategy_position(volt);
+        drop(fund_data);
+        if info.pending_deposit {
+            friktion_claim_pending_deposit(program_id, accounts)
+        } else if info.pending_withdrawal {
+            friktion_claim_pending_withdrawal(program_id, accounts)
+        } else {
+            Err(FundError::InvalidStateAccount.into())
+        }
+    }
+
+    fn update_value(&self, program_id: &Pubkey, accounts: &[AccountInfo]) -> Result<(), ProgramError> {
+        update_friktion_value(program_id, accounts)
+    }
+
+    // only the pending underlying is counted, as before positions were generic
+    fn get_value(&self, platform_data: &PlatformData, fund_data: &FundAccount, position: usize, now_ts: i64) -> Result<U64F64, ProgramError> {
+        let info = fund_data.strategy_position(position);
+        check!(now_ts - info.last_updated <= 100, FundError::PriceStaleInAccount);
+        get_position_ul_value(platform_data, fund_data, position, info.ul_balance.checked_sub(info.ul_debt).unwrap(), now_ts)
+    }
+
+    fn get_exit_amounts(&self, fund_data: &FundAccount, position: usize, share: U64F64) -> (u64, u64) {
+        let info = fund_data.strategy_position(position);
+        let ul_amount: u64 = U64F64::to_num(
+            U64F64::from_num(info.ul_balance.checked_sub(info.ul_debt).unwrap())
+        .checked_mul(share).unwrap());
+        let receipt_amount: u64 = U64F64::to_num(
+            U64F64::from_num(info.receipt_balance.checked_sub(info.receipt_debt).unwrap())
+        .checked_mul(share).unwrap());
+        (ul_amount, receipt_amount)
+    }
+
+    fn book_investor_exit(&self, fund_data: &mut FundAccount, volt: usize, investor_data: &mut InvestorData, share: U64F64) -> Result<(), ProgramError> {
+        let (ul_withdraw_amount, fc_withdraw_amount) = self.get_exit_amounts(fund_data, volt, share);
+        let volt_info = fund_data.strategy_position_mut(volt);
+        investor_data.set_friktion_ul_debt(volt, ul_withdraw_amount);
+        volt_info.ul_debt = volt_info.ul_debt.checked_add(ul_withdraw_amount).unwrap();
+        check!(volt_info.ul_balance >= volt_info.ul_debt, ProgramError::InsufficientFunds);
+
+        investor_data.set_friktion_fc_debt(volt, fc_withdraw_amount);
+        volt_info.receipt_debt = volt_info.receipt_debt.checked_add(fc_withdraw_amount).unwrap();
+        check!(volt_info.receipt_balance >= volt_info.receipt_debt, ProgramError::InsufficientFunds);
+        Ok(())
+    }
+
+    // fcTokens only, the underlying keeps its FriktionInvestorWithdrawUL / UL2 pair
+    fn investor_exit(&self, program_id: &Pubkey, accounts: &[AccountInfo]) -> Result<(), ProgramError> {
+        friktion_investor_withdraw_ftokens(program_id, accounts)
+    }
+}
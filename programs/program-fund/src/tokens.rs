@@ -15,8 +15,11 @@ use bytemuck::{ from_bytes };
 use spl_token::state::Mint;
 
 use crate::error::FundError;
+use crate::events::{emit_event, FundEvent};
+use crate::jup_utils::MAX_SLIPPAGE_BPS;
 use crate::processor::parse_token_account;
-use crate::state::{FundAccount, PlatformData, AmmInfo, Loadable};
+use crate::state::{NUM_TOKENS, FundAccount, PlatformData, TokenSlot, AmmInfo, Loadable};
+use crate::clmm_utils::*;
 
 macro_rules! check_eq {
     ($x:expr, $y:expr) => {
@@ -52,8 +55,8 @@ pub fn add_token_to_whitelist (
     check_eq!(investin_admin_acc.is_signer, true); // signer check
     check_eq!(platform_data.investin_admin, *investin_admin_acc.key); // only admin is allowed to add token
 
-    // token id check => 0 for Raydium and 1 for Orca for now!
-    check!(token_id < 2, ProgramError::InvalidArgument);
+    // token id check => 0 for Raydium, 1 for Orca, 3 for Orca Whirlpool and 4 for Raydium CLMM
+    check!(token_id < 2 || is_clmm(token_id), ProgramError::InvalidArgument);
     
     //later can keep if else condition 
     // check!(pc_index < 2, ProgramError::InvalidArgument);
@@ -64,11 +67,20 @@ pub fn add_token_to_whitelist (
     let pool_pc_account = next_account_info(accounts_iter)?;
 
     let mint_data = Mint::unpack(&mint_account.data.borrow())?;
-    let pool_coin_data = parse_token_account(pool_coin_account)?;
     let pool_pc_data = parse_token_account(pool_pc_account)?;
-
-    check_eq!(pool_coin_data.mint, *mint_account.key);
     check_eq!(pool_pc_data.mint, platform_data.token_list[pc_index as usize].mint); // 0 -> USDC... pc should be whitlisted first which is refrenced here
+
+    // CLMM: pool_coin_account is the pool state and pool_pc_account its pc vault
+    let pool_price = if is_clmm(token_id) {
+        let pool = load_clmm_pool(pool_coin_account, token_id)?;
+        check_eq!(pool.get_vault(&pool_pc_data.mint), Some(*pool_pc_account.key));
+        pool.get_price(mint_account.key)?
+    } else {
+        let pool_coin_data = parse_token_account(pool_coin_account)?;
+        check_eq!(pool_coin_data.mint, *mint_account.key);
+        U64F64::from_num(pool_pc_data.amount)
+        .checked_div(U64F64::from_num(pool_coin_data.amount)).unwrap()
+    };
     check_eq!(platform_data.token_list[pc_index as usize].pc_index, 0); // pc should either be USDC itself or have a USDC pair
     check_eq!(platform_data.get_token_index(mint_account.key, token_id), None);
 
@@ -79,15 +91,59 @@ pub fn add_token_to_whitelist (
     platform_data.token_list[index].pool_coin_account = *pool_coin_account.key;
     platform_data.token_list[index].pool_pc_account = *pool_pc_account.key;
     platform_data.token_list[index].pc_index = pc_index;
-    platform_data.token_list[index].pool_price = U64F64::from_num(pool_pc_data.amount)
-    .checked_div(U64F64::from_num(pool_coin_data.amount)).unwrap();
+    platform_data.token_list[index].pool_price = pool_price;
     platform_data.token_list[index].last_updated = clock.unix_timestamp;
+    platform_data.token_list[index].last_updated_slot = clock.slot as u16;
 
     platform_data.token_count += 1;
 
     Ok(())
 }
 
+pub fn set_token_slippage (
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    token_index: u8,
+    max_slippage_bps: u16
+) -> Result<(), ProgramError> {
+    let accounts_iter = &mut accounts.iter();
+
+    let platform_acc = next_account_info(accounts_iter)?;
+    let investin_admin_acc = next_account_info(accounts_iter)?;
+
+    let mut platform_data = PlatformData::load_mut_checked(platform_acc, program_id)?;
+
+    check_eq!(investin_admin_acc.is_signer, true); // signer check
+    check_eq!(platform_data.investin_admin, *investin_admin_acc.key);
+    check!(token_index < platform_data.token_count, ProgramError::InvalidArgument);
+    check!(max_slippage_bps <= MAX_SLIPPAGE_BPS, ProgramError::InvalidArgument);
+
+    platform_data.token_list[token_index as usize].max_slippage_bps = max_slippage_bps;
+    Ok(())
+}
+
+pub fn set_token_fund_slippage_ceiling (
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    token_index: u8,
+    max_fund_slippage_bps: u16
+) -> Result<(), ProgramError> {
+    let accounts_iter = &mut accounts.iter();
+
+    let platform_acc = next_account_info(accounts_iter)?;
+    let investin_admin_acc = next_account_info(accounts_iter)?;
+
+    let mut platform_data = PlatformData::load_mut_checked(platform_acc, program_id)?;
+
+    check_eq!(investin_admin_acc.is_signer, true); // signer check
+    check_eq!(platform_data.investin_admin, *investin_admin_acc.key);
+    check!(token_index < platform_data.token_count, ProgramError::InvalidArgument);
+    check!(max_fund_slippage_bps <= MAX_SLIPPAGE_BPS, ProgramError::InvalidArgument);
+
+    platform_data.token_list[token_index as usize].max_fund_slippage_bps = max_fund_slippage_bps;
+    Ok(())
+}
+
 pub fn update_token_prices (
     program_id: &Pubkey,
     accounts: &[AccountInfo],
@@ -105,31 +161,41 @@ pub fn update_token_prices (
     for _i in 0..count {
 
         let pool_coin_account = next_account_info(accounts_iter)?;
-        let pool_pc_account = next_account_info(accounts_iter)?;
-        
-
-        let pool_coin_data = parse_token_account(pool_coin_account)?;
-        let pool_pc_data = parse_token_account(pool_pc_account)?;
-
-        let index = platform_data.get_token_index_by_coin(pool_coin_account.key).unwrap() as usize;
-
+        let index = platform_data.get_token_index_by_coin(pool_coin_account.key).ok_or(ProgramError::InvalidArgument)? as usize;
         check_eq!(platform_data.token_list[index].pool_coin_account, *pool_coin_account.key);
-        check_eq!(platform_data.token_list[index].pool_pc_account, *pool_pc_account.key);
 
         let mux = platform_data.token_list[index].token_id;
         msg!("MUX: {:?}", mux);
-        if mux == 0 {
-            let amm_open_orders_account = next_account_info(accounts_iter)?;
-            let amm_info_account = next_account_info(accounts_iter)?;
-            let amm_open_order_data = load_open_orders(amm_open_orders_account)?;
-            let amm_info_data = AmmInfo::load(amm_info_account)?;
-            platform_data.token_list[index].pool_price = U64F64::from_num(pool_pc_data.amount + amm_open_order_data.native_pc_total - amm_info_data.need_take_pnl_pc)
-            .checked_div(U64F64::from_num(pool_coin_data.amount + amm_open_order_data.native_coin_total - amm_info_data.need_take_pnl_coin)).unwrap();
+        // CLMM pools price from sqrt_price, no pc account
+        if is_clmm(mux) {
+            let pool = load_clmm_pool(pool_coin_account, mux)?;
+            platform_data.token_list[index].pool_price = pool.get_price(&platform_data.token_list[index].mint)?;
         } else {
-            platform_data.token_list[index].pool_price = U64F64::from_num(pool_pc_data.amount)
-            .checked_div(U64F64::from_num(pool_coin_data.amount)).unwrap();
+            let pool_pc_account = next_account_info(accounts_iter)?;
+            let pool_coin_data = parse_token_account(pool_coin_account)?;
+            let pool_pc_data = parse_token_account(pool_pc_account)?;
+            check_eq!(platform_data.token_list[index].pool_pc_account, *pool_pc_account.key);
+
+            if mux == 0 {
+                let amm_open_orders_account = next_account_info(accounts_iter)?;
+                let amm_info_account = next_account_info(accounts_iter)?;
+                let amm_open_order_data = load_open_orders(amm_open_orders_account)?;
+                let amm_info_data = AmmInfo::load(amm_info_account)?;
+                platform_data.token_list[index].pool_price = U64F64::from_num(pool_pc_data.amount + amm_open_order_data.native_pc_total - amm_info_data.need_take_pnl_pc)
+                .checked_div(U64F64::from_num(pool_coin_data.amount + amm_open_order_data.native_coin_total - amm_info_data.need_take_pnl_coin)).unwrap();
+            } else {
+                platform_data.token_list[index].pool_price = U64F64::from_num(pool_pc_data.amount)
+                .checked_div(U64F64::from_num(pool_coin_data.amount)).unwrap();
+            }
         }
         platform_data.token_list[index].last_updated = clock.unix_timestamp;
+        platform_data.token_list[index].last_updated_slot = clock.slot as u16;
+        emit_event(&FundEvent::PriceUpdate {
+            token_index: index as u8,
+            mint: platform_data.token_list[index].mint,
+            price: platform_data.token_list[index].pool_price.to_bits(),
+            timestamp: clock.unix_timestamp,
+        });
     }
     msg!("Timestamp1: {:?}, Timstamp2: {:?}", clock.unix_timestamp, Clock::get()?.unix_timestamp);                           
     Ok(())
@@ -159,29 +225,9 @@ pub fn add_token_to_fund (
     let vault_info = parse_token_account(vault_acc)?;
     check_eq!(vault_info.owner, fund_data.fund_pda);
     check_eq!(fund_data.tokens[index as usize].is_active, false);
+    check!(!fund_data.mandate.is_banned(mint_acc.key), FundError::MandateViolation);
 
-    let token_index_1 = platform_data.get_token_index(mint_acc.key, 0);
-    let token_index_2 = platform_data.get_token_index(mint_acc.key, 1);
-    
-    // both indexes cant be None
-    check!(((token_index_1 != None) || (token_index_2 != None)), ProgramError::InvalidAccountData);
-
-    //what happens when it is both on ray and Orca MUX=??
-    if token_index_1 != None {
-        fund_data.tokens[index as usize].mux = 0;
-        fund_data.tokens[index as usize].index[0] = token_index_1.unwrap() as u8;
-    }
-    else {
-        fund_data.tokens[index as usize].index[0] = 255; // Max u8
-    }
-
-    if token_index_2 != None {
-        fund_data.tokens[index as usize].mux = 1;
-        fund_data.tokens[index as usize].index[1] = token_index_2.unwrap() as u8;
-    }
-    else {
-        fund_data.tokens[index as usize].index[1] = 255;
-    }
+    set_token_slot_indexes(&platform_data, &mut fund_data.tokens[index as usize], mint_acc.key)?;
 
     fund_data.tokens[index as usize].is_active = true;    
     fund_data.tokens[index as usize].balance = 0;
@@ -192,6 +238,62 @@ pub fn add_token_to_fund (
     Ok(())
 }
 
+/// Points the slot at every pool the mint is whitelisted on (255 if not listed).
+/// AMM pools keep priority for the mux, orca over raydium as before.
+pub fn set_token_slot_indexes (
+    platform_data: &PlatformData,
+    slot: &mut TokenSlot,
+    mint: &Pubkey
+) -> Result<(), ProgramError> {
+    let mut listed = false;
+    for token_id in [0, 1, WHIRLPOOL_TOKEN_ID, RAYDIUM_CLMM_TOKEN_ID] {
+        match platform_data.get_token_index(mint, token_id) {
+            Some(token_index) => {
+                slot.set_index(token_id as usize, token_index as u8);
+                if token_id < 2 || !listed {
+                    slot.mux = token_id;
+                }
+                listed = true;
+            }
+            None => slot.set_index(token_id as usize, 255), // Max u8
+        }
+    }
+    // cant be listed nowhere
+    check!(listed, ProgramError::InvalidAccountData);
+    Ok(())
+}
+
+/// Re-runs `set_token_slot_indexes` on an active slot against the current whitelist,
+/// the slot keeps its venue while that is still listed
+///
+/// Accounts expected
+/// 0. []   Platform State Account
+/// 1. [WRITE]  Fund State Account
+pub fn refresh_token_slot_indexes (
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    index: u8
+) -> Result<(), ProgramError> {
+    let accounts_iter = &mut accounts.iter();
+    let platform_acc = next_account_info(accounts_iter)?;
+    let fund_account_acc = next_account_info(accounts_iter)?;
+
+    let platform_data = PlatformData::load_checked(platform_acc, program_id)?;
+    let mut fund_data = FundAccount::load_mut_checked(fund_account_acc, program_id)?;
+    check!((index as usize) < NUM_TOKENS, ProgramError::InvalidArgument);
+    let slot = &mut fund_data.tokens[index as usize];
+    check!(slot.is_active, ProgramError::InvalidArgument);
+
+    let mux = slot.mux;
+    let mint = platform_data.token_list[slot.get_active_index()].mint;
+    set_token_slot_indexes(&platform_data, slot, &mint)?;
+    if slot.get_index(mux as usize) != 255 {
+        slot.mux = mux;
+    }
+    msg!("slot {} indexes {:?} {:?}, mux {}", index, slot.index, slot.clmm_index, slot.mux);
+    Ok(())
+}
+
 pub fn remove_token_from_fund (
     program_id: &Pubkey,
     accounts: &[AccountInfo],
@@ -220,11 +322,12 @@ pub fn remove_token_from_fund (
     check!(fund_data.tokens[token_slot].balance<=10, ProgramError::InsufficientFunds);
     check_eq!(fund_data.tokens[token_slot].debt, 0);
     // check_eq!(fund_data.tokens[token_slot].is_on_mango, 0);
-    check_eq!((fund_data.tokens[token_slot].index[mux] == 0), false); // cant remove USDC
+    check_eq!((fund_data.tokens[token_slot].get_index(mux) == 0), false); // cant remove USDC
 
     fund_data.tokens[token_slot].is_active = false;
     fund_data.tokens[token_slot].index[0] = 0;
     fund_data.tokens[token_slot].index[1] = 0;
+    fund_data.tokens[token_slot].clmm_index = [0; 2];
     fund_data.tokens[token_slot].mux = 0;
 
     fund_data.tokens[token_slot].balance = 0;
@@ -0,0 +1,143 @@
+use fixed::types::U64F64;
+
+use solana_program::{
+    account_info::{AccountInfo, next_account_info},
+    msg,
+    program_error::ProgramError,
+    program_pack::IsInitialized,
+    pubkey::Pubkey,
+    sysvar::{Sysvar, clock::Clock},
+};
+
+use crate::error::FundError;
+use crate::state::{NUM_TOKENS, GUARD_SOURCE_STOP_LOSS, GUARD_SOURCE_TAKE_PROFIT, FundAccount, PlatformData, SpotTrigger};
+use crate::jup_utils::{arm_swap_guard, check_guard_pairing, check_price_slots, get_slot_price};
+
+macro_rules! check {
+    ($cond:expr, $err:expr) => {
+        if !($cond) {
+            return Err(($err).into())
+        }
+    }
+}
+macro_rules! check_eq {
+    ($x:expr, $y:expr) => {
+        if ($x != $y) {
+            return Err(FundError::Default.into())
+        }
+    }
+}
+
+fn get_trigger(fund_data: &mut FundAccount, slot: usize, is_stop_loss: bool) -> &mut SpotTrigger {
+    if is_stop_loss {
+        &mut fund_data.stop_loss[slot]
+    } else {
+        &mut fund_data.take_profit[slot]
+    }
+}
+
+/// Stop-loss sells once the price is at or below `trigger_price`, take-profit at or above.
+/// An amount of 0 clears the trigger.
+///
+/// Accounts expected
+/// 0. [WRITE]  Fund State Account
+/// 1. [SIGNER] Manager Account
+pub fn set_spot_trigger(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    token_fund_slot: u8,
+    is_stop_loss: bool,
+    trigger_price: U64F64,
+    amount: u64,
+    token_out_fund_slot: u8,
+) -> Result<(), ProgramError> {
+    let accounts_iter = &mut accounts.iter();
+    let fund_account_ai = next_account_info(accounts_iter)?;
+    let manager_ai = next_account_info(accounts_iter)?;
+
+    let mut fund_data = FundAccount::load_mut_checked(fund_account_ai, program_id)?;
+    check!(fund_data.is_initialized(), FundError::InvalidStateAccount);
+    check!(manager_ai.is_signer, ProgramError::MissingRequiredSignature);
+    check!(fund_data.manager_account == *manager_ai.key, FundError::ManagerMismatch);
+    let (slot, out_slot) = (token_fund_slot as usize, token_out_fund_slot as usize);
+    // USDC is the unit of account, nothing to trigger on
+    check!(slot > 0 && slot < NUM_TOKENS && fund_data.tokens[slot].is_active, ProgramError::InvalidArgument);
+    if amount != 0 {
+        check!(trigger_price > U64F64::from_num(0), ProgramError::InvalidArgument);
+        check!(out_slot < NUM_TOKENS && out_slot != slot && fund_data.tokens[out_slot].is_active, ProgramError::InvalidArgument);
+    }
+
+    let trigger = get_trigger(&mut fund_data, slot, is_stop_loss);
+    trigger.trigger_price = trigger_price;
+    trigger.amount = amount;
+    trigger.token_out_slot = token_out_fund_slot;
+    trigger.is_active = amount != 0;
+    Ok(())
+}
+
+/// Permissionless: arms the swap guard for a trigger whose price crossed.
+/// JupiterSwap and CheckSwapGuard signed by the keeper must follow in the same transaction.
+/// Both token prices must have been updated in an earlier slot.
+///
+/// Accounts expected
+/// 0. []   Instructions Sysvar
+/// 1. []   Platform State Account
+/// 2. [SIGNER] Keeper Account
+/// 3. [WRITE]  Fund State Account
+pub fn execute_spot_trigger(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    token_fund_slot: u8,
+    is_stop_loss: bool,
+) -> Result<(), ProgramError> {
+    let accounts_iter = &mut accounts.iter();
+    let sysvar_ix_ai = next_account_info(accounts_iter)?;
+    check_eq!(*sysvar_ix_ai.key, solana_program::sysvar::instructions::id());
+    let platform_ai = next_account_info(accounts_iter)?;
+    let keeper_ai = next_account_info(accounts_iter)?;
+    let fund_pda_ai = next_account_info(accounts_iter)?;
+
+    let platform_data = PlatformData::load_checked(platform_ai, program_id)?;
+    let mut fund_data = FundAccount::load_mut_checked(fund_pda_ai, program_id)?;
+    check!(keeper_ai.is_signer, ProgramError::MissingRequiredSignature);
+    let slot = token_fund_slot as usize;
+    check!(slot > 0 && slot < NUM_TOKENS, ProgramError::InvalidArgument);
+    let trigger = *get_trigger(&mut fund_data, slot, is_stop_loss);
+    check!(trigger.is_active, FundError::InvalidInstruction);
+
+    let clock = Clock::get()?;
+    let now_ts = clock.unix_timestamp;
+    // a live guard is never overridden
+    check!(!fund_data.guard.is_active || now_ts - fund_data.guard.triggered_at >= 100, FundError::InvalidInstruction);
+    // the trigger price and the min out are read off these
+    check_price_slots(&platform_data, &fund_data, &[slot, trigger.token_out_slot as usize], clock.slot)?;
+
+    let price = get_slot_price(&platform_data, &fund_data, slot, now_ts)?;
+    msg!("price {:?}, trigger price {:?}", price, trigger.trigger_price);
+    if is_stop_loss {
+        check!(price <= trigger.trigger_price, FundError::InvalidInstruction);
+    } else {
+        check!(price >= trigger.trigger_price, FundError::InvalidInstruction);
+    }
+
+    let available = fund_data.tokens[slot].balance.checked_sub(fund_data.tokens[slot].debt).unwrap();
+    let amount_in = trigger.amount.min(available);
+    check!(amount_in > 0, ProgramError::InsufficientFunds);
+
+    arm_swap_guard(&platform_data, &mut fund_data, token_fund_slot, &[], &[trigger.token_out_slot],
+        amount_in, 0, *keeper_ai.key, now_ts)?;
+    fund_data.guard.source = if is_stop_loss { GUARD_SOURCE_STOP_LOSS } else { GUARD_SOURCE_TAKE_PROFIT };
+    fund_data.guard.source_slot = token_fund_slot;
+    // consumed only once its swap is checked, CheckSwapGuard books the fill
+    check_guard_pairing(program_id, sysvar_ix_ai, &fund_data, fund_pda_ai.key, keeper_ai.key)
+}
+
+/// Takes a checked fill off the trigger. A partial fill leaves the rest armed while there is
+/// still something to sell, called by CheckSwapGuard
+pub fn book_trigger_fill(fund_data: &mut FundAccount, slot: usize, is_stop_loss: bool, amount_in: u64) {
+    let available = fund_data.tokens[slot].balance.saturating_sub(fund_data.tokens[slot].debt);
+    let trigger = get_trigger(fund_data, slot, is_stop_loss);
+    trigger.amount = trigger.amount.saturating_sub(amount_in);
+    trigger.is_active = trigger.amount > 0 && available > 0;
+    msg!("trigger filled {:?}, left {:?}", amount_in, trigger.amount);
+}